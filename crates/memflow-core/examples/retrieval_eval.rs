@@ -0,0 +1,92 @@
+//! 离线检索评测（recall@k / MRR / nDCG，对比 bm25、vector、hybrid）
+//!
+//! ```text
+//! cargo run -p memflow-core --example retrieval_eval -- [FIXTURE] [--k 5] [--embeddings stub|local] [--model NAME] [--db PATH] [--json]
+//! ```
+//!
+//! - 默认使用 `fixtures/retrieval_eval.json`，活动写入内存数据库后评测
+//! - `--db` 指向已有的 SQLite 数据库（例如 memflow.db 的副本）时只使用 FIXTURE 中的查询；
+//!   库中的向量必须由同一个模型生成
+//! - `--embeddings local` 需要启用 `local-embeddings` feature
+
+use anyhow::{anyhow, Result};
+use memflow_core::ai::embedding::{self, EmbeddingConfig, EmbeddingProviderKind};
+use memflow_core::ai::eval::{self, EvalFixture};
+use memflow_core::ai::rag::HybridSearch;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::str::FromStr;
+
+const DEFAULT_FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/retrieval_eval.json");
+
+struct Args {
+    fixture: String,
+    k: usize,
+    embeddings: EmbeddingProviderKind,
+    model: String,
+    db: Option<String>,
+    json: bool,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        fixture: DEFAULT_FIXTURE.to_string(),
+        k: 5,
+        embeddings: EmbeddingProviderKind::Stub,
+        model: String::new(),
+        db: None,
+        json: false,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| anyhow!("{} 缺少参数值", arg));
+        match arg.as_str() {
+            "--k" => args.k = value()?.parse()?,
+            "--embeddings" => args.embeddings = value()?.parse()?,
+            "--model" => args.model = value()?,
+            "--db" => args.db = Some(value()?),
+            "--json" => args.json = true,
+            _ if !arg.starts_with("--") => args.fixture = arg,
+            _ => return Err(anyhow!("未知参数: {}", arg)),
+        }
+    }
+    Ok(args)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = parse_args()?;
+    let fixture = EvalFixture::from_json(&std::fs::read_to_string(&args.fixture)?)?;
+
+    let provider = embedding::create_embedding_provider(&EmbeddingConfig {
+        provider: args.embeddings,
+        model: args.model.clone(),
+        cache_dir: dirs::cache_dir().map(|dir| dir.join("memflow").join("models")),
+        ..Default::default()
+    })
+    .await?;
+
+    let pool = match &args.db {
+        Some(path) => {
+            let options = SqliteConnectOptions::from_str(path)?.read_only(true);
+            SqlitePoolOptions::new().connect_with(options).await?
+        }
+        None => {
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect(":memory:")
+                .await?;
+            sqlx::migrate!("./migrations").run(&pool).await?;
+            eval::load_fixture_impl(&pool, &fixture, provider.as_ref(), chrono::Utc::now().timestamp()).await?;
+            pool
+        }
+    };
+
+    let report = eval::evaluate_impl(&pool, provider.as_ref(), &HybridSearch::new(), &fixture.queries, args.k).await?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+-- 重建 FTS5 索引：除 OCR 文本外同时索引窗口标题和应用名
+-- 这样 OCR 为空、或只有标题包含关键词的活动也能被关键词检索命中
+-- 列顺序 (window_title, app_name, ocr_text) 与 db.rs 中 bm25() 的列权重一一对应
+
+DROP TRIGGER IF EXISTS activity_logs_fts_insert;
+DROP TRIGGER IF EXISTS activity_logs_fts_update;
+DROP TRIGGER IF EXISTS activity_logs_fts_delete;
+
+DROP TABLE IF EXISTS activity_logs_fts;
+
+CREATE VIRTUAL TABLE activity_logs_fts USING fts5(
+    window_title,
+    app_name,
+    ocr_text,
+    tokenize='unicode61'
+);
+
+-- 回填已有数据
+INSERT INTO activity_logs_fts(rowid, window_title, app_name, ocr_text)
+SELECT id, COALESCE(window_title, ''), COALESCE(app_name, ''), COALESCE(ocr_text, '')
+FROM activity_logs;
+
+-- 插入触发器：每条活动都有标题和应用名，因此无条件写入 FTS
+CREATE TRIGGER IF NOT EXISTS activity_logs_fts_insert
+AFTER INSERT ON activity_logs
+BEGIN
+    INSERT INTO activity_logs_fts(rowid, window_title, app_name, ocr_text)
+    VALUES (NEW.id, COALESCE(NEW.window_title, ''), COALESCE(NEW.app_name, ''), COALESCE(NEW.ocr_text, ''));
+END;
+
+-- 更新触发器：任一被索引的列变化时重建该行的 FTS 记录
+CREATE TRIGGER IF NOT EXISTS activity_logs_fts_update
+AFTER UPDATE OF window_title, app_name, ocr_text ON activity_logs
+BEGIN
+    DELETE FROM activity_logs_fts WHERE rowid = OLD.id;
+    INSERT INTO activity_logs_fts(rowid, window_title, app_name, ocr_text)
+    VALUES (NEW.id, COALESCE(NEW.window_title, ''), COALESCE(NEW.app_name, ''), COALESCE(NEW.ocr_text, ''));
+END;
+
+-- 删除触发器：当活动记录删除时，同步删除 FTS 索引
+CREATE TRIGGER IF NOT EXISTS activity_logs_fts_delete
+AFTER DELETE ON activity_logs
+BEGIN
+    DELETE FROM activity_logs_fts WHERE rowid = OLD.id;
+END;
@@ -0,0 +1,26 @@
+-- 向量存储改为小端 f32 BLOB，并记录生成向量的模型与维度
+-- 不同模型的向量空间互不兼容，检索时按 model_id 过滤，不再混在一起比较
+-- 旧数据是 JSON 文本且来源模型未知：标记为 model_id='legacy'、dim=0，
+-- 由应用层（vector_db::convert_legacy_embeddings）在启动时转换为二进制格式
+
+CREATE TABLE vector_embeddings_v2 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    activity_id INTEGER NOT NULL,
+    model_id TEXT NOT NULL,
+    dim INTEGER NOT NULL,
+    embedding BLOB NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (activity_id) REFERENCES activity_logs(id) ON DELETE CASCADE,
+    UNIQUE (activity_id, model_id)
+);
+
+-- 旧表没有唯一约束，同一活动只保留最新的一条
+INSERT INTO vector_embeddings_v2 (activity_id, model_id, dim, embedding, created_at)
+SELECT activity_id, 'legacy', 0, embedding, created_at
+FROM vector_embeddings
+WHERE id IN (SELECT MAX(id) FROM vector_embeddings GROUP BY activity_id);
+
+DROP TABLE vector_embeddings;
+ALTER TABLE vector_embeddings_v2 RENAME TO vector_embeddings;
+
+CREATE INDEX IF NOT EXISTS idx_vector_embeddings_model ON vector_embeddings(model_id, dim);
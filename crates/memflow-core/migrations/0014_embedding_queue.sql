@@ -0,0 +1,16 @@
+-- 向量生成任务队列表（结构与 ocr_queue 保持一致）
+-- OCR/UIA 文本写入后入队，由后台 worker 批量生成向量；失败时按 next_attempt_at 退避重试
+CREATE TABLE IF NOT EXISTS embedding_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    activity_id INTEGER NOT NULL UNIQUE,
+    status TEXT NOT NULL DEFAULT 'pending', -- pending | processing | done | failed
+    retry_count INTEGER DEFAULT 0,
+    error_message TEXT,
+    next_attempt_at INTEGER DEFAULT 0,
+    created_at INTEGER DEFAULT (strftime('%s','now')),
+    updated_at INTEGER DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (activity_id) REFERENCES activity_logs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_embedding_queue_status ON embedding_queue(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_embedding_queue_updated ON embedding_queue(updated_at);
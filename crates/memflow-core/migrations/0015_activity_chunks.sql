@@ -0,0 +1,58 @@
+-- 长文本分块：OCR 文本按句切分为相互重叠的块，每块有独立的向量与 FTS 记录
+-- 检索时按块打分，再取每个活动得分最高的块作为上下文
+-- 块由应用层（chunks::replace_chunks_impl）在生成向量时写入；text_seg 规则与 activity_logs 的 *_seg 列一致
+
+CREATE TABLE IF NOT EXISTS activity_chunks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    activity_id INTEGER NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    text_seg TEXT NOT NULL DEFAULT '',
+    start_offset INTEGER NOT NULL, -- 在 ocr_text 中的字节偏移
+    end_offset INTEGER NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (activity_id) REFERENCES activity_logs(id) ON DELETE CASCADE,
+    UNIQUE (activity_id, chunk_index)
+);
+
+-- 分块向量（结构与 vector_embeddings 一致，activity_id 冗余存储便于按活动过滤）
+CREATE TABLE IF NOT EXISTS chunk_embeddings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chunk_id INTEGER NOT NULL,
+    activity_id INTEGER NOT NULL,
+    model_id TEXT NOT NULL,
+    dim INTEGER NOT NULL,
+    embedding BLOB NOT NULL,
+    created_at INTEGER DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (chunk_id) REFERENCES activity_chunks(id) ON DELETE CASCADE,
+    UNIQUE (chunk_id, model_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_chunk_embeddings_activity ON chunk_embeddings(activity_id, model_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS activity_chunks_fts USING fts5(
+    text,
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS activity_chunks_fts_insert
+AFTER INSERT ON activity_chunks
+BEGIN
+    INSERT INTO activity_chunks_fts(rowid, text)
+    VALUES (NEW.id, COALESCE(NULLIF(NEW.text_seg, ''), NEW.text));
+END;
+
+CREATE TRIGGER IF NOT EXISTS activity_chunks_fts_update
+AFTER UPDATE OF text, text_seg ON activity_chunks
+BEGIN
+    DELETE FROM activity_chunks_fts WHERE rowid = OLD.id;
+    INSERT INTO activity_chunks_fts(rowid, text)
+    VALUES (NEW.id, COALESCE(NULLIF(NEW.text_seg, ''), NEW.text));
+END;
+
+-- 活动删除时级联删除分块，同步清理 FTS
+CREATE TRIGGER IF NOT EXISTS activity_chunks_fts_delete
+AFTER DELETE ON activity_chunks
+BEGIN
+    DELETE FROM activity_chunks_fts WHERE rowid = OLD.id;
+END;
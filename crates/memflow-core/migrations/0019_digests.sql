@@ -0,0 +1,16 @@
+-- 日报 / 周报
+-- 迁移文件：0019_digests.sql
+-- 每个时段只保存一份报告，由应用层（digest）生成；时间区间按本地时区对齐，start_ts 包含、end_ts 不包含
+
+CREATE TABLE IF NOT EXISTS digests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    period TEXT NOT NULL, -- day | week
+    start_ts INTEGER NOT NULL,
+    end_ts INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    markdown TEXT NOT NULL,
+    -- 导出到笔记目录后的文件路径（未导出为 NULL）
+    file_path TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (period, start_ts)
+);
@@ -0,0 +1,12 @@
+-- 活动摘要的失败记录
+-- 迁移文件：0020_summary_failures.sql
+-- 同一区间连续失败达到上限后写入空摘要跳过，避免一直卡住上一级的汇总；生成成功后删除对应记录
+
+CREATE TABLE IF NOT EXISTS summary_failures (
+    granularity TEXT NOT NULL, -- hour | day | week
+    start_ts INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    PRIMARY KEY (granularity, start_ts)
+);
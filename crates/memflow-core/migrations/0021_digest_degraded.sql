@@ -0,0 +1,5 @@
+-- 日报 / 周报的降级标记
+-- 迁移文件：0021_digest_degraded.sql
+-- 模型调用失败、任务总结改为直接列出摘要的报告标记为 degraded，下次生成同一时段时重新生成并覆盖
+
+ALTER TABLE digests ADD COLUMN degraded INTEGER NOT NULL DEFAULT 0;
@@ -23,7 +23,9 @@ use tokio::sync::Mutex;
 
 use crate::context::RuntimeContext;
 
+use crate::ai::context_builder::{assemble_context, ContextBudget, ContextItem};
 use crate::ai::prompts::get_agent_config;
+use crate::ai::structured::StructuredOutputError;
 use crate::db::get_pool;
 use crate::ai::prompt_engine::PromptTemplate;
 use crate::agent::tools::{create_default_registry, ToolRegistry};
@@ -79,47 +81,6 @@ fn split_into_sessions(rows: &[sqlx::sqlite::SqliteRow], gap_minutes: i64) -> Ve
     sessions
 }
 
-/// 智能选择上下文行：优先选择最近且活动较多的会话
-fn select_context_rows<'a>(
-    sessions: &'a [Vec<&'a sqlx::sqlite::SqliteRow>],
-    max_items: usize,
-) -> Vec<&'a sqlx::sqlite::SqliteRow> {
-    if sessions.is_empty() {
-        return vec![];
-    }
-    
-    let mut selected: Vec<&sqlx::sqlite::SqliteRow> = vec![];
-    let mut remaining = max_items;
-    
-    // 策略：从最近的会话开始，每个会话取适当数量的记录
-    // 较大的会话（可能是主要工作）获得更多配额
-    for session in sessions.iter() {
-        if remaining == 0 {
-            break;
-        }
-        
-        // 根据会话大小分配配额：较大的会话获得更多
-        let session_quota = if sessions.len() == 1 {
-            remaining
-        } else {
-            // 至少取 5 条，或者按比例分配
-            let min_quota = 5.min(remaining);
-            let proportional = (session.len() * remaining / sessions.iter().map(|s| s.len()).sum::<usize>()).max(min_quota);
-            proportional.min(remaining)
-        };
-        
-        for row in session.iter().take(session_quota) {
-            selected.push(row);
-            remaining -= 1;
-            if remaining == 0 {
-                break;
-            }
-        }
-    }
-    
-    selected
-}
-
 // ============================================
 // DTO / Params
 // ============================================
@@ -267,8 +228,6 @@ pub async fn propose_automation(
 
     // 从配置获取上下文构建参数
     let agent_config = get_agent_config().await;
-    let context_max_items = agent_config.context_max_items;
-    let max_chars_per_ocr = agent_config.context_max_chars_per_ocr;
     let session_gap_minutes = agent_config.session_gap_minutes;
     
     // 基于时间间隔的会话分割
@@ -279,33 +238,38 @@ pub async fn propose_automation(
         session_gap_minutes
     );
     
-    // 智能选择会话：优先选择最近且活动较多的会话
-    let selected_rows = select_context_rows(&sessions, context_max_items);
-    
-    // 构建上下文（使用配置的参数）
-    let context_items: Vec<String> = selected_rows.iter().map(|row| {
-        let timestamp: i64 = row.get(1);
-        let app_name: String = row.get(2);
-        let window_title: String = row.get(3);
-        let ocr_text: Option<String> = row.get(4);
-        
-        let mut line = if let Some(dt) = chrono::DateTime::from_timestamp(timestamp, 0) {
-             let local: chrono::DateTime<chrono::Local> = chrono::DateTime::from(dt);
-             format!("[{}] {}: {}", local.format("%H:%M"), app_name, window_title)
-        } else {
-             format!("{}: {}", app_name, window_title)
-        };
-
-        if let Some(text) = ocr_text {
-             if !text.trim().is_empty() {
-                 // 使用配置的 OCR 文本截断长度
-                 let truncated = truncate_chars(&text, max_chars_per_ocr);
-                 line.push_str(&format!(" | 内容: {}", truncated.replace("\n", " ")));
-             }
-        }
-        line
-    }).collect();
-    let context_text = context_items.join("\n");
+    // 较大的会话（可能是主要工作）相关度更高，与新近度一起决定预算分配
+    let largest_session = sessions.iter().map(Vec::len).max().unwrap_or(1) as f32;
+    let items: Vec<ContextItem> = sessions
+        .iter()
+        .flat_map(|session| {
+            let relevance = session.len() as f32 / largest_session;
+            session.iter().map(move |row| {
+                let app_name: String = row.get(2);
+                let window_title: String = row.get(3);
+                let ocr_text: Option<String> = row.get(4);
+                ContextItem::new(row.get(0), row.get(1), &app_name, &window_title, ocr_text.as_deref())
+                    .with_relevance(relevance)
+            })
+        })
+        .collect();
+    let budget = ContextBudget {
+        max_tokens: agent_config.context_max_tokens,
+        max_tokens_per_item: agent_config.context_max_tokens_per_item,
+        max_items: agent_config.context_max_items,
+        ..Default::default()
+    };
+    let context = assemble_context(items, &budget);
+    tracing::info!(
+        "agent propose: 上下文 {} 条，约 {}/{} tokens（合并 {} 条重复截图，截断 {} 条，丢弃 {} 条）",
+        context.entries.len(),
+        context.used_tokens,
+        context.budget_tokens,
+        context.merged,
+        context.truncated,
+        context.dropped.len()
+    );
+    let context_text = context.render("\n");
 
     // 使用 Prompt Template 渲染提示词
     let template = PromptTemplate::new(
@@ -325,7 +289,8 @@ pub async fn propose_automation(
     let mut proposals: Vec<AutomationProposalDto> = Vec::new();
 
     tracing::info!("agent propose: start ai analysis (context chars={})", context_text.len());
-    match tokio::time::timeout(Duration::from_secs(60), ctx.analyze_for_proposals(&prompt)).await {
+    // 留出一次输出修正的时间
+    match tokio::time::timeout(Duration::from_secs(120), ctx.analyze_for_proposals(&prompt)).await {
         Ok(Ok(analysis)) => {
             tracing::info!("agent propose: ai analysis ok, tasks={}", analysis.tasks.len());
             
@@ -411,11 +376,20 @@ pub async fn propose_automation(
         }
         Ok(Err(e)) => {
             tracing::warn!("agent propose: ai analysis failed, fallback: {:?}", e);
+            // 区分模型输出无效与调用失败
+            let invalid_output = e
+                .downcast_ref::<StructuredOutputError>()
+                .is_some_and(StructuredOutputError::is_invalid_output);
+            let description = if invalid_output {
+                "AI 返回的内容无法解析，生成基础活动摘要。"
+            } else {
+                "AI 分析失败，生成基础活动摘要。"
+            };
             // 回退提案
             let fallback_proposal = AutomationProposalDto {
                 id: 0,
                 title: format!("生成最近 {} 小时活动摘要（规则）", time_window_hours),
-                description: "AI 分析失败，生成基础活动摘要。".to_string(),
+                description: description.to_string(),
                 confidence: 0.60,
                 risk_level: "low".to_string(),
                 steps: vec![AutomationStep::CreateNote { content: rule_based_summary }],
@@ -0,0 +1,270 @@
+//! 长文本分块
+//!
+//! 一张截图的 OCR 文本可能有上千字，整段生成一个向量会稀释相关段落，也容易超出模型的
+//! token 上限。这里按句子切分后装箱成长度受限、相邻之间有重叠的块：
+//! - 句末标点同时识别中英文（`。！？；` 与 `.!?;`），换行也视为句子边界
+//! - 英文句点后必须跟空白或结尾才算句末，避免切开 `3.14`、`example.com`
+//! - 超长句子按字符数硬切，英文优先在空白处断开，中文直接按字切
+//!
+//! 块的偏移均为原文中的字节偏移，与 [`crate::highlight`] 一致。
+
+use crate::ai::nlp::is_cjk_char;
+use serde::{Deserialize, Serialize};
+
+/// 分块参数（长度均按字符计）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkerConfig {
+    /// 单个块的最大字符数
+    pub max_chars: usize,
+    /// 相邻块之间最多重叠的字符数（以整句为单位回退）
+    pub overlap_chars: usize,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: 400,
+            overlap_chars: 80,
+        }
+    }
+}
+
+/// 一个文本块，`[start, end)` 为原文中的字节区间
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextChunk {
+    pub index: usize,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '；' | '…' | '!' | '?' | ';' | '\n')
+}
+
+/// 句末标点后可以附带的收尾符号（引号、括号）
+fn is_closing(c: char) -> bool {
+    matches!(c, '"' | '\'' | '”' | '’' | ')' | '）' | '」' | '』' | '】' | '》')
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// 去掉区间两端的空白，全是空白时返回 `None`
+fn trim_span(text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let slice = &text[start..end];
+    let trimmed_start = slice.len() - slice.trim_start().len();
+    let trimmed = slice.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let s = start + trimmed_start;
+    Some((s, s + trimmed.len()))
+}
+
+/// 切分句子，返回每句在原文中的字节区间（已去掉两端空白）
+pub fn split_sentences(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let ends = if c == '.' {
+            chars.peek().is_none_or(|(_, next)| next.is_whitespace() || is_closing(*next))
+        } else {
+            is_sentence_end(c)
+        };
+        if !ends {
+            continue;
+        }
+
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if is_closing(next) || (next != '\n' && is_sentence_end(next)) || next == '.' {
+                end = j + next.len_utf8();
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if let Some(span) = trim_span(text, start, end) {
+            spans.push(span);
+        }
+        start = end;
+    }
+
+    if let Some(span) = trim_span(text, start, text.len()) {
+        spans.push(span);
+    }
+    spans
+}
+
+/// 将超过 `max_chars` 的区间硬切成多段
+fn split_long_span(text: &str, (start, end): (usize, usize), max_chars: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+
+    while char_len(&text[piece_start..end]) > max_chars {
+        let slice = &text[piece_start..end];
+        let (limit, _) = slice.char_indices().nth(max_chars).unwrap_or((slice.len(), ' '));
+        let window = &slice[..limit];
+
+        // 英文在窗口后半段的最后一个空白处断开，避免切断单词
+        let boundary_char = slice[limit..].chars().next();
+        let breaks_word = !window.ends_with(char::is_whitespace)
+            && boundary_char.is_some_and(|c| !c.is_whitespace() && !is_cjk_char(c));
+        let cut = if breaks_word {
+            window
+                .rfind(char::is_whitespace)
+                .filter(|&pos| char_len(&window[..pos]) >= max_chars / 2)
+                .unwrap_or(limit)
+        } else {
+            limit
+        };
+
+        if let Some(span) = trim_span(text, piece_start, piece_start + cut) {
+            pieces.push(span);
+        }
+        piece_start += cut;
+    }
+
+    if let Some(span) = trim_span(text, piece_start, end) {
+        pieces.push(span);
+    }
+    pieces
+}
+
+/// 按句子切分并装箱成相互重叠的块
+///
+/// 每块不超过 `max_chars` 个字符；新块会从上一块末尾回退若干整句（总长不超过 `overlap_chars`）
+/// 作为重叠，保证跨句的上下文不会恰好被切断。
+pub fn chunk_text(text: &str, config: &ChunkerConfig) -> Vec<TextChunk> {
+    let max_chars = config.max_chars.max(1);
+    let units: Vec<(usize, usize)> = split_sentences(text)
+        .into_iter()
+        .flat_map(|span| split_long_span(text, span, max_chars))
+        .collect();
+    if units.is_empty() {
+        return Vec::new();
+    }
+
+    let span_len = |first: usize, last: usize| char_len(&text[units[first].0..units[last].1]);
+
+    // 每块由 units[first..=last] 组成
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut first = 0;
+    for last in 1..units.len() {
+        if span_len(first, last) <= max_chars {
+            continue;
+        }
+
+        let prev_last = last - 1;
+        ranges.push((first, prev_last));
+
+        // 从上一块末尾回退整句作为重叠，但至少前进一句
+        let mut next_first = last;
+        while next_first > first + 1
+            && span_len(next_first - 1, prev_last) <= config.overlap_chars
+        {
+            next_first -= 1;
+        }
+        // 重叠加上当前句超长时，缩减重叠
+        while next_first < last && span_len(next_first, last) > max_chars {
+            next_first += 1;
+        }
+        first = next_first;
+    }
+    ranges.push((first, units.len() - 1));
+
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(index, (first, last))| {
+            let (start, end) = (units[first].0, units[last].1);
+            TextChunk {
+                index,
+                text: text[start..end].to_string(),
+                start,
+                end,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentences(text: &str) -> Vec<&str> {
+        split_sentences(text).into_iter().map(|(s, e)| &text[s..e]).collect()
+    }
+
+    #[test]
+    fn splits_mixed_language_sentences() {
+        assert_eq!(
+            sentences("今天修复了登录问题。测试通过了吗？通过！Version 3.14 is out. See example.com now\n下一行"),
+            vec![
+                "今天修复了登录问题。",
+                "测试通过了吗？",
+                "通过！",
+                "Version 3.14 is out.",
+                "See example.com now",
+                "下一行",
+            ]
+        );
+        assert_eq!(sentences("他说：“好的。”然后离开了"), vec!["他说：“好的。”", "然后离开了"]);
+        assert!(sentences("  \n \n").is_empty());
+    }
+
+    #[test]
+    fn short_text_is_single_chunk() {
+        let chunks = chunk_text("  Hello world. Second sentence.  ", &ChunkerConfig::default());
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, "Hello world. Second sentence.");
+        assert_eq!((chunks[0].start, chunks[0].end), (2, 31));
+        assert!(chunk_text("", &ChunkerConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn chunks_overlap_and_respect_limit() {
+        let text = (1..=12)
+            .map(|i| format!("第{}句话讲的是一个相对完整的意思。", i))
+            .collect::<String>();
+        let config = ChunkerConfig {
+            max_chars: 50,
+            overlap_chars: 20,
+        };
+        let chunks = chunk_text(&text, &config);
+        assert!(chunks.len() > 2);
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.index, i);
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+            assert!(char_len(&chunk.text) <= config.max_chars);
+            assert!(chunk.text.ends_with('。'));
+        }
+        for pair in chunks.windows(2) {
+            // 相邻块有重叠且不断向前推进
+            assert!(pair[1].start < pair[0].end);
+            assert!(pair[1].start > pair[0].start);
+        }
+        assert_eq!(chunks.last().unwrap().end, text.len());
+    }
+
+    #[test]
+    fn long_sentences_are_hard_split() {
+        let english = "word ".repeat(60);
+        let chunks = chunk_text(&english, &ChunkerConfig { max_chars: 32, overlap_chars: 0 });
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(char_len(&chunk.text) <= 32);
+            assert!(chunk.text.split(' ').all(|w| w == "word"), "{:?}", chunk.text);
+        }
+
+        let chinese = "长".repeat(100);
+        let chunks = chunk_text(&chinese, &ChunkerConfig { max_chars: 30, overlap_chars: 10 });
+        assert_eq!(chunks.iter().map(|c| char_len(&c.text)).collect::<Vec<_>>(), vec![30, 30, 30, 10]);
+    }
+}
@@ -0,0 +1,195 @@
+//! 回答中的活动引用
+//!
+//! 对话上下文中的活动按 1、2、3… 编号，模型在回答里用 `[n]` 标注依据。
+//! 回答生成后解析这些编号，映射回活动 ID 并随助手消息保存到 `chat_messages.context_ids`，
+//! 前端和 MCP 据此从回答跳转到对应的截图；指向上下文之外的编号视为无效并丢弃。
+
+use crate::ai::provider::CompletionRequest;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// 追加在系统提示词后的引用要求
+pub const CITATION_INSTRUCTIONS: &str = "桌面活动记录带有编号。回答中用到某条记录时，在相应句子末尾标注它的编号，例如 [1] 或 [2][3]；\
+只能使用给出的编号，不要编造编号，没有依据的内容不要标注。";
+
+/// 引用标记中允许的最长内容（超过的一般不是引用，例如普通的方括号文本）
+const MAX_MARKER_CHARS: usize = 24;
+
+/// 给一条上下文记录加上编号（`n` 从 1 开始）
+pub fn numbered_entry(n: usize, entry: &str) -> String {
+    format!("[{}] {}", n, entry)
+}
+
+/// 在系统提示词后追加引用要求
+pub fn with_citation_instructions(mut request: CompletionRequest) -> CompletionRequest {
+    request.system = Some(match request.system.take() {
+        Some(system) if !system.is_empty() => format!("{}\n\n{}", system, CITATION_INSTRUCTIONS),
+        _ => CITATION_INSTRUCTIONS.to_string(),
+    });
+    request
+}
+
+/// 回答中的引用
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Citations {
+    /// 被引用的活动 ID（按首次引用的顺序去重）
+    pub activity_ids: Vec<i64>,
+    /// 指向上下文之外的编号
+    pub invalid: Vec<usize>,
+}
+
+/// 一个引用标记在文本中的位置及其中的编号
+struct Marker {
+    range: Range<usize>,
+    numbers: Vec<usize>,
+}
+
+/// 找出 `[1]`、`[1, 2]`、`【3】` 这类只包含编号的方括号
+fn scan_markers(text: &str) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    let mut rest = text.char_indices();
+
+    while let Some((start, c)) = rest.next() {
+        let close = match c {
+            '[' => ']',
+            '【' => '】',
+            _ => continue,
+        };
+
+        let inner_start = start + c.len_utf8();
+        let Some(inner_len) = text[inner_start..]
+            .char_indices()
+            .take(MAX_MARKER_CHARS + 1)
+            .find(|&(_, ch)| ch == close || ch == '[' || ch == '【' || ch == '\n')
+            .filter(|&(_, ch)| ch == close)
+            .map(|(i, _)| i)
+        else {
+            continue;
+        };
+        let inner = &text[inner_start..inner_start + inner_len];
+
+        let parts: Vec<&str> = inner
+            .split([',', '，', '、'])
+            .map(str::trim)
+            .collect();
+        if parts.is_empty() || !parts.iter().all(|p| !p.is_empty() && p.chars().all(|ch| ch.is_ascii_digit())) {
+            continue;
+        }
+        let numbers = parts.iter().filter_map(|p| p.parse().ok()).collect();
+
+        let end = inner_start + inner_len + close.len_utf8();
+        markers.push(Marker {
+            range: start..end,
+            numbers,
+        });
+        // 跳过已经识别的标记
+        while rest.clone().next().is_some_and(|(i, _)| i < end) {
+            rest.next();
+        }
+    }
+    markers
+}
+
+/// 回答中出现的引用编号（按首次出现的顺序去重，不校验范围）
+pub fn parse_citations(text: &str) -> Vec<usize> {
+    let mut numbers = Vec::new();
+    for n in scan_markers(text).into_iter().flat_map(|m| m.numbers) {
+        if !numbers.contains(&n) {
+            numbers.push(n);
+        }
+    }
+    numbers
+}
+
+/// 把引用编号映射回活动 ID；`sources[n - 1]` 是第 n 条上下文对应的活动
+pub fn resolve_citations(text: &str, sources: &[i64]) -> Citations {
+    let mut citations = Citations::default();
+    for n in parse_citations(text) {
+        match n.checked_sub(1).and_then(|i| sources.get(i)) {
+            Some(&id) => {
+                if !citations.activity_ids.contains(&id) {
+                    citations.activity_ids.push(id);
+                }
+            }
+            None => citations.invalid.push(n),
+        }
+    }
+    if !citations.invalid.is_empty() {
+        tracing::debug!("丢弃无效引用编号: {:?}（上下文共 {} 条）", citations.invalid, sources.len());
+    }
+    citations
+}
+
+const FULL_WIDTH_PUNCTUATION: [char; 12] = ['，', '。', '、', '；', '：', '！', '？', '（', '【', '《', '「', '“'];
+
+/// 去掉文本中指向上下文之外的编号；整个标记都无效时连同方括号一起删除
+pub fn strip_invalid_citations(text: &str, source_count: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for marker in scan_markers(text) {
+        let valid: Vec<String> = marker
+            .numbers
+            .iter()
+            .filter(|&&n| n >= 1 && n <= source_count)
+            .map(|n| n.to_string())
+            .collect();
+        if valid.len() == marker.numbers.len() {
+            continue;
+        }
+
+        result.push_str(&text[last..marker.range.start]);
+        last = marker.range.end;
+        let rest = &text[last..];
+        if !valid.is_empty() {
+            result.push_str(&format!("[{}]", valid.join(", ")));
+        } else if result.ends_with(' ') && rest.starts_with([' ', '。', '.', '，', ',']) {
+            // 删除标记后不留下多余的空格
+            result.pop();
+        } else if rest.starts_with(' ')
+            && (result.is_empty() || result.ends_with(['\n', '(']) || result.ends_with(FULL_WIDTH_PUNCTUATION))
+        {
+            // 标记前是行首或全角标点时，后面的空格也一并删除（ASCII 标点后的空格是正常的英文间距）
+            last += 1;
+        }
+    }
+
+    result.push_str(&text[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_validates_citations() {
+        let answer = "你在 VS Code 里修改了 rag.rs [1][3]，随后在终端跑了测试【2】。\
+            参考 [2, 5] 和 [1、3]；[链接](https://example.com) 与 [2024-01-01 10:00] 不是引用，[7] 是编造的。";
+        assert_eq!(parse_citations(answer), vec![1, 3, 2, 5, 7]);
+
+        let citations = resolve_citations(answer, &[101, 102, 103]);
+        assert_eq!(citations.activity_ids, vec![101, 103, 102]);
+        assert_eq!(citations.invalid, vec![5, 7]);
+
+        assert_eq!(
+            strip_invalid_citations(answer, 3),
+            "你在 VS Code 里修改了 rag.rs [1][3]，随后在终端跑了测试【2】。\
+            参考 [2] 和 [1、3]；[链接](https://example.com) 与 [2024-01-01 10:00] 不是引用，是编造的。"
+        );
+        assert_eq!(strip_invalid_citations("没有依据 [0]。", 3), "没有依据。");
+        assert_eq!(strip_invalid_citations("[9] 开头的引用\n[9] 第二行", 3), "开头的引用\n第二行");
+        assert_eq!(strip_invalid_citations("See (foo).[9] Next [9] step", 3), "See (foo). Next step");
+        assert_eq!(resolve_citations("[1] [1]", &[]).invalid, vec![1]);
+    }
+
+    #[test]
+    fn numbers_context_and_adds_instructions() {
+        assert_eq!(numbered_entry(2, "[10:00] 应用: Code"), "[2] [10:00] 应用: Code");
+        let request = with_citation_instructions(CompletionRequest::from_query("q", "ctx", Some("系统")));
+        assert_eq!(request.system.as_deref(), Some(format!("系统\n\n{}", CITATION_INSTRUCTIONS).as_str()));
+        // 时间戳形式的方括号不会被当作引用
+        assert!(parse_citations(&numbered_entry(1, "[10:00:01] 应用: Code")).eq(&[1]));
+    }
+}
@@ -0,0 +1,510 @@
+//! 按 token 预算组装对话上下文
+//!
+//! 活动记录按条数和固定字符数截断时，既不考虑模型的上下文窗口，也会被连续几张几乎相同的
+//! 截图占满。这里统一处理：
+//! - 按模型估算 token（英文按字符数折算，中文按字计），上下文预算取模型窗口的一部分
+//! - 同一窗口、时间相邻且 OCR 文本高度重叠的记录合并为一条，保留信息最多的那一张
+//! - 按相关度与新近度的加权分数依次分配预算，放不下的正文截断、连标题都放不下的丢弃
+//! - 结果按时间顺序输出，并报告合并、截断和丢弃的记录，便于调参与排查
+
+use crate::ai::nlp::is_cjk_char;
+use chrono::{Local, TimeZone};
+use std::collections::HashSet;
+
+/// 上下文最多占用的 token（窗口很大的模型也不需要把上下文塞满）
+const MAX_CONTEXT_TOKENS: usize = 12_000;
+/// 正文剩余预算低于该值时不再带正文，只保留标题行
+const MIN_TEXT_TOKENS: usize = 16;
+/// 两条记录间隔超过该秒数时不合并
+const MERGE_GAP_SECS: i64 = 10 * 60;
+/// OCR 文本相似度达到该值视为重复截图
+const MERGE_SIMILARITY: f32 = 0.6;
+
+/// 模型系列：(名称片段, 上下文窗口, 每 token 的英文字符数, 每个汉字的 token 数)，按顺序匹配
+const MODEL_FAMILIES: &[(&str, usize, f32, f32)] = &[
+    ("gpt-4o", 128_000, 4.0, 0.8),
+    ("gpt-4.1", 128_000, 4.0, 0.8),
+    ("gpt-4-turbo", 128_000, 4.0, 1.2),
+    ("gpt-4", 8_192, 4.0, 1.2),
+    ("gpt-3.5", 16_385, 4.0, 1.2),
+    ("claude", 200_000, 3.5, 1.2),
+    ("deepseek", 64_000, 3.5, 0.7),
+    ("qwen", 32_768, 3.5, 0.7),
+    ("glm", 128_000, 3.5, 0.7),
+    ("llama3.1", 128_000, 3.5, 1.0),
+    ("llama3.2", 128_000, 3.5, 1.0),
+    ("llama", 8_192, 3.5, 1.0),
+    ("mistral", 32_768, 3.5, 1.0),
+    ("gemma", 8_192, 3.5, 1.0),
+    ("o1", 128_000, 4.0, 0.8),
+    ("o3", 128_000, 4.0, 0.8),
+    ("o4", 128_000, 4.0, 0.8),
+];
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+/// 粗略的 token 估算（不依赖分词器）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenEstimator {
+    /// 平均每个 token 对应的 ASCII 字符数
+    pub ascii_chars_per_token: f32,
+    /// 平均每个汉字对应的 token 数
+    pub cjk_tokens_per_char: f32,
+}
+
+impl Default for TokenEstimator {
+    fn default() -> Self {
+        Self {
+            ascii_chars_per_token: 3.5,
+            cjk_tokens_per_char: 1.0,
+        }
+    }
+}
+
+impl TokenEstimator {
+    pub fn for_model(model: &str) -> Self {
+        model_family(model).map_or_else(Self::default, |&(_, _, ascii, cjk)| Self {
+            ascii_chars_per_token: ascii,
+            cjk_tokens_per_char: cjk,
+        })
+    }
+
+    fn char_cost(&self, c: char) -> f32 {
+        if c.is_ascii() {
+            1.0 / self.ascii_chars_per_token
+        } else if is_cjk_char(c) {
+            self.cjk_tokens_per_char
+        } else {
+            1.0
+        }
+    }
+
+    pub fn estimate(&self, text: &str) -> usize {
+        text.chars().map(|c| self.char_cost(c)).sum::<f32>().ceil() as usize
+    }
+
+    /// 截断到不超过 `max_tokens`（含省略号）；返回截断后的文本以及是否发生了截断
+    fn truncate(&self, text: &str, max_tokens: usize) -> (String, bool) {
+        if self.estimate(text) <= max_tokens {
+            return (text.to_string(), false);
+        }
+        let limit = max_tokens as f32 - 1.0;
+        let mut used = 0.0;
+        for (i, c) in text.char_indices() {
+            used += self.char_cost(c);
+            if used > limit {
+                return (format!("{}…", text[..i].trim_end()), true);
+            }
+        }
+        (text.to_string(), false)
+    }
+}
+
+fn model_family(model: &str) -> Option<&'static (&'static str, usize, f32, f32)> {
+    let model = model.to_ascii_lowercase();
+    MODEL_FAMILIES.iter().find(|(name, ..)| model.contains(name))
+}
+
+/// 模型的上下文窗口（token），未知模型按 8K 计
+pub fn context_window_tokens(model: &str) -> usize {
+    model_family(model).map_or(DEFAULT_CONTEXT_WINDOW, |&(_, window, ..)| window)
+}
+
+/// 上下文预算
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContextBudget {
+    /// 上下文总 token 数
+    pub max_tokens: usize,
+    /// 单条记录正文的 token 上限
+    pub max_tokens_per_item: usize,
+    /// 合并后最多保留的条数
+    pub max_items: usize,
+    /// 排序时相关度的权重（0..=1），其余给新近度
+    pub relevance_weight: f32,
+    pub estimator: TokenEstimator,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self {
+            max_tokens: 4_000,
+            max_tokens_per_item: 300,
+            max_items: 50,
+            relevance_weight: 0.5,
+            estimator: TokenEstimator::default(),
+        }
+    }
+}
+
+impl ContextBudget {
+    /// 按模型确定预算：窗口的一半留给上下文（最多 [`MAX_CONTEXT_TOKENS`]），其余给提示词、历史和回答
+    pub fn for_model(model: &str) -> Self {
+        Self {
+            max_tokens: (context_window_tokens(model) / 2).min(MAX_CONTEXT_TOKENS),
+            estimator: TokenEstimator::for_model(model),
+            ..Default::default()
+        }
+    }
+}
+
+/// 待组装的一条活动记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextItem {
+    pub activity_id: i64,
+    pub timestamp: i64,
+    pub app_name: String,
+    pub window_title: String,
+    pub text: Option<String>,
+    /// 与问题的相关度（0..=1）；按时间列出的记录统一为 1
+    pub relevance: f32,
+}
+
+impl ContextItem {
+    pub fn new(activity_id: i64, timestamp: i64, app_name: &str, window_title: &str, text: Option<&str>) -> Self {
+        Self {
+            activity_id,
+            timestamp,
+            app_name: app_name.to_string(),
+            window_title: window_title.to_string(),
+            text: text.map(str::trim).filter(|t| !t.is_empty()).map(str::to_string),
+            relevance: 1.0,
+        }
+    }
+
+    pub fn with_relevance(mut self, relevance: f32) -> Self {
+        self.relevance = relevance.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// 组装后的一条上下文（可能由多条相邻的重复记录合并而来）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextEntry {
+    /// 代表记录（正文取自这一条），用于引用
+    pub activity_id: i64,
+    /// 合并进来的全部记录
+    pub activity_ids: Vec<i64>,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub app_name: String,
+    pub window_title: String,
+    pub text: Option<String>,
+    /// 正文是否因预算被截断
+    pub truncated: bool,
+    relevance: f32,
+}
+
+/// 组装结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssembledContext {
+    /// 按时间顺序排列的上下文
+    pub entries: Vec<ContextEntry>,
+    pub used_tokens: usize,
+    pub budget_tokens: usize,
+    /// 作为重复截图并入其它条目的记录数
+    pub merged: usize,
+    /// 正文被截断的条目数
+    pub truncated: usize,
+    /// 因预算或条数限制被丢弃的记录
+    pub dropped: Vec<i64>,
+    /// 跨天时在时间中带上日期
+    multi_day: bool,
+}
+
+impl AssembledContext {
+    /// 各条目的代表记录 ID（与 [`AssembledContext::entries`] 顺序一致）
+    pub fn sources(&self) -> Vec<i64> {
+        self.entries.iter().map(|entry| entry.activity_id).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 渲染一条上下文：`[时间] 应用: … | 窗口: …`，有正文时另起一行 `内容: …`
+    pub fn render_entry(&self, entry: &ContextEntry) -> String {
+        let mut line = entry_header(entry, self.multi_day);
+        if let Some(text) = &entry.text {
+            line.push_str("\n内容: ");
+            line.push_str(text);
+        }
+        line
+    }
+
+    /// 用 `separator` 连接所有条目
+    pub fn render(&self, separator: &str) -> String {
+        self.entries
+            .iter()
+            .map(|entry| self.render_entry(entry))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+fn format_time(ts: i64, multi_day: bool) -> String {
+    let format = if multi_day { "%Y-%m-%d %H:%M:%S" } else { "%H:%M:%S" };
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map(|dt| dt.format(format).to_string())
+        .unwrap_or_default()
+}
+
+fn entry_header(entry: &ContextEntry, multi_day: bool) -> String {
+    let time = if entry.start_ts == entry.end_ts {
+        format_time(entry.start_ts, multi_day)
+    } else {
+        format!(
+            "{} ~ {}",
+            format_time(entry.start_ts, multi_day),
+            format_time(entry.end_ts, multi_day)
+        )
+    };
+    let mut header = format!("[{}] 应用: {} | 窗口: {}", time, entry.app_name, entry.window_title);
+    if entry.activity_ids.len() > 1 {
+        header.push_str(&format!(" | 连续 {} 张相似截图", entry.activity_ids.len()));
+    }
+    header
+}
+
+/// 相似度用的词集合：英文按单词，中文按相邻两字
+fn shingles(text: &str) -> HashSet<String> {
+    let mut set = HashSet::new();
+    let mut word = String::new();
+    let mut prev_cjk: Option<char> = None;
+    for c in text.chars() {
+        if is_cjk_char(c) {
+            if let Some(p) = prev_cjk {
+                set.insert(format!("{}{}", p, c));
+            }
+            prev_cjk = Some(c);
+        } else {
+            prev_cjk = None;
+        }
+        if c.is_alphanumeric() && !is_cjk_char(c) {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            set.insert(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        set.insert(word);
+    }
+    set
+}
+
+/// 两段 OCR 文本的重叠程度（较小集合被覆盖的比例），两段都为空时视为相同
+fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let smaller = a.len().min(b.len());
+    if smaller == 0 {
+        return if a.len() == b.len() { 1.0 } else { 0.0 };
+    }
+    a.intersection(b).count() as f32 / smaller as f32
+}
+
+/// 把同一窗口、时间相邻、文本重叠的记录合并；输入须按时间升序
+fn collapse_duplicates(items: Vec<ContextItem>) -> (Vec<ContextEntry>, usize) {
+    let mut entries: Vec<ContextEntry> = Vec::new();
+    let mut last_shingles: HashSet<String> = HashSet::new();
+    let mut merged = 0;
+
+    for item in items {
+        let item_shingles = shingles(item.text.as_deref().unwrap_or_default());
+        if let Some(last) = entries.last_mut() {
+            let same_window = last.app_name == item.app_name && last.window_title == item.window_title;
+            if same_window
+                && item.timestamp - last.end_ts <= MERGE_GAP_SECS
+                && overlap(&last_shingles, &item_shingles) >= MERGE_SIMILARITY
+            {
+                last.activity_ids.push(item.activity_id);
+                last.end_ts = item.timestamp;
+                last.relevance = last.relevance.max(item.relevance);
+                // 正文保留信息最多的一张
+                if item.text.as_ref().map_or(0, String::len) > last.text.as_ref().map_or(0, String::len) {
+                    last.activity_id = item.activity_id;
+                    last.text = item.text;
+                    last_shingles = item_shingles;
+                }
+                merged += 1;
+                continue;
+            }
+        }
+
+        last_shingles = item_shingles;
+        entries.push(ContextEntry {
+            activity_id: item.activity_id,
+            activity_ids: vec![item.activity_id],
+            start_ts: item.timestamp,
+            end_ts: item.timestamp,
+            app_name: item.app_name,
+            window_title: item.window_title,
+            text: item.text,
+            truncated: false,
+            relevance: item.relevance,
+        });
+    }
+    (entries, merged)
+}
+
+/// 在预算内组装上下文
+pub fn assemble_context(mut items: Vec<ContextItem>, budget: &ContextBudget) -> AssembledContext {
+    items.sort_by_key(|item| (item.timestamp, item.activity_id));
+    let multi_day = match (items.first(), items.last()) {
+        (Some(first), Some(last)) => {
+            let day = |ts| Local.timestamp_opt(ts, 0).single().map(|dt| dt.date_naive());
+            day(first.timestamp) != day(last.timestamp)
+        }
+        _ => false,
+    };
+    let (entries, merged) = collapse_duplicates(items);
+
+    // 新近度按时间在整个范围内的位置折算到 0..=1
+    let oldest = entries.first().map_or(0, |e| e.end_ts);
+    let newest = entries.last().map_or(0, |e| e.end_ts);
+    let span = (newest - oldest).max(1) as f32;
+    let weight = budget.relevance_weight.clamp(0.0, 1.0);
+    let score = |entry: &ContextEntry| {
+        weight * entry.relevance + (1.0 - weight) * (entry.end_ts - oldest) as f32 / span
+    };
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by(|&a, &b| score(&entries[b]).total_cmp(&score(&entries[a])).then(b.cmp(&a)));
+
+    let estimator = &budget.estimator;
+    let mut selected = vec![false; entries.len()];
+    let mut entries = entries;
+    let mut used = 0;
+    let mut truncated = 0;
+    let mut dropped = Vec::new();
+
+    for index in order {
+        let entry = &mut entries[index];
+        // 标题行 + 条目之间的分隔
+        let header_tokens = estimator.estimate(&entry_header(entry, multi_day)) + 2;
+        let fits = selected.iter().filter(|&&s| s).count() < budget.max_items
+            && used + header_tokens <= budget.max_tokens;
+        if !fits {
+            dropped.extend(entry.activity_ids.iter().copied());
+            continue;
+        }
+        used += header_tokens;
+        selected[index] = true;
+
+        let Some(text) = entry.text.take() else {
+            continue;
+        };
+        let allowance = budget.max_tokens_per_item.min((budget.max_tokens - used).saturating_sub(2));
+        if allowance < MIN_TEXT_TOKENS {
+            entry.truncated = true;
+            truncated += 1;
+            continue;
+        }
+        let (text, was_truncated) = estimator.truncate(&text, allowance);
+        used += estimator.estimate(&text) + 2;
+        entry.truncated = was_truncated;
+        truncated += usize::from(was_truncated);
+        entry.text = Some(text);
+    }
+
+    dropped.sort_unstable();
+    AssembledContext {
+        entries: entries
+            .into_iter()
+            .zip(selected)
+            .filter_map(|(entry, keep)| keep.then_some(entry))
+            .collect(),
+        used_tokens: used,
+        budget_tokens: budget.max_tokens,
+        merged,
+        truncated,
+        dropped,
+        multi_day,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, ts: i64, title: &str, text: &str) -> ContextItem {
+        ContextItem::new(id, ts, "Code", title, Some(text))
+    }
+
+    #[test]
+    fn estimates_tokens_per_model() {
+        let gpt = TokenEstimator::for_model("gpt-4o-mini");
+        assert_eq!(gpt.estimate("abcdefgh"), 2);
+        assert_eq!(TokenEstimator::for_model("qwen2.5:7b").estimate("上周三下午"), 4);
+        assert_eq!(TokenEstimator::default().estimate("上周三下午"), 5);
+        assert_eq!(context_window_tokens("claude-3-5-sonnet-latest"), 200_000);
+        assert_eq!(context_window_tokens("unknown"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(ContextBudget::for_model("gpt-4o").max_tokens, MAX_CONTEXT_TOKENS);
+        assert_eq!(ContextBudget::for_model("llama3:8b").max_tokens, 4_096);
+
+        let (text, truncated) = gpt.truncate("one two three four five", 3);
+        assert!(truncated);
+        assert_eq!(text, "one two…");
+    }
+
+    #[test]
+    fn collapses_consecutive_near_duplicate_screenshots() {
+        let base = 1_700_000_000;
+        let items = vec![
+            item(3, base + 20, "rag.rs", "fn hybrid_search query embedding fusion rerank"),
+            item(1, base, "rag.rs", "fn hybrid_search query embedding fusion"),
+            item(2, base + 10, "rag.rs", "fn hybrid_search query embedding fusion"),
+            // 同一窗口但内容不同：不合并
+            item(4, base + 30, "rag.rs", "cargo test failed assertion left right"),
+            // 内容相同但窗口不同：不合并
+            item(5, base + 40, "db.rs", "cargo test failed assertion left right"),
+        ];
+
+        let context = assemble_context(items, &ContextBudget::default());
+        assert_eq!(context.merged, 2);
+        assert!(context.dropped.is_empty());
+        assert_eq!(context.sources(), vec![3, 4, 5]);
+        assert_eq!(context.entries[0].activity_ids, vec![1, 2, 3]);
+        assert_eq!(context.entries[0].start_ts, base);
+        assert!(context.render_entry(&context.entries[0]).contains("连续 3 张相似截图"));
+        assert!(context.render("\n").contains("内容: cargo test failed"));
+    }
+
+    #[test]
+    fn allocates_budget_by_relevance_and_recency() {
+        let base = 1_700_000_000;
+        let long_text = "rust ".repeat(400);
+        let items = (0..6)
+            .map(|i| {
+                let relevance = if i == 0 { 1.0 } else { 0.1 };
+                item(i, base + i * 3600, &format!("window {}", i), &format!("{} {}", i, long_text))
+                    .with_relevance(relevance)
+            })
+            .collect();
+        let budget = ContextBudget {
+            max_tokens: 300,
+            max_tokens_per_item: 80,
+            max_items: 10,
+            relevance_weight: 0.7,
+            estimator: TokenEstimator::for_model("gpt-4o"),
+        };
+
+        let context = assemble_context(items, &budget);
+        assert!(context.used_tokens <= budget.max_tokens);
+        // 最相关的最早一条与最新的几条保留，中间的被丢弃
+        let kept = context.sources();
+        assert_eq!(kept.first(), Some(&0));
+        assert!(kept.contains(&5));
+        assert!(!context.dropped.is_empty());
+        assert!(context.dropped.iter().all(|id| !kept.contains(id)));
+        assert_eq!(context.truncated, kept.len());
+        assert!(context.entries.iter().all(|e| e.text.as_deref().is_none_or(|t| t.ends_with('…'))));
+
+        // 条数限制
+        let limited = ContextBudget {
+            max_items: 2,
+            ..budget
+        };
+        let items: Vec<ContextItem> = (0..5).map(|i| item(i, base + i * 3600, &format!("w{}", i), "")).collect();
+        let context = assemble_context(items, &limited);
+        assert_eq!(context.sources(), vec![3, 4]);
+        assert_eq!(context.dropped, vec![0, 1, 2]);
+    }
+}
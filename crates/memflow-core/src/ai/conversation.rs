@@ -0,0 +1,556 @@
+//! 多轮对话
+//!
+//! 根据会话 ID 读取 `chat_messages`：
+//! - 最近几条消息原样带给模型
+//! - 更早的消息由 LLM 压缩进 `chat_sessions.summary`（滚动摘要），压缩进度记在
+//!   `summary_until_message_id`，同一段历史只压缩一次
+//! - 追问（“那前一天呢？”）先结合历史改写成独立的检索查询，再交给意图解析与混合检索
+
+use crate::ai::provider::{ChatMessage, ChatRole, CompletionRequest, LlmProvider};
+use anyhow::Result;
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+
+/// 改写追问时使用的系统提示词
+const REWRITE_SYSTEM_PROMPT: &str = "你负责把对话中的追问改写成可以独立检索的查询。\
+结合对话摘要和最近的对话，补全追问中省略的主题、应用、时间范围；“那个”“前一天”“它”等指代以对话中的具体内容为准，相对时间以对话中提到的日期为基准换算。\
+只输出改写后的一句查询，使用与追问相同的语言，不要解释；如果追问本身已经完整，原样输出。";
+
+/// 压缩历史时使用的系统提示词
+const SUMMARY_SYSTEM_PROMPT: &str = "你负责维护一段对话的滚动摘要。\
+把已有摘要和新增的对话合并成一段简洁的摘要（不超过 300 字），保留用户关心的主题、涉及的应用与时间范围、已经得到的结论和尚未解决的问题。\
+只输出摘要本身。";
+
+/// 多轮对话参数
+#[derive(Debug, Clone)]
+pub struct ConversationConfig {
+    /// 原样带给模型的最近消息条数（用户与助手各算一条）
+    pub recent_messages: usize,
+    /// 摘要之外累积的消息超出 `recent_messages` 这么多条后才触发一次压缩，避免每轮都调用
+    pub summarize_batch: usize,
+    /// 每条历史消息带入的最大字符数（助手的长回答只保留开头）
+    pub max_message_chars: usize,
+    /// 改写追问的超时，超时后直接用原问题检索
+    pub rewrite_timeout: Duration,
+    /// 压缩历史的超时，超时后沿用已有摘要，下一轮再试
+    pub summarize_timeout: Duration,
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        Self {
+            recent_messages: 6,
+            summarize_batch: 4,
+            max_message_chars: 1500,
+            rewrite_timeout: Duration::from_secs(10),
+            summarize_timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+/// 一轮提问所需的会话上下文
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    /// 更早轮次的滚动摘要
+    pub summary: Option<String>,
+    /// 最近的消息（按时间顺序，不含本轮问题）
+    pub recent: Vec<ChatMessage>,
+    /// 用于意图解析和检索的独立查询（无历史时即原问题）
+    pub standalone_query: String,
+}
+
+impl Conversation {
+    /// 没有会话历史的单轮提问
+    pub fn single(query: &str) -> Self {
+        Self {
+            standalone_query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn has_history(&self) -> bool {
+        self.summary.is_some() || !self.recent.is_empty()
+    }
+
+    /// 带上会话历史的补全请求：摘要追加到系统提示词，最近消息放在本轮问题之前
+    pub fn completion_request(&self, query: &str, context: &str, system_prompt: Option<&str>) -> CompletionRequest {
+        let mut request = CompletionRequest::from_query(query, context, system_prompt);
+        if let Some(summary) = &self.summary {
+            let system = request.system.take().unwrap_or_default();
+            request.system = Some(format!("{}\n\n此前对话的摘要：\n{}", system, summary));
+        }
+        request.messages.splice(0..0, self.recent.iter().cloned());
+        request
+    }
+}
+
+struct StoredMessage {
+    id: i64,
+    message: ChatMessage,
+}
+
+/// 为会话中的新问题准备上下文（使用全局连接池）
+pub async fn prepare_conversation(
+    provider: &dyn LlmProvider,
+    session_id: i64,
+    query: &str,
+    config: &ConversationConfig,
+) -> Result<Conversation> {
+    let pool = crate::db::get_pool().await?;
+    prepare_conversation_impl(&pool, provider, session_id, query, config).await
+}
+
+/// 为会话中的新问题准备上下文：必要时更新滚动摘要，并把追问改写成独立查询
+///
+/// 前端会先保存本轮的用户消息再发起提问，因此末尾与 `query` 相同的用户消息不计入历史。
+pub async fn prepare_conversation_impl(
+    pool: &SqlitePool,
+    provider: &dyn LlmProvider,
+    session_id: i64,
+    query: &str,
+    config: &ConversationConfig,
+) -> Result<Conversation> {
+    let row = sqlx::query("SELECT summary, summary_until_message_id FROM chat_sessions WHERE id = ?")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(row) = row else {
+        return Err(anyhow::anyhow!("会话不存在: {}", session_id));
+    };
+    let mut summary: Option<String> = row.get::<Option<String>, _>(0).filter(|s| !s.trim().is_empty());
+    let summarized_until: i64 = row.get(1);
+
+    let mut messages = load_messages_after(pool, session_id, summarized_until, config.max_message_chars).await?;
+    if messages
+        .last()
+        .is_some_and(|m| m.message.role == ChatRole::User && m.message.content.trim() == query.trim())
+    {
+        messages.pop();
+    }
+
+    // 未压缩的消息太多时，把最近几条之前的部分并入摘要；最近消息从用户消息开始（部分接口要求首条为 user）
+    let condense = messages.len() > config.recent_messages + config.summarize_batch;
+    let mut split = if condense { messages.len() - config.recent_messages } else { 0 };
+    while split < messages.len() && messages[split].message.role != ChatRole::User {
+        split += 1;
+    }
+
+    if condense && split > 0 {
+        let older = &messages[..split];
+        match summarize(provider, summary.as_deref(), older, config.summarize_timeout).await {
+            Ok(updated) => {
+                sqlx::query("UPDATE chat_sessions SET summary = ?, summary_until_message_id = ? WHERE id = ?")
+                    .bind(&updated)
+                    .bind(older[older.len() - 1].id)
+                    .bind(session_id)
+                    .execute(pool)
+                    .await?;
+                tracing::debug!("会话 {} 摘要已更新，压缩了 {} 条消息", session_id, older.len());
+                summary = Some(updated);
+            }
+            // 压缩失败或超时时沿用已有摘要，本轮只带最近消息，下一轮再试
+            Err(e) => tracing::warn!(
+                "会话 {} 摘要更新失败: {}",
+                session_id,
+                crate::redact::redact_secrets(&e.to_string())
+            ),
+        }
+    }
+
+    let recent: Vec<ChatMessage> = messages.drain(split..).map(|m| m.message).collect();
+    let mut conversation = Conversation {
+        summary,
+        recent,
+        standalone_query: query.to_string(),
+    };
+    if conversation.has_history() {
+        conversation.standalone_query = rewrite_follow_up(
+            provider,
+            conversation.summary.as_deref(),
+            &conversation.recent,
+            query,
+            config.rewrite_timeout,
+        )
+        .await;
+    }
+    Ok(conversation)
+}
+
+async fn load_messages_after(
+    pool: &SqlitePool,
+    session_id: i64,
+    after_id: i64,
+    max_chars: usize,
+) -> Result<Vec<StoredMessage>> {
+    let rows = sqlx::query(
+        "SELECT id, role, content FROM chat_messages WHERE session_id = ? AND id > ? ORDER BY id ASC",
+    )
+    .bind(session_id)
+    .bind(after_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let role = match row.get::<String, _>(1).as_str() {
+                "user" => ChatRole::User,
+                "assistant" => ChatRole::Assistant,
+                _ => return None,
+            };
+            let content: String = row.get(2);
+            let content = content.trim();
+            if content.is_empty() {
+                return None;
+            }
+            Some(StoredMessage {
+                id: row.get(0),
+                message: ChatMessage {
+                    role,
+                    content: truncate_chars(content, max_chars),
+                },
+            })
+        })
+        .collect())
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+fn format_transcript(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let speaker = if m.role == ChatRole::User { "用户" } else { "助手" };
+            format!("{}: {}", speaker, m.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn summarize(
+    provider: &dyn LlmProvider,
+    previous: Option<&str>,
+    messages: &[StoredMessage],
+    timeout: Duration,
+) -> Result<String> {
+    let transcript = format_transcript(&messages.iter().map(|m| m.message.clone()).collect::<Vec<_>>());
+    let prompt = format!(
+        "已有摘要：\n{}\n\n新增对话：\n{}",
+        previous.unwrap_or("（无）"),
+        transcript
+    );
+    let request = CompletionRequest {
+        system: Some(SUMMARY_SYSTEM_PROMPT.to_string()),
+        messages: vec![ChatMessage::user(prompt)],
+        ..Default::default()
+    }
+    .max_tokens(600)
+    .temperature(0.2);
+
+    let completion = tokio::time::timeout(timeout, provider.complete(&request))
+        .await
+        .map_err(|_| anyhow::anyhow!("{} 压缩对话历史超时({}ms)", provider.name(), timeout.as_millis()))??;
+    let summary = completion.text.trim().to_string();
+    if summary.is_empty() {
+        return Err(anyhow::anyhow!("{} 返回了空摘要", provider.name()));
+    }
+    Ok(summary)
+}
+
+/// 结合会话历史把追问改写成独立查询；调用失败、超时或结果不可用时返回原问题
+pub async fn rewrite_follow_up(
+    provider: &dyn LlmProvider,
+    summary: Option<&str>,
+    recent: &[ChatMessage],
+    query: &str,
+    timeout: Duration,
+) -> String {
+    let mut prompt = format!("当前日期：{}\n\n", chrono::Local::now().format("%Y-%m-%d"));
+    if let Some(summary) = summary {
+        prompt.push_str(&format!("对话摘要：\n{}\n\n", summary));
+    }
+    if !recent.is_empty() {
+        prompt.push_str(&format!("最近的对话：\n{}\n\n", format_transcript(recent)));
+    }
+    prompt.push_str(&format!("追问：{}", query));
+
+    let request = CompletionRequest {
+        system: Some(REWRITE_SYSTEM_PROMPT.to_string()),
+        messages: vec![ChatMessage::user(prompt)],
+        ..Default::default()
+    }
+    .max_tokens(200)
+    .temperature(0.0);
+
+    let response = match tokio::time::timeout(timeout, provider.complete(&request)).await {
+        Ok(Ok(completion)) => completion.text,
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "改写追问失败，使用原问题检索: {}",
+                crate::redact::redact_secrets(&e.to_string())
+            );
+            return query.to_string();
+        }
+        Err(_) => {
+            tracing::warn!("改写追问超时({}ms)，使用原问题检索", timeout.as_millis());
+            return query.to_string();
+        }
+    };
+
+    match clean_rewritten_query(&response, query) {
+        Some(rewritten) => {
+            tracing::debug!("追问改写: {} -> {}", query, rewritten);
+            rewritten
+        }
+        None => query.to_string(),
+    }
+}
+
+/// 取模型输出的第一行，去掉“查询：”之类的前缀和引号；空结果或明显跑题的长输出返回 `None`
+fn clean_rewritten_query(response: &str, query: &str) -> Option<String> {
+    let line = response.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = ["改写后的查询：", "改写后的查询:", "查询：", "查询:", "Query:", "query:"]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+        .unwrap_or(line);
+    let line = line
+        .trim()
+        .trim_matches(|c| matches!(c, '"' | '\'' | '“' | '”' | '「' | '」' | '`'))
+        .trim();
+
+    let max_chars = (query.chars().count() * 4).max(200);
+    if line.is_empty() || line.chars().count() > max_chars {
+        return None;
+    }
+    Some(line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::provider::{Completion, MockLlmProvider};
+    use async_trait::async_trait;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tokio_util::sync::CancellationToken;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn insert_session(pool: &SqlitePool, turns: &[(&str, &str)]) -> i64 {
+        let session_id = sqlx::query("INSERT INTO chat_sessions (title, created_at, updated_at) VALUES ('t', 0, 0)")
+            .execute(pool)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+        for (i, (role, content)) in turns.iter().enumerate() {
+            sqlx::query("INSERT INTO chat_messages (session_id, role, content, created_at) VALUES (?, ?, ?, ?)")
+                .bind(session_id)
+                .bind(role)
+                .bind(content)
+                .bind(i as i64)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        session_id
+    }
+
+    #[tokio::test]
+    async fn follow_up_is_rewritten_with_recent_turns() {
+        let pool = setup_pool().await;
+        let session_id = insert_session(
+            &pool,
+            &[
+                ("user", "昨天我在 VS Code 里改了什么？"),
+                ("assistant", "昨天你主要在修改 rag.rs 的混合检索。"),
+                ("user", "那前一天呢？"),
+            ],
+        )
+        .await;
+
+        let provider = MockLlmProvider::with_responses(["查询：“前天我在 VS Code 里改了什么”"]);
+        let conversation = prepare_conversation_impl(
+            &pool,
+            &provider,
+            session_id,
+            "那前一天呢？",
+            &ConversationConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(conversation.standalone_query, "前天我在 VS Code 里改了什么");
+        assert_eq!(conversation.summary, None);
+        // 刚保存的本轮问题不算历史
+        assert_eq!(conversation.recent.len(), 2);
+        let rewrite_prompt = &provider.requests()[0].messages[0].content;
+        assert!(rewrite_prompt.contains("用户: 昨天我在 VS Code 里改了什么？"));
+        assert!(rewrite_prompt.ends_with("追问：那前一天呢？"));
+
+        let request = conversation.completion_request("那前一天呢？", "上下文", None);
+        assert_eq!(request.messages.len(), 3);
+        assert_eq!(request.messages[0].role, ChatRole::User);
+        assert_eq!(request.messages[1].role, ChatRole::Assistant);
+        assert!(request.messages[2].content.starts_with("那前一天呢？"));
+
+        // 新会话没有历史，不调用模型
+        let empty = insert_session(&pool, &[("user", "你好")]).await;
+        let single = prepare_conversation_impl(&pool, &provider, empty, "你好", &ConversationConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(single, Conversation::single("你好"));
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn older_turns_are_condensed_into_running_summary() {
+        let pool = setup_pool().await;
+        let turns: Vec<(&str, String)> = (0..12)
+            .map(|i| if i % 2 == 0 { ("user", format!("问题{}", i / 2)) } else { ("assistant", format!("回答{}", i / 2)) })
+            .collect();
+        let turns: Vec<(&str, &str)> = turns.iter().map(|(r, c)| (*r, c.as_str())).collect();
+        let session_id = insert_session(&pool, &turns).await;
+
+        let config = ConversationConfig {
+            recent_messages: 4,
+            summarize_batch: 2,
+            ..Default::default()
+        };
+        let provider = MockLlmProvider::with_responses(["摘要A", "独立查询"]);
+        let conversation = prepare_conversation_impl(&pool, &provider, session_id, "接着说", &config)
+            .await
+            .unwrap();
+
+        assert_eq!(conversation.summary.as_deref(), Some("摘要A"));
+        assert_eq!(
+            conversation.recent.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(),
+            vec!["问题4", "回答4", "问题5", "回答5"]
+        );
+        assert_eq!(conversation.standalone_query, "独立查询");
+        let request = conversation.completion_request("接着说", "", None);
+        assert!(request.system.unwrap().ends_with("此前对话的摘要：\n摘要A"));
+
+        let (summary, until): (String, i64) =
+            sqlx::query_as("SELECT summary, summary_until_message_id FROM chat_sessions WHERE id = ?")
+                .bind(session_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(summary, "摘要A");
+        assert_eq!(until, 8);
+
+        // 下一轮只新增一问一答（加上刚保存的本轮问题），未达到压缩批量，沿用已有摘要
+        for (role, content) in [("user", "接着说"), ("assistant", "回答6"), ("user", "再说")] {
+            sqlx::query("INSERT INTO chat_messages (session_id, role, content, created_at) VALUES (?, ?, ?, 0)")
+                .bind(session_id)
+                .bind(role)
+                .bind(content)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let provider = MockLlmProvider::with_responses(["独立查询2"]);
+        let next = prepare_conversation_impl(&pool, &provider, session_id, "再说", &config)
+            .await
+            .unwrap();
+        assert_eq!(next.summary.as_deref(), Some("摘要A"));
+        assert_eq!(
+            next.recent.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(),
+            vec!["问题4", "回答4", "问题5", "回答5", "接着说", "回答6"]
+        );
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    /// 永远不返回的 provider，用于模拟响应很慢的服务
+    struct HangingProvider;
+
+    #[async_trait]
+    impl LlmProvider for HangingProvider {
+        fn name(&self) -> &str {
+            "Hanging"
+        }
+
+        fn model(&self) -> &str {
+            "slow"
+        }
+
+        async fn complete(&self, _request: &CompletionRequest) -> Result<Completion> {
+            std::future::pending().await
+        }
+
+        async fn stream(
+            &self,
+            _request: &CompletionRequest,
+            _on_chunk: &(dyn Fn(String) + Send + Sync),
+            _cancel: &CancellationToken,
+        ) -> Result<Completion> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_condensing_times_out_and_keeps_previous_summary() {
+        let pool = setup_pool().await;
+        let turns: Vec<(&str, String)> = (0..12)
+            .map(|i| if i % 2 == 0 { ("user", format!("问题{}", i / 2)) } else { ("assistant", format!("回答{}", i / 2)) })
+            .collect();
+        let turns: Vec<(&str, &str)> = turns.iter().map(|(r, c)| (*r, c.as_str())).collect();
+        let session_id = insert_session(&pool, &turns).await;
+        sqlx::query("UPDATE chat_sessions SET summary = '旧摘要' WHERE id = ?")
+            .bind(session_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let config = ConversationConfig {
+            recent_messages: 4,
+            summarize_batch: 2,
+            rewrite_timeout: Duration::from_millis(50),
+            summarize_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let conversation = tokio::time::timeout(
+            Duration::from_secs(5),
+            prepare_conversation_impl(&pool, &HangingProvider, session_id, "接着说", &config),
+        )
+        .await
+        .expect("压缩超时后应继续")
+        .unwrap();
+
+        assert_eq!(conversation.summary.as_deref(), Some("旧摘要"));
+        assert_eq!(conversation.recent.len(), 4);
+        assert_eq!(conversation.standalone_query, "接着说");
+        let until: i64 = sqlx::query_scalar("SELECT summary_until_message_id FROM chat_sessions WHERE id = ?")
+            .bind(session_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(until, 0);
+    }
+
+    #[tokio::test]
+    async fn rewrite_falls_back_to_original_query() {
+        let provider = MockLlmProvider::with_responses([""]);
+        let recent = vec![ChatMessage::user("a"), ChatMessage::assistant("b")];
+        let timeout = Duration::from_secs(1);
+        assert_eq!(rewrite_follow_up(&provider, None, &recent, "原问题", timeout).await, "原问题");
+
+        provider.push_error("boom");
+        assert_eq!(rewrite_follow_up(&provider, None, &recent, "原问题", timeout).await, "原问题");
+
+        assert_eq!(clean_rewritten_query("\n  Query: \"what did I do on Monday\"\n解释", "and Monday?").as_deref(), Some("what did I do on Monday"));
+        assert_eq!(clean_rewritten_query(&"很长".repeat(200), "短"), None);
+    }
+}
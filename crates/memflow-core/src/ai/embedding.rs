@@ -0,0 +1,402 @@
+//! Embedding providers
+//!
+//! 文档入库和查询必须使用同一个模型生成向量，否则相似度没有意义。
+//! 这里统一定义 [`EmbeddingProvider`]，由配置选择具体实现：
+//! - `local`：进程内 fastembed 模型（需要 `local-embeddings` feature）
+//! - `openai`：OpenAI 兼容的 `/embeddings` 接口
+//! - `stub`：基于词哈希的确定性向量，仅用于测试
+
+use crate::ai::provider::{embeddings_with_openai, ProviderConfig};
+use crate::vector_db::Embedding;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// 默认的本地模型（与 MCP 服务一直使用的模型一致）
+pub const DEFAULT_LOCAL_MODEL: &str = "BAAI/bge-small-en-v1.5";
+
+/// OpenAI 兼容接口单次请求的最大文本数
+const OPENAI_MAX_BATCH: usize = 64;
+
+type CachedProvider = (EmbeddingConfig, Arc<dyn EmbeddingProvider>);
+
+static SHARED_PROVIDER: once_cell::sync::Lazy<tokio::sync::Mutex<Option<CachedProvider>>> =
+    once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(None));
+
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// 写入 `vector_embeddings.model_id` 的模型标识
+    fn model_id(&self) -> &str;
+
+    /// 为待入库的文档批量生成向量，顺序与输入一致
+    async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Embedding>>;
+
+    /// 为检索查询生成向量
+    async fn embed_query(&self, text: &str) -> Result<Embedding> {
+        self.embed_documents(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding provider returned no vector"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingProviderKind {
+    #[default]
+    Local,
+    #[serde(alias = "openai-compatible")]
+    OpenAi,
+    Stub,
+}
+
+impl std::str::FromStr for EmbeddingProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(Value::String(s.trim().to_ascii_lowercase()))
+            .map_err(|_| anyhow::anyhow!("Unknown embedding provider: {}", s))
+    }
+}
+
+/// 创建 [`EmbeddingProvider`] 所需的配置
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EmbeddingConfig {
+    pub provider: EmbeddingProviderKind,
+    /// 模型名；为空时使用各实现的默认值
+    pub model: String,
+    /// OpenAI 兼容接口地址
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    /// 本地模型文件缓存目录
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl EmbeddingConfig {
+    /// 从桌面端的 `config.json` 读取向量相关配置（供 MCP 等无法访问 AppConfig 的进程使用）
+    ///
+    /// API Key 不在配置文件中，需要调用方另行提供。
+    pub fn from_app_config(value: &Value) -> Self {
+        let text = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+
+        let provider = text("embeddingProvider")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_default();
+        let model = match provider {
+            EmbeddingProviderKind::Local => text("localEmbeddingModel"),
+            EmbeddingProviderKind::OpenAi => text("embeddingModel"),
+            EmbeddingProviderKind::Stub => None,
+        };
+
+        Self {
+            provider,
+            model: model.unwrap_or_default(),
+            base_url: text("embeddingBaseUrl").or_else(|| text("openaiBaseUrl")),
+            api_key: None,
+            cache_dir: None,
+        }
+    }
+}
+
+/// 根据配置创建 provider（本地模型首次创建时可能需要下载）
+pub async fn create_embedding_provider(config: &EmbeddingConfig) -> Result<Arc<dyn EmbeddingProvider>> {
+    match config.provider {
+        EmbeddingProviderKind::Local => create_local_provider(config).await,
+        EmbeddingProviderKind::OpenAi => {
+            let api_key = config
+                .api_key
+                .clone()
+                .filter(|k| !k.trim().is_empty())
+                .ok_or_else(|| anyhow::anyhow!("未配置 Embeddings API Key"))?;
+            let model = if config.model.is_empty() {
+                "text-embedding-3-small".to_string()
+            } else {
+                config.model.clone()
+            };
+            Ok(Arc::new(OpenAiEmbeddingProvider::new(
+                model,
+                ProviderConfig::new(api_key, config.base_url.clone(), "https://api.openai.com/v1"),
+            )))
+        }
+        EmbeddingProviderKind::Stub => Ok(Arc::new(StubEmbeddingProvider::default())),
+    }
+}
+
+/// 按配置返回共享的 provider；配置未变化时复用已加载的实例（本地模型加载开销较大）
+pub async fn shared_embedding_provider(config: &EmbeddingConfig) -> Result<Arc<dyn EmbeddingProvider>> {
+    let mut shared = SHARED_PROVIDER.lock().await;
+    if let Some((cached_config, provider)) = shared.as_ref() {
+        if cached_config == config {
+            return Ok(provider.clone());
+        }
+    }
+
+    let provider = create_embedding_provider(config).await?;
+    tracing::info!(
+        "Embedding provider 已加载: {:?}, model={}",
+        config.provider,
+        provider.model_id()
+    );
+    *shared = Some((config.clone(), provider.clone()));
+    Ok(provider)
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn create_local_provider(config: &EmbeddingConfig) -> Result<Arc<dyn EmbeddingProvider>> {
+    let model = if config.model.is_empty() {
+        DEFAULT_LOCAL_MODEL.to_string()
+    } else {
+        config.model.clone()
+    };
+    let cache_dir = config.cache_dir.clone();
+    let provider = tokio::task::spawn_blocking(move || local::LocalEmbeddingProvider::new(&model, cache_dir)).await??;
+    Ok(Arc::new(provider))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+async fn create_local_provider(_config: &EmbeddingConfig) -> Result<Arc<dyn EmbeddingProvider>> {
+    Err(anyhow::anyhow!(
+        "本地向量模型不可用：memflow-core 未启用 local-embeddings feature"
+    ))
+}
+
+#[cfg(feature = "local-embeddings")]
+pub use local::LocalEmbeddingProvider;
+
+#[cfg(feature = "local-embeddings")]
+mod local {
+    use super::*;
+    use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+
+    /// 支持的本地模型：(模型名, fastembed 模型, 是否需要 e5 风格的 query/passage 前缀)
+    const LOCAL_MODELS: &[(&str, EmbeddingModel, bool)] = &[
+        ("BAAI/bge-small-en-v1.5", EmbeddingModel::BGESmallENV15, false),
+        ("BAAI/bge-base-en-v1.5", EmbeddingModel::BGEBaseENV15, false),
+        ("BAAI/bge-small-zh-v1.5", EmbeddingModel::BGESmallZHV15, false),
+        ("intfloat/multilingual-e5-small", EmbeddingModel::MultilingualE5Small, true),
+        ("intfloat/multilingual-e5-base", EmbeddingModel::MultilingualE5Base, true),
+        ("sentence-transformers/all-MiniLM-L6-v2", EmbeddingModel::AllMiniLML6V2, false),
+    ];
+
+    /// 进程内 fastembed 模型
+    pub struct LocalEmbeddingProvider {
+        model_id: String,
+        model: Arc<TextEmbedding>,
+        e5_prefix: bool,
+    }
+
+    impl LocalEmbeddingProvider {
+        /// 加载模型（阻塞，首次使用时会下载模型文件）
+        pub fn new(model_id: &str, cache_dir: Option<PathBuf>) -> Result<Self> {
+            let (name, model, e5_prefix) = LOCAL_MODELS
+                .iter()
+                .find(|(name, _, _)| name.eq_ignore_ascii_case(model_id))
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("不支持的本地向量模型: {}", model_id))?;
+
+            let mut options = InitOptions::new(model).with_show_download_progress(false);
+            if let Some(dir) = cache_dir {
+                options = options.with_cache_dir(dir);
+            }
+            let model = TextEmbedding::try_new(options)?;
+
+            Ok(Self {
+                model_id: name.to_string(),
+                model: Arc::new(model),
+                e5_prefix,
+            })
+        }
+
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Embedding>> {
+            let model = self.model.clone();
+            let vectors = tokio::task::spawn_blocking(move || model.embed(texts, None)).await??;
+            Ok(vectors
+                .into_iter()
+                .map(|v| Embedding::new(self.model_id.clone(), v))
+                .collect())
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for LocalEmbeddingProvider {
+        fn model_id(&self) -> &str {
+            &self.model_id
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+            let texts = if self.e5_prefix {
+                texts.iter().map(|t| format!("passage: {}", t)).collect()
+            } else {
+                texts.to_vec()
+            };
+            self.embed(texts).await
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Embedding> {
+            let text = if self.e5_prefix {
+                format!("query: {}", text)
+            } else {
+                text.to_string()
+            };
+            self.embed(vec![text])
+                .await?
+                .pop()
+                .ok_or_else(|| anyhow::anyhow!("Embedding model returned no vector"))
+        }
+    }
+}
+
+/// OpenAI 兼容的 `/embeddings` 接口
+pub struct OpenAiEmbeddingProvider {
+    model: String,
+    config: ProviderConfig,
+}
+
+impl OpenAiEmbeddingProvider {
+    pub fn new(model: impl Into<String>, config: ProviderConfig) -> Self {
+        Self {
+            model: model.into(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(OPENAI_MAX_BATCH) {
+            let vectors = embeddings_with_openai(chunk, &self.model, &self.config).await?;
+            embeddings.extend(vectors.into_iter().map(|v| Embedding::new(self.model.clone(), v)));
+        }
+        Ok(embeddings)
+    }
+}
+
+/// 测试用的确定性 provider：把分词结果哈希到固定维度并归一化
+///
+/// 共享词越多的文本余弦相似度越高，足以在没有真实模型时验证检索流程。
+pub struct StubEmbeddingProvider {
+    model_id: String,
+    dim: usize,
+}
+
+impl StubEmbeddingProvider {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            model_id: format!("stub-{}", dim),
+            dim: dim.max(1),
+        }
+    }
+
+    pub fn embed_text(&self, text: &str) -> Embedding {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let mut vector = vec![0.0f32; self.dim];
+        for term in crate::ai::nlp::query_terms(&text.to_lowercase()) {
+            let mut hasher = DefaultHasher::new();
+            term.hash(&mut hasher);
+            vector[(hasher.finish() % self.dim as u64) as usize] += 1.0;
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        Embedding::new(self.model_id.clone(), vector)
+    }
+}
+
+impl Default for StubEmbeddingProvider {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for StubEmbeddingProvider {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+        Ok(texts.iter().map(|t| self.embed_text(t)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_db::cosine_similarity;
+
+    #[tokio::test]
+    async fn stub_provider_is_deterministic_and_lexical() {
+        let provider = StubEmbeddingProvider::default();
+        let docs = provider
+            .embed_documents(&[
+                "rust 错误处理 最佳实践".to_string(),
+                "weekly sales report".to_string(),
+            ])
+            .await
+            .unwrap();
+        let query = provider.embed_query("rust 错误处理").await.unwrap();
+
+        assert_eq!(query.model_id, "stub-64");
+        assert_eq!(query, provider.embed_query("rust 错误处理").await.unwrap());
+        assert!(cosine_similarity(&query.vector, &docs[0].vector) > cosine_similarity(&query.vector, &docs[1].vector));
+    }
+
+    #[test]
+    fn reads_provider_from_app_config() {
+        let config = EmbeddingConfig::from_app_config(&serde_json::json!({
+            "embeddingProvider": "openai",
+            "embeddingModel": "text-embedding-3-large",
+            "localEmbeddingModel": "intfloat/multilingual-e5-small",
+            "openaiBaseUrl": "http://localhost:11434/v1"
+        }));
+        assert_eq!(config.provider, EmbeddingProviderKind::OpenAi);
+        assert_eq!(config.model, "text-embedding-3-large");
+        assert_eq!(config.base_url.as_deref(), Some("http://localhost:11434/v1"));
+
+        // 旧配置没有 embeddingProvider 字段时默认使用本地模型
+        let config = EmbeddingConfig::from_app_config(&serde_json::json!({
+            "embeddingModel": "text-embedding-3-small"
+        }));
+        assert_eq!(config.provider, EmbeddingProviderKind::Local);
+        assert_eq!(config.model, "");
+
+        assert_eq!("Stub".parse::<EmbeddingProviderKind>().unwrap(), EmbeddingProviderKind::Stub);
+        assert!("bogus".parse::<EmbeddingProviderKind>().is_err());
+    }
+
+    #[tokio::test]
+    async fn openai_provider_requires_api_key() {
+        let config = EmbeddingConfig {
+            provider: EmbeddingProviderKind::OpenAi,
+            ..Default::default()
+        };
+        assert!(create_embedding_provider(&config).await.is_err());
+
+        let config = EmbeddingConfig {
+            provider: EmbeddingProviderKind::Stub,
+            ..Default::default()
+        };
+        let provider = shared_embedding_provider(&config).await.unwrap();
+        assert_eq!(provider.model_id(), "stub-64");
+    }
+}
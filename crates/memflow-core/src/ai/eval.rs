@@ -0,0 +1,339 @@
+//! 离线检索评测
+//!
+//! 调整 [`crate::ai::rag`] 的衰减、权重或分词后，用一组标注好相关活动的“黄金查询”
+//! 衡量效果。分别评测三种检索方式：
+//! - `bm25`：仅 FTS5 关键词检索
+//! - `vector`：仅向量检索（活动向量与最相关块取较大值）
+//! - `hybrid`：完整的混合检索（含时间衰减，以及配置了的重排）
+//!
+//! 指标均按前 k 个结果计算：recall@k、MRR@k 与二元相关度的 nDCG@k。
+//! 全程离线运行，向量可以用 [`StubEmbeddingProvider`](crate::ai::embedding::StubEmbeddingProvider)
+//! 或本地模型生成。仓库自带的样例见 `fixtures/retrieval_eval.json`。
+
+use crate::ai::embedding::EmbeddingProvider;
+use crate::ai::rag::HybridSearch;
+use crate::{db, embedding_queue};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
+use std::fmt;
+
+/// 评测用的活动记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureActivity {
+    pub id: i64,
+    /// 距离评测开始时间的小时数（换算成时间戳，使时间衰减的效果稳定可复现）
+    pub age_hours: f64,
+    pub app: String,
+    pub title: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+/// 一条黄金查询及其相关活动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoldenQuery {
+    pub query: String,
+    pub relevant: Vec<i64>,
+}
+
+/// 评测数据集：活动 + 黄金查询
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvalFixture {
+    #[serde(default)]
+    pub activities: Vec<FixtureActivity>,
+    pub queries: Vec<GoldenQuery>,
+}
+
+impl EvalFixture {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// 评测的检索方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetrievalMode {
+    Bm25,
+    Vector,
+    Hybrid,
+}
+
+impl RetrievalMode {
+    pub const ALL: [RetrievalMode; 3] = [RetrievalMode::Bm25, RetrievalMode::Vector, RetrievalMode::Hybrid];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetrievalMode::Bm25 => "bm25",
+            RetrievalMode::Vector => "vector",
+            RetrievalMode::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// 单条查询的结果与指标
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMetrics {
+    pub query: String,
+    pub retrieved: Vec<i64>,
+    pub recall: f64,
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+}
+
+/// 一种检索方式在整个查询集上的平均指标
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModeMetrics {
+    pub mode: RetrievalMode,
+    pub recall: f64,
+    pub mrr: f64,
+    pub ndcg: f64,
+    pub queries: Vec<QueryMetrics>,
+}
+
+/// 评测报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvalReport {
+    pub k: usize,
+    pub model_id: String,
+    pub modes: Vec<ModeMetrics>,
+}
+
+impl EvalReport {
+    pub fn mode(&self, mode: RetrievalMode) -> Option<&ModeMetrics> {
+        self.modes.iter().find(|m| m.mode == mode)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    /// 输出 Markdown 表格，便于贴到 PR 描述中对比
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let k = self.k;
+        writeln!(f, "model: {}", self.model_id)?;
+        writeln!(f, "| mode | recall@{k} | MRR@{k} | nDCG@{k} |")?;
+        writeln!(f, "|------|------|------|------|")?;
+        for m in &self.modes {
+            writeln!(f, "| {} | {:.3} | {:.3} | {:.3} |", m.mode.as_str(), m.recall, m.mrr, m.ndcg)?;
+        }
+        Ok(())
+    }
+}
+
+/// 前 k 个结果覆盖的相关活动比例
+pub fn recall_at_k(retrieved: &[i64], relevant: &HashSet<i64>, k: usize) -> f64 {
+    if relevant.is_empty() {
+        return 0.0;
+    }
+    let hits = retrieved.iter().take(k).filter(|id| relevant.contains(id)).count();
+    hits as f64 / relevant.len() as f64
+}
+
+/// 前 k 个结果中第一个相关活动排名的倒数，没有命中时为 0
+pub fn reciprocal_rank(retrieved: &[i64], relevant: &HashSet<i64>, k: usize) -> f64 {
+    retrieved
+        .iter()
+        .take(k)
+        .position(|id| relevant.contains(id))
+        .map_or(0.0, |pos| 1.0 / (pos + 1) as f64)
+}
+
+/// 二元相关度的 nDCG@k
+pub fn ndcg_at_k(retrieved: &[i64], relevant: &HashSet<i64>, k: usize) -> f64 {
+    let gain = |rank: usize| 1.0 / ((rank + 2) as f64).log2();
+    let dcg: f64 = retrieved
+        .iter()
+        .take(k)
+        .enumerate()
+        .filter(|(_, id)| relevant.contains(id))
+        .map(|(rank, _)| gain(rank))
+        .sum();
+    let ideal: f64 = (0..relevant.len().min(k)).map(gain).sum();
+    if ideal > 0.0 {
+        dcg / ideal
+    } else {
+        0.0
+    }
+}
+
+/// 将评测活动写入数据库，并用 `provider` 生成向量（含分块向量）
+///
+/// 时间戳按 `now - age_hours` 换算。数据库需已执行迁移，通常是内存库或一次性的文件库。
+pub async fn load_fixture_impl(
+    pool: &SqlitePool,
+    fixture: &EvalFixture,
+    provider: &dyn EmbeddingProvider,
+    now: i64,
+) -> Result<()> {
+    for activity in &fixture.activities {
+        let timestamp = now - (activity.age_hours * 3600.0) as i64;
+        let title_seg = crate::ai::nlp::segment_for_index(&activity.title).unwrap_or_default();
+        sqlx::query(
+            "INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, window_title_seg)
+             VALUES (?, ?, ?, ?, '', ?)",
+        )
+        .bind(activity.id)
+        .bind(timestamp)
+        .bind(&activity.app)
+        .bind(&activity.title)
+        .bind(title_seg)
+        .execute(pool)
+        .await?;
+
+        if !activity.text.trim().is_empty() {
+            db::update_activity_ocr_impl(pool, activity.id, &activity.text).await?;
+        }
+        embedding_queue::enqueue_impl(pool, activity.id).await?;
+    }
+
+    loop {
+        let batch = embedding_queue::process_batch_impl(pool, 32, provider).await?;
+        if batch.failed > 0 {
+            return Err(anyhow::anyhow!("评测数据生成向量失败: {} 条", batch.failed));
+        }
+        if batch.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 对 `queries` 依次执行三种检索方式并计算指标
+///
+/// 数据库中的向量必须由同一个 `provider` 生成；`search` 决定混合检索的配置（权重、衰减、重排）。
+pub async fn evaluate_impl(
+    pool: &SqlitePool,
+    provider: &dyn EmbeddingProvider,
+    search: &HybridSearch,
+    queries: &[GoldenQuery],
+    k: usize,
+) -> Result<EvalReport> {
+    let filter = db::ActivityQuery::default();
+    let mut per_mode: Vec<Vec<QueryMetrics>> = vec![Vec::new(); RetrievalMode::ALL.len()];
+
+    for golden in queries {
+        let relevant: HashSet<i64> = golden.relevant.iter().copied().collect();
+        let embedding = provider.embed_query(&golden.query).await?;
+
+        for (i, mode) in RetrievalMode::ALL.iter().enumerate() {
+            let retrieved: Vec<i64> = match mode {
+                RetrievalMode::Bm25 => search
+                    .keyword_search_impl(pool, &golden.query, &filter, k)
+                    .await?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect(),
+                RetrievalMode::Vector => search
+                    .vector_search_impl(pool, &embedding, &filter, k)
+                    .await?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect(),
+                RetrievalMode::Hybrid => search
+                    .search_with_embedding_impl(pool, &golden.query, embedding.clone(), k)
+                    .await?
+                    .into_iter()
+                    .map(|r| r.id)
+                    .collect(),
+            };
+
+            per_mode[i].push(QueryMetrics {
+                query: golden.query.clone(),
+                recall: recall_at_k(&retrieved, &relevant, k),
+                reciprocal_rank: reciprocal_rank(&retrieved, &relevant, k),
+                ndcg: ndcg_at_k(&retrieved, &relevant, k),
+                retrieved,
+            });
+        }
+    }
+
+    let mean = |values: &[QueryMetrics], f: fn(&QueryMetrics) -> f64| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().map(f).sum::<f64>() / values.len() as f64
+        }
+    };
+    let modes = RetrievalMode::ALL
+        .iter()
+        .zip(per_mode)
+        .map(|(mode, queries)| ModeMetrics {
+            mode: *mode,
+            recall: mean(&queries, |q| q.recall),
+            mrr: mean(&queries, |q| q.reciprocal_rank),
+            ndcg: mean(&queries, |q| q.ndcg),
+            queries,
+        })
+        .collect();
+
+    Ok(EvalReport {
+        k,
+        model_id: provider.model_id().to_string(),
+        modes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::embedding::StubEmbeddingProvider;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const FIXTURE: &str = include_str!("../../fixtures/retrieval_eval.json");
+
+    #[test]
+    fn metrics_match_hand_computed_values() {
+        let relevant: HashSet<i64> = [1, 2].into_iter().collect();
+        let retrieved = [3, 1, 4, 2];
+
+        assert_eq!(recall_at_k(&retrieved, &relevant, 2), 0.5);
+        assert_eq!(recall_at_k(&retrieved, &relevant, 4), 1.0);
+        assert_eq!(reciprocal_rank(&retrieved, &relevant, 4), 0.5);
+        assert_eq!(reciprocal_rank(&retrieved, &relevant, 1), 0.0);
+
+        // DCG = 1/log2(3) + 1/log2(5)，IDCG = 1 + 1/log2(3)
+        let expected = (1.0 / 3f64.log2() + 1.0 / 5f64.log2()) / (1.0 + 1.0 / 3f64.log2());
+        assert!((ndcg_at_k(&retrieved, &relevant, 4) - expected).abs() < 1e-9);
+        assert_eq!(ndcg_at_k(&[1, 2], &relevant, 5), 1.0);
+        assert_eq!(ndcg_at_k(&retrieved, &HashSet::new(), 5), 0.0);
+    }
+
+    /// 在自带样例上跑完整评测；输出报告（`cargo test -- --nocapture` 查看），并防止检索质量明显回退
+    #[tokio::test]
+    async fn golden_queries_on_fixture() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let fixture = EvalFixture::from_json(FIXTURE).unwrap();
+        let provider = StubEmbeddingProvider::default();
+        load_fixture_impl(&pool, &fixture, &provider, chrono::Utc::now().timestamp())
+            .await
+            .unwrap();
+
+        let report = evaluate_impl(&pool, &provider, &HybridSearch::new(), &fixture.queries, 5)
+            .await
+            .unwrap();
+        println!("{}", report);
+
+        for mode in RetrievalMode::ALL {
+            let metrics = report.mode(mode).unwrap();
+            assert_eq!(metrics.queries.len(), fixture.queries.len());
+            assert!(metrics.recall > 0.0 && metrics.recall <= 1.0);
+        }
+        let hybrid = report.mode(RetrievalMode::Hybrid).unwrap();
+        assert!(hybrid.recall >= 0.8, "{}", report);
+        assert!(hybrid.mrr >= 0.8, "{}", report);
+        assert!(hybrid.ndcg >= 0.75, "{}", report);
+    }
+}
@@ -1,26 +1,51 @@
 //! AI module - Core AI functionality for MemFlow
 //!
 //! This module provides pure, Tauri-independent AI utilities:
+//! - Chunker: Sentence-aware splitting of long OCR text into overlapping chunks
+//! - Citations: Numbered context sources and validation of `[n]` citations in answers
+//! - Context Builder: Token-budgeted context assembly with near-duplicate collapsing
+//! - Conversation: Multi-turn chat history, running session summaries and follow-up query rewriting
+//! - Embedding: Embedding providers (local fastembed, OpenAI-compatible, stub)
+//! - Eval: Offline retrieval evaluation (recall@k, MRR, nDCG) on golden queries
 //! - NLP: Keyword extraction and text analysis
 //! - Prompt Engine: Template-based prompt generation
 //! - Prompts: Prompt configuration management
-//! - Provider: LLM API client implementations
-//! - RAG: Hybrid search combining BM25 and vector similarity
+//! - Provider: LlmProvider trait, OpenAI/Anthropic/mock implementations and the config-keyed registry
+//! - RAG: Hybrid search combining BM25 and vector similarity, with optional reranking
+//! - Structured: JSON-schema constrained model output with validation and one repair retry
+//! - Summaries: Hierarchical activity summaries (hour → day → week) and granularity selection for chat
+//! - Usage: Per-call usage records (`llm_calls`) and an optional content-addressed response cache
 //!
 //! Note: High-level chat/analysis functions that require config/API keys
 //! are in src-tauri/src/ai.rs which wraps these core functions.
 
+pub mod chunker;
+pub mod citations;
+pub mod context_builder;
+pub mod conversation;
+pub mod embedding;
+pub mod eval;
 pub mod nlp;
 pub mod prompt_engine;
 pub mod prompts;
 pub mod provider;
 pub mod rag;
+pub mod structured;
+pub mod summaries;
+pub mod usage;
 
 // Re-export commonly used types
+pub use context_builder::{assemble_context, AssembledContext, ContextBudget, ContextItem};
+pub use conversation::{Conversation, ConversationConfig};
 pub use prompt_engine::PromptTemplate;
 pub use prompts::{PromptsConfig, AgentConfig};
-pub use provider::ProviderConfig;
-pub use rag::{HybridSearch, HybridSearchResult};
+pub use provider::{
+    CompletionRequest, LlmConfig, LlmProvider, LlmProviderKind, MockLlmProvider, ProviderConfig, ResponseSchema,
+};
+pub use rag::{FusionMode, HybridSearch, HybridSearchConfig, HybridSearchResult, Reranker, RerankerConfig};
+pub use structured::{StructuredFailure, StructuredOutputError};
+pub use summaries::{Summary, SummaryGranularity};
+pub use usage::{LlmPurpose, LlmUsageSummary, MeteredLlmProvider};
 
 use serde::{Deserialize, Serialize};
 
@@ -45,6 +70,7 @@ pub struct TaskContext {
 pub struct FilterParams {
     pub app_name: Option<String>,
     pub keywords: Vec<String>,
+    /// 问题中的时间表达（如 `yesterday`、`last 3 days`、`上周三下午`），由 [`crate::time_range`] 解析
     pub date_range: Option<String>,
     pub has_ocr: Option<bool>,
 }
@@ -91,18 +117,12 @@ pub fn fallback_filter_params(query: &str) -> FilterParams {
     let q = query.trim();
     let lower = q.to_lowercase();
 
-    let mut date_range = None;
-    if lower.contains("yesterday") {
-        date_range = Some("yesterday".to_string());
-    } else if lower.contains("today") {
-        date_range = Some("today".to_string());
-    } else if lower.contains("last week") || lower.contains("last_week") {
-        date_range = Some("last_week".to_string());
-    } else if lower.contains("this week") || lower.contains("this_week") {
-        date_range = Some("this_week".to_string());
-    } else if lower.contains("this month") || lower.contains("this_month") {
-        date_range = Some("this_month".to_string());
-    }
+    // 时间表达交给 time_range 解析，不再作为关键词
+    let date_range = crate::time_range::extract_time_expression(q);
+    let rest = match &date_range {
+        Some(expr) => q.replacen(expr.as_str(), " ", 1),
+        None => q.to_string(),
+    };
 
     let has_ocr = if lower.contains("ocr")
         || lower.contains("content")
@@ -115,7 +135,7 @@ pub fn fallback_filter_params(query: &str) -> FilterParams {
         None
     };
 
-    let keywords = q
+    let keywords = rest
         .split_whitespace()
         .filter_map(|w| {
             let trimmed = w.trim_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '-');
@@ -134,6 +154,88 @@ pub fn fallback_filter_params(query: &str) -> FilterParams {
     }
 }
 
+/// [`FilterParams`] 的 JSON Schema
+pub fn filter_params_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "filter_params".to_string(),
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "app_name": { "type": ["string", "null"] },
+                "keywords": { "type": "array", "items": { "type": "string" } },
+                "date_range": { "type": ["string", "null"] },
+                "has_ocr": { "type": ["boolean", "null"] }
+            },
+            "required": ["app_name", "keywords", "date_range", "has_ocr"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+/// [`AiAnalysisResult`] 的 JSON Schema
+pub fn analysis_schema() -> ResponseSchema {
+    let strings = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+    ResponseSchema {
+        name: "task_contexts".to_string(),
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "tasks": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": { "type": "string" },
+                            "summary": { "type": "string" },
+                            "related_urls": strings,
+                            "related_files": strings,
+                            "related_apps": strings
+                        },
+                        "required": ["title", "summary", "related_urls", "related_files", "related_apps"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["tasks"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+/// 用 LLM 解析查询意图；调用失败、超时或输出修正后仍无效时回退到 [`fallback_filter_params`]
+pub async fn parse_query_intent_with(
+    provider: &dyn LlmProvider,
+    query: &str,
+    system_prompt: &str,
+    timeout: std::time::Duration,
+) -> FilterParams {
+    let request = CompletionRequest::from_query(query, "", Some(system_prompt));
+    match structured::complete_structured(provider, request, &filter_params_schema(), timeout).await {
+        Ok(params) => params,
+        Err(e) => {
+            tracing::warn!(
+                "parse_query_intent: {} model={} 未得到有效意图（{:?}），使用回退解析: {}",
+                provider.name(),
+                provider.model(),
+                e.kind(),
+                e
+            );
+            fallback_filter_params(query)
+        }
+    }
+}
+
+/// 让 LLM 从活动记录中提炼任务建议（按 [`analysis_schema`] 校验，必要时修正一次）
+pub async fn analyze_for_proposals_with(
+    provider: &dyn LlmProvider,
+    context_text: &str,
+    system_prompt: &str,
+    timeout: std::time::Duration,
+) -> Result<AiAnalysisResult, StructuredOutputError> {
+    let request = CompletionRequest::from_query("请分析活动记录并生成建议", context_text, Some(system_prompt));
+    structured::complete_structured(provider, request, &analysis_schema(), timeout).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,8 +264,59 @@ mod tests {
     #[test]
     fn fallback_never_panics_and_returns_keywords() {
         let parsed = fallback_filter_params("Find pdf last week rust ocr");
-        assert!(!parsed.keywords.is_empty());
-        assert_eq!(parsed.date_range.as_deref(), Some("last_week"));
+        assert_eq!(parsed.keywords, vec!["Find", "pdf", "rust", "ocr"]);
+        assert_eq!(parsed.date_range.as_deref(), Some("last week"));
+        assert!(crate::time_range::parse_time_range("last week").is_some());
         assert_eq!(parsed.has_ocr, Some(true));
+
+        let parsed = fallback_filter_params("上周三下午 VS Code 的提交");
+        assert_eq!(parsed.date_range.as_deref(), Some("上周三下午"));
+    }
+
+    #[tokio::test]
+    async fn intent_parsing_uses_provider_and_falls_back() {
+        let timeout = std::time::Duration::from_secs(1);
+        let provider = MockLlmProvider::with_responses([
+            r#"```json
+{"app_name":"Code","keywords":["rust"],"date_range":"today","has_ocr":null}
+```"#,
+            "not json",
+        ]);
+        provider.push_error("429 Too Many Requests");
+
+        let parsed = parse_query_intent_with(&provider, "今天写的 rust", "解析意图", timeout).await;
+        assert_eq!(parsed.app_name.as_deref(), Some("Code"));
+        assert_eq!(parsed.date_range.as_deref(), Some("today"));
+
+        let requests = provider.requests();
+        assert!(requests[0].system.as_deref().unwrap().starts_with("解析意图\n\n只返回符合以下 JSON Schema"));
+        assert_eq!(requests[0].messages[0].content, "今天写的 rust");
+
+        // 无法解析时要求修正一次；修正失败或调用失败都回退到规则解析
+        for _ in 0..2 {
+            let parsed = parse_query_intent_with(&provider, "rust today", "解析意图", timeout).await;
+            assert_eq!(parsed.app_name, None);
+            assert_eq!(parsed.date_range.as_deref(), Some("today"));
+        }
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[2].messages[1].content, "not json");
+    }
+
+    #[tokio::test]
+    async fn proposals_are_parsed_from_provider_output() {
+        let provider = MockLlmProvider::with_responses([
+            r#"{"tasks":[{"title":"修复检索","summary":"s","related_urls":[],"related_files":["rag.rs"],"related_apps":["Code"]}]}"#,
+        ]);
+        let timeout = std::time::Duration::from_secs(1);
+        let result = analyze_for_proposals_with(&provider, "[10:00] 应用: Code", "生成建议", timeout).await.unwrap();
+        assert_eq!(result.tasks.len(), 1);
+        assert_eq!(result.tasks[0].related_files, vec!["rag.rs".to_string()]);
+        assert!(provider.requests()[0].messages[0].content.contains("[10:00] 应用: Code"));
+
+        // 队列为空时 mock 回显问题，修正后仍不是合法 JSON
+        let error = analyze_for_proposals_with(&provider, "", "生成建议", timeout).await.unwrap_err();
+        assert_eq!(error.kind(), StructuredFailure::InvalidJson);
+        assert_eq!(provider.requests().len(), 3);
     }
 }
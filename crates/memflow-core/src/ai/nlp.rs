@@ -193,6 +193,171 @@ pub fn is_chinese_text(text: &str) -> bool {
     chinese_count as f64 / total_alpha as f64 > 0.3
 }
 
+/// 是否为 CJK 表意文字
+pub(crate) fn is_cjk_char(c: char) -> bool {
+    matches!(c as u32, 0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0xF900..=0xFAFF)
+}
+
+/// 文本中是否包含 CJK 字符
+pub fn contains_cjk(text: &str) -> bool {
+    text.chars().any(is_cjk_char)
+}
+
+/// 为 FTS 索引预分词
+///
+/// unicode61 分词器不会切分连续的中文，这里用 jieba 搜索模式切词并以空格连接，
+/// 长词会同时输出其包含的短词（"错误处理" -> "错误 处理 错误处理"）。
+/// 不含 CJK 字符时返回 `None`，调用方直接索引原文即可。
+pub fn segment_for_index(text: &str) -> Option<String> {
+    if !contains_cjk(text) {
+        return None;
+    }
+
+    let tokens: Vec<&str> = JIEBA
+        .cut_for_search(text, false)
+        .into_iter()
+        .map(str::trim)
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .collect();
+
+    Some(tokens.join(" "))
+}
+
+/// 将一段连续中文切成与索引侧对齐的最细粒度词
+///
+/// 索引侧对长词会额外输出其中的短词，因此查询侧把长词拆成短词更容易命中，
+/// 不依赖两边对上下文的切分完全一致。
+fn segment_cjk_run(run: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in JIEBA.cut(run, false) {
+        if word.chars().count() > 2 {
+            let subs: Vec<&str> = JIEBA
+                .cut_for_search(word, false)
+                .into_iter()
+                .filter(|w| *w != word)
+                .collect();
+            if !subs.is_empty() {
+                tokens.extend(subs.into_iter().map(String::from));
+                continue;
+            }
+        }
+        tokens.push(word.to_string());
+    }
+    tokens
+}
+
+/// 从检索词中提取用于原文高亮的词（去掉 FTS 运算符与符号，中文按索引粒度切分）
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut push = |term: String| {
+        if !term.is_empty() && !terms.contains(&term) {
+            terms.push(term);
+        }
+    };
+
+    for raw in query.split_whitespace() {
+        if matches!(raw, "OR" | "AND" | "NOT" | "NEAR") {
+            continue;
+        }
+        // 去掉列过滤前缀（如 ocr_text:）
+        let raw = raw.rsplit(':').next().unwrap_or(raw);
+
+        let mut word = String::new();
+        let mut run = String::new();
+        for c in raw.chars() {
+            if is_cjk_char(c) {
+                if !word.is_empty() {
+                    push(std::mem::take(&mut word));
+                }
+                run.push(c);
+            } else {
+                if !run.is_empty() {
+                    segment_cjk_run(&std::mem::take(&mut run)).into_iter().for_each(&mut push);
+                }
+                if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                    word.push(c);
+                } else if !word.is_empty() {
+                    push(std::mem::take(&mut word));
+                }
+            }
+        }
+        if !run.is_empty() {
+            segment_cjk_run(&run).into_iter().for_each(&mut push);
+        }
+        if !word.is_empty() {
+            push(word);
+        }
+    }
+
+    terms
+}
+
+/// 改写 FTS5 MATCH 表达式，使其中的中文片段与预分词索引匹配
+///
+/// 运算符、列过滤、引号和前缀 `*` 保持不变，只替换连续的 CJK 片段：
+/// 引号内按索引侧的切分替换为短语，引号外切出多个词时改写为 `NEAR(...)`。
+pub fn segment_fts_query(query: &str) -> String {
+    if !contains_cjk(query) {
+        return query.to_string();
+    }
+
+    let chars: Vec<char> = query.chars().collect();
+    let mut out = String::with_capacity(query.len() * 2);
+    let mut in_quotes = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if !is_cjk_char(c) {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && is_cjk_char(chars[i]) {
+            i += 1;
+        }
+        let run: String = chars[start..i].iter().collect();
+        // 短语需要与索引中的词序完全一致，因此引号内沿用索引侧的切分方式
+        let tokens = if in_quotes {
+            JIEBA
+                .cut_for_search(&run, false)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        } else {
+            segment_cjk_run(&run)
+        };
+        let prefix = !in_quotes && chars.get(i) == Some(&'*');
+
+        if !out.is_empty() && !out.ends_with([' ', '"', '(', ':']) {
+            out.push(' ');
+        }
+
+        if in_quotes || tokens.len() == 1 {
+            out.push_str(&tokens.join(" "));
+        } else {
+            out.push_str("NEAR(");
+            out.push_str(&tokens.join(" "));
+            if prefix {
+                out.push('*');
+                i += 1;
+            }
+            out.push_str(&format!(", {})", tokens.len() * 2));
+        }
+
+        if i < chars.len() && !matches!(chars[i], ' ' | '"' | ')' | '*') {
+            out.push(' ');
+        }
+    }
+
+    out
+}
+
 /// 提取文本中的专有名词（简单实现）
 pub fn extract_named_entities(text: &str) -> Vec<String> {
     let mut entities = Vec::new();
@@ -262,4 +427,36 @@ mod tests {
         assert!(!is_chinese_text("This is text"));
         assert!(is_chinese_text("这是中文混合"));
     }
+
+    #[test]
+    fn test_segment_for_index() {
+        assert_eq!(segment_for_index("cargo build --release"), None);
+
+        let seg = segment_for_index("我们需要改进项目里的错误处理逻辑，避免崩溃").unwrap();
+        let tokens: Vec<&str> = seg.split(' ').collect();
+        assert!(tokens.contains(&"错误"));
+        assert!(tokens.contains(&"处理"));
+        assert!(tokens.contains(&"错误处理"));
+        // 标点不进入索引
+        assert!(!tokens.contains(&"，"));
+    }
+
+    #[test]
+    fn test_segment_fts_query() {
+        // 非中文查询原样返回
+        assert_eq!(segment_fts_query("hello OR world"), "hello OR world");
+
+        assert_eq!(segment_fts_query("错误处理"), "NEAR(错误 处理, 4)");
+        assert_eq!(segment_fts_query("\"错误处理\""), "\"错误 处理 错误处理\"");
+        assert_eq!(segment_fts_query("rust错误处理"), "rust NEAR(错误 处理, 4)");
+        assert_eq!(segment_fts_query("ocr_text:错误 OR 会议纪要*"), "ocr_text:错误 OR NEAR(会议 纪要*, 4)");
+    }
+
+    #[test]
+    fn test_query_terms() {
+        assert_eq!(
+            query_terms("\"错误处理\" OR ocr_text:invoice* rust错误"),
+            vec!["错误", "处理", "invoice", "rust"]
+        );
+    }
 }
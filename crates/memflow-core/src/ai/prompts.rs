@@ -35,18 +35,34 @@ pub struct AnalyzePrompts {
     pub system: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     #[serde(default = "default_context_max_items")]
     pub context_max_items: usize,
-    #[serde(default = "default_context_max_chars_per_ocr")]
-    pub context_max_chars_per_ocr: usize,
+    /// 活动上下文的 token 预算
+    #[serde(default = "default_context_max_tokens")]
+    pub context_max_tokens: usize,
+    /// 单条活动 OCR 文本的 token 上限
+    #[serde(default = "default_context_max_tokens_per_item")]
+    pub context_max_tokens_per_item: usize,
     #[serde(default = "default_session_gap_minutes")]
     pub session_gap_minutes: i64,
 }
 
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            context_max_items: default_context_max_items(),
+            context_max_tokens: default_context_max_tokens(),
+            context_max_tokens_per_item: default_context_max_tokens_per_item(),
+            session_gap_minutes: default_session_gap_minutes(),
+        }
+    }
+}
+
 fn default_context_max_items() -> usize { 40 }
-fn default_context_max_chars_per_ocr() -> usize { 100 }
+fn default_context_max_tokens() -> usize { 3000 }
+fn default_context_max_tokens_per_item() -> usize { 120 }
 fn default_session_gap_minutes() -> i64 { 5 }
 
 impl Default for PromptsConfig {
@@ -63,7 +79,7 @@ Your goal is to extract search filters from the user's natural language query.
 Return a JSON object with the following fields:
 - "app_name": (string | null) Filter by application name (e.g., "Chrome", "VS Code"). If the user mentions "pdf", map it to a likely pdf reader or just "pdf".
 - "keywords": (string[]) List of keywords to search in OCR text or window titles.
-- "date_range": (string | null) One of: "today", "yesterday", "this_week", "last_week", "this_month", or null if not specified.
+- "date_range": (string | null) The time expression exactly as written in the query (e.g. "yesterday", "last 3 days", "past 2 hours", "this morning", "between March 2 and 5", "上周三下午"), or null if not specified. Do not convert it to dates; it is resolved locally.
 - "has_ocr": (boolean | null) true if user wants to search within text/content, null otherwise.
 
 Example 1:
@@ -72,9 +88,13 @@ Output: { "app_name": "Chrome", "keywords": [], "date_range": "yesterday", "has_
 
 Example 2:
 Input: "Find PDF files about rust from last week"
-Output: { "app_name": "pdf", "keywords": ["rust"], "date_range": "last_week", "has_ocr": true }
+Output: { "app_name": "pdf", "keywords": ["rust"], "date_range": "last week", "has_ocr": true }
 
 Example 3:
+Input: "上周三下午在 VS Code 里改了哪些文件"
+Output: { "app_name": "Code", "keywords": ["文件"], "date_range": "上周三下午", "has_ocr": null }
+
+Example 4:
 Input: "coding session"
 Output: { "app_name": "Code", "keywords": ["coding"], "date_range": null, "has_ocr": null }
 
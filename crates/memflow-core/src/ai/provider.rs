@@ -1,6 +1,51 @@
+//! LLM providers
+//!
+//! 所有对话、意图解析、建议生成都通过 [`LlmProvider`] 调用模型，由配置（[`LlmConfig`]）
+//! 选择具体实现，调用方不再关心模型属于哪家接口：
+//! - `openai`：OpenAI 兼容的 `/chat/completions`
+//! - `anthropic`：Anthropic `/v1/messages`
+//! - `ollama`：本机 Ollama 的 `/api/chat`、`/api/embed`
+//! - `local`：OpenAI 兼容的本地推理服务（llama.cpp server、LM Studio 等），无需 API Key
+//! - `mock`：按预设脚本返回的确定性实现，用于单元测试
+//!
+//! 每个 provider 持有一个 [`HttpClient`]（provider 按配置共享，连接池随之复用）：
+//! - 连接超时与读取超时分开：连接超时较短，服务没启动时尽快失败；读取超时限制等待响应头与相邻两段数据的间隔，
+//!   流式回答总时长不受限制。本地模型在 CPU 上推理可能很慢，本地提供商使用更长的读取超时（[`LOCAL_REQUEST_TIMEOUT`]）
+//! - 429、5xx 与复用连接被重置时按指数退避加随机抖动重试，优先遵守 `Retry-After`
+//! - 流式补全接受 [`CancellationToken`]，取消后立即断开连接并返回已生成的部分
+
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+pub use tokio_util::sync::CancellationToken;
+
+static SHARED_LLM_PROVIDERS: once_cell::sync::Lazy<tokio::sync::Mutex<HashMap<LlmConfig, Arc<dyn LlmProvider>>>> =
+    once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(HashMap::new()));
+
+/// 云端接口的读取超时
+const CLOUD_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 本地推理服务的读取超时（首次加载模型 + CPU 推理）
+pub const LOCAL_REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 列出模型等轻量请求的读取超时
+const METADATA_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+const OPENAI_CHAT_API: &str = "OpenAI Chat API";
+const ANTHROPIC_CHAT_API: &str = "Anthropic Chat API";
+const OLLAMA_API: &str = "Ollama API";
+
+/// 建立连接的超时；本地服务没启动时应尽快失败
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 未提供自定义系统提示词时使用的默认提示词
+const DEFAULT_SYSTEM_PROMPT: &str =
+    "你是桌面活动记录分析助手。直接回答用户的问题，简洁明了。如果用户只是测试，简单确认即可。";
+const DEFAULT_CONTEXT_SYSTEM_PROMPT: &str =
+    "你是桌面活动记录分析助手。基于用户提供的桌面活动记录（OCR文本、应用名称等）回答问题。只回答事实，不要解释如何设计系统。";
 
 /// 安全地截取字符串到指定字符数，确保在字符边界处分割
 fn safe_truncate(s: &str, max_chars: usize) -> &str {
@@ -15,6 +60,7 @@ fn safe_truncate(s: &str, max_chars: usize) -> &str {
 }
 
 /// 提供商配置
+#[derive(Clone)]
 pub struct ProviderConfig {
     pub api_key: String,
     pub base_url: String,
@@ -29,698 +75,1655 @@ impl ProviderConfig {
     }
 }
 
-/// 使用 OpenAI API 进行对话
-pub async fn chat_with_openai(
-    query: &str,
-    context: &str,
-    model: &str,
-    config: &ProviderConfig,
-    custom_system_prompt: Option<&str>,
-) -> Result<String> {
-    fn extract_final_answer_from_reasoning(reasoning: &str) -> Option<String> {
-        let s = reasoning.trim();
-        if s.is_empty() {
-            return None;
-        }
-
-        // 跳过明显的"设计说明"部分（包含这些关键词的段落）
-        let design_keywords = [
-            "分析用户输入",
-            "分析上下文",
-            "确定目标",
-            "起草回复",
-            "润色回复",
-            "自我修正",
-            "更好的版本",
-        ];
-        let lines: Vec<&str> = s.lines().collect();
-        let mut found_final = false;
-        let mut final_lines = Vec::new();
-
-        for line in lines {
-            let line_lower = line.to_lowercase();
-            // 如果遇到"最终"相关的标记，开始收集
-            if line.contains("最终")
-                || line.contains("更好的版本")
-                || line.contains("回复（中文）")
-                || line.contains("最终润色")
-                || line.contains("最终回复")
-            {
-                found_final = true;
-                // 跳过标记行本身
-                continue;
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
 
-            // 如果包含设计关键词但不是最终部分，跳过
-            if design_keywords.iter().any(|&kw| line_lower.contains(kw)) && !found_final {
-                continue;
-            }
+/// 对话消息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
 
-            // 收集最终部分或所有非设计说明的内容
-            if found_final || !design_keywords.iter().any(|&kw| line_lower.contains(kw)) {
-                let cleaned = line.trim();
-                if !cleaned.is_empty() && !cleaned.starts_with("**") && !cleaned.starts_with("##") {
-                    // 跳过明显的标题行
-                    if cleaned.len() < 100
-                        || cleaned.chars().filter(|c| c.is_alphanumeric()).count() > 20
-                    {
-                        final_lines.push(cleaned);
-                    }
-                }
-            }
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.into(),
         }
+    }
 
-        // 如果找到了最终部分，返回它；否则返回所有非设计说明的内容
-        if !final_lines.is_empty() {
-            let joined = final_lines.join("\n").trim().to_string();
-            if joined.len() > 10 {
-                // 至少要有一定长度
-                return Some(joined);
-            }
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.into(),
         }
+    }
+}
 
-        // 回退：尝试找最后一个引号或对话风格的内容
-        if let Some(quote_idx) = s.rfind('"') {
-            if let Some(start_quote) = s[..quote_idx].rfind('"') {
-                let extracted = &s[start_quote + 1..quote_idx];
-                if extracted.len() > 10 {
-                    return Some(extracted.trim().to_string());
-                }
-            }
+/// 一次补全请求
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionRequest {
+    pub system: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    /// 为空时使用各实现的默认值
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    /// 要求按该 JSON Schema 输出（仅对 [`LlmProvider::supports_json_schema`] 为 true 的实现生效）
+    pub response_schema: Option<ResponseSchema>,
+}
+
+/// 结构化输出的 JSON Schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSchema {
+    /// 只能包含字母、数字、`_` 与 `-`（OpenAI 的要求）
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+impl CompletionRequest {
+    /// 单轮提问：把检索到的活动记录附在问题后面
+    ///
+    /// 未指定 `system_prompt` 时按是否有上下文选择默认提示词。
+    pub fn from_query(query: &str, context: &str, system_prompt: Option<&str>) -> Self {
+        let system = system_prompt.unwrap_or(if context.is_empty() {
+            DEFAULT_SYSTEM_PROMPT
+        } else {
+            DEFAULT_CONTEXT_SYSTEM_PROMPT
+        });
+        let user_content = if context.is_empty() {
+            query.to_string()
+        } else {
+            format!("{}\n\n--- 相关桌面活动记录 ---\n{}", query, context)
+        };
+
+        Self {
+            system: Some(system.to_string()),
+            messages: vec![ChatMessage::user(user_content)],
+            ..Default::default()
         }
+    }
 
-        // 最后回退：返回整个 reasoning，但过滤掉明显的设计说明段落
-        let filtered: Vec<&str> = s
-            .lines()
-            .filter(|l| {
-                let lower = l.to_lowercase();
-                !design_keywords.iter().any(|&kw| lower.contains(kw))
-            })
-            .filter(|l| l.trim().len() > 5)
-            .collect();
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn response_schema(mut self, schema: ResponseSchema) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    /// 所有消息的总字符数（含系统提示词）
+    fn content_len(&self) -> usize {
+        self.system.as_ref().map_or(0, |s| s.len()) + self.messages.iter().map(|m| m.content.len()).sum::<usize>()
+    }
+}
+
+/// 接口返回的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+/// 可用模型（模型发现）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    /// 模型文件大小（字节），接口未提供时为 `None`
+    pub size_bytes: Option<u64>,
+}
+
+/// 补全结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    pub text: String,
+    /// 接口未返回用量时为 `None`
+    pub usage: Option<TokenUsage>,
+}
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// 展示给用户的提供商名称（用于错误提示）
+    fn name(&self) -> &str;
+
+    /// 请求使用的模型
+    fn model(&self) -> &str;
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion>;
+
+    /// 流式补全：每收到一段文本调用一次 `on_chunk`，返回完整结果
+    ///
+    /// `cancel` 被取消后停止请求，返回已生成的部分。
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion>;
+
+    /// 批量生成向量，顺序与输入一致（不支持时返回错误）
+    async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow::anyhow!("{} 不支持生成向量", self.name()))
+    }
+
+    /// 列出服务端可用的模型（不支持时返回错误）
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Err(anyhow::anyhow!("{} 不支持列出模型", self.name()))
+    }
+
+    /// 是否支持按 [`CompletionRequest::response_schema`] 约束输出；不支持时由调用方在提示词中说明格式
+    fn supports_json_schema(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmProviderKind {
+    #[default]
+    OpenAi,
+    Anthropic,
+    Ollama,
+    Local,
+    Mock,
+}
 
-        if !filtered.is_empty() {
-            Some(filtered.join("\n").trim().to_string())
+impl LlmProviderKind {
+    /// 未显式指定提供商时按模型名推断（`claude-*` 为 Anthropic，其余按 OpenAI 兼容接口）
+    pub fn infer_from_model(model: &str) -> Self {
+        if model.starts_with("claude-") {
+            LlmProviderKind::Anthropic
         } else {
-            None
+            LlmProviderKind::OpenAi
         }
     }
 
-    #[derive(Serialize)]
-    struct ChatRequest {
-        model: String,
-        messages: Vec<Message>,
-        max_tokens: u32,
-        temperature: f32,
+    /// 保存 API Key 的 secure storage 服务名（本地服务与 mock 不需要）
+    pub fn key_service(&self) -> Option<&'static str> {
+        match self {
+            LlmProviderKind::OpenAi => Some("openai"),
+            LlmProviderKind::Anthropic => Some("anthropic"),
+            LlmProviderKind::Ollama | LlmProviderKind::Local | LlmProviderKind::Mock => None,
+        }
     }
 
-    #[derive(Serialize)]
-    struct Message {
-        role: String,
-        content: String,
+    /// 是否在本机推理（不需要云端 API Key）
+    pub fn is_local(&self) -> bool {
+        matches!(self, LlmProviderKind::Ollama | LlmProviderKind::Local)
     }
 
-    #[derive(Deserialize, Debug)]
-    struct ChatResponse {
-        choices: Vec<Choice>,
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            LlmProviderKind::OpenAi => "OpenAI",
+            LlmProviderKind::Anthropic => "Anthropic",
+            LlmProviderKind::Ollama => "Ollama",
+            LlmProviderKind::Local => "本地模型服务",
+            LlmProviderKind::Mock => "Mock",
+        }
     }
 
-    #[derive(Deserialize, Debug)]
-    struct Choice {
-        message: Option<MessageResponse>,
-        // 某些 API 使用 text 字段（旧版 OpenAI 格式）
-        text: Option<String>,
-        // 流式响应中的 delta
-        delta: Option<MessageResponse>,
+    pub fn default_base_url(&self) -> &'static str {
+        match self {
+            LlmProviderKind::OpenAi => "https://api.openai.com/v1",
+            LlmProviderKind::Anthropic => "https://api.anthropic.com",
+            LlmProviderKind::Ollama => "http://127.0.0.1:11434",
+            LlmProviderKind::Local => "http://127.0.0.1:8080/v1",
+            LlmProviderKind::Mock => "",
+        }
     }
+}
 
-    #[derive(Deserialize, Debug)]
-    struct MessageResponse {
-        // 某些 API 可能返回 null，使用 Option 处理
-        content: Option<String>,
-        // 部分 OpenAI 兼容实现会把“推理/思考”放到该字段
-        reasoning_content: Option<String>,
+impl std::str::FromStr for LlmProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.trim().to_ascii_lowercase()))
+            .map_err(|_| anyhow::anyhow!("Unknown LLM provider: {}", s))
     }
+}
 
-    // 构建系统提示词
-    let default_system_prompt = if context.is_empty() {
-        "你是桌面活动记录分析助手。直接回答用户的问题，简洁明了。如果用户只是测试，简单确认即可。"
-            .to_string()
-    } else {
-        "你是桌面活动记录分析助手。基于用户提供的桌面活动记录（OCR文本、应用名称等）回答问题。只回答事实，不要解释如何设计系统。".to_string()
-    };
-    
-    let system_prompt = custom_system_prompt
-        .map(|s| s.to_string())
-        .unwrap_or(default_system_prompt);
-
-    // 构建用户消息
-    let user_content = if context.is_empty() {
-        query.to_string()
-    } else {
-        format!("{}\n\n--- 相关桌面活动记录 ---\n{}", query, context)
-    };
+/// 创建 [`LlmProvider`] 所需的配置，同时也是共享实例的缓存键
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct LlmConfig {
+    pub provider: LlmProviderKind,
+    pub model: String,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+}
 
-    tracing::debug!("发送给模型的上下文长度: {} 字符", context.len());
-    if !context.is_empty() {
-        tracing::debug!("上下文预览: {}", safe_truncate(context, 200));
+impl LlmConfig {
+    /// `provider` 为空或 `auto` 时按模型名推断
+    pub fn resolve(provider: &str, model: &str) -> Result<Self> {
+        let provider = match provider.trim() {
+            "" | "auto" => LlmProviderKind::infer_from_model(model),
+            name => name.parse()?,
+        };
+        Ok(Self {
+            provider,
+            model: model.to_string(),
+            ..Default::default()
+        })
     }
+}
 
-    let request = ChatRequest {
-        model: model.to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: system_prompt,
-            },
-            Message {
-                role: "user".to_string(),
-                content: user_content,
-            },
-        ],
-        max_tokens: 4096,
-        temperature: 0.7,
-    };
+impl std::fmt::Debug for LlmConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LlmConfig")
+            .field("provider", &self.provider)
+            .field("model", &self.model)
+            .field("base_url", &self.base_url)
+            .field("api_key", &self.api_key.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .context("创建 HTTP 客户端失败")?;
-    // 智能处理 URL：如果已经包含 /chat/completions 则不再追加
-    let base = config.base_url.trim_end_matches('/');
-    let url = if base.ends_with("/chat/completions") {
-        base.to_string()
+/// 根据配置创建 provider
+pub fn create_llm_provider(config: &LlmConfig) -> Result<Arc<dyn LlmProvider>> {
+    let provider = config.provider;
+    let base_url = || config.base_url.clone().filter(|url| !url.trim().is_empty());
+    match provider {
+        LlmProviderKind::Mock => return Ok(Arc::new(MockLlmProvider::new(config.model.clone()))),
+        LlmProviderKind::Ollama => {
+            let base_url = base_url().unwrap_or_else(|| provider.default_base_url().to_string());
+            return Ok(Arc::new(OllamaProvider::new(config.model.clone(), base_url)));
+        }
+        LlmProviderKind::Local => {
+            // 本地服务一般不校验 Key，配置了就带上
+            let provider_config = ProviderConfig::new(
+                config.api_key.clone().unwrap_or_default(),
+                base_url(),
+                provider.default_base_url(),
+            );
+            return Ok(Arc::new(
+                OpenAiProvider::new(provider.display_name(), config.model.clone(), provider_config)
+                    .with_timeout(LOCAL_REQUEST_TIMEOUT),
+            ));
+        }
+        LlmProviderKind::OpenAi | LlmProviderKind::Anthropic => {}
+    }
+
+    let api_key = config
+        .api_key
+        .clone()
+        .filter(|k| !k.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("未配置 {} API Key", provider.display_name()))?;
+    let provider_config = ProviderConfig::new(api_key, config.base_url.clone(), provider.default_base_url());
+
+    Ok(match provider {
+        LlmProviderKind::Anthropic => Arc::new(AnthropicProvider::new(config.model.clone(), provider_config)),
+        _ => Arc::new(OpenAiProvider::new(provider.display_name(), config.model.clone(), provider_config)),
+    })
+}
+
+/// 列出配置对应服务上的可用模型（用于设置页选择本地模型）
+pub async fn list_models(config: &LlmConfig) -> Result<Vec<ModelInfo>> {
+    create_llm_provider(config)?.list_models().await
+}
+
+/// 按配置返回共享的 provider（同一配置复用同一个实例）
+pub async fn shared_llm_provider(config: &LlmConfig) -> Result<Arc<dyn LlmProvider>> {
+    let mut shared = SHARED_LLM_PROVIDERS.lock().await;
+    if let Some(provider) = shared.get(config) {
+        return Ok(provider.clone());
+    }
+
+    let provider = create_llm_provider(config)?;
+    tracing::debug!("LLM provider 已创建: {:?}", config);
+    // 配置变化（如更换 API Key）后旧实例不再使用
+    shared.retain(|cached, _| cached.provider != config.provider);
+    shared.insert(config.clone(), provider.clone());
+    Ok(provider)
+}
+
+/// 某些推理模型的 content 为空，最终答复混在 reasoning_content 里，这里尽量提取出来
+fn extract_final_answer_from_reasoning(reasoning: &str) -> Option<String> {
+    let s = reasoning.trim();
+    if s.is_empty() {
+        return None;
+    }
+
+    // 跳过明显的"设计说明"部分（包含这些关键词的段落）
+    let design_keywords = [
+        "分析用户输入",
+        "分析上下文",
+        "确定目标",
+        "起草回复",
+        "润色回复",
+        "自我修正",
+        "更好的版本",
+    ];
+    let lines: Vec<&str> = s.lines().collect();
+    let mut found_final = false;
+    let mut final_lines = Vec::new();
+
+    for line in lines {
+        let line_lower = line.to_lowercase();
+        // 如果遇到"最终"相关的标记，开始收集
+        if line.contains("最终")
+            || line.contains("更好的版本")
+            || line.contains("回复（中文）")
+            || line.contains("最终润色")
+            || line.contains("最终回复")
+        {
+            found_final = true;
+            // 跳过标记行本身
+            continue;
+        }
+
+        // 如果包含设计关键词但不是最终部分，跳过
+        if design_keywords.iter().any(|&kw| line_lower.contains(kw)) && !found_final {
+            continue;
+        }
+
+        // 收集最终部分或所有非设计说明的内容
+        if found_final || !design_keywords.iter().any(|&kw| line_lower.contains(kw)) {
+            let cleaned = line.trim();
+            if !cleaned.is_empty() && !cleaned.starts_with("**") && !cleaned.starts_with("##") {
+                // 跳过明显的标题行
+                if cleaned.len() < 100
+                    || cleaned.chars().filter(|c| c.is_alphanumeric()).count() > 20
+                {
+                    final_lines.push(cleaned);
+                }
+            }
+        }
+    }
+
+    // 如果找到了最终部分，返回它；否则返回所有非设计说明的内容
+    if !final_lines.is_empty() {
+        let joined = final_lines.join("\n").trim().to_string();
+        if joined.len() > 10 {
+            // 至少要有一定长度
+            return Some(joined);
+        }
+    }
+
+    // 回退：尝试找最后一个引号或对话风格的内容
+    if let Some(quote_idx) = s.rfind('"') {
+        if let Some(start_quote) = s[..quote_idx].rfind('"') {
+            let extracted = &s[start_quote + 1..quote_idx];
+            if extracted.len() > 10 {
+                return Some(extracted.trim().to_string());
+            }
+        }
+    }
+
+    // 最后回退：返回整个 reasoning，但过滤掉明显的设计说明段落
+    let filtered: Vec<&str> = s
+        .lines()
+        .filter(|l| {
+            let lower = l.to_lowercase();
+            !design_keywords.iter().any(|&kw| lower.contains(kw))
+        })
+        .filter(|l| l.trim().len() > 5)
+        .collect();
+
+    if !filtered.is_empty() {
+        Some(filtered.join("\n").trim().to_string())
     } else {
-        format!("{}/chat/completions", base)
-    };
+        None
+    }
+}
 
-    tracing::info!("chat_with_openai: 发送请求到 {}", url);
-    let start = std::time::Instant::now();
-    
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("OpenAI Chat API 请求失败")?;
-    
-    tracing::info!("chat_with_openai: 收到响应, 耗时 {}ms, status={}", start.elapsed().as_millis(), response.status());
+/// HTTP 请求失败的原因，供重试判断与调用记录分类（[`crate::ai::usage::LlmErrorClass`]）
+#[derive(Debug, thiserror::Error)]
+pub enum HttpFailure {
+    /// 非 2xx 响应
+    #[error("{api} 返回错误: {status} - {body}")]
+    Status {
+        api: String,
+        status: reqwest::StatusCode,
+        body: String,
+        /// `Retry-After`（或 `retry-after-ms`）要求的等待时间
+        retry_after: Option<Duration>,
+    },
+    /// 超过读取超时仍没有收到数据
+    #[error("{api} 读取超时：{}s 内没有收到数据", timeout.as_secs())]
+    ReadTimeout { api: String, timeout: Duration },
+    #[error("{api} 请求已取消")]
+    Cancelled { api: String },
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
-        return Err(anyhow::anyhow!(
-            "OpenAI Chat API 返回错误: {} - {}",
-            status,
-            safe_truncate(&error_text, 800)
-        ));
+/// 失败重试策略：第 n 次重试前等待 `base_delay * 2^n`（不超过 `max_delay`），并在后一半区间内随机抖动
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(20),
+        }
     }
+}
 
-    let response_text = response.text().await.context("读取响应体失败")?;
-    let response_preview = crate::redact::redact_secrets(&response_text);
-    tracing::debug!(
-        "OpenAI API 原始响应: {}",
-        safe_truncate(&response_preview, 1000)
-    );
+impl RetryPolicy {
+    /// 不重试
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// 第 `attempt` 次重试（从 0 开始）前的退避时间
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(1u32 << attempt.min(16)).min(self.max_delay);
+        // 等量抖动：[exp/2, exp]，避免多个请求同时重试
+        exp / 2 + exp.mul_f64(random_fraction() / 2.0)
+    }
+}
+
+/// [0, 1) 的随机数（RandomState 每次使用新的随机种子，足够用于抖动）
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let bits = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
 
-    let result: ChatResponse =
-        serde_json::from_str(&response_text).context("解析 OpenAI Chat API 响应失败")?;
+/// 解析 `retry-after-ms`（OpenAI）或 `Retry-After`（秒数或 HTTP 日期）
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(secs.max(0.0)));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
+}
 
-    if result.choices.is_empty() {
-        return Err(anyhow::anyhow!("OpenAI API 返回空选择"));
+async fn error_for_status(response: reqwest::Response, api: &str) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let retry_after = retry_after(response.headers());
+    let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
+    Err(HttpFailure::Status {
+        api: api.to_string(),
+        status,
+        body: safe_truncate(&error_text, 800).to_string(),
+        retry_after,
     }
+    .into())
+}
 
-    let choice = &result.choices[0];
+/// 失败后值得重试的请求：限流、服务端暂时不可用，以及复用的连接被对端关闭
+///
+/// 连接不上（服务没启动、断网）不重试，尽快把错误告诉用户。
+fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(HttpFailure::Status { status, .. }) = error.downcast_ref::<HttpFailure>() {
+        return matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504);
+    }
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .is_some_and(|e| e.is_request() && !e.is_connect() && !e.is_timeout())
+}
 
-    // 尝试从多种可能的字段中提取内容
-    let mut content = choice
-        .message
-        .as_ref()
-        .and_then(|m| m.content.clone())
-        .or_else(|| choice.delta.as_ref().and_then(|d| d.content.clone()))
-        .or_else(|| choice.text.clone())
-        .unwrap_or_default();
+/// 带连接池、读取超时与失败重试的 HTTP 客户端
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    read_timeout: Duration,
+    retry: RetryPolicy,
+    /// 连接失败时附加的提示
+    unreachable_hint: Option<&'static str>,
+}
 
-    // BigModel/部分推理模型：content 可能为空，但 reasoning_content 里包含最终答复草稿
-    if content.trim().is_empty() {
-        if let Some(reasoning) = choice
-            .message
-            .as_ref()
-            .and_then(|m| m.reasoning_content.clone())
-        {
-            tracing::debug!("检测到 reasoning_content，长度: {}", reasoning.len());
-            // 优先尝试智能提取
-            if let Some(extracted) = extract_final_answer_from_reasoning(&reasoning) {
-                tracing::debug!(
-                    "从 reasoning_content 中提取到最终答案，长度: {}",
-                    extracted.len()
-                );
-                content = extracted;
-            } else {
-                // 如果提取失败，尝试找最后一个引号内的内容（通常是最终回答）
-                if let Some(last_quote) = reasoning.rfind('"') {
-                    if let Some(start_quote) = reasoning[..last_quote].rfind('"') {
-                        let candidate = &reasoning[start_quote + 1..last_quote];
-                        if candidate.len() > 10 && candidate.len() < 500 {
-                            content = candidate.trim().to_string();
-                            tracing::debug!("从引号中提取到内容");
-                        }
-                    }
+impl HttpClient {
+    pub fn new(read_timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .build()
+            .unwrap_or_else(|e| {
+                tracing::error!("创建 HTTP 客户端失败，使用默认配置: {}", e);
+                reqwest::Client::new()
+            });
+        Self {
+            client,
+            read_timeout,
+            retry: RetryPolicy::default(),
+            unreachable_hint: None,
+        }
+    }
+
+    pub fn with_unreachable_hint(mut self, hint: &'static str) -> Self {
+        self.unreachable_hint = Some(hint);
+        self
+    }
+
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn post(&self, url: impl reqwest::IntoUrl) -> reqwest::RequestBuilder {
+        self.client.post(url)
+    }
+
+    fn get(&self, url: impl reqwest::IntoUrl) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// 发送请求，返回 2xx 响应；可重试的失败按 [`RetryPolicy`] 重试
+    async fn send(&self, api: &str, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.send_cancellable(api, request, &CancellationToken::new()).await
+    }
+
+    /// 同 [`HttpClient::send`]，`cancel` 被取消时返回 [`HttpFailure::Cancelled`]
+    async fn send_cancellable(
+        &self,
+        api: &str,
+        request: reqwest::RequestBuilder,
+        cancel: &CancellationToken,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            // 请求体无法克隆（流式上传）时只发送一次
+            let Some(current) = request.try_clone() else {
+                return self.send_once(api, request, cancel).await;
+            };
+            let error = match self.send_once(api, current, cancel).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            if attempt >= self.retry.max_retries || !is_retryable(&error) {
+                return Err(error);
+            }
+
+            let delay = match error.downcast_ref::<HttpFailure>() {
+                // 要求等待太久时直接报错，不让界面一直转圈
+                Some(HttpFailure::Status { retry_after: Some(wait), .. }) if *wait > self.retry.max_delay => {
+                    return Err(error)
                 }
-                // 最后的回退：使用最后一段非空内容
-                if content.trim().is_empty() {
-                    let lines: Vec<&str> = reasoning.lines().collect();
-                    for line in lines.iter().rev() {
-                        let trimmed = line.trim();
-                        if trimmed.len() > 10
-                            && !trimmed.starts_with("*")
-                            && !trimmed.starts_with("##")
-                        {
-                            content = trimmed.to_string();
-                            tracing::debug!("使用最后一段作为答案");
-                            break;
-                        }
+                Some(HttpFailure::Status { retry_after: Some(wait), .. }) => *wait,
+                _ => self.retry.backoff(attempt),
+            };
+            attempt += 1;
+            tracing::warn!(
+                "{} 请求失败，{}ms 后重试（{}/{}）: {}",
+                api,
+                delay.as_millis(),
+                attempt,
+                self.retry.max_retries,
+                error
+            );
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(HttpFailure::Cancelled { api: api.to_string() }.into()),
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+
+    /// 发送一次请求，等待响应头的时间受读取超时限制
+    async fn send_once(
+        &self,
+        api: &str,
+        request: reqwest::RequestBuilder,
+        cancel: &CancellationToken,
+    ) -> Result<reqwest::Response> {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(HttpFailure::Cancelled { api: api.to_string() }.into()),
+            result = tokio::time::timeout(self.read_timeout, request.send()) => match result {
+                Ok(Ok(response)) => error_for_status(response, api).await,
+                Ok(Err(e)) => {
+                    let message = match self.unreachable_hint {
+                        Some(hint) => format!("{} 请求失败，{}", api, hint),
+                        None => format!("{} 请求失败", api),
+                    };
+                    Err(anyhow::Error::new(e).context(message))
+                }
+                Err(_) => Err(HttpFailure::ReadTimeout { api: api.to_string(), timeout: self.read_timeout }.into()),
+            },
+        }
+    }
+
+    /// 读取完整响应体（受读取超时限制）
+    async fn text(&self, api: &str, response: reqwest::Response) -> Result<String> {
+        match tokio::time::timeout(self.read_timeout, response.text()).await {
+            Ok(text) => text.with_context(|| format!("读取 {} 响应体失败", api)),
+            Err(_) => Err(HttpFailure::ReadTimeout { api: api.to_string(), timeout: self.read_timeout }.into()),
+        }
+    }
+
+    async fn json<T: serde::de::DeserializeOwned>(&self, api: &str, response: reqwest::Response) -> Result<T> {
+        let text = self.text(api, response).await?;
+        serde_json::from_str(&text).with_context(|| format!("解析 {} 响应失败", api))
+    }
+
+    /// 逐行读取流式响应（去掉两端空白、跳过空行）；`on_line` 返回 `false` 或 `cancel` 被取消时提前结束
+    async fn read_lines(
+        &self,
+        api: &str,
+        mut response: reqwest::Response,
+        cancel: &CancellationToken,
+        mut on_line: impl FnMut(&str) -> bool,
+    ) -> Result<()> {
+        let mut buffer = Vec::new();
+
+        loop {
+            let chunk = tokio::select! {
+                biased;
+                // 丢弃响应即断开连接，服务端随之停止生成
+                _ = cancel.cancelled() => return Ok(()),
+                chunk = tokio::time::timeout(self.read_timeout, response.chunk()) => match chunk {
+                    Ok(chunk) => chunk.with_context(|| format!("读取 {} 流式响应失败", api))?,
+                    Err(_) => {
+                        return Err(HttpFailure::ReadTimeout { api: api.to_string(), timeout: self.read_timeout }.into())
                     }
+                },
+            };
+            let Some(chunk) = chunk else { break };
+            buffer.extend_from_slice(&chunk);
+
+            // 按字节切行，避免多字节字符被拆在两个 chunk 之间时出现乱码
+            while let Some(line_end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=line_end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if !line.is_empty() && !on_line(line) {
+                    return Ok(());
                 }
             }
         }
+
+        // 最后一行可能没有换行符
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim();
+        if !line.is_empty() {
+            on_line(line);
+        }
+        Ok(())
     }
 
-    if content.is_empty() {
-        tracing::warn!("API 返回了空内容，完整响应: {:?}", result);
-        return Err(anyhow::anyhow!("API 返回了空内容，请检查模型配置"));
+    /// 逐行读取 SSE 响应，把每个 `data:` 负载交给 `on_data`；返回 `false` 时提前结束
+    async fn read_sse_data(
+        &self,
+        api: &str,
+        response: reqwest::Response,
+        cancel: &CancellationToken,
+        mut on_data: impl FnMut(&str) -> bool,
+    ) -> Result<()> {
+        self.read_lines(api, response, cancel, |line| match line.strip_prefix("data:") {
+            Some(data) => on_data(data.trim_start()),
+            None => true,
+        })
+        .await
     }
+}
 
-    Ok(content)
+/// 流式请求在收到响应前就被取消时返回 `None`
+fn unless_cancelled(result: Result<reqwest::Response>) -> Result<Option<reqwest::Response>> {
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(e) if matches!(e.downcast_ref::<HttpFailure>(), Some(HttpFailure::Cancelled { .. })) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
-/// 使用 OpenAI API 进行流式对话
-pub async fn chat_with_openai_stream<F>(
-    query: &str,
-    context: &str,
-    model: &str,
-    config: &ProviderConfig,
-    custom_system_prompt: Option<&str>,
-    on_chunk: F,
-) -> Result<String>
-where
-    F: Fn(String) + Send + Sync + 'static,
-{
-    #[derive(Serialize)]
-    struct ChatRequestStream {
-        model: String,
-        messages: Vec<Message>,
-        max_tokens: u32,
-        temperature: f32,
-        stream: bool,
+/// OpenAI 兼容的 `/chat/completions` 接口
+pub struct OpenAiProvider {
+    name: &'static str,
+    model: String,
+    config: ProviderConfig,
+    embedding_model: String,
+    http: HttpClient,
+}
+
+#[derive(Serialize)]
+struct OpenAiRequest<'a> {
+    model: &'a str,
+    messages: Vec<OpenAiMessage<'a>>,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    /// 流式请求默认不返回用量，需要显式要求在最后一个事件里附带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct OpenAiMessage<'a> {
+    role: ChatRole,
+    content: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+impl From<OpenAiUsage> for TokenUsage {
+    fn from(usage: OpenAiUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
     }
+}
 
-    #[derive(Serialize)]
-    struct Message {
-        role: String,
-        content: String,
+impl OpenAiProvider {
+    /// `name` 用于日志与调用记录，区分 OpenAI 与其他兼容服务（如本地模型服务）
+    pub fn new(name: &'static str, model: impl Into<String>, config: ProviderConfig) -> Self {
+        Self {
+            name,
+            model: model.into(),
+            config,
+            embedding_model: "text-embedding-3-small".to_string(),
+            http: HttpClient::new(CLOUD_REQUEST_TIMEOUT),
+        }
     }
 
-    #[derive(Deserialize, Debug)]
-    struct StreamResponse {
-        choices: Vec<StreamChoice>,
+    /// [`LlmProvider::embed`] 使用的向量模型
+    pub fn with_embedding_model(mut self, model: impl Into<String>) -> Self {
+        self.embedding_model = model.into();
+        self
     }
 
-    #[derive(Deserialize, Debug)]
-    struct StreamChoice {
-        delta: StreamDelta,
-        finish_reason: Option<String>,
+    /// 读取超时（本地推理服务需要更长时间）
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.with_read_timeout(timeout);
+        self
     }
 
-    #[derive(Deserialize, Debug)]
-    struct StreamDelta {
-        content: Option<String>,
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.http = self.http.with_retry_policy(retry);
+        self
     }
 
-    // 构建系统提示词
-    let default_system_prompt = if context.is_empty() {
-        "你是桌面活动记录分析助手。直接回答用户的问题，简洁明了。如果用户只是测试，简单确认即可。"
-            .to_string()
-    } else {
-        "你是桌面活动记录分析助手。基于用户提供的桌面活动记录（OCR文本、应用名称等）回答问题。只回答事实，不要解释如何设计系统。".to_string()
-    };
-    
-    let system_prompt = custom_system_prompt
-        .map(|s| s.to_string())
-        .unwrap_or(default_system_prompt);
-
-    // 构建用户消息
-    let user_content = if context.is_empty() {
-        query.to_string()
-    } else {
-        format!("{}\n\n--- 相关桌面活动记录 ---\n{}", query, context)
-    };
+    /// 去掉 `/chat/completions` 后的接口根地址
+    fn api_base(&self) -> &str {
+        let base = self.config.base_url.trim_end_matches('/');
+        base.strip_suffix("/chat/completions").unwrap_or(base)
+    }
 
-    let request = ChatRequestStream {
-        model: model.to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: system_prompt,
-            },
-            Message {
-                role: "user".to_string(),
-                content: user_content,
-            },
-        ],
-        max_tokens: 2000,
-        temperature: 0.7,
-        stream: true,
-    };
+    fn url(&self) -> String {
+        // 智能处理 URL：如果已经包含 /chat/completions 则不再追加
+        format!("{}/chat/completions", self.api_base())
+    }
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .context("创建 HTTP 客户端失败")?;
+    fn authorize(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        // 本地服务通常不需要 Key
+        if self.config.api_key.is_empty() {
+            builder
+        } else {
+            builder.header("Authorization", format!("Bearer {}", self.config.api_key))
+        }
+    }
 
-    let base = config.base_url.trim_end_matches('/');
-    let url = if base.ends_with("/chat/completions") {
-        base.to_string()
-    } else {
-        format!("{}/chat/completions", base)
-    };
+    fn body<'a>(&'a self, request: &'a CompletionRequest, default_max_tokens: u32, stream: bool) -> OpenAiRequest<'a> {
+        let mut messages = Vec::with_capacity(request.messages.len() + 1);
+        if let Some(system) = &request.system {
+            messages.push(OpenAiMessage {
+                role: ChatRole::System,
+                content: system,
+            });
+        }
+        messages.extend(request.messages.iter().map(|m| OpenAiMessage {
+            role: m.role,
+            content: &m.content,
+        }));
 
-    let mut response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("OpenAI Chat API 请求失败")?;
+        let response_format = request
+            .response_schema
+            .as_ref()
+            .filter(|_| self.supports_json_schema())
+            .map(|schema| {
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": schema.name, "schema": schema.schema },
+                })
+            });
+
+        OpenAiRequest {
+            model: &self.model,
+            messages,
+            max_tokens: request.max_tokens.unwrap_or(default_max_tokens),
+            temperature: request.temperature.unwrap_or(0.7),
+            stream,
+            stream_options: stream.then(|| serde_json::json!({ "include_usage": true })),
+            response_format,
+        }
+    }
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
-        return Err(anyhow::anyhow!(
-            "OpenAI Chat API 返回错误: {} - {}",
-            status,
-            safe_truncate(&error_text, 800)
-        ));
+    fn chat_request(&self, body: &OpenAiRequest<'_>) -> reqwest::RequestBuilder {
+        self.authorize(self.http.post(self.url()))
+            .header("Content-Type", "application/json")
+            .json(body)
     }
+}
 
-    let mut full_response = String::new();
-    let mut buffer = String::new();
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        self.name
+    }
 
-    while let Some(chunk) = response.chunk().await? {
-        let chunk_str = String::from_utf8_lossy(&chunk);
-        buffer.push_str(&chunk_str);
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-        while let Some(line_end) = buffer.find('\n') {
-            let line = buffer[..line_end].trim();
-            let line_content = line.to_string(); // copy for processing
-            buffer.drain(..line_end + 1); // remove line + newline
+    /// 兼容接口（DeepSeek、本地服务等）对 `json_schema` 的支持参差不齐，只对官方接口启用
+    fn supports_json_schema(&self) -> bool {
+        self.api_base().starts_with("https://api.openai.com")
+    }
 
-            if line_content.is_empty() {
-                continue;
-            }
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        #[derive(Deserialize, Debug)]
+        struct ChatResponse {
+            choices: Vec<Choice>,
+            usage: Option<OpenAiUsage>,
+        }
 
-            if line_content.starts_with("data: ") {
-                let json_str = &line_content[6..];
-                if json_str == "[DONE]" {
-                    break;
-                }
+        #[derive(Deserialize, Debug)]
+        struct Choice {
+            message: Option<MessageResponse>,
+            // 某些 API 使用 text 字段（旧版 OpenAI 格式）
+            text: Option<String>,
+            // 流式响应中的 delta
+            delta: Option<MessageResponse>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct MessageResponse {
+            // 某些 API 可能返回 null，使用 Option 处理
+            content: Option<String>,
+            // 部分 OpenAI 兼容实现会把“推理/思考”放到该字段
+            reasoning_content: Option<String>,
+        }
 
-                if let Ok(stream_resp) = serde_json::from_str::<StreamResponse>(json_str) {
-                    if let Some(choice) = stream_resp.choices.first() {
-                        if let Some(content) = &choice.delta.content {
-                            if !content.is_empty() {
-                                on_chunk(content.clone());
-                                full_response.push_str(content);
+        tracing::debug!("发送给模型的消息长度: {} 字符", request.content_len());
+        let url = self.url();
+        tracing::info!("OpenAiProvider: 发送请求到 {}", url);
+        let start = std::time::Instant::now();
+
+        let response = self
+            .http
+            .send(OPENAI_CHAT_API, self.chat_request(&self.body(request, 4096, false)))
+            .await?;
+        tracing::info!(
+            "OpenAiProvider: 收到响应, 耗时 {}ms, status={}",
+            start.elapsed().as_millis(),
+            response.status()
+        );
+
+        let response_text = self.http.text(OPENAI_CHAT_API, response).await?;
+        let response_preview = crate::redact::redact_secrets(&response_text);
+        tracing::debug!(
+            "OpenAI API 原始响应: {}",
+            safe_truncate(&response_preview, 1000)
+        );
+
+        let result: ChatResponse =
+            serde_json::from_str(&response_text).context("解析 OpenAI Chat API 响应失败")?;
+
+        let Some(choice) = result.choices.first() else {
+            return Err(anyhow::anyhow!("OpenAI API 返回空选择"));
+        };
+
+        // 尝试从多种可能的字段中提取内容
+        let mut content = choice
+            .message
+            .as_ref()
+            .and_then(|m| m.content.clone())
+            .or_else(|| choice.delta.as_ref().and_then(|d| d.content.clone()))
+            .or_else(|| choice.text.clone())
+            .unwrap_or_default();
+
+        // BigModel/部分推理模型：content 可能为空，但 reasoning_content 里包含最终答复草稿
+        if content.trim().is_empty() {
+            if let Some(reasoning) = choice
+                .message
+                .as_ref()
+                .and_then(|m| m.reasoning_content.clone())
+            {
+                tracing::debug!("检测到 reasoning_content，长度: {}", reasoning.len());
+                // 优先尝试智能提取
+                if let Some(extracted) = extract_final_answer_from_reasoning(&reasoning) {
+                    tracing::debug!(
+                        "从 reasoning_content 中提取到最终答案，长度: {}",
+                        extracted.len()
+                    );
+                    content = extracted;
+                } else {
+                    // 如果提取失败，尝试找最后一个引号内的内容（通常是最终回答）
+                    if let Some(last_quote) = reasoning.rfind('"') {
+                        if let Some(start_quote) = reasoning[..last_quote].rfind('"') {
+                            let candidate = &reasoning[start_quote + 1..last_quote];
+                            if candidate.len() > 10 && candidate.len() < 500 {
+                                content = candidate.trim().to_string();
+                                tracing::debug!("从引号中提取到内容");
                             }
                         }
                     }
-                } else {
-                    tracing::warn!("解析流式响应行失败: {}", safe_truncate(json_str, 100));
+                    // 最后的回退：使用最后一段非空内容
+                    if content.trim().is_empty() {
+                        for line in reasoning.lines().rev() {
+                            let trimmed = line.trim();
+                            if trimmed.len() > 10
+                                && !trimmed.starts_with("*")
+                                && !trimmed.starts_with("##")
+                            {
+                                content = trimmed.to_string();
+                                tracing::debug!("使用最后一段作为答案");
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if content.is_empty() {
+            tracing::warn!("API 返回了空内容，完整响应: {:?}", result);
+            return Err(anyhow::anyhow!("API 返回了空内容，请检查模型配置"));
+        }
+
+        Ok(Completion {
+            text: content,
+            usage: result.usage.map(TokenUsage::from),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion> {
+        #[derive(Deserialize, Debug)]
+        struct StreamResponse {
+            #[serde(default)]
+            choices: Vec<StreamChoice>,
+            usage: Option<OpenAiUsage>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct StreamChoice {
+            delta: StreamDelta,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct StreamDelta {
+            content: Option<String>,
+        }
+
+        let request = self.chat_request(&self.body(request, 2000, true));
+        let Some(response) = unless_cancelled(self.http.send_cancellable(OPENAI_CHAT_API, request, cancel).await)? else {
+            return Ok(Completion::default());
+        };
+
+        let mut completion = Completion::default();
+        self.http.read_sse_data(OPENAI_CHAT_API, response, cancel, |data| {
+            if data == "[DONE]" {
+                return false;
+            }
+            match serde_json::from_str::<StreamResponse>(data) {
+                Ok(event) => {
+                    if let Some(content) = event.choices.first().and_then(|c| c.delta.content.as_ref()) {
+                        if !content.is_empty() {
+                            on_chunk(content.clone());
+                            completion.text.push_str(content);
+                        }
+                    }
+                    // 用量在 choices 为空的最后一个事件里（见 stream_options.include_usage）
+                    if let Some(usage) = event.usage {
+                        completion.usage = Some(usage.into());
+                    }
                 }
+                Err(_) => tracing::warn!("解析流式响应行失败: {}", safe_truncate(data, 100)),
             }
+            true
+        })
+        .await?;
+
+        Ok(completion)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        openai_embeddings(&self.http, texts, &self.embedding_model, &self.config).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        #[derive(Deserialize)]
+        struct ModelsResponse {
+            data: Vec<ModelEntry>,
+        }
+
+        #[derive(Deserialize)]
+        struct ModelEntry {
+            id: String,
         }
+
+        let http = self.http.clone().with_read_timeout(METADATA_REQUEST_TIMEOUT);
+        let request = self.authorize(http.get(format!("{}/models", self.api_base())));
+        let response = http.send("OpenAI Models API", request).await?;
+        let result: ModelsResponse = http.json("OpenAI Models API", response).await?;
+
+        Ok(result
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                size_bytes: None,
+            })
+            .collect())
     }
+}
 
-    Ok(full_response)
+/// Anthropic `/v1/messages` 接口
+pub struct AnthropicProvider {
+    model: String,
+    config: ProviderConfig,
+    http: HttpClient,
 }
 
-/// 使用 Anthropic API 进行对话
-pub async fn chat_with_anthropic(
-    query: &str,
-    context: &str,
-    model: &str,
-    config: &ProviderConfig,
-    custom_system_prompt: Option<&str>,
-) -> Result<String> {
-    #[derive(Serialize)]
-    struct ChatRequest {
-        model: String,
-        max_tokens: u32,
-        messages: Vec<Message>,
-        system: Option<String>,
+#[derive(Serialize)]
+struct AnthropicRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    messages: Vec<OpenAiMessage<'a>>,
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+impl AnthropicProvider {
+    pub fn new(model: impl Into<String>, config: ProviderConfig) -> Self {
+        Self {
+            model: model.into(),
+            config,
+            http: HttpClient::new(CLOUD_REQUEST_TIMEOUT),
+        }
     }
 
-    #[derive(Serialize)]
-    struct Message {
-        role: String,
-        content: String,
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.http = self.http.with_retry_policy(retry);
+        self
     }
 
-    #[derive(Deserialize)]
-    struct ChatResponse {
-        content: Vec<ContentBlock>,
+    fn url(&self) -> String {
+        // 智能处理 URL：如果已经包含 /v1/messages 则不再追加
+        let base = self.config.base_url.trim_end_matches('/');
+        if base.ends_with("/v1/messages") || base.ends_with("/messages") {
+            base.to_string()
+        } else if base.ends_with("/v1") {
+            format!("{}/messages", base)
+        } else {
+            format!("{}/v1/messages", base)
+        }
     }
 
-    #[derive(Deserialize)]
-    struct ContentBlock {
-        text: String,
+    /// Anthropic 的系统提示词是单独的字段，消息中的 system 角色合并进去
+    fn body<'a>(&'a self, request: &'a CompletionRequest, default_max_tokens: u32, stream: bool) -> AnthropicRequest<'a> {
+        let mut system: Vec<&str> = request.system.iter().map(String::as_str).collect();
+        let mut messages = Vec::with_capacity(request.messages.len());
+        for message in &request.messages {
+            match message.role {
+                ChatRole::System => system.push(&message.content),
+                role => messages.push(OpenAiMessage {
+                    role,
+                    content: &message.content,
+                }),
+            }
+        }
+
+        AnthropicRequest {
+            model: &self.model,
+            max_tokens: request.max_tokens.unwrap_or(default_max_tokens),
+            messages,
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            temperature: request.temperature,
+            stream,
+        }
     }
 
-    // 构建系统提示词
-    let default_system_prompt = if context.is_empty() {
-        "你是桌面活动记录分析助手。直接回答用户的问题，简洁明了。如果用户只是测试，简单确认即可。"
-            .to_string()
-    } else {
-        "你是桌面活动记录分析助手。基于用户提供的桌面活动记录（OCR文本、应用名称等）回答问题。只回答事实，不要解释如何设计系统。".to_string()
-    };
-    
-    let system_prompt = custom_system_prompt
-        .map(|s| s.to_string())
-        .unwrap_or(default_system_prompt);
-
-    // 构建用户消息
-    let user_content = if context.is_empty() {
-        query.to_string()
-    } else {
-        format!("{}\n\n--- 相关桌面活动记录 ---\n{}", query, context)
-    };
+    fn chat_request(&self, body: &AnthropicRequest<'_>) -> reqwest::RequestBuilder {
+        self.http
+            .post(self.url())
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(body)
+    }
+}
 
-    tracing::debug!("发送给 Anthropic 的上下文长度: {} 字符", context.len());
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "Anthropic"
+    }
 
-    let request = ChatRequest {
-        model: model.to_string(),
-        max_tokens: 2000,
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: user_content,
-        }],
-        system: Some(system_prompt),
-    };
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .context("创建 HTTP 客户端失败")?;
-    // 智能处理 URL：如果已经包含 /v1/messages 则不再追加
-    let base = config.base_url.trim_end_matches('/');
-    let url = if base.ends_with("/v1/messages") || base.ends_with("/messages") {
-        base.to_string()
-    } else if base.ends_with("/v1") {
-        format!("{}/messages", base)
-    } else {
-        format!("{}/v1/messages", base)
-    };
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        #[derive(Deserialize)]
+        struct ChatResponse {
+            content: Vec<ContentBlock>,
+            #[serde(default)]
+            usage: Option<AnthropicUsage>,
+        }
 
-    let response = client
-        .post(&url)
-        .header("x-api-key", &config.api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("Anthropic Chat API 请求失败")?;
+        #[derive(Deserialize)]
+        struct ContentBlock {
+            #[serde(default)]
+            text: String,
+        }
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
-        return Err(anyhow::anyhow!(
-            "Anthropic Chat API 返回错误: {} - {}",
-            status,
-            safe_truncate(&error_text, 800)
-        ));
+        tracing::debug!("发送给 Anthropic 的消息长度: {} 字符", request.content_len());
+
+        let response = self
+            .http
+            .send(ANTHROPIC_CHAT_API, self.chat_request(&self.body(request, 2000, false)))
+            .await?;
+        let result: ChatResponse = self.http.json(ANTHROPIC_CHAT_API, response).await?;
+
+        let Some(block) = result.content.into_iter().next() else {
+            return Err(anyhow::anyhow!("Anthropic API 返回空内容"));
+        };
+
+        Ok(Completion {
+            text: block.text,
+            usage: result.usage.map(|u| TokenUsage {
+                prompt_tokens: u.input_tokens,
+                completion_tokens: u.output_tokens,
+            }),
+        })
     }
 
-    let result: ChatResponse = response
-        .json()
-        .await
-        .context("解析 Anthropic Chat API 响应失败")?;
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion> {
+        // 这些结构体用于解析 SSE 事件数据
+        #[derive(Deserialize, Debug)]
+        struct StreamEvent {
+            #[serde(rename = "type")]
+            event_type: String,
+            delta: Option<StreamDelta>,
+            message: Option<StreamMessage>,
+            usage: Option<AnthropicUsage>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct StreamDelta {
+            #[serde(rename = "type", default)]
+            delta_type: String,
+            text: Option<String>,
+        }
 
-    if result.content.is_empty() {
-        return Err(anyhow::anyhow!("Anthropic API 返回空内容"));
+        #[derive(Deserialize, Debug)]
+        struct StreamMessage {
+            usage: Option<AnthropicUsage>,
+        }
+
+        let request = self.chat_request(&self.body(request, 4096, true));
+        let Some(response) = unless_cancelled(self.http.send_cancellable(ANTHROPIC_CHAT_API, request, cancel).await)? else {
+            return Ok(Completion::default());
+        };
+
+        let mut completion = Completion::default();
+        let mut usage = AnthropicUsage::default();
+        self.http.read_sse_data(ANTHROPIC_CHAT_API, response, cancel, |data| {
+            // Anthropic SSE 结束事件通常是 event: message_stop，这里逐个解析 event data
+            let Ok(event) = serde_json::from_str::<StreamEvent>(data) else {
+                return true;
+            };
+            match event.event_type.as_str() {
+                "content_block_delta" => {
+                    if let Some(text) = event.delta.filter(|d| d.delta_type == "text_delta").and_then(|d| d.text) {
+                        on_chunk(text.clone());
+                        completion.text.push_str(&text);
+                    }
+                }
+                "message_start" => {
+                    if let Some(u) = event.message.and_then(|m| m.usage) {
+                        usage.input_tokens = u.input_tokens;
+                    }
+                }
+                "message_delta" => {
+                    if let Some(u) = event.usage {
+                        usage.output_tokens = u.output_tokens;
+                        completion.usage = Some(TokenUsage {
+                            prompt_tokens: usage.input_tokens,
+                            completion_tokens: usage.output_tokens,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            true
+        })
+        .await?;
+
+        Ok(completion)
     }
+}
 
-    Ok(result.content[0].text.clone())
+/// 本机 Ollama 服务（`/api/chat`、`/api/embed`、`/api/tags`）
+pub struct OllamaProvider {
+    model: String,
+    base_url: String,
+    embedding_model: String,
+    http: HttpClient,
 }
 
-/// 使用 Anthropic API 进行流式对话
-pub async fn chat_with_anthropic_stream<F>(
-    query: &str,
-    context: &str,
-    model: &str,
-    config: &ProviderConfig,
-    custom_system_prompt: Option<&str>,
-    on_chunk: F,
-) -> Result<String>
-where
-    F: Fn(String) + Send + Sync + 'static,
-{
-    #[derive(Serialize)]
-    struct ChatRequestStream {
-        model: String,
-        max_tokens: u32,
-        messages: Vec<Message>,
-        system: Option<String>,
-        stream: bool,
+#[derive(Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<OpenAiMessage<'a>>,
+    stream: bool,
+    options: OllamaOptions,
+    /// JSON Schema 约束（Ollama 0.5+）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+/// `/api/chat` 的响应；流式时每行一个，最后一行 `done` 为 true 并带有用量
+#[derive(Deserialize)]
+struct OllamaChatResponse {
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+}
+
+impl OllamaChatResponse {
+    fn usage(&self) -> Option<TokenUsage> {
+        (self.prompt_eval_count.is_some() || self.eval_count.is_some()).then(|| TokenUsage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or(0),
+            completion_tokens: self.eval_count.unwrap_or(0),
+        })
     }
+}
 
-    #[derive(Serialize)]
-    struct Message {
-        role: String,
-        content: String,
+impl OllamaProvider {
+    pub fn new(model: impl Into<String>, base_url: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            base_url: base_url.into(),
+            embedding_model: "nomic-embed-text".to_string(),
+            http: HttpClient::new(LOCAL_REQUEST_TIMEOUT).with_unreachable_hint("请确认 Ollama 已启动"),
+        }
     }
 
-    // 这些结构体用于解析 SSE 事件数据
-    #[derive(Deserialize, Debug)]
-    struct StreamEvent {
-        #[serde(rename = "type")]
-        event_type: String,
-        delta: Option<StreamDelta>,
+    /// [`LlmProvider::embed`] 使用的向量模型
+    pub fn with_embedding_model(mut self, model: impl Into<String>) -> Self {
+        self.embedding_model = model.into();
+        self
     }
 
-    #[derive(Deserialize, Debug)]
-    struct StreamDelta {
-        #[serde(rename = "type")]
-        delta_type: String,
-        text: Option<String>,
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.with_read_timeout(timeout);
+        self
     }
 
-    // 构建系统提示词
-    let default_system_prompt = if context.is_empty() {
-        "你是桌面活动记录分析助手。直接回答用户的问题，简洁明了。如果用户只是测试，简单确认即可。"
-            .to_string()
-    } else {
-        "你是桌面活动记录分析助手。基于用户提供的桌面活动记录（OCR文本、应用名称等）回答问题。只回答事实，不要解释如何设计系统。".to_string()
-    };
-    
-    let system_prompt = custom_system_prompt
-        .map(|s| s.to_string())
-        .unwrap_or(default_system_prompt);
-
-    // 构建用户消息
-    let user_content = if context.is_empty() {
-        query.to_string()
-    } else {
-        format!("{}\n\n--- 相关桌面活动记录 ---\n{}", query, context)
-    };
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.http = self.http.with_retry_policy(retry);
+        self
+    }
 
-    let request = ChatRequestStream {
-        model: model.to_string(),
-        max_tokens: 4096,
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: user_content,
-        }],
-        system: Some(system_prompt),
-        stream: true,
-    };
+    fn endpoint(&self, path: &str) -> String {
+        let base = self.base_url.trim_end_matches('/');
+        let base = base.strip_suffix("/api").unwrap_or(base);
+        format!("{}/api/{}", base, path)
+    }
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .context("创建 HTTP 客户端失败")?;
+    fn chat_request(&self, request: &CompletionRequest, stream: bool) -> reqwest::RequestBuilder {
+        let mut messages = Vec::with_capacity(request.messages.len() + 1);
+        if let Some(system) = &request.system {
+            messages.push(OpenAiMessage {
+                role: ChatRole::System,
+                content: system,
+            });
+        }
+        messages.extend(request.messages.iter().map(|m| OpenAiMessage {
+            role: m.role,
+            content: &m.content,
+        }));
+        let body = OllamaChatRequest {
+            model: &self.model,
+            messages,
+            stream,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                num_predict: request.max_tokens,
+            },
+            format: request.response_schema.as_ref().map(|schema| &schema.schema),
+        };
 
-    // 智能处理 URL
-    let base = config.base_url.trim_end_matches('/');
-    let url = if base.ends_with("/v1/messages") || base.ends_with("/messages") {
-        base.to_string()
-    } else if base.ends_with("/v1") {
-        format!("{}/messages", base)
-    } else {
-        format!("{}/v1/messages", base)
-    };
+        self.http.post(self.endpoint("chat")).json(&body)
+    }
+}
 
-    let mut response = client
-        .post(&url)
-        .header("x-api-key", &config.api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("Anthropic Chat API 请求失败")?;
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "Ollama"
+    }
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
-        return Err(anyhow::anyhow!(
-            "Anthropic Chat API 返回错误: {} - {}",
-            status,
-            safe_truncate(&error_text, 800)
-        ));
+    fn model(&self) -> &str {
+        &self.model
     }
 
-    let mut full_response = String::new();
-    let mut buffer = String::new();
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
 
-    while let Some(chunk) = response.chunk().await? {
-        let chunk_str = String::from_utf8_lossy(&chunk);
-        buffer.push_str(&chunk_str);
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        tracing::debug!("发送给 Ollama 的消息长度: {} 字符", request.content_len());
+        let response = self.http.send(OLLAMA_API, self.chat_request(request, false)).await?;
+        let result: OllamaChatResponse = self.http.json(OLLAMA_API, response).await?;
+        if let Some(error) = result.error {
+            return Err(anyhow::anyhow!("Ollama 返回错误: {}", error));
+        }
 
-        while let Some(line_end) = buffer.find('\n') {
-            let line = buffer[..line_end].trim();
-            let line_content = line.to_string(); 
-            buffer.drain(..line_end + 1);
+        let usage = result.usage();
+        let text = result.message.map(|m| m.content).unwrap_or_default();
+        if text.is_empty() {
+            return Err(anyhow::anyhow!("Ollama 返回了空内容，请检查模型配置"));
+        }
+        Ok(Completion { text, usage })
+    }
 
-            if line_content.is_empty() {
-                continue;
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion> {
+        let request = self.chat_request(request, true);
+        let Some(response) = unless_cancelled(self.http.send_cancellable(OLLAMA_API, request, cancel).await)? else {
+            return Ok(Completion::default());
+        };
+
+        let mut completion = Completion::default();
+        let mut error = None;
+        self.http.read_lines(OLLAMA_API, response, cancel, |line| {
+            let Ok(event) = serde_json::from_str::<OllamaChatResponse>(line) else {
+                tracing::warn!("解析 Ollama 流式响应行失败: {}", safe_truncate(line, 100));
+                return true;
+            };
+            if let Some(e) = event.error {
+                error = Some(e);
+                return false;
+            }
+            if let Some(content) = event.message.as_ref().map(|m| &m.content).filter(|c| !c.is_empty()) {
+                on_chunk(content.clone());
+                completion.text.push_str(content);
+            }
+            if event.done {
+                completion.usage = event.usage();
             }
+            !event.done
+        })
+        .await?;
 
-            if line_content.starts_with("data: ") {
-                let json_str = &line_content[6..];
-                // Anthropic SSE 结束事件通常是 event: message_stop，data 里可能不包含 update
-                // 这里我们解析每个 event data
-                
-                if let Ok(event) = serde_json::from_str::<StreamEvent>(json_str) {
-                     if event.event_type == "content_block_delta" {
-                         if let Some(delta) = event.delta {
-                             if delta.delta_type == "text_delta" {
-                                 if let Some(text) = delta.text {
-                                     on_chunk(text.clone());
-                                     full_response.push_str(&text);
-                                 }
-                             }
-                         }
-                     }
-                }
+        match error {
+            Some(e) => Err(anyhow::anyhow!("Ollama 返回错误: {}", e)),
+            None => Ok(completion),
+        }
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        #[derive(Serialize)]
+        struct EmbedRequest<'a> {
+            model: &'a str,
+            input: &'a [String],
+        }
+
+        #[derive(Deserialize)]
+        struct EmbedResponse {
+            embeddings: Vec<Vec<f32>>,
+        }
+
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let request = self.http.post(self.endpoint("embed")).json(&EmbedRequest {
+            model: &self.embedding_model,
+            input: texts,
+        });
+        let response = self.http.send("Ollama Embed API", request).await?;
+        let result: EmbedResponse = self.http.json("Ollama Embed API", response).await?;
+
+        if result.embeddings.len() != texts.len() {
+            return Err(anyhow::anyhow!(
+                "Ollama 返回的向量数量不匹配: 期望 {}，实际 {}",
+                texts.len(),
+                result.embeddings.len()
+            ));
+        }
+        Ok(result.embeddings)
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        #[derive(Deserialize)]
+        struct TagsResponse {
+            #[serde(default)]
+            models: Vec<TagEntry>,
+        }
+
+        #[derive(Deserialize)]
+        struct TagEntry {
+            name: String,
+            size: Option<u64>,
+        }
+
+        let http = self.http.clone().with_read_timeout(METADATA_REQUEST_TIMEOUT);
+        let response = http.send(OLLAMA_API, http.get(self.endpoint("tags"))).await?;
+        let result: TagsResponse = http.json(OLLAMA_API, response).await?;
+
+        Ok(result
+            .models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.name,
+                size_bytes: m.size,
+            })
+            .collect())
+    }
+}
+
+/// 按脚本返回预设回复的 provider，用于测试依赖 LLM 的流程
+///
+/// 回复按入队顺序依次返回；队列为空时回显最后一条用户消息。所有请求都会被记录，
+/// 便于断言提示词与上下文。
+pub struct MockLlmProvider {
+    model: String,
+    json_schema: bool,
+    responses: Mutex<VecDeque<Result<String, String>>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl MockLlmProvider {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            json_schema: false,
+            responses: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 模拟支持 JSON Schema 约束输出的提供商
+    pub fn with_json_schema_support(mut self) -> Self {
+        self.json_schema = true;
+        self
+    }
+
+    pub fn with_responses<I, S>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let provider = Self::new("mock");
+        for response in responses {
+            provider.push_response(response);
+        }
+        provider
+    }
+
+    pub fn push_response(&self, text: impl Into<String>) {
+        self.responses.lock().unwrap().push_back(Ok(text.into()));
+    }
+
+    /// 下一次调用返回错误
+    pub fn push_error(&self, message: impl Into<String>) {
+        self.responses.lock().unwrap().push_back(Err(message.into()));
+    }
+
+    /// 到目前为止收到的请求
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_response(&self, request: &CompletionRequest) -> Result<Completion> {
+        self.requests.lock().unwrap().push(request.clone());
+        let scripted = self.responses.lock().unwrap().pop_front();
+        let text = match scripted {
+            Some(Ok(text)) => text,
+            Some(Err(message)) => return Err(anyhow::anyhow!(message)),
+            None => request
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == ChatRole::User)
+                .map(|m| format!("mock: {}", m.content))
+                .unwrap_or_default(),
+        };
+
+        let usage = TokenUsage {
+            prompt_tokens: request.content_len().div_ceil(4) as u32,
+            completion_tokens: text.len().div_ceil(4) as u32,
+        };
+        Ok(Completion {
+            text,
+            usage: Some(usage),
+        })
+    }
+}
+
+impl Default for MockLlmProvider {
+    fn default() -> Self {
+        Self::new("mock")
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockLlmProvider {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        self.json_schema
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        self.next_response(request)
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion> {
+        let completion = self.next_response(request)?;
+        let chars: Vec<char> = completion.text.chars().collect();
+        // 取消后不再输出，只返回已输出的部分
+        let mut streamed = String::new();
+        for piece in chars.chunks(4) {
+            if cancel.is_cancelled() {
+                break;
             }
+            let piece: String = piece.iter().collect();
+            streamed.push_str(&piece);
+            on_chunk(piece);
         }
+        Ok(Completion {
+            text: streamed,
+            ..completion
+        })
     }
 
-    Ok(full_response)
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let stub = crate::ai::embedding::StubEmbeddingProvider::default();
+        Ok(texts.iter().map(|t| stub.embed_text(t).vector).collect())
+    }
 }
 
 /// 使用 OpenAI API 生成嵌入向量
@@ -729,10 +1732,33 @@ pub async fn embedding_with_openai(
     model: &str,
     config: &ProviderConfig,
 ) -> Result<Vec<f32>> {
+    let mut embeddings = embeddings_with_openai(&[text.to_string()], model, config).await?;
+    Ok(embeddings.swap_remove(0))
+}
+
+/// 不经过 provider 生成向量时共用的客户端
+static OPENAI_EMBEDDINGS_HTTP: once_cell::sync::Lazy<HttpClient> =
+    once_cell::sync::Lazy::new(|| HttpClient::new(CLOUD_REQUEST_TIMEOUT));
+
+/// 使用 OpenAI API 批量生成嵌入向量，返回顺序与输入一致
+pub async fn embeddings_with_openai(
+    texts: &[String],
+    model: &str,
+    config: &ProviderConfig,
+) -> Result<Vec<Vec<f32>>> {
+    openai_embeddings(&OPENAI_EMBEDDINGS_HTTP, texts, model, config).await
+}
+
+async fn openai_embeddings(
+    http: &HttpClient,
+    texts: &[String],
+    model: &str,
+    config: &ProviderConfig,
+) -> Result<Vec<Vec<f32>>> {
     #[derive(Serialize)]
-    struct EmbeddingRequest {
-        model: String,
-        input: String,
+    struct EmbeddingRequest<'a> {
+        model: &'a str,
+        input: &'a [String],
     }
 
     #[derive(Deserialize)]
@@ -742,17 +1768,16 @@ pub async fn embedding_with_openai(
 
     #[derive(Deserialize)]
     struct EmbeddingData {
+        #[serde(default)]
+        index: usize,
         embedding: Vec<f32>,
     }
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(300))
-        .build()
-        .context("创建 HTTP 客户端失败")?;
-    let request = EmbeddingRequest {
-        model: model.to_string(),
-        input: text.to_string(),
-    };
+    const API: &str = "OpenAI Embeddings API";
+
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
 
     // 智能处理 URL：如果已经包含 /embeddings 则不再追加
     let base = config.base_url.trim_end_matches('/');
@@ -762,33 +1787,390 @@ pub async fn embedding_with_openai(
         format!("{}/embeddings", base)
     };
 
-    let response = client
+    let request = http
         .post(&url)
         .header("Authorization", format!("Bearer {}", config.api_key))
         .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .context("OpenAI Embeddings API 请求失败")?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = crate::redact::redact_secrets(&response.text().await.unwrap_or_default());
+        .json(&EmbeddingRequest {
+            model,
+            input: texts,
+        });
+    let response = http.send(API, request).await?;
+    let mut result: EmbeddingResponse = http.json(API, response).await?;
+
+    if result.data.len() != texts.len() {
         return Err(anyhow::anyhow!(
-            "OpenAI Embeddings API 返回错误: {} - {}",
-            status,
-            safe_truncate(&error_text, 800)
+            "OpenAI API 返回的向量数量不匹配: 期望 {}，实际 {}",
+            texts.len(),
+            result.data.len()
         ));
     }
 
-    let result: EmbeddingResponse = response
-        .json()
-        .await
-        .context("解析 OpenAI Embeddings API 响应失败")?;
+    result.data.sort_by_key(|d| d.index);
+    Ok(result.data.into_iter().map(|d| d.embedding).collect())
+}
 
-    if result.data.is_empty() {
-        return Err(anyhow::anyhow!("OpenAI API 返回空数据"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_provider_from_config() {
+        let config = LlmConfig::resolve("auto", "claude-3-5-sonnet-latest").unwrap();
+        assert_eq!(config.provider, LlmProviderKind::Anthropic);
+        assert_eq!(LlmConfig::resolve("", "gpt-4o-mini").unwrap().provider, LlmProviderKind::OpenAi);
+        // 显式指定时不再按模型名推断
+        assert_eq!(LlmConfig::resolve("openai", "claude-proxy").unwrap().provider, LlmProviderKind::OpenAi);
+        assert_eq!(LlmConfig::resolve(" Mock ", "x").unwrap().provider, LlmProviderKind::Mock);
+        assert!(LlmConfig::resolve("gemini", "x").is_err());
+
+        let err = create_llm_provider(&LlmConfig::resolve("anthropic", "claude-3").unwrap()).err().unwrap();
+        assert!(err.to_string().contains("Anthropic API Key"));
+        assert!(!format!("{:?}", LlmConfig { api_key: Some("sk-secret".into()), ..Default::default() }).contains("sk-secret"));
     }
 
-    Ok(result.data[0].embedding.clone())
+    #[tokio::test]
+    async fn shared_providers_are_cached_per_config() {
+        let config = LlmConfig::resolve("mock", "mock-a").unwrap();
+        let a = shared_llm_provider(&config).await.unwrap();
+        let b = shared_llm_provider(&config).await.unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.model(), "mock-a");
+
+        let other = shared_llm_provider(&LlmConfig::resolve("mock", "mock-b").unwrap()).await.unwrap();
+        assert_eq!(other.model(), "mock-b");
+    }
+
+    #[tokio::test]
+    async fn mock_provider_scripts_and_streams() {
+        let provider = MockLlmProvider::with_responses(["第一条回复", "second"]);
+        provider.push_error("boom");
+
+        let request = CompletionRequest::from_query("问题", "上下文", None);
+        assert_eq!(provider.complete(&request).await.unwrap().text, "第一条回复");
+
+        let chunks = Mutex::new(Vec::new());
+        let completion = provider
+            .stream(&request, &|chunk| chunks.lock().unwrap().push(chunk), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(completion.text, "second");
+        assert_eq!(chunks.into_inner().unwrap(), vec!["seco", "nd"]);
+        assert!(completion.usage.is_some());
+
+        assert!(provider.complete(&request).await.is_err());
+        // 脚本用完后回显最后一条用户消息
+        let echoed = provider.complete(&CompletionRequest::from_query("ping", "", None)).await.unwrap();
+        assert_eq!(echoed.text, "mock: ping");
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].messages[0].content, "问题\n\n--- 相关桌面活动记录 ---\n上下文");
+        assert_eq!(provider.embed(&["a b".to_string()]).await.unwrap().len(), 1);
+    }
+
+    /// 本地 HTTP 桩收到的请求
+    struct StubRequest {
+        head: String,
+        body: String,
+    }
+
+    /// 读取一个完整的 HTTP 请求（请求头 + Content-Length 指定的请求体）
+    async fn read_stub_request(socket: &mut tokio::net::TcpStream) -> StubRequest {
+        use tokio::io::AsyncReadExt;
+
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let head_end = loop {
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "连接提前关闭");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+        let content_length = head
+            .lines()
+            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+            .unwrap_or(0usize);
+        while buf.len() < head_end + content_length {
+            let n = socket.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        let body = String::from_utf8_lossy(&buf[head_end..]).to_string();
+        StubRequest { head, body }
+    }
+
+    /// 起一个只处理 `Connection: close` 请求的本地 HTTP 桩；每条路由按顺序使用一次，未匹配返回 404
+    async fn stub_server(routes: Vec<(&'static str, &'static str)>) -> (String, Arc<Mutex<Vec<StubRequest>>>) {
+        stub_server_with(routes.into_iter().map(|(path, payload)| (path, "200 OK", payload)).collect()).await
+    }
+
+    /// 同 [`stub_server`]，路由可指定状态行与额外的响应头（如 `"429 Too Many Requests\r\nRetry-After: 0"`）
+    async fn stub_server_with(
+        routes: Vec<(&'static str, &'static str, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<StubRequest>>>) {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+
+        tokio::spawn(async move {
+            let mut routes = routes;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_stub_request(&mut socket).await;
+                let path = request.head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let (status, payload) = match routes.iter().position(|(p, _, _)| *p == path) {
+                    Some(i) => {
+                        let (_, status, payload) = routes.remove(i);
+                        (status, payload)
+                    }
+                    None => ("404 Not Found", "{\"error\":\"not found\"}"),
+                };
+                log.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    payload.len(),
+                    payload
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (base_url, seen)
+    }
+
+    /// 返回一段 SSE 数据后不再发送、也不关闭连接的桩（模拟生成很慢或卡住的服务）
+    async fn stalled_sse_server(first_event: &'static str) -> String {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    read_stub_request(&mut socket).await;
+                    let response = format!("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\ndata: {}\n\n", first_event);
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                });
+            }
+        });
+        base_url
+    }
+
+    #[tokio::test]
+    async fn ollama_provider_talks_to_local_server() {
+        let (base_url, seen) = stub_server(vec![
+            (
+                "/api/chat",
+                r#"{"model":"qwen2.5","message":{"role":"assistant","content":"本地回复"},"done":true,"prompt_eval_count":21,"eval_count":4}"#,
+            ),
+            (
+                "/api/chat",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"你好\"},\"done\":false}\n{\"message\":{\"role\":\"assistant\",\"content\":\"，世界\"},\"done\":false}\n{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":9,\"eval_count\":2}\n",
+            ),
+            ("/api/embed", r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2],[0.3,0.4]]}"#),
+            (
+                "/api/tags",
+                r#"{"models":[{"name":"qwen2.5:7b","size":4683087332},{"name":"nomic-embed-text:latest"}]}"#,
+            ),
+        ])
+        .await;
+
+        let config = LlmConfig {
+            provider: LlmProviderKind::Ollama,
+            model: "qwen2.5:7b".to_string(),
+            base_url: Some(format!("{}/", base_url)),
+            api_key: None,
+        };
+        let provider = create_llm_provider(&config).unwrap();
+        assert_eq!(provider.name(), "Ollama");
+
+        let request = CompletionRequest::from_query("问题", "", Some("系统提示")).max_tokens(128);
+        let completion = provider.complete(&request).await.unwrap();
+        assert_eq!(completion.text, "本地回复");
+        assert_eq!(completion.usage, Some(TokenUsage { prompt_tokens: 21, completion_tokens: 4 }));
+
+        let chunks = Mutex::new(Vec::new());
+        let streamed = provider
+            .stream(&request, &|chunk| chunks.lock().unwrap().push(chunk), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(chunks.into_inner().unwrap(), vec!["你好", "，世界"]);
+        assert_eq!(streamed.text, "你好，世界");
+        assert_eq!(streamed.usage, Some(TokenUsage { prompt_tokens: 9, completion_tokens: 2 }));
+
+        let vectors = provider.embed(&["a".to_string(), "b".to_string()]).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+
+        let models = list_models(&config).await.unwrap();
+        assert_eq!(
+            models,
+            vec![
+                ModelInfo { id: "qwen2.5:7b".to_string(), size_bytes: Some(4683087332) },
+                ModelInfo { id: "nomic-embed-text:latest".to_string(), size_bytes: None },
+            ]
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 4);
+        let chat: serde_json::Value = serde_json::from_str(&seen[0].body).unwrap();
+        assert_eq!(chat["stream"], false);
+        assert_eq!(chat["options"]["num_predict"], 128);
+        assert_eq!(chat["messages"][0]["role"], "system");
+        assert_eq!(chat["messages"][0]["content"], "系统提示");
+        let stream: serde_json::Value = serde_json::from_str(&seen[1].body).unwrap();
+        assert_eq!(stream["stream"], true);
+        let embed: serde_json::Value = serde_json::from_str(&seen[2].body).unwrap();
+        assert_eq!(embed["model"], "nomic-embed-text");
+        assert!(seen[3].head.starts_with("GET /api/tags"));
+    }
+
+    #[tokio::test]
+    async fn local_openai_compatible_server_needs_no_key() {
+        let (base_url, seen) = stub_server(vec![
+            (
+                "/v1/chat/completions",
+                r#"{"choices":[{"message":{"role":"assistant","content":"llama 回复"}}],"usage":{"prompt_tokens":5,"completion_tokens":2}}"#,
+            ),
+            ("/v1/models", r#"{"object":"list","data":[{"id":"qwen2.5-7b-instruct-q4_k_m.gguf","object":"model"}]}"#),
+            (
+                "/v1/chat/completions",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"}}],\"usage\":null}\n\n\
+                 data: {\"choices\":[{\"delta\":{\"content\":\"，世界\"}}],\"usage\":null}\n\n\
+                 data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":3}}\n\n\
+                 data: [DONE]\n\n",
+            ),
+        ])
+        .await;
+
+        let config = LlmConfig {
+            provider: LlmProviderKind::Local,
+            model: "qwen2.5-7b-instruct".to_string(),
+            base_url: Some(format!("{}/v1", base_url)),
+            api_key: None,
+        };
+        let provider = create_llm_provider(&config).unwrap();
+        assert_eq!(provider.name(), LlmProviderKind::Local.display_name());
+        let request = CompletionRequest::from_query("hi", "", None);
+        let completion = provider.complete(&request).await.unwrap();
+        assert_eq!(completion.text, "llama 回复");
+
+        let models = list_models(&config).await.unwrap();
+        assert_eq!(models[0].id, "qwen2.5-7b-instruct-q4_k_m.gguf");
+
+        // 流式回答也要拿到最后一个事件里的用量
+        let streamed = provider.stream(&request, &|_| {}, &CancellationToken::new()).await.unwrap();
+        assert_eq!(streamed.text, "你好，世界");
+        assert_eq!(streamed.usage, Some(TokenUsage { prompt_tokens: 7, completion_tokens: 3 }));
+
+        let seen = seen.lock().unwrap();
+        assert!(seen.iter().all(|r| !r.head.to_ascii_lowercase().contains("authorization")));
+        assert!(seen[1].head.starts_with("GET /v1/models"));
+        let chat: serde_json::Value = serde_json::from_str(&seen[0].body).unwrap();
+        assert!(chat.get("stream_options").is_none());
+        let stream: serde_json::Value = serde_json::from_str(&seen[2].body).unwrap();
+        assert_eq!(stream["stream"], true);
+        assert_eq!(stream["stream_options"]["include_usage"], true);
+    }
+
+    #[tokio::test]
+    async fn unreachable_ollama_reports_hint() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let provider = OllamaProvider::new("qwen2.5", base_url);
+        let err = provider.list_models().await.unwrap_err();
+        assert!(err.to_string().contains("请确认 Ollama 已启动"));
+        assert!(LlmProviderKind::Ollama.is_local() && LlmProviderKind::Ollama.key_service().is_none());
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors_with_backoff() {
+        const CHAT: &str = "/v1/chat/completions";
+        let (base_url, seen) = stub_server_with(vec![
+            (CHAT, "429 Too Many Requests\r\nRetry-After: 0", r#"{"error":"slow down"}"#),
+            (CHAT, "503 Service Unavailable", r#"{"error":"overloaded"}"#),
+            (CHAT, "200 OK", r#"{"choices":[{"message":{"role":"assistant","content":"重试成功"}}]}"#),
+            (CHAT, "400 Bad Request", r#"{"error":"bad request"}"#),
+            (CHAT, "429 Too Many Requests\r\nRetry-After: 3600", r#"{"error":"quota"}"#),
+        ])
+        .await;
+
+        let retry = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_secs(1),
+        };
+        let config = ProviderConfig::new(String::new(), Some(format!("{}/v1", base_url)), "");
+        let provider = OpenAiProvider::new("OpenAI", "gpt-4o-mini", config).with_retry_policy(retry);
+        let request = CompletionRequest::from_query("hi", "", None);
+
+        assert_eq!(provider.complete(&request).await.unwrap().text, "重试成功");
+        assert_eq!(seen.lock().unwrap().len(), 3);
+
+        // 客户端错误不重试；要求等待超过上限时直接报错
+        let err = provider.complete(&request).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(HttpFailure::Status { status, .. }) if status.as_u16() == 400));
+        let err = provider.complete(&request).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(HttpFailure::Status { retry_after: Some(wait), .. }) if *wait == Duration::from_secs(3600)
+        ));
+        assert!(err.to_string().starts_with("OpenAI Chat API 返回错误: 429 Too Many Requests"));
+        assert_eq!(seen.lock().unwrap().len(), 5);
+
+        let policy = RetryPolicy::default();
+        for attempt in 0..8 {
+            let exp = policy.base_delay.saturating_mul(1 << attempt).min(policy.max_delay);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= exp / 2 && delay <= exp, "attempt {}: {:?}", attempt, delay);
+        }
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(1500)));
+    }
+
+    #[tokio::test]
+    async fn streaming_stops_on_cancel_and_read_timeout() {
+        let base_url = stalled_sse_server(r#"{"choices":[{"delta":{"content":"部分回答"}}]}"#).await;
+        let config = ProviderConfig::new(String::new(), Some(base_url), "");
+        let request = CompletionRequest::from_query("hi", "", None);
+
+        // 收到第一段后取消：立即返回已生成的部分
+        let provider = OpenAiProvider::new("OpenAI", "gpt-4o-mini", config.clone());
+        let cancel = CancellationToken::new();
+        let streamed = tokio::time::timeout(
+            Duration::from_secs(5),
+            provider.stream(&request, &|_| cancel.cancel(), &cancel),
+        )
+        .await
+        .expect("取消后应立即返回")
+        .unwrap();
+        assert_eq!(streamed.text, "部分回答");
+
+        // 超过读取超时没有新数据时报错
+        let provider = OpenAiProvider::new("OpenAI", "gpt-4o-mini", config).with_timeout(Duration::from_millis(200));
+        let err = provider.stream(&request, &|_| {}, &CancellationToken::new()).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(HttpFailure::ReadTimeout { .. })));
+
+        // 发出请求前已取消时不发送
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        let mock = MockLlmProvider::with_responses(["不会输出"]);
+        assert_eq!(mock.stream(&request, &|_| {}, &cancelled).await.unwrap().text, "");
+        let streamed = provider.stream(&request, &|_| panic!("不应输出"), &cancelled).await.unwrap();
+        assert!(streamed.text.is_empty());
+    }
 }
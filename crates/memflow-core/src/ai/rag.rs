@@ -2,35 +2,295 @@
 //! 
 //! Implements hybrid search combining BM25 keyword matching and vector similarity.
 //! This is the Tauri-independent core - embedding generation is passed in.
+//!
+//! 关键词得分直接取 FTS5 的 `bm25()`（索引是预分词文本，中文同样适用），按本次查询的
+//! 最高分归一化到 [0, 1] 后与余弦相似度融合。融合方式见 [`FusionMode`]。
+//!
+//! 可选的重排阶段：配置 [`Reranker`] 后，融合结果的前 `rerank_top_n` 条会交给交叉编码器
+//! 按 (query, passage) 重新打分。重排模型较慢，可以按需关闭（见 [`RerankerConfig`]）。
 
+use crate::chunks::{self, ActivityChunk, ChunkSearchResult};
 use crate::db;
-use crate::vector_db;
+use crate::vector_db::{self, Embedding};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqlitePool;
 use sqlx::Row;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// 默认半衰期（天）：约等于每 30 天衰减 10%，与早期固定衰减系数一致
+pub const DEFAULT_HALF_LIFE_DAYS: f64 = 197.37;
+
+/// 没有时间戳的活动得分折半
+const UNDATED_PENALTY: f64 = 0.5;
+
+/// 默认的本地重排模型（中英文）
+pub const DEFAULT_RERANKER_MODEL: &str = "BAAI/bge-reranker-base";
+
+/// 送去重排的段落最大字符数（没有分块的活动截取标题 + OCR 文本）
+const RERANK_PASSAGE_MAX_CHARS: usize = 1000;
+
+type CachedReranker = (RerankerConfig, Arc<dyn Reranker>);
+
+static SHARED_RERANKER: once_cell::sync::Lazy<tokio::sync::Mutex<Option<CachedReranker>>> =
+    once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(None));
 
 /// Hybrid search result with activity ID and combined score
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HybridSearchResult {
     pub id: i64,
     pub score: f64,
+    /// 与查询最相关的文本块；活动尚未分块时为 `None`，调用方应回退到完整 OCR 文本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<ActivityChunk>,
 }
 
-/// Hybrid search engine combining BM25 and vector similarity
-pub struct HybridSearch {
-    /// BM25 parameter k1 (term frequency saturation)
-    #[allow(dead_code)]
-    k1: f64,
-    /// BM25 parameter b (document length normalization)
-    #[allow(dead_code)]
-    b: f64,
+/// 关键词与向量两路结果的融合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMode {
+    /// 归一化后的得分加权求和
+    #[default]
+    Weighted,
+    /// Reciprocal Rank Fusion：只看名次，`weight / (rrf_k + rank)` 累加，不受两路得分量纲影响
+    Rrf,
 }
 
-impl Default for HybridSearch {
+/// 混合检索参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HybridSearchConfig {
+    pub fusion: FusionMode,
+    /// 向量相似度的权重
+    pub vector_weight: f64,
+    /// 关键词得分的权重
+    pub keyword_weight: f64,
+    /// RRF 的平滑常数 k
+    pub rrf_k: f64,
+    /// 关键词候选数 = max(limit × keyword_candidate_factor, min_keyword_candidates)
+    pub keyword_candidate_factor: usize,
+    pub min_keyword_candidates: usize,
+    /// 向量候选数 = limit × vector_candidate_factor
+    pub vector_candidate_factor: usize,
+    /// 时间衰减半衰期（天），不大于 0 时不衰减
+    pub half_life_days: f64,
+    /// 交给重排模型打分的候选数（仅在配置了 [`Reranker`] 时生效，不小于 limit）
+    pub rerank_top_n: usize,
+}
+
+impl Default for HybridSearchConfig {
     fn default() -> Self {
-        Self { k1: 1.5, b: 0.75 }
+        Self {
+            fusion: FusionMode::Weighted,
+            vector_weight: 0.6,
+            keyword_weight: 0.4,
+            rrf_k: 60.0,
+            keyword_candidate_factor: 4,
+            min_keyword_candidates: 50,
+            vector_candidate_factor: 2,
+            half_life_days: DEFAULT_HALF_LIFE_DAYS,
+            rerank_top_n: 20,
+        }
+    }
+}
+
+/// 交叉编码器重排：对 (query, passage) 成对打分，比双塔向量更准但也更慢
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    fn model_id(&self) -> &str;
+
+    /// 为每个段落打分（越大越相关），顺序与 `passages` 一致；得分不要求归一化
+    async fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f64>>;
+}
+
+/// 重排配置
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RerankerConfig {
+    pub enabled: bool,
+    /// 模型名；为空时使用 [`DEFAULT_RERANKER_MODEL`]，`stub` 为测试用的确定性实现
+    pub model: String,
+    /// 本地模型文件缓存目录
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl RerankerConfig {
+    /// 从桌面端的 `config.json` 读取重排配置（供 MCP 等无法访问 AppConfig 的进程使用）
+    pub fn from_app_config(value: &Value) -> Self {
+        Self {
+            enabled: value.get("rerankEnabled").and_then(Value::as_bool).unwrap_or(false),
+            model: value
+                .get("rerankerModel")
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .unwrap_or_default()
+                .to_string(),
+            cache_dir: None,
+        }
+    }
+}
+
+/// 根据配置创建重排模型；未启用时返回 `None`（本地模型首次创建时可能需要下载）
+pub async fn create_reranker(config: &RerankerConfig) -> Result<Option<Arc<dyn Reranker>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    if config.model.eq_ignore_ascii_case("stub") {
+        return Ok(Some(Arc::new(StubReranker)));
     }
+    create_local_reranker(config).await.map(Some)
+}
+
+/// 按配置返回共享的重排模型；配置未变化时复用已加载的实例
+pub async fn shared_reranker(config: &RerankerConfig) -> Result<Option<Arc<dyn Reranker>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let mut shared = SHARED_RERANKER.lock().await;
+    if let Some((cached_config, reranker)) = shared.as_ref() {
+        if cached_config == config {
+            return Ok(Some(reranker.clone()));
+        }
+    }
+
+    let Some(reranker) = create_reranker(config).await? else {
+        return Ok(None);
+    };
+    tracing::info!("重排模型已加载: {}", reranker.model_id());
+    *shared = Some((config.clone(), reranker.clone()));
+    Ok(Some(reranker))
+}
+
+#[cfg(feature = "local-embeddings")]
+async fn create_local_reranker(config: &RerankerConfig) -> Result<Arc<dyn Reranker>> {
+    let model = if config.model.is_empty() {
+        DEFAULT_RERANKER_MODEL.to_string()
+    } else {
+        config.model.clone()
+    };
+    let cache_dir = config.cache_dir.clone();
+    let reranker = tokio::task::spawn_blocking(move || local::LocalReranker::new(&model, cache_dir)).await??;
+    Ok(Arc::new(reranker))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+async fn create_local_reranker(_config: &RerankerConfig) -> Result<Arc<dyn Reranker>> {
+    Err(anyhow::anyhow!(
+        "本地重排模型不可用：memflow-core 未启用 local-embeddings feature"
+    ))
+}
+
+#[cfg(feature = "local-embeddings")]
+pub use local::LocalReranker;
+
+#[cfg(feature = "local-embeddings")]
+mod local {
+    use super::*;
+    use fastembed::{RerankInitOptions, RerankerModel, TextRerank};
+
+    /// 支持的本地重排模型
+    const LOCAL_RERANKERS: &[(&str, RerankerModel)] = &[
+        ("BAAI/bge-reranker-base", RerankerModel::BGERerankerBase),
+        ("rozgo/bge-reranker-v2-m3", RerankerModel::BGERerankerV2M3),
+        ("jinaai/jina-reranker-v1-turbo-en", RerankerModel::JINARerankerV1TurboEn),
+        (
+            "jinaai/jina-reranker-v2-base-multilingual",
+            RerankerModel::JINARerankerV2BaseMultiligual,
+        ),
+    ];
+
+    /// 进程内 fastembed 交叉编码器
+    pub struct LocalReranker {
+        model_id: String,
+        model: Arc<TextRerank>,
+    }
+
+    impl LocalReranker {
+        /// 加载模型（阻塞，首次使用时会下载模型文件）
+        pub fn new(model_id: &str, cache_dir: Option<PathBuf>) -> Result<Self> {
+            let (name, model) = LOCAL_RERANKERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(model_id))
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("不支持的本地重排模型: {}", model_id))?;
+
+            let mut options = RerankInitOptions::new(model).with_show_download_progress(false);
+            if let Some(dir) = cache_dir {
+                options = options.with_cache_dir(dir);
+            }
+
+            Ok(Self {
+                model_id: name.to_string(),
+                model: Arc::new(TextRerank::try_new(options)?),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Reranker for LocalReranker {
+        fn model_id(&self) -> &str {
+            &self.model_id
+        }
+
+        async fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f64>> {
+            let model = self.model.clone();
+            let query = query.to_string();
+            let passages = passages.to_vec();
+            let results = tokio::task::spawn_blocking(move || {
+                let documents: Vec<&str> = passages.iter().map(String::as_str).collect();
+                model.rerank(query.as_str(), documents, false, None)
+            })
+            .await??;
+
+            // rerank() 按得分排序返回，这里还原为输入顺序
+            let mut scores = vec![f64::MIN; results.len()];
+            for result in results {
+                if let Some(slot) = scores.get_mut(result.index) {
+                    *slot = result.score as f64;
+                }
+            }
+            Ok(scores)
+        }
+    }
+}
+
+/// 按查询词覆盖率打分的确定性重排（用于测试，或在没有模型时验证流程）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StubReranker;
+
+#[async_trait]
+impl Reranker for StubReranker {
+    fn model_id(&self) -> &str {
+        "stub"
+    }
+
+    async fn score(&self, query: &str, passages: &[String]) -> Result<Vec<f64>> {
+        let terms: Vec<String> = crate::ai::nlp::query_terms(query)
+            .into_iter()
+            .map(|t| t.to_lowercase())
+            .collect();
+        Ok(passages
+            .iter()
+            .map(|passage| {
+                if terms.is_empty() {
+                    return 0.0;
+                }
+                let passage = passage.to_lowercase();
+                terms.iter().filter(|t| passage.contains(t.as_str())).count() as f64 / terms.len() as f64
+            })
+            .collect())
+    }
+}
+
+/// Hybrid search engine combining BM25 and vector similarity
+#[derive(Clone, Default)]
+pub struct HybridSearch {
+    config: HybridSearchConfig,
+    reranker: Option<Arc<dyn Reranker>>,
 }
 
 impl HybridSearch {
@@ -38,77 +298,289 @@ impl HybridSearch {
         Self::default()
     }
 
+    pub fn with_config(config: HybridSearchConfig) -> Self {
+        Self {
+            config,
+            reranker: None,
+        }
+    }
+
+    /// 设置重排模型；`None` 表示关闭重排
+    pub fn with_reranker(mut self, reranker: Option<Arc<dyn Reranker>>) -> Self {
+        self.reranker = reranker;
+        self
+    }
+
+    pub fn config(&self) -> &HybridSearchConfig {
+        &self.config
+    }
+
     /// Hybrid search with pre-computed query embedding
     /// 
     /// # Arguments
     /// * `query` - The search query text
-    /// * `query_embedding` - Pre-computed embedding vector for semantic search
+    /// * `query_embedding` - Pre-computed embedding; only vectors from the same model are compared
     /// * `limit` - Maximum number of results
     pub async fn search_with_embedding(
         &self,
         query: &str,
-        query_embedding: Vec<f32>,
+        query_embedding: Embedding,
+        limit: usize,
+    ) -> Result<Vec<HybridSearchResult>> {
+        let pool = db::get_pool().await?;
+        self.search_with_embedding_impl(&pool, query, query_embedding, limit).await
+    }
+
+    pub async fn search_with_embedding_impl(
+        &self,
+        pool: &SqlitePool,
+        query: &str,
+        query_embedding: Embedding,
+        limit: usize,
+    ) -> Result<Vec<HybridSearchResult>> {
+        self.search_with_filter_impl(pool, query, query_embedding, &db::ActivityQuery::default(), limit)
+            .await
+    }
+
+    /// Hybrid search restricted by the structured filters of an [`db::ActivityQuery`]
+    ///
+    /// 应用、时间范围、是否有 OCR 等条件在 SQL 中先于打分生效（关键词与向量两个阶段都是），
+    /// 因此不会出现“先取前 N 条再过滤导致结果变少”的情况。`filter` 中的全文查询、排序与分页字段被忽略。
+    pub async fn search_with_filter(
+        &self,
+        query: &str,
+        query_embedding: Embedding,
+        filter: &db::ActivityQuery,
+        limit: usize,
+    ) -> Result<Vec<HybridSearchResult>> {
+        let pool = db::get_pool().await?;
+        self.search_with_filter_impl(&pool, query, query_embedding, filter, limit).await
+    }
+
+    pub async fn search_with_filter_impl(
+        &self,
+        pool: &SqlitePool,
+        query: &str,
+        query_embedding: Embedding,
+        filter: &db::ActivityQuery,
         limit: usize,
     ) -> Result<Vec<HybridSearchResult>> {
-        let candidate_size = (limit * 4).max(50);
+        let config = &self.config;
+        let keyword_candidates = (limit * config.keyword_candidate_factor).max(config.min_keyword_candidates);
+        let match_query = crate::search_query::fts_any_of(query.split_whitespace());
+        let filter = db::ActivityQuery {
+            query: None,
+            limit: None,
+            offset: None,
+            order_by: None,
+            cursor: None,
+            highlight: None,
+            ..filter.clone()
+        };
 
         // 1. BM25 keyword search (get candidates)
-        let bm25_results = self.bm25_search(query, candidate_size).await?;
-        
-        let candidate_ids: Vec<i64> = bm25_results.iter().map(|r| r.id).collect();
-        
+        let keyword_results = match &match_query {
+            Some(match_query) => Self::bm25_search(pool, match_query, &filter, keyword_candidates).await?,
+            None => Vec::new(),
+        };
+        let candidate_ids: Vec<i64> = keyword_results.iter().map(|(id, _)| *id).collect();
+
         // 2. Vector semantic search (only on candidates)
-        let vector_results = if candidate_ids.is_empty() {
-            vector_db::search_similar(query_embedding, limit * 2).await?
+        let vector_limit = limit * config.vector_candidate_factor;
+        let vector_results = if candidate_ids.is_empty() && filter.has_filters() {
+            vector_db::search_similar_filtered_impl(pool, &query_embedding, vector_limit, &filter).await?
         } else {
-            vector_db::search_similar_with_candidates(
-                query_embedding,
-                limit * 2,
-                Some(&candidate_ids),
-            )
-            .await?
+            vector_db::search_similar_with_candidates_impl(pool, &query_embedding, vector_limit, Some(&candidate_ids))
+                .await?
         };
 
-        // 3. Merge results (weighted average)
-        let mut combined: HashMap<i64, (f64, usize)> = HashMap::new();
-
-        // Vector results (weight 0.6)
-        for result in vector_results.iter() {
-            let weight = 0.6 * result.score;
-            let entry = combined.entry(result.id).or_insert((0.0, 0));
-            entry.0 += weight;
-            entry.1 += 1;
-        }
+        // 3. 分块打分：活动整体向量是各块的平均，最相关段落的相似度更能代表命中程度
+        let mut scored_ids = candidate_ids.clone();
+        scored_ids.extend(vector_results.iter().map(|r| r.id));
+        scored_ids.sort_unstable();
+        scored_ids.dedup();
+        let chunk_vectors = chunks::search_similar_chunks_impl(pool, &query_embedding, &scored_ids).await?;
+        let chunk_keywords = match &match_query {
+            Some(match_query) => {
+                let fts_query = crate::ai::nlp::segment_fts_query(match_query);
+                chunks::search_chunks_fts_impl(pool, &fts_query, keyword_candidates * 4).await?
+            }
+            None => Vec::new(),
+        };
 
-        // BM25 results (weight 0.4)
-        for result in bm25_results.iter() {
-            let weight = 0.4 * result.score;
-            let entry = combined.entry(result.id).or_insert((0.0, 0));
-            entry.0 += weight;
-            entry.1 += 1;
+        let mut vector_scores: HashMap<i64, f64> =
+            vector_results.iter().map(|r| (r.id, r.score)).collect();
+        for chunk in &chunk_vectors {
+            let entry = vector_scores.entry(chunk.activity_id).or_insert(chunk.score);
+            *entry = entry.max(chunk.score);
         }
+        let mut vector_ranked: Vec<(i64, f64)> = vector_scores.into_iter().collect();
+        sort_ranked(&mut vector_ranked);
 
-        // 4. Apply time decay
-        let ids: Vec<i64> = combined.keys().cloned().collect();
-        let timestamps = self.get_timestamps(&ids).await?;
+        // 4. 融合并按时间衰减
+        let fused = self.fuse(&keyword_results, &vector_ranked);
+        let ids: Vec<i64> = fused.iter().map(|(id, _)| *id).collect();
+        let timestamps = Self::get_timestamps(pool, &ids).await?;
         let now = chrono::Utc::now().timestamp();
 
-        let mut final_results: Vec<HybridSearchResult> = combined
+        let mut ranked: Vec<(i64, f64)> = fused
             .into_iter()
-            .map(|(id, (score, _))| {
+            .map(|(id, score)| {
                 let timestamp = timestamps.get(&id).copied().unwrap_or(0);
-                let new_score = Self::calculate_decayed_score(score, timestamp, now);
-                HybridSearchResult { id, score: new_score }
+                (id, Self::calculate_decayed_score(score, timestamp, now, config.half_life_days))
             })
             .collect();
 
-        // 5. Sort and limit
-        final_results.sort_by(|a, b| {
-            b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal)
-        });
-        final_results.truncate(limit);
+        // 5. Sort and limit（启用重排时多保留一些候选）
+        sort_ranked(&mut ranked);
+        let keep = match self.reranker {
+            Some(_) => config.rerank_top_n.max(limit),
+            None => limit,
+        };
+        ranked.truncate(keep);
+
+        // 6. 为每个结果挑选最相关的块
+        let best_chunks = self.best_chunk_per_activity(&chunk_vectors, &chunk_keywords);
+        let chunk_ids: Vec<i64> = ranked
+            .iter()
+            .filter_map(|(id, _)| best_chunks.get(id).copied())
+            .collect();
+        let mut chunk_rows: HashMap<i64, ActivityChunk> = chunks::get_chunks_impl(pool, &chunk_ids)
+            .await?
+            .into_iter()
+            .map(|c| (c.activity_id, c))
+            .collect();
+
+        // 7. 可选的交叉编码器重排
+        if let Some(reranker) = &self.reranker {
+            ranked = self
+                .rerank(pool, reranker.as_ref(), query, ranked, &chunk_rows, &timestamps, now)
+                .await?;
+        }
+        ranked.truncate(limit);
+
+        Ok(ranked
+            .into_iter()
+            .map(|(id, score)| HybridSearchResult {
+                id,
+                score,
+                chunk: chunk_rows.remove(&id),
+            })
+            .collect())
+    }
+
+    /// 用交叉编码器为候选重新打分并排序
+    ///
+    /// 重排得分经 sigmoid 映射到 (0, 1) 后再做时间衰减；模型出错时保持融合排序。
+    #[allow(clippy::too_many_arguments)]
+    async fn rerank(
+        &self,
+        pool: &SqlitePool,
+        reranker: &dyn Reranker,
+        query: &str,
+        ranked: Vec<(i64, f64)>,
+        chunk_rows: &HashMap<i64, ActivityChunk>,
+        timestamps: &HashMap<i64, i64>,
+        now: i64,
+    ) -> Result<Vec<(i64, f64)>> {
+        if ranked.is_empty() {
+            return Ok(ranked);
+        }
+
+        let ids: Vec<i64> = ranked.iter().map(|(id, _)| *id).collect();
+        let activities: HashMap<i64, db::ActivityLog> =
+            db::list_activities_impl(pool, &db::ActivityQuery::new().ids(ids))
+                .await?
+                .into_iter()
+                .map(|a| (a.id, a))
+                .collect();
+
+        let passages: Vec<String> = ranked
+            .iter()
+            .map(|(id, _)| match (chunk_rows.get(id), activities.get(id)) {
+                (Some(chunk), _) => chunk.text.clone(),
+                (None, Some(activity)) => {
+                    let text = format!(
+                        "{}\n{}",
+                        activity.window_title,
+                        activity.ocr_text.as_deref().unwrap_or_default()
+                    );
+                    text.chars().take(RERANK_PASSAGE_MAX_CHARS).collect()
+                }
+                (None, None) => String::new(),
+            })
+            .collect();
+
+        let scores = match reranker.score(query, &passages).await {
+            Ok(scores) if scores.len() == passages.len() => scores,
+            Ok(scores) => {
+                tracing::warn!("重排结果数量不符（期望 {}，实际 {}），保持原排序", passages.len(), scores.len());
+                return Ok(ranked);
+            }
+            Err(e) => {
+                tracing::warn!("重排失败，保持原排序: {}", e);
+                return Ok(ranked);
+            }
+        };
+
+        let mut reranked: Vec<(i64, f64)> = ranked
+            .iter()
+            .zip(scores)
+            .map(|((id, _), score)| {
+                let probability = 1.0 / (1.0 + (-score).exp());
+                let timestamp = timestamps.get(id).copied().unwrap_or(0);
+                (
+                    *id,
+                    Self::calculate_decayed_score(probability, timestamp, now, self.config.half_life_days),
+                )
+            })
+            .collect();
+        sort_ranked(&mut reranked);
+        Ok(reranked)
+    }
+
+    /// 仅关键词检索（BM25），返回归一化得分降序的 `(活动 ID, 得分)`，不做时间衰减
+    ///
+    /// 与混合检索的关键词阶段相同，主要用于离线评测对比（见 [`crate::ai::eval`]）。
+    pub async fn keyword_search_impl(
+        &self,
+        pool: &SqlitePool,
+        query: &str,
+        filter: &db::ActivityQuery,
+        limit: usize,
+    ) -> Result<Vec<(i64, f64)>> {
+        match crate::search_query::fts_any_of(query.split_whitespace()) {
+            Some(match_query) => Self::bm25_search(pool, &match_query, filter, limit).await,
+            None => Ok(Vec::new()),
+        }
+    }
 
-        Ok(final_results)
+    /// 仅向量检索：活动向量与最相关块的相似度取较大值，不做时间衰减
+    pub async fn vector_search_impl(
+        &self,
+        pool: &SqlitePool,
+        query_embedding: &Embedding,
+        filter: &db::ActivityQuery,
+        limit: usize,
+    ) -> Result<Vec<(i64, f64)>> {
+        let vector_limit = limit * self.config.vector_candidate_factor;
+        let vector_results = if filter.has_filters() {
+            vector_db::search_similar_filtered_impl(pool, query_embedding, vector_limit, filter).await?
+        } else {
+            vector_db::search_similar_with_candidates_impl(pool, query_embedding, vector_limit, None).await?
+        };
+
+        let ids: Vec<i64> = vector_results.iter().map(|r| r.id).collect();
+        let mut scores: HashMap<i64, f64> = vector_results.iter().map(|r| (r.id, r.score)).collect();
+        for chunk in chunks::search_similar_chunks_impl(pool, query_embedding, &ids).await? {
+            let entry = scores.entry(chunk.activity_id).or_insert(chunk.score);
+            *entry = entry.max(chunk.score);
+        }
+
+        let mut ranked: Vec<(i64, f64)> = scores.into_iter().collect();
+        sort_ranked(&mut ranked);
+        ranked.truncate(limit);
+        Ok(ranked)
     }
 
     /// Convenience method using placeholder embedding (for testing or fallback)
@@ -121,114 +593,399 @@ impl HybridSearch {
         self.search_with_embedding(query, embedding, limit).await
     }
 
-    /// BM25 keyword search using FTS5
-    async fn bm25_search(&self, query: &str, limit: usize) -> Result<Vec<HybridSearchResult>> {
-        let pool = db::get_pool().await?;
+    /// 融合两路已按得分降序排列的结果，返回按融合得分降序排列的 `(活动 ID, 得分)`
+    ///
+    /// `keyword` 为 [`normalize_bm25`] 归一化后的得分，`vector` 为余弦相似度（负值按 0 计）。
+    pub fn fuse(&self, keyword: &[(i64, f64)], vector: &[(i64, f64)]) -> Vec<(i64, f64)> {
+        let config = &self.config;
+        let mut combined: HashMap<i64, f64> = HashMap::new();
 
-        let query_terms: Vec<&str> = query.split_whitespace().collect();
-        let fts_query = query_terms.join(" OR ");
-
-        let rows = sqlx::query(
-            "SELECT rowid, ocr_text FROM activity_logs_fts 
-             WHERE activity_logs_fts MATCH ? 
-             LIMIT ?",
-        )
-        .bind(&fts_query)
-        .bind(limit as i64)
-        .fetch_all(&pool)
-        .await
-        .unwrap_or_default();
-
-        let mut results = Vec::new();
-
-        for row in rows {
-            let activity_id: i64 = row.get(0);
-            let ocr_text: Option<String> = row.get(1);
-
-            if let Some(ref text) = ocr_text {
-                let score = self.calculate_tf_idf(query, text);
-                results.push(HybridSearchResult {
-                    id: activity_id,
-                    score,
-                });
+        match config.fusion {
+            FusionMode::Weighted => {
+                for (id, score) in vector {
+                    *combined.entry(*id).or_insert(0.0) += config.vector_weight * score.max(0.0);
+                }
+                for (id, score) in keyword {
+                    *combined.entry(*id).or_insert(0.0) += config.keyword_weight * score;
+                }
+            }
+            FusionMode::Rrf => {
+                for (rank, (id, _)) in vector.iter().enumerate() {
+                    *combined.entry(*id).or_insert(0.0) += config.vector_weight / (config.rrf_k + rank as f64 + 1.0);
+                }
+                for (rank, (id, _)) in keyword.iter().enumerate() {
+                    *combined.entry(*id).or_insert(0.0) += config.keyword_weight / (config.rrf_k + rank as f64 + 1.0);
+                }
             }
         }
 
-        Ok(results)
+        let mut fused: Vec<(i64, f64)> = combined.into_iter().collect();
+        sort_ranked(&mut fused);
+        fused
     }
 
-    /// Calculate simplified TF-IDF score
-    fn calculate_tf_idf(&self, query: &str, document: &str) -> f64 {
-        let query_terms: Vec<&str> = query.split_whitespace().collect();
-        let doc_terms: Vec<&str> = document.split_whitespace().collect();
-
-        let mut score = 0.0;
+    /// 按融合权重选出每个活动得分最高的块
+    ///
+    /// 块的关键词得分按活动内的最高分归一化到 [0, 1]，与相似度处于同一量级。
+    fn best_chunk_per_activity(
+        &self,
+        vector_hits: &[ChunkSearchResult],
+        keyword_hits: &[ChunkSearchResult],
+    ) -> HashMap<i64, i64> {
+        let mut max_keyword: HashMap<i64, f64> = HashMap::new();
+        for hit in keyword_hits {
+            let entry = max_keyword.entry(hit.activity_id).or_insert(0.0);
+            *entry = entry.max(hit.score);
+        }
 
-        for term in query_terms {
-            let term_lower = term.to_lowercase();
-            let tf = doc_terms
-                .iter()
-                .filter(|t| t.to_lowercase() == term_lower)
-                .count() as f64;
+        // chunk_id -> (activity_id, score)
+        let mut chunk_scores: HashMap<i64, (i64, f64)> = HashMap::new();
+        for hit in vector_hits {
+            chunk_scores.entry(hit.chunk_id).or_insert((hit.activity_id, 0.0)).1 +=
+                self.config.vector_weight * hit.score.max(0.0);
+        }
+        for hit in keyword_hits {
+            let max = max_keyword.get(&hit.activity_id).copied().unwrap_or(0.0);
+            if max > 0.0 {
+                chunk_scores.entry(hit.chunk_id).or_insert((hit.activity_id, 0.0)).1 +=
+                    self.config.keyword_weight * hit.score / max;
+            }
+        }
 
-            if tf > 0.0 {
-                let idf = 1.0 + (doc_terms.len() as f64 / (tf + 1.0)).ln();
-                score += tf * idf;
+        let mut best: HashMap<i64, (i64, f64)> = HashMap::new();
+        for (chunk_id, (activity_id, score)) in chunk_scores {
+            let entry = best.entry(activity_id).or_insert((chunk_id, f64::MIN));
+            // 同分时取靠前的块，保证结果稳定
+            if score > entry.1 || (score == entry.1 && chunk_id < entry.0) {
+                *entry = (chunk_id, score);
             }
         }
+        best.into_iter().map(|(activity_id, (chunk_id, _))| (activity_id, chunk_id)).collect()
+    }
+
+    /// BM25 keyword search using FTS5
+    ///
+    /// `match_query` 为未分词的 MATCH 表达式（由 `push_filters` 改写）；
+    /// 返回按相关度降序排列、经 [`normalize_bm25`] 归一化的 `(活动 ID, 得分)`。
+    async fn bm25_search(
+        pool: &SqlitePool,
+        match_query: &str,
+        filter: &db::ActivityQuery,
+        limit: usize,
+    ) -> Result<Vec<(i64, f64)>> {
+        let query = db::ActivityQuery {
+            query: Some(match_query.to_string()),
+            ..filter.clone()
+        };
+
+        // 标题与 OCR 文本一起参与打分（列权重见 FTS_RANK_EXPR）
+        let mut builder = sqlx::QueryBuilder::new("SELECT a.id, -");
+        builder.push(db::FTS_RANK_EXPR);
+        query.push_filters(&mut builder);
+        builder.push("ORDER BY ");
+        builder.push(db::FTS_RANK_EXPR);
+        builder.push(", a.id DESC LIMIT ");
+        builder.push_bind(limit as i64);
+
+        let rows = builder.build().fetch_all(pool).await?;
 
-        score
+        let raw: Vec<(i64, f64)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+        Ok(normalize_bm25(&raw))
     }
 
     /// Batch fetch activity timestamps
-    async fn get_timestamps(&self, ids: &[i64]) -> Result<HashMap<i64, i64>> {
+    async fn get_timestamps(pool: &SqlitePool, ids: &[i64]) -> Result<HashMap<i64, i64>> {
         if ids.is_empty() {
             return Ok(HashMap::new());
         }
 
-        let pool = db::get_pool().await?;
-        let mut builder = sqlx::QueryBuilder::new("SELECT id, timestamp FROM activity_logs WHERE id IN (");
-        
-        let mut separated = builder.separated(", ");
-        for id in ids {
-            separated.push_bind(id);
-        }
-        separated.push_unseparated(")");
-
-        let rows = builder.build().fetch_all(&pool).await?;
-
-        let mut timestamps = HashMap::new();
-        for row in rows {
-            let id: i64 = row.get(0);
-            let timestamp: i64 = row.get(1);
-            timestamps.insert(id, timestamp);
-        }
+        let activities = db::list_activities_impl(pool, &db::ActivityQuery::new().ids(ids.to_vec())).await?;
 
-        Ok(timestamps)
+        Ok(activities.into_iter().map(|a| (a.id, a.timestamp)).collect())
     }
 
     /// Calculate decayed score based on age
-    /// Decay: 10% reduction per 30 days (multiply by 0.9)
-    fn calculate_decayed_score(original_score: f64, timestamp: i64, now: i64) -> f64 {
+    ///
+    /// 得分每经过 `half_life_days` 天减半；没有时间戳的活动得分折半。
+    fn calculate_decayed_score(original_score: f64, timestamp: i64, now: i64, half_life_days: f64) -> f64 {
         if timestamp == 0 {
-            return original_score * 0.5;
+            return original_score * UNDATED_PENALTY;
         }
 
         let diff_seconds = now - timestamp;
-        if diff_seconds < 0 {
+        if diff_seconds < 0 || half_life_days <= 0.0 {
             return original_score;
         }
 
         let days_diff = diff_seconds as f64 / 86400.0;
-        let decay_factor = 0.9f64.powf(days_diff / 30.0);
-        
-        original_score * decay_factor
+        original_score * 0.5f64.powf(days_diff / half_life_days)
     }
 }
 
+/// 将取反后的 bm25 得分（越大越相关）按最高分归一化到 [0, 1]
+pub fn normalize_bm25(scores: &[(i64, f64)]) -> Vec<(i64, f64)> {
+    let max = scores.iter().map(|(_, s)| *s).fold(0.0, f64::max);
+    scores
+        .iter()
+        .map(|(id, s)| (*id, if max > 0.0 { (s / max).max(0.0) } else { 0.0 }))
+        .collect()
+}
+
+/// 按得分降序排序；同分时较新的活动（ID 更大）在前，保证排序稳定
+fn sort_ranked(ranked: &mut [(i64, f64)]) {
+    ranked.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| b.0.cmp(&a.0))
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ai::embedding::{EmbeddingProvider, StubEmbeddingProvider};
+    use crate::embedding_queue;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn returns_best_chunk_per_activity() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let filler = "The weather today is sunny with a light breeze and clear skies. ";
+        let long_text = format!(
+            "{}The deploy pipeline failed on the database migration step. {}",
+            filler.repeat(12),
+            filler.repeat(12)
+        );
+        let now = chrono::Utc::now().timestamp();
+        for (id, text) in [(1, long_text.as_str()), (2, "Lunch menu for the week.")] {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, 'Terminal', 'shell', '', ?)")
+                .bind(id)
+                .bind(now)
+                .bind(text)
+                .execute(&pool)
+                .await
+                .unwrap();
+            embedding_queue::enqueue_impl(&pool, id).await.unwrap();
+        }
+        let stub = StubEmbeddingProvider::default();
+        let batch = embedding_queue::process_batch_impl(&pool, 10, &stub).await.unwrap();
+        assert_eq!(batch.embedded, 2);
+
+        let query = "deploy pipeline migration";
+        let embedding = stub.embed_query(query).await.unwrap();
+        let results = HybridSearch::new()
+            .search_with_embedding_impl(&pool, query, embedding, 5)
+            .await
+            .unwrap();
+
+        assert_eq!(results[0].id, 1);
+        let chunk = results[0].chunk.as_ref().expect("long activity should be chunked");
+        assert!(chunk.text.contains("deploy pipeline failed"));
+        assert!(chunk.text.chars().count() < long_text.chars().count() / 2);
+        assert_eq!(&long_text[chunk.start_offset as usize..chunk.end_offset as usize], chunk.text);
+    }
+
+    async fn setup_pool(docs: &[(i64, &str, &str)]) -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        for (id, title, text) in docs {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (?, ?, 'Code', ?, '')")
+                .bind(id)
+                .bind(now)
+                .bind(title)
+                .execute(&pool)
+                .await
+                .unwrap();
+            crate::db::update_activity_ocr_impl(&pool, *id, text).await.unwrap();
+        }
+        pool
+    }
+
+    fn ids(ranked: &[(i64, f64)]) -> Vec<i64> {
+        ranked.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn normalizes_bm25_by_best_score() {
+        assert_eq!(normalize_bm25(&[(1, 8.0), (2, 4.0), (3, 0.0)]), vec![(1, 1.0), (2, 0.5), (3, 0.0)]);
+        assert_eq!(normalize_bm25(&[(1, 0.0)]), vec![(1, 0.0)]);
+        assert!(normalize_bm25(&[]).is_empty());
+    }
+
+    #[test]
+    fn weighted_fusion_combines_normalized_scores() {
+        let search = HybridSearch::new();
+        let fused = search.fuse(&[(1, 1.0), (2, 0.5)], &[(2, 0.9), (3, 0.8), (4, -0.2)]);
+        assert_eq!(ids(&fused), vec![2, 3, 1, 4]);
+        assert!((fused[0].1 - (0.6 * 0.9 + 0.4 * 0.5)).abs() < 1e-9);
+        assert_eq!(fused[3].1, 0.0);
+
+        let keyword_only = HybridSearch::with_config(HybridSearchConfig {
+            vector_weight: 0.0,
+            keyword_weight: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(ids(&keyword_only.fuse(&[(1, 1.0), (2, 0.5)], &[(2, 0.9)])), vec![1, 2]);
+    }
+
+    #[test]
+    fn rrf_fusion_uses_ranks_only() {
+        let search = HybridSearch::with_config(HybridSearchConfig {
+            fusion: FusionMode::Rrf,
+            ..Default::default()
+        });
+        let fused = search.fuse(&[(3, 1.0), (1, 0.01)], &[(1, 0.9), (2, 0.8), (3, 0.7)]);
+        assert_eq!(ids(&fused), vec![1, 3, 2]);
+        // 得分量纲不影响结果
+        let rescaled = search.fuse(&[(3, 100.0), (1, 99.0)], &[(1, 0.3), (2, 0.2), (3, 0.1)]);
+        assert_eq!(fused, rescaled);
+    }
+
+    #[test]
+    fn ties_are_broken_deterministically() {
+        for fusion in [FusionMode::Weighted, FusionMode::Rrf] {
+            let search = HybridSearch::with_config(HybridSearchConfig {
+                fusion,
+                ..Default::default()
+            });
+            let keyword = [(1, 1.0), (2, 1.0), (3, 1.0)];
+            let expected = search.fuse(&keyword, &[]);
+            assert_eq!(ids(&expected), if fusion == FusionMode::Weighted { vec![3, 2, 1] } else { vec![1, 2, 3] });
+            for _ in 0..20 {
+                assert_eq!(search.fuse(&keyword, &[]), expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn keyword_ranking_uses_fts_bm25_for_chinese() {
+        let pool = setup_pool(&[
+            (1, "周报", "今天开会讨论了很多事情，顺便提到数据库迁移要排期。"),
+            (2, "数据库迁移方案", "数据库迁移脚本已经写好，迁移前先备份数据库。"),
+            (3, "午饭", "今天中午吃面条。"),
+        ])
+        .await;
+        let search = HybridSearch::with_config(HybridSearchConfig {
+            vector_weight: 0.0,
+            keyword_weight: 1.0,
+            ..Default::default()
+        });
+        let embedding = StubEmbeddingProvider::default().embed_text("数据库迁移");
+
+        let first = search
+            .search_with_embedding_impl(&pool, "数据库迁移", embedding.clone(), 10)
+            .await
+            .unwrap();
+        let ranked: Vec<i64> = first.iter().map(|r| r.id).collect();
+        assert_eq!(ranked, vec![2, 1]);
+        assert!(first[0].score > first[1].score && first[1].score > 0.0);
+
+        // 重复查询结果完全一致
+        for _ in 0..5 {
+            let again = search
+                .search_with_embedding_impl(&pool, "数据库迁移", embedding.clone(), 10)
+                .await
+                .unwrap();
+            let pairs = |results: &[HybridSearchResult]| results.iter().map(|r| (r.id, r.score)).collect::<Vec<_>>();
+            assert_eq!(pairs(&again), pairs(&first));
+        }
+    }
+
+    #[tokio::test]
+    async fn fts_failures_are_reported_not_swallowed() {
+        let pool = setup_pool(&[(1, "数据库迁移方案", "数据库迁移脚本已经写好。")]).await;
+        let search = HybridSearch::new();
+        let embedding = StubEmbeddingProvider::default().embed_text("数据库迁移");
+        assert!(search
+            .search_with_embedding_impl(&pool, "数据库迁移", embedding.clone(), 10)
+            .await
+            .is_ok());
+
+        sqlx::query("DROP TABLE activity_chunks_fts").execute(&pool).await.unwrap();
+        assert!(search
+            .search_with_embedding_impl(&pool, "数据库迁移", embedding.clone(), 10)
+            .await
+            .is_err());
+
+        sqlx::query("DROP TABLE activity_logs_fts").execute(&pool).await.unwrap();
+        assert!(HybridSearch::bm25_search(&pool, "数据库", &db::ActivityQuery::new(), 10)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn filters_apply_before_scoring_in_both_stages() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let docs = [
+            (1, now - 3600, "Chrome", "release notes for the rust compiler"),
+            (2, now - 3600, "Code", "rust compiler error in main.rs"),
+            (3, now - 10 * 86400, "Code", "rust compiler upgrade plan"),
+            (4, now - 3600, "Code", ""),
+        ];
+        for (id, ts, app, text) in docs {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, ?, 'rust compiler', '', ?)")
+                .bind(id)
+                .bind(ts)
+                .bind(app)
+                .bind(text)
+                .execute(&pool)
+                .await
+                .unwrap();
+            embedding_queue::enqueue_impl(&pool, id).await.unwrap();
+        }
+        let stub = StubEmbeddingProvider::default();
+        embedding_queue::process_batch_impl(&pool, 10, &stub).await.unwrap();
+
+        let search = HybridSearch::new();
+        let run = |query: &'static str, filter: db::ActivityQuery| {
+            let pool = pool.clone();
+            let search = search.clone();
+            let embedding = stub.embed_text(query);
+            async move {
+                let mut ids: Vec<i64> = search
+                    .search_with_filter_impl(&pool, query, embedding, &filter, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.id)
+                    .collect();
+                ids.sort_unstable();
+                ids
+            }
+        };
+
+        assert_eq!(run("rust compiler", db::ActivityQuery::default()).await, vec![1, 2, 3, 4]);
+        assert_eq!(run("rust compiler", db::ActivityQuery::new().app("code")).await, vec![2, 3, 4]);
+        assert_eq!(
+            run("rust compiler", db::ActivityQuery::new().app("code").from_ts(now - 86400)).await,
+            vec![2, 4]
+        );
+        assert_eq!(
+            run("rust compiler", db::ActivityQuery::new().app("code").has_ocr(true)).await,
+            vec![2, 3]
+        );
+
+        // 没有关键词命中时，向量阶段同样只在过滤后的活动中检索
+        assert_eq!(run("kubernetes", db::ActivityQuery::new().app("chrome")).await, vec![1]);
+        assert!(run("kubernetes", db::ActivityQuery::new().app("firefox")).await.is_empty());
+    }
 
     #[test]
     fn test_time_decay_logic() {
@@ -236,17 +993,141 @@ mod tests {
         let score = 100.0;
 
         // Same day (no decay)
-        let decayed = HybridSearch::calculate_decayed_score(score, now, now);
+        let decayed = HybridSearch::calculate_decayed_score(score, now, now, DEFAULT_HALF_LIFE_DAYS);
         assert!((decayed - 100.0).abs() < 0.001);
 
         // 30 days ago (should be ~90.0)
         let past_30_days = now - 30 * 24 * 3600;
-        let decayed_30 = HybridSearch::calculate_decayed_score(score, past_30_days, now);
+        let decayed_30 = HybridSearch::calculate_decayed_score(score, past_30_days, now, DEFAULT_HALF_LIFE_DAYS);
         assert!((decayed_30 - 90.0).abs() < 0.001);
 
         // 60 days ago (should be ~81.0)
         let past_60_days = now - 60 * 24 * 3600;
-        let decayed_60 = HybridSearch::calculate_decayed_score(score, past_60_days, now);
+        let decayed_60 = HybridSearch::calculate_decayed_score(score, past_60_days, now, DEFAULT_HALF_LIFE_DAYS);
         assert!((decayed_60 - 81.0).abs() < 0.001);
     }
+
+    /// 按预设顺序打分并记录收到的段落数
+    struct FixedReranker {
+        preferred: Vec<&'static str>,
+        seen: std::sync::Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl Reranker for FixedReranker {
+        fn model_id(&self) -> &str {
+            "fixed"
+        }
+
+        async fn score(&self, _query: &str, passages: &[String]) -> Result<Vec<f64>> {
+            self.seen.lock().unwrap().push(passages.len());
+            Ok(passages
+                .iter()
+                .map(|p| {
+                    self.preferred
+                        .iter()
+                        .position(|word| p.contains(word))
+                        .map_or(-10.0, |rank| 10.0 - rank as f64)
+                })
+                .collect())
+        }
+    }
+
+    struct FailingReranker;
+
+    #[async_trait]
+    impl Reranker for FailingReranker {
+        fn model_id(&self) -> &str {
+            "failing"
+        }
+
+        async fn score(&self, _query: &str, _passages: &[String]) -> Result<Vec<f64>> {
+            Err(anyhow::anyhow!("model unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn reranker_reorders_top_candidates() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let docs = [
+            (1, "rust rust rust compiler notes alpha"),
+            (2, "rust compiler beta"),
+            (3, "rust compiler gamma"),
+            (4, "rust compiler delta"),
+        ];
+        for (id, text) in docs {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, 'Code', 'editor', '', ?)")
+                .bind(id)
+                .bind(now)
+                .bind(text)
+                .execute(&pool)
+                .await
+                .unwrap();
+            embedding_queue::enqueue_impl(&pool, id).await.unwrap();
+        }
+        let stub = StubEmbeddingProvider::default();
+        embedding_queue::process_batch_impl(&pool, 10, &stub).await.unwrap();
+
+        let query = "rust compiler";
+        let config = HybridSearchConfig {
+            rerank_top_n: 3,
+            ..Default::default()
+        };
+        let ids = |results: Vec<HybridSearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let baseline = ids(HybridSearch::with_config(config.clone())
+            .search_with_embedding_impl(&pool, query, stub.embed_text(query), 3)
+            .await
+            .unwrap());
+        assert_eq!(baseline.len(), 3);
+
+        // 重排只看到前 rerank_top_n 个候选，并决定最终顺序：这里把融合排序整个倒过来
+        let word = |id: i64| docs.iter().find(|(doc_id, _)| *doc_id == id).unwrap().1.rsplit(' ').next().unwrap();
+        let reranker = Arc::new(FixedReranker {
+            preferred: baseline.iter().rev().map(|id| word(*id)).collect(),
+            seen: Default::default(),
+        });
+        let results = HybridSearch::with_config(config.clone())
+            .with_reranker(Some(reranker.clone()))
+            .search_with_embedding_impl(&pool, query, stub.embed_text(query), 2)
+            .await
+            .unwrap();
+        assert_eq!(*reranker.seen.lock().unwrap(), vec![3]);
+        assert!(results[0].score > results[1].score);
+        assert!(results.iter().all(|r| r.score > 0.0 && r.score < 1.0));
+        assert_eq!(ids(results), vec![baseline[2], baseline[1]]);
+
+        // 模型出错时退回融合排序
+        let fallback = ids(HybridSearch::with_config(config)
+            .with_reranker(Some(Arc::new(FailingReranker)))
+            .search_with_embedding_impl(&pool, query, stub.embed_text(query), 2)
+            .await
+            .unwrap());
+        assert_eq!(fallback, baseline[..2]);
+    }
+
+    #[tokio::test]
+    async fn stub_reranker_scores_term_coverage() {
+        let passages = vec![
+            "部署流水线在数据库迁移时失败".to_string(),
+            "午餐菜单".to_string(),
+            "deploy finished".to_string(),
+        ];
+        let scores = StubReranker.score("deploy 数据库", &passages).await.unwrap();
+        assert_eq!(scores.len(), 3);
+        assert!(scores[0] > scores[1]);
+        assert!(scores[2] > scores[1]);
+        assert_eq!(scores[1], 0.0);
+
+        let disabled = RerankerConfig::from_app_config(&serde_json::json!({ "rerankerModel": "stub" }));
+        assert!(create_reranker(&disabled).await.unwrap().is_none());
+        let enabled = RerankerConfig::from_app_config(&serde_json::json!({ "rerankEnabled": true, "rerankerModel": "stub" }));
+        assert_eq!(create_reranker(&enabled).await.unwrap().unwrap().model_id(), "stub");
+    }
 }
@@ -0,0 +1,362 @@
+//! 结构化输出
+//!
+//! 需要模型返回 JSON 的地方（意图解析、任务提案、建议操作）统一走这里：
+//! - 提供商支持时随请求发送 JSON Schema（OpenAI `response_format`、Ollama `format`），否则把 Schema 写进系统提示词
+//! - 回复去掉代码块标记后按 Schema 校验，再反序列化为目标类型
+//! - 不是 JSON 或不符合 Schema 时，带上错误信息让模型修正一次
+//! - 失败原因分为调用失败、超时、不是 JSON、不符合 Schema，调用方据此区分“模型输出无效”和“确实没有结果”
+
+use crate::ai::provider::{ChatMessage, CompletionRequest, LlmProvider, ResponseSchema};
+use crate::ai::strip_json_code_fence;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// 单条校验错误最多报告的数量（修正提示不宜过长）
+const MAX_REPORTED_ERRORS: usize = 8;
+
+/// 失败原因的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuredFailure {
+    /// 提供商调用失败（网络、鉴权、限流等）
+    Provider,
+    Timeout,
+    /// 模型输出不是 JSON
+    InvalidJson,
+    /// 模型输出是 JSON，但不符合 Schema
+    SchemaViolation,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum StructuredOutputError {
+    #[error("{provider} 调用失败: {message}")]
+    Provider { provider: String, message: String },
+    #[error("{provider} 响应超时（{timeout_ms}ms）")]
+    Timeout { provider: String, timeout_ms: u128 },
+    #[error("模型输出不是有效的 JSON: {error}")]
+    InvalidJson { error: String, raw: String },
+    #[error("模型输出不符合 Schema: {}", errors.join("; "))]
+    SchemaViolation { errors: Vec<String>, raw: String },
+}
+
+impl StructuredOutputError {
+    pub fn kind(&self) -> StructuredFailure {
+        match self {
+            Self::Provider { .. } => StructuredFailure::Provider,
+            Self::Timeout { .. } => StructuredFailure::Timeout,
+            Self::InvalidJson { .. } => StructuredFailure::InvalidJson,
+            Self::SchemaViolation { .. } => StructuredFailure::SchemaViolation,
+        }
+    }
+
+    /// 模型给出了回复，但内容无法使用
+    pub fn is_invalid_output(&self) -> bool {
+        matches!(self.kind(), StructuredFailure::InvalidJson | StructuredFailure::SchemaViolation)
+    }
+}
+
+/// 请求结构化输出：校验失败时带上错误让模型修正一次；`timeout` 针对每一次调用
+pub async fn complete_structured<T: DeserializeOwned>(
+    provider: &dyn LlmProvider,
+    request: CompletionRequest,
+    schema: &ResponseSchema,
+    timeout: Duration,
+) -> Result<T, StructuredOutputError> {
+    let mut request = with_schema(provider, request, schema);
+
+    let raw = complete_once(provider, &request, timeout).await?;
+    let error = match parse_structured(&raw, &schema.schema) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    tracing::warn!(
+        "{} 的结构化输出 `{}` 无效，要求修正: {}",
+        provider.name(),
+        schema.name,
+        crate::redact::redact_secrets(&error.to_string())
+    );
+
+    request.messages.push(ChatMessage::assistant(raw));
+    request.messages.push(ChatMessage::user(repair_prompt(&error)));
+    let raw = complete_once(provider, &request, timeout).await?;
+    parse_structured(&raw, &schema.schema)
+}
+
+/// 支持 Schema 的提供商直接随请求发送，否则在系统提示词中说明格式
+fn with_schema(provider: &dyn LlmProvider, request: CompletionRequest, schema: &ResponseSchema) -> CompletionRequest {
+    if provider.supports_json_schema() {
+        return request.response_schema(schema.clone());
+    }
+
+    let mut request = request;
+    let instructions = format!(
+        "只返回符合以下 JSON Schema 的 JSON，不要输出其它内容：\n{}",
+        serde_json::to_string_pretty(&schema.schema).unwrap_or_default()
+    );
+    request.system = Some(match request.system.take() {
+        Some(system) if !system.is_empty() => format!("{}\n\n{}", system, instructions),
+        _ => instructions,
+    });
+    request
+}
+
+async fn complete_once(
+    provider: &dyn LlmProvider,
+    request: &CompletionRequest,
+    timeout: Duration,
+) -> Result<String, StructuredOutputError> {
+    match tokio::time::timeout(timeout, provider.complete(request)).await {
+        Ok(Ok(completion)) => Ok(completion.text),
+        Ok(Err(e)) => Err(StructuredOutputError::Provider {
+            provider: provider.name().to_string(),
+            message: crate::redact::redact_secrets(&e.to_string()),
+        }),
+        Err(_) => Err(StructuredOutputError::Timeout {
+            provider: provider.name().to_string(),
+            timeout_ms: timeout.as_millis(),
+        }),
+    }
+}
+
+fn repair_prompt(error: &StructuredOutputError) -> String {
+    format!(
+        "上面的输出无法使用：{}\n请修正后重新输出，只返回 JSON，不要包含解释或代码块标记。",
+        error
+    )
+}
+
+/// 从回复中取出 JSON：去掉代码块标记；前后夹杂说明文字时取第一个 `{`/`[` 到最后一个 `}`/`]`
+fn extract_json(text: &str) -> Result<Value, String> {
+    let stripped = strip_json_code_fence(text);
+    let first_error = match serde_json::from_str(stripped) {
+        Ok(value) => return Ok(value),
+        Err(e) => e.to_string(),
+    };
+
+    let start = stripped.find(['{', '[']);
+    let end = stripped.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str(&stripped[start..=end]).map_err(|_| first_error)
+        }
+        _ => Err(first_error),
+    }
+}
+
+/// 解析并校验一段模型输出
+pub fn parse_structured<T: DeserializeOwned>(raw: &str, schema: &Value) -> Result<T, StructuredOutputError> {
+    let value = extract_json(raw).map_err(|error| StructuredOutputError::InvalidJson {
+        error,
+        raw: raw.to_string(),
+    })?;
+
+    let errors = validate_json(&value, schema);
+    if !errors.is_empty() {
+        return Err(StructuredOutputError::SchemaViolation {
+            errors,
+            raw: raw.to_string(),
+        });
+    }
+
+    serde_json::from_value(value).map_err(|e| StructuredOutputError::SchemaViolation {
+        errors: vec![e.to_string()],
+        raw: raw.to_string(),
+    })
+}
+
+/// 按 JSON Schema 校验（支持 type、properties、required、additionalProperties、items、enum、
+/// minItems / maxItems、minimum / maximum），返回带路径的错误
+pub fn validate_json(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(value, schema, "$", &mut errors);
+    errors.truncate(MAX_REPORTED_ERRORS);
+    errors
+}
+
+fn type_matches(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(ty) = schema.get("type") {
+        let types: Vec<&str> = match ty {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(value, t)) {
+            errors.push(format!("{}: 应为 {}", path, types.join(" | ")));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(format!("{}: 只能是 {} 之一", path, allowed.join(", ")));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                errors.push(format!("{}: 不能小于 {}", path, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                errors.push(format!("{}: 不能大于 {}", path, max));
+            }
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        errors.push(format!("{}: 缺少字段 `{}`", path, key));
+                    }
+                }
+            }
+            for (key, child) in map {
+                match properties.and_then(|p| p.get(key)) {
+                    Some(child_schema) => validate_at(child, child_schema, &format!("{}.{}", path, key), errors),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{}: 不允许的字段 `{}`", path, key));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(format!("{}: 至少需要 {} 项", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    errors.push(format!("{}: 最多 {} 项", path, max));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item, item_schema, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::provider::MockLlmProvider;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Answer {
+        label: String,
+        score: i64,
+    }
+
+    fn schema() -> ResponseSchema {
+        ResponseSchema {
+            name: "answer".to_string(),
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "label": { "type": "string" },
+                    "score": { "type": "integer", "minimum": 0, "maximum": 10 },
+                    "tags": { "type": "array", "items": { "enum": ["a", "b"] }, "maxItems": 2 }
+                },
+                "required": ["label", "score"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    #[test]
+    fn validates_against_schema() {
+        let schema = schema().schema;
+        assert!(validate_json(&json!({ "label": "x", "score": 3, "tags": ["a"] }), &schema).is_empty());
+        assert_eq!(
+            validate_json(&json!({ "label": 1, "score": 11, "tags": ["c", "a", "b"], "extra": true }), &schema),
+            vec![
+                "$: 不允许的字段 `extra`",
+                "$.label: 应为 string",
+                "$.score: 不能大于 10",
+                "$.tags: 最多 2 项",
+                "$.tags[0]: 只能是 \"a\", \"b\" 之一",
+            ]
+        );
+        assert_eq!(validate_json(&json!([]), &schema), vec!["$: 应为 object"]);
+
+        let parsed: Answer = parse_structured("好的：\n```json\n{\"label\":\"x\",\"score\":2}\n```", &schema).unwrap();
+        assert_eq!(parsed, Answer { label: "x".to_string(), score: 2 });
+        let parsed: Answer = parse_structured("结果是 {\"label\":\"y\",\"score\":1}。", &schema).unwrap();
+        assert_eq!(parsed.label, "y");
+
+        let error = parse_structured::<Answer>("no json here", &schema).unwrap_err();
+        assert_eq!(error.kind(), StructuredFailure::InvalidJson);
+        let error = parse_structured::<Answer>("{\"label\":\"x\"}", &schema).unwrap_err();
+        assert_eq!(error.kind(), StructuredFailure::SchemaViolation);
+        assert!(error.is_invalid_output());
+    }
+
+    #[tokio::test]
+    async fn repairs_once_with_validation_error() {
+        let timeout = Duration::from_secs(1);
+        let request = CompletionRequest::from_query("评分", "", Some("你是评分助手"));
+
+        // 不支持 Schema：写进系统提示词；第一次输出无效，修正后成功
+        let provider = MockLlmProvider::with_responses([r#"{"label":"x","score":"high"}"#, r#"{"label":"x","score":8}"#]);
+        let answer: Answer = complete_structured(&provider, request.clone(), &schema(), timeout).await.unwrap();
+        assert_eq!(answer.score, 8);
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].system.as_deref().unwrap().starts_with("你是评分助手\n\n只返回符合以下 JSON Schema"));
+        assert_eq!(requests[0].response_schema, None);
+        assert_eq!(requests[1].messages.len(), 3);
+        assert_eq!(requests[1].messages[1].content, r#"{"label":"x","score":"high"}"#);
+        assert!(requests[1].messages[2].content.contains("$.score: 应为 integer"));
+
+        // 支持 Schema：随请求发送；修正后仍无效时返回类型化的错误
+        let provider = MockLlmProvider::new("mock").with_json_schema_support();
+        provider.push_response("抱歉");
+        provider.push_response("还是不行");
+        let error = complete_structured::<Answer>(&provider, request.clone(), &schema(), timeout)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), StructuredFailure::InvalidJson);
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].system.as_deref(), Some("你是评分助手"));
+        assert_eq!(requests[0].response_schema.as_ref().map(|s| s.name.as_str()), Some("answer"));
+
+        // 调用失败不重试
+        let provider = MockLlmProvider::new("mock");
+        provider.push_error("429 Too Many Requests");
+        let error = complete_structured::<Answer>(&provider, request, &schema(), timeout)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), StructuredFailure::Provider);
+        assert!(!error.is_invalid_output());
+        assert_eq!(provider.requests().len(), 1);
+    }
+}
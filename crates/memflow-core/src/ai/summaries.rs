@@ -0,0 +1,830 @@
+//! 分层活动摘要（小时 → 日 → 周）
+//!
+//! - 小时摘要：一小时内的活动记录经 [`assemble_context`] 合并重复截图、按预算截断后交给模型总结
+//! - 日摘要由当天的小时摘要汇总，周摘要（周一开始）由当周的日摘要汇总
+//! - 结果写入 `summaries`，带时间区间和来源 ID；每一级从已写入的最后一个区间（水位）之后增量推进，
+//!   下一级追上之前不汇总上一级，保证写入日/周摘要时其下的摘要已经齐全
+//! - 最多回溯 [`SummarizerConfig::backfill_horizon`]，首次开启时不会为全部历史调用模型；
+//!   同一区间连续失败 [`SummarizerConfig::max_attempts`] 次后写入空摘要跳过，上一级照常汇总
+//! - 问答按问题的时间跨度用 [`SummaryGranularity::for_span`] 选粒度，由 [`summaries_for_range_impl`]
+//!   取出覆盖该区间的摘要；粗粒度还没有生成的部分（如本周尚未结束）用更细的摘要补齐
+
+use crate::ai::context_builder::{assemble_context, ContextBudget, ContextItem};
+use crate::ai::provider::{ChatMessage, CompletionRequest, LlmProvider};
+use crate::time_range::local_timestamp;
+use anyhow::Result;
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::time::Duration;
+
+const HOUR_SYSTEM_PROMPT: &str = "你负责把用户一小时内的电脑活动记录总结成简短的工作日志。\
+根据应用、窗口标题和屏幕文字，概括这段时间在做什么：涉及的项目、文档、网页、遇到的问题和进展。\
+合并重复的内容，忽略无意义的界面文字，不要逐条罗列；不超过 150 字，只输出摘要本身。";
+
+const DAY_SYSTEM_PROMPT: &str = "你负责把用户一天中各小时的活动摘要汇总成当天的工作日志。\
+按主题归纳当天做了哪些事、时间主要花在哪些项目上、得到了什么结论、还有哪些未完成的事项，保留关键的项目名、文档名和时间段。\
+不超过 300 字，只输出摘要本身。";
+
+const WEEK_SYSTEM_PROMPT: &str = "你负责把用户一周中每天的活动摘要汇总成一周的工作回顾。\
+按主题归纳本周的主要工作、各项目的进展和投入、重要的结论与遗留问题，保留关键的项目名、文档名和日期。\
+不超过 400 字，只输出摘要本身。";
+
+const WEEKDAYS: [&str; 7] = ["周一", "周二", "周三", "周四", "周五", "周六", "周日"];
+
+const HOUR_SECS: i64 = 60 * 60;
+const DAY_SECS: i64 = 24 * HOUR_SECS;
+
+/// 摘要粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryGranularity {
+    Hour,
+    Day,
+    Week,
+}
+
+impl SummaryGranularity {
+    /// 从细到粗，也是生成的顺序
+    pub const ALL: [SummaryGranularity; 3] = [SummaryGranularity::Hour, SummaryGranularity::Day, SummaryGranularity::Week];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummaryGranularity::Hour => "hour",
+            SummaryGranularity::Day => "day",
+            SummaryGranularity::Week => "week",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.as_str() == value)
+    }
+
+    /// 汇总来源的下一级粒度；小时摘要直接来自活动记录
+    pub fn finer(&self) -> Option<Self> {
+        match self {
+            SummaryGranularity::Hour => None,
+            SummaryGranularity::Day => Some(SummaryGranularity::Hour),
+            SummaryGranularity::Week => Some(SummaryGranularity::Day),
+        }
+    }
+
+    /// 按查询区间（两端都包含）的跨度选粒度：一天以内直接用活动记录（`None`），
+    /// 一周以内用小时摘要，一个月以内用日摘要，更长用周摘要
+    pub fn for_span(from_ts: i64, to_ts: i64) -> Option<Self> {
+        let span = to_ts - from_ts + 1;
+        if span <= DAY_SECS {
+            None
+        } else if span <= 7 * DAY_SECS {
+            Some(SummaryGranularity::Hour)
+        } else if span <= 31 * DAY_SECS {
+            Some(SummaryGranularity::Day)
+        } else {
+            Some(SummaryGranularity::Week)
+        }
+    }
+
+    /// 包含 `ts` 的区间起点（本地时区；一周从周一开始）
+    pub fn period_start(&self, ts: i64) -> i64 {
+        let Some(dt) = Local.timestamp_opt(ts, 0).earliest() else {
+            return ts;
+        };
+        let date = dt.date_naive();
+        let day_start = match self {
+            // 按本地分秒截断，夏令时切换前后的小时也不会错位
+            SummaryGranularity::Hour => return ts - (dt.minute() * 60 + dt.second()) as i64,
+            SummaryGranularity::Day => date,
+            SummaryGranularity::Week => date - ChronoDuration::days(date.weekday().num_days_from_monday() as i64),
+        };
+        day_start_ts(day_start).unwrap_or(ts)
+    }
+
+    /// 从 `start` 开始的区间终点（不包含）
+    pub fn period_end(&self, start: i64) -> i64 {
+        let days = match self {
+            SummaryGranularity::Hour => return start + HOUR_SECS,
+            SummaryGranularity::Day => 1,
+            SummaryGranularity::Week => 7,
+        };
+        Local
+            .timestamp_opt(start, 0)
+            .earliest()
+            .and_then(|dt| day_start_ts(dt.date_naive() + ChronoDuration::days(days)))
+            .unwrap_or(start + days * DAY_SECS)
+    }
+
+    /// 区间的可读标签：`2024-01-08 09:00-10:00`、`2024-01-08 周一`、`2024-01-08 至 2024-01-14`
+    pub fn label(&self, start: i64, end: i64) -> String {
+        let (Some(from), Some(to)) = (
+            Local.timestamp_opt(start, 0).earliest(),
+            Local.timestamp_opt(end - 1, 0).earliest(),
+        ) else {
+            return format!("{}-{}", start, end);
+        };
+        match self {
+            SummaryGranularity::Hour => format!("{}-{:02}:00", from.format("%Y-%m-%d %H:%M"), (to.hour() + 1) % 24),
+            SummaryGranularity::Day => format!(
+                "{} {}",
+                from.format("%Y-%m-%d"),
+                WEEKDAYS[from.weekday().num_days_from_monday() as usize]
+            ),
+            SummaryGranularity::Week => format!("{} 至 {}", from.format("%Y-%m-%d"), to.format("%Y-%m-%d")),
+        }
+    }
+}
+
+fn day_start_ts(date: NaiveDate) -> Option<i64> {
+    local_timestamp(date.and_hms_opt(0, 0, 0)?)
+}
+
+/// 一条摘要
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub id: i64,
+    pub granularity: SummaryGranularity,
+    pub start_ts: i64,
+    /// 不包含
+    pub end_ts: i64,
+    pub content: String,
+    /// 小时摘要为活动 ID，日/周摘要为下一级摘要的 ID
+    pub source_ids: Vec<i64>,
+    /// 覆盖的活动记录数
+    pub activity_count: i64,
+}
+
+impl Summary {
+    pub fn label(&self) -> String {
+        self.granularity.label(self.start_ts, self.end_ts)
+    }
+
+    fn from_row(row: &SqliteRow) -> Result<Self> {
+        let granularity: String = row.get("granularity");
+        let source_ids: String = row.get("source_ids");
+        Ok(Self {
+            id: row.get("id"),
+            granularity: SummaryGranularity::parse(&granularity)
+                .ok_or_else(|| anyhow::anyhow!("未知的摘要粒度: {}", granularity))?,
+            start_ts: row.get("start_ts"),
+            end_ts: row.get("end_ts"),
+            content: row.get("content"),
+            source_ids: serde_json::from_str(&source_ids)?,
+            activity_count: row.get("activity_count"),
+        })
+    }
+}
+
+/// 摘要生成参数
+#[derive(Debug, Clone)]
+pub struct SummarizerConfig {
+    /// 区间结束后等这么久再总结，留出 OCR 补全文本的时间
+    pub settle_delay: Duration,
+    /// 单次运行最多调用模型的次数；首次运行补历史时分多次完成
+    pub max_calls_per_run: usize,
+    /// 小时摘要带入的活动记录预算
+    pub hour_budget: ContextBudget,
+    /// 单次调用的超时
+    pub timeout: Duration,
+    /// 最多回溯这么久；更早的活动（如首次开启前的历史）不再总结
+    pub backfill_horizon: Duration,
+    /// 同一区间连续失败这么多次后跳过，不再阻塞上一级的汇总
+    pub max_attempts: u32,
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        Self {
+            settle_delay: Duration::from_secs(15 * 60),
+            max_calls_per_run: 24,
+            hour_budget: ContextBudget {
+                max_tokens: 3_000,
+                max_tokens_per_item: 200,
+                max_items: 60,
+                ..Default::default()
+            },
+            timeout: Duration::from_secs(60),
+            backfill_horizon: Duration::from_secs(7 * DAY_SECS as u64),
+            max_attempts: 3,
+        }
+    }
+}
+
+/// 一次运行新写入的摘要数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryRunStats {
+    pub hours: usize,
+    pub days: usize,
+    pub weeks: usize,
+    /// 连续失败达到上限、或下一级全部被跳过而写入空摘要的区间数
+    pub skipped: usize,
+    /// 是否已处理完所有到期的区间；为 false 时说明达到了单次调用上限，应尽快再运行一次
+    pub caught_up: bool,
+}
+
+impl SummaryRunStats {
+    pub fn written(&self) -> usize {
+        self.hours + self.days + self.weeks
+    }
+
+    fn record(&mut self, granularity: SummaryGranularity) {
+        match granularity {
+            SummaryGranularity::Hour => self.hours += 1,
+            SummaryGranularity::Day => self.days += 1,
+            SummaryGranularity::Week => self.weeks += 1,
+        }
+    }
+}
+
+/// 生成所有到期的摘要（使用全局连接池）
+pub async fn summarize_pending(provider: &dyn LlmProvider, config: &SummarizerConfig) -> Result<SummaryRunStats> {
+    let pool = crate::db::get_pool().await?;
+    summarize_pending_impl(&pool, provider, Local::now().timestamp(), config).await
+}
+
+/// 按小时、日、周的顺序生成截至 `now - settle_delay` 已经结束的区间的摘要
+///
+/// 模型调用失败时返回错误，已写入的摘要保留，下次从失败的区间继续；
+/// 同一区间失败达到 `max_attempts` 次后写入空摘要，跳过该区间继续处理。
+pub async fn summarize_pending_impl(
+    pool: &SqlitePool,
+    provider: &dyn LlmProvider,
+    now: i64,
+    config: &SummarizerConfig,
+) -> Result<SummaryRunStats> {
+    let cutoff = now - config.settle_delay.as_secs() as i64;
+    let horizon = SummaryGranularity::Hour.period_start(cutoff - config.backfill_horizon.as_secs() as i64);
+    let mut stats = SummaryRunStats::default();
+    let mut calls_left = config.max_calls_per_run;
+
+    for granularity in SummaryGranularity::ALL {
+        for start in pending_periods(pool, granularity, cutoff, horizon).await? {
+            // 下一级没有追上之前不汇总上一级
+            if calls_left == 0 {
+                return Ok(stats);
+            }
+            calls_left -= 1;
+
+            let end = granularity.period_end(start);
+            let result = match granularity {
+                SummaryGranularity::Hour => summarize_hour(pool, provider, start, end, config).await.map(|()| true),
+                _ => roll_up(pool, provider, granularity, start, end, config).await,
+            };
+            match result {
+                Ok(true) => {
+                    clear_failure(pool, granularity, start).await?;
+                    stats.record(granularity);
+                }
+                Ok(false) => stats.skipped += 1,
+                Err(e) => {
+                    let attempts = record_failure(pool, granularity, start, &e).await?;
+                    if attempts < config.max_attempts {
+                        return Err(e);
+                    }
+                    tracing::warn!(
+                        "{} 摘要连续失败 {} 次，跳过: {}",
+                        granularity.label(start, end),
+                        attempts,
+                        crate::redact::redact_secrets(&e.to_string())
+                    );
+                    store_summary(pool, provider, granularity, start, end, "", &[], 0).await?;
+                    stats.skipped += 1;
+                }
+            }
+        }
+    }
+
+    stats.caught_up = true;
+    Ok(stats)
+}
+
+/// 水位之后、`cutoff` 之前已经结束、且有内容可总结的区间起点（升序）
+///
+/// 小时摘要不早于 `horizon`；日/周摘要由已有的下一级摘要汇总，自然也不会更早。
+async fn pending_periods(
+    pool: &SqlitePool,
+    granularity: SummaryGranularity,
+    cutoff: i64,
+    horizon: i64,
+) -> Result<Vec<i64>> {
+    let watermark: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(end_ts), 0) FROM summaries WHERE granularity = ?")
+        .bind(granularity.as_str())
+        .fetch_one(pool)
+        .await?;
+
+    let source_ts: Vec<i64> = match granularity.finer() {
+        // 按本地小时分组，每组取一条即可定位区间
+        None => {
+            if watermark < horizon {
+                log_skipped_backfill(pool, watermark, horizon).await?;
+            }
+            sqlx::query_scalar(
+                "SELECT MIN(timestamp) FROM activity_logs
+                 WHERE timestamp >= ? AND timestamp < ?
+                 GROUP BY strftime('%Y-%m-%d %H', timestamp, 'unixepoch', 'localtime')
+                 ORDER BY 1",
+            )
+            .bind(watermark.max(horizon))
+            .bind(cutoff)
+            .fetch_all(pool)
+            .await?
+        }
+        Some(finer) => {
+            sqlx::query_scalar(
+                "SELECT start_ts FROM summaries WHERE granularity = ? AND start_ts >= ? ORDER BY start_ts",
+            )
+            .bind(finer.as_str())
+            .bind(watermark)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    let mut periods: Vec<i64> = Vec::new();
+    for ts in source_ts {
+        let start = granularity.period_start(ts);
+        if granularity.period_end(start) > cutoff {
+            break;
+        }
+        if periods.last() != Some(&start) {
+            periods.push(start);
+        }
+    }
+    Ok(periods)
+}
+
+async fn log_skipped_backfill(pool: &SqlitePool, watermark: i64, horizon: i64) -> Result<()> {
+    let skipped: i64 = sqlx::query_scalar(
+        "SELECT COUNT(DISTINCT strftime('%Y-%m-%d %H', timestamp, 'unixepoch', 'localtime')) FROM activity_logs
+         WHERE timestamp >= ? AND timestamp < ?",
+    )
+    .bind(watermark)
+    .bind(horizon)
+    .fetch_one(pool)
+    .await?;
+    if skipped > 0 {
+        tracing::info!(
+            "活动摘要只回溯到 {}，跳过更早的 {} 个小时",
+            SummaryGranularity::Hour.label(horizon, horizon + HOUR_SECS),
+            skipped
+        );
+    }
+    Ok(())
+}
+
+/// 记录一次失败，返回该区间累计的失败次数
+async fn record_failure(
+    pool: &SqlitePool,
+    granularity: SummaryGranularity,
+    start: i64,
+    error: &anyhow::Error,
+) -> Result<u32> {
+    let attempts: i64 = sqlx::query_scalar(
+        "INSERT INTO summary_failures (granularity, start_ts, attempts, last_error) VALUES (?, ?, 1, ?)
+         ON CONFLICT(granularity, start_ts) DO UPDATE SET
+             attempts = attempts + 1,
+             last_error = excluded.last_error,
+             updated_at = strftime('%s', 'now')
+         RETURNING attempts",
+    )
+    .bind(granularity.as_str())
+    .bind(start)
+    .bind(crate::redact::redact_secrets(&error.to_string()))
+    .fetch_one(pool)
+    .await?;
+    Ok(attempts as u32)
+}
+
+async fn clear_failure(pool: &SqlitePool, granularity: SummaryGranularity, start: i64) -> Result<()> {
+    sqlx::query("DELETE FROM summary_failures WHERE granularity = ? AND start_ts = ?")
+        .bind(granularity.as_str())
+        .bind(start)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn summarize_hour(
+    pool: &SqlitePool,
+    provider: &dyn LlmProvider,
+    start: i64,
+    end: i64,
+    config: &SummarizerConfig,
+) -> Result<()> {
+    let rows = sqlx::query(
+        "SELECT id, timestamp, app_name, window_title, ocr_text FROM activity_logs
+         WHERE timestamp >= ? AND timestamp < ? ORDER BY timestamp",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+
+    let ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+    let items = rows
+        .iter()
+        .map(|row| {
+            ContextItem::new(
+                row.get("id"),
+                row.get("timestamp"),
+                row.get("app_name"),
+                row.get("window_title"),
+                row.get::<Option<String>, _>("ocr_text").as_deref(),
+            )
+        })
+        .collect();
+    let context = assemble_context(items, &config.hour_budget);
+
+    let prompt = format!(
+        "时间：{}\n\n活动记录：\n{}",
+        SummaryGranularity::Hour.label(start, end),
+        context.render("\n\n")
+    );
+    let content = generate(provider, HOUR_SYSTEM_PROMPT, prompt, 400, config.timeout).await?;
+    let count = ids.len() as i64;
+    store_summary(pool, provider, SummaryGranularity::Hour, start, end, &content, &ids, count).await
+}
+
+/// 汇总下一级摘要；下一级全部被跳过时写入空摘要并返回 `false`
+async fn roll_up(
+    pool: &SqlitePool,
+    provider: &dyn LlmProvider,
+    granularity: SummaryGranularity,
+    start: i64,
+    end: i64,
+    config: &SummarizerConfig,
+) -> Result<bool> {
+    let Some(finer) = granularity.finer() else {
+        return Err(anyhow::anyhow!("{} 摘要没有下一级", granularity.as_str()));
+    };
+    let children = load_summaries(pool, finer, start, end - 1).await?;
+    if children.is_empty() {
+        tracing::debug!("{} 的下一级摘要都已跳过", granularity.label(start, end));
+        store_summary(pool, provider, granularity, start, end, "", &[], 0).await?;
+        return Ok(false);
+    }
+
+    let (system_prompt, heading, max_tokens) = match granularity {
+        SummaryGranularity::Day => (DAY_SYSTEM_PROMPT, "各小时的摘要", 800),
+        _ => (WEEK_SYSTEM_PROMPT, "每天的摘要", 1000),
+    };
+    let sections = children
+        .iter()
+        .map(|child| format!("[{}]\n{}", child.label(), child.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let prompt = format!("时间：{}\n\n{}：\n{}", granularity.label(start, end), heading, sections);
+    let content = generate(provider, system_prompt, prompt, max_tokens, config.timeout).await?;
+
+    let ids: Vec<i64> = children.iter().map(|child| child.id).collect();
+    let count = children.iter().map(|child| child.activity_count).sum();
+    store_summary(pool, provider, granularity, start, end, &content, &ids, count).await?;
+    Ok(true)
+}
+
+async fn generate(
+    provider: &dyn LlmProvider,
+    system_prompt: &str,
+    prompt: String,
+    max_tokens: u32,
+    timeout: Duration,
+) -> Result<String> {
+    let request = CompletionRequest {
+        system: Some(system_prompt.to_string()),
+        messages: vec![ChatMessage::user(prompt)],
+        ..Default::default()
+    }
+    .max_tokens(max_tokens)
+    .temperature(0.2);
+
+    let completion = tokio::time::timeout(timeout, provider.complete(&request))
+        .await
+        .map_err(|_| anyhow::anyhow!("{} 生成摘要超时({}s)", provider.name(), timeout.as_secs()))??;
+    let content = completion.text.trim().to_string();
+    if content.is_empty() {
+        return Err(anyhow::anyhow!("{} 返回了空摘要", provider.name()));
+    }
+    Ok(content)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn store_summary(
+    pool: &SqlitePool,
+    provider: &dyn LlmProvider,
+    granularity: SummaryGranularity,
+    start: i64,
+    end: i64,
+    content: &str,
+    source_ids: &[i64],
+    activity_count: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO summaries (granularity, start_ts, end_ts, content, source_ids, activity_count, provider, model)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(granularity, start_ts) DO UPDATE SET
+             end_ts = excluded.end_ts,
+             content = excluded.content,
+             source_ids = excluded.source_ids,
+             activity_count = excluded.activity_count,
+             provider = excluded.provider,
+             model = excluded.model,
+             created_at = strftime('%s', 'now')",
+    )
+    .bind(granularity.as_str())
+    .bind(start)
+    .bind(end)
+    .bind(content)
+    .bind(serde_json::to_string(source_ids)?)
+    .bind(activity_count)
+    .bind(provider.name())
+    .bind(provider.model())
+    .execute(pool)
+    .await?;
+    tracing::debug!("已生成{}摘要: {}", granularity.as_str(), granularity.label(start, end));
+    Ok(())
+}
+
+/// 与 `[from_ts, to_ts]`（两端都包含）有重叠的某一粒度的摘要，按时间升序（不含跳过时写入的空摘要）
+async fn load_summaries(
+    pool: &SqlitePool,
+    granularity: SummaryGranularity,
+    from_ts: i64,
+    to_ts: i64,
+) -> Result<Vec<Summary>> {
+    let rows = sqlx::query(
+        "SELECT id, granularity, start_ts, end_ts, content, source_ids, activity_count FROM summaries
+         WHERE granularity = ? AND start_ts <= ? AND end_ts > ? AND content != ''
+         ORDER BY start_ts",
+    )
+    .bind(granularity.as_str())
+    .bind(to_ts)
+    .bind(from_ts)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(Summary::from_row).collect()
+}
+
+/// 覆盖查询区间的摘要（使用全局连接池）
+pub async fn summaries_for_range(from_ts: i64, to_ts: i64, granularity: SummaryGranularity) -> Result<Vec<Summary>> {
+    let pool = crate::db::get_pool().await?;
+    summaries_for_range_impl(&pool, from_ts, to_ts, granularity).await
+}
+
+/// 覆盖 `[from_ts, to_ts]` 的摘要，按时间升序
+///
+/// 优先使用 `granularity`，该粒度还没有覆盖到的时间段依次用更细的摘要补齐
+/// （例如本周尚未结束时，用已经结束的日摘要加上今天的小时摘要）。
+pub async fn summaries_for_range_impl(
+    pool: &SqlitePool,
+    from_ts: i64,
+    to_ts: i64,
+    granularity: SummaryGranularity,
+) -> Result<Vec<Summary>> {
+    let mut selected: Vec<Summary> = Vec::new();
+    let mut level = Some(granularity);
+    while let Some(current) = level {
+        for summary in load_summaries(pool, current, from_ts, to_ts).await? {
+            let covered = selected
+                .iter()
+                .any(|s| s.start_ts < summary.end_ts && summary.start_ts < s.end_ts);
+            if !covered {
+                selected.push(summary);
+            }
+        }
+        level = current.finer();
+    }
+    selected.sort_by_key(|s| s.start_ts);
+    Ok(selected)
+}
+
+/// 把摘要渲染成问答上下文；超出 token 预算时保留最近的部分
+pub fn render_summaries(summaries: &[Summary], budget: &ContextBudget) -> String {
+    let mut used = 0;
+    let mut kept = Vec::new();
+    for summary in summaries.iter().rev() {
+        let entry = format!("[{}] {}", summary.label(), summary.content);
+        used += budget.estimator.estimate(&entry);
+        if used > budget.max_tokens && !kept.is_empty() {
+            break;
+        }
+        kept.push(entry);
+    }
+    kept.reverse();
+    kept.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::provider::MockLlmProvider;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().timestamp()
+    }
+
+    async fn insert_activity(pool: &SqlitePool, ts: i64, window_title: &str) -> i64 {
+        sqlx::query(
+            "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, ocr_text)
+             VALUES (?, 'Code', ?, 'x.png', ?)",
+        )
+        .bind(ts)
+        .bind(window_title)
+        .bind(format!("{} 的内容", window_title))
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid()
+    }
+
+    #[test]
+    fn periods_align_to_local_calendar() {
+        // 2024-01-10 是周三
+        let ts = local(2024, 1, 10, 14, 25);
+        let hour = SummaryGranularity::Hour.period_start(ts);
+        assert_eq!(hour, local(2024, 1, 10, 14, 0));
+        assert_eq!(SummaryGranularity::Hour.period_end(hour), local(2024, 1, 10, 15, 0));
+        assert_eq!(SummaryGranularity::Day.period_start(ts), local(2024, 1, 10, 0, 0));
+        let week = SummaryGranularity::Week.period_start(ts);
+        assert_eq!(week, local(2024, 1, 8, 0, 0));
+        assert_eq!(SummaryGranularity::Week.period_end(week), local(2024, 1, 15, 0, 0));
+
+        assert_eq!(SummaryGranularity::Hour.label(hour, hour + HOUR_SECS), "2024-01-10 14:00-15:00");
+        assert_eq!(SummaryGranularity::Day.label(local(2024, 1, 10, 0, 0), local(2024, 1, 11, 0, 0)), "2024-01-10 周三");
+        assert_eq!(SummaryGranularity::Week.label(week, local(2024, 1, 15, 0, 0)), "2024-01-08 至 2024-01-14");
+
+        assert_eq!(SummaryGranularity::for_span(0, DAY_SECS - 1), None);
+        assert_eq!(SummaryGranularity::for_span(0, 7 * DAY_SECS - 1), Some(SummaryGranularity::Hour));
+        assert_eq!(SummaryGranularity::for_span(0, 31 * DAY_SECS - 1), Some(SummaryGranularity::Day));
+        assert_eq!(SummaryGranularity::for_span(0, 90 * DAY_SECS), Some(SummaryGranularity::Week));
+    }
+
+    #[tokio::test]
+    async fn rolls_hours_into_days_and_weeks_incrementally() {
+        let pool = setup_pool().await;
+        let provider = MockLlmProvider::default();
+        let config = SummarizerConfig::default();
+
+        // 周一两个小时、周二一个小时有活动
+        let a = insert_activity(&pool, local(2024, 1, 8, 9, 10), "rag.rs").await;
+        let b = insert_activity(&pool, local(2024, 1, 8, 9, 40), "rag.rs").await;
+        insert_activity(&pool, local(2024, 1, 8, 10, 20), "设计文档").await;
+        insert_activity(&pool, local(2024, 1, 9, 14, 5), "周报").await;
+
+        // 周二 14 点这一小时刚结束，还在等待 OCR 补全
+        let stats = summarize_pending_impl(&pool, &provider, local(2024, 1, 9, 15, 5), &config).await.unwrap();
+        assert_eq!((stats.hours, stats.days, stats.weeks, stats.caught_up), (2, 1, 0, true));
+
+        let stats = summarize_pending_impl(&pool, &provider, local(2024, 1, 9, 16, 0), &config).await.unwrap();
+        assert_eq!((stats.hours, stats.days, stats.weeks), (1, 0, 0));
+
+        let monday = load_summaries(&pool, SummaryGranularity::Hour, local(2024, 1, 8, 0, 0), local(2024, 1, 8, 23, 59))
+            .await
+            .unwrap();
+        assert_eq!(monday[0].source_ids, vec![a, b]);
+        assert_eq!(monday[0].start_ts, local(2024, 1, 8, 9, 0));
+        let day = load_summaries(&pool, SummaryGranularity::Day, local(2024, 1, 8, 0, 0), local(2024, 1, 8, 23, 59))
+            .await
+            .unwrap();
+        assert_eq!(day[0].source_ids, vec![monday[0].id, monday[1].id]);
+        assert_eq!(day[0].activity_count, 3);
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].messages[0].content.contains("时间：2024-01-08 09:00-10:00"));
+        assert!(requests[0].messages[0].content.contains("rag.rs 的内容"));
+        assert!(requests[2].messages[0].content.contains("[2024-01-08 10:00-11:00]\nmock: "));
+
+        // 本周尚未结束：周粒度用日摘要加今天的小时摘要补齐
+        let range = summaries_for_range_impl(&pool, local(2024, 1, 8, 0, 0), local(2024, 1, 9, 23, 59), SummaryGranularity::Week)
+            .await
+            .unwrap();
+        let spans: Vec<_> = range.iter().map(|s| s.granularity).collect();
+        assert_eq!(spans, vec![SummaryGranularity::Day, SummaryGranularity::Hour]);
+
+        // 单次调用上限：先补完小时、日，周摘要留到下次
+        let limited = SummarizerConfig {
+            max_calls_per_run: 1,
+            ..SummarizerConfig::default()
+        };
+        let next_monday = local(2024, 1, 15, 1, 0);
+        let stats = summarize_pending_impl(&pool, &provider, next_monday, &limited).await.unwrap();
+        assert_eq!((stats.days, stats.weeks, stats.caught_up), (1, 0, false));
+        let stats = summarize_pending_impl(&pool, &provider, next_monday, &config).await.unwrap();
+        assert_eq!((stats.written(), stats.weeks, stats.caught_up), (1, 1, true));
+
+        let range = summaries_for_range_impl(&pool, local(2024, 1, 1, 0, 0), local(2024, 1, 31, 0, 0), SummaryGranularity::Week)
+            .await
+            .unwrap();
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].activity_count, 4);
+        assert_eq!(range[0].label(), "2024-01-08 至 2024-01-14");
+
+        // 没有新的活动时不再调用模型
+        let calls = provider.requests().len();
+        let stats = summarize_pending_impl(&pool, &provider, next_monday + DAY_SECS, &config).await.unwrap();
+        assert_eq!(stats.written(), 0);
+        assert_eq!(provider.requests().len(), calls);
+    }
+
+    #[tokio::test]
+    async fn failed_hour_is_retried_then_skipped() {
+        let pool = setup_pool().await;
+        let provider = MockLlmProvider::default();
+        provider.push_error("503 Service Unavailable");
+        insert_activity(&pool, local(2024, 1, 8, 9, 10), "rag.rs").await;
+
+        let now = local(2024, 1, 9, 12, 0);
+        let config = SummarizerConfig::default();
+        assert!(summarize_pending_impl(&pool, &provider, now, &config).await.is_err());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM summaries").fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 0);
+
+        // 偶发失败：下次运行重试成功，失败记录随之清除
+        let stats = summarize_pending_impl(&pool, &provider, now, &config).await.unwrap();
+        assert_eq!((stats.hours, stats.days, stats.skipped), (1, 1, 0));
+        let failures: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM summary_failures").fetch_one(&pool).await.unwrap();
+        assert_eq!(failures, 0);
+
+        // 一直失败的小时：达到上限后写入空摘要跳过，当天的其余小时照常汇总
+        let broken = local(2024, 1, 10, 9, 0);
+        insert_activity(&pool, broken + 600, "超长的页面").await;
+        let ok = insert_activity(&pool, local(2024, 1, 10, 10, 30), "设计文档").await;
+        for _ in 0..config.max_attempts {
+            provider.push_error("context length exceeded");
+        }
+        let now = local(2024, 1, 11, 12, 0);
+        for _ in 1..config.max_attempts {
+            assert!(summarize_pending_impl(&pool, &provider, now, &config).await.is_err());
+        }
+        let stats = summarize_pending_impl(&pool, &provider, now, &config).await.unwrap();
+        assert_eq!((stats.hours, stats.days, stats.skipped, stats.caught_up), (1, 1, 1, true));
+
+        let day = load_summaries(&pool, SummaryGranularity::Day, local(2024, 1, 10, 0, 0), local(2024, 1, 10, 23, 59))
+            .await
+            .unwrap();
+        let hours = load_summaries(&pool, SummaryGranularity::Hour, local(2024, 1, 10, 0, 0), local(2024, 1, 10, 23, 59))
+            .await
+            .unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].source_ids, vec![ok]);
+        assert_eq!(day[0].source_ids, vec![hours[0].id]);
+        let attempts: i64 = sqlx::query_scalar("SELECT attempts FROM summary_failures WHERE start_ts = ?")
+            .bind(broken)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(attempts, config.max_attempts as i64);
+
+        // 下一级全部被跳过时上一级也写入空摘要，不调用模型
+        let calls = provider.requests().len();
+        for _ in 0..config.max_attempts {
+            provider.push_error("503 Service Unavailable");
+        }
+        insert_activity(&pool, local(2024, 1, 12, 9, 0), "rag.rs").await;
+        let now = local(2024, 1, 13, 12, 0);
+        for _ in 1..config.max_attempts {
+            assert!(summarize_pending_impl(&pool, &provider, now, &config).await.is_err());
+        }
+        let stats = summarize_pending_impl(&pool, &provider, now, &config).await.unwrap();
+        assert_eq!((stats.written(), stats.skipped), (0, 2));
+        assert_eq!(provider.requests().len(), calls + config.max_attempts as usize);
+    }
+
+    #[tokio::test]
+    async fn first_run_only_backfills_within_horizon() {
+        let pool = setup_pool().await;
+        let provider = MockLlmProvider::default();
+        let config = SummarizerConfig::default();
+
+        insert_activity(&pool, local(2024, 1, 2, 9, 0), "很久以前").await;
+        insert_activity(&pool, local(2023, 12, 1, 9, 0), "更早以前").await;
+        let recent = insert_activity(&pool, local(2024, 1, 10, 9, 0), "rag.rs").await;
+
+        let stats = summarize_pending_impl(&pool, &provider, local(2024, 1, 11, 12, 0), &config).await.unwrap();
+        assert_eq!((stats.hours, stats.days, stats.caught_up), (1, 1, true));
+        let hours = load_summaries(&pool, SummaryGranularity::Hour, 0, i64::MAX).await.unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].source_ids, vec![recent]);
+
+        // 放宽回溯范围后不会回头补水位之前的历史
+        let wide = SummarizerConfig {
+            backfill_horizon: Duration::from_secs(365 * DAY_SECS as u64),
+            ..SummarizerConfig::default()
+        };
+        let stats = summarize_pending_impl(&pool, &provider, local(2024, 1, 11, 12, 0), &wide).await.unwrap();
+        assert_eq!(stats.written(), 0);
+        assert_eq!(provider.requests().len(), 2);
+    }
+}
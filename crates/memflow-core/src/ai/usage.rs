@@ -0,0 +1,638 @@
+//! LLM 调用记录与响应缓存
+//!
+//! [`MeteredLlmProvider`] 包装任意 [`LlmProvider`]：
+//! - 每次调用写入 `llm_calls`（提供商、模型、用途、token 用量、耗时、错误类别），接口未返回用量时按字符数估算
+//! - 可选的响应缓存：按请求内容的 SHA-256 寻址，TTL 内相同请求直接返回缓存结果（只对非流式、可缓存的用途生效）
+//! - 调用方超时或取消时丢弃的调用记为 `cancelled`
+//!
+//! [`llm_usage_summary`] 按提供商/模型/用途汇总用量，供设置页展示。
+
+use crate::ai::context_builder::TokenEstimator;
+use crate::ai::provider::{
+    CancellationToken, Completion, CompletionRequest, HttpFailure, LlmProvider, ModelInfo, TokenUsage,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 调用模型的用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmPurpose {
+    /// 问答回答
+    Chat,
+    /// 查询意图解析
+    Intent,
+    /// Agent 任务提案
+    Proposals,
+    /// 主动上下文的建议操作
+    Suggestions,
+    /// 会话摘要与追问改写
+    Conversation,
+    /// 分层活动摘要
+    Summaries,
+    /// 日报 / 周报的任务总结
+    Digest,
+    /// 设置页的连接测试
+    ConnectionTest,
+}
+
+impl LlmPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LlmPurpose::Chat => "chat",
+            LlmPurpose::Intent => "intent",
+            LlmPurpose::Proposals => "proposals",
+            LlmPurpose::Suggestions => "suggestions",
+            LlmPurpose::Conversation => "conversation",
+            LlmPurpose::Summaries => "summaries",
+            LlmPurpose::Digest => "digest",
+            LlmPurpose::ConnectionTest => "connection_test",
+        }
+    }
+
+    /// 是否允许使用响应缓存：问答回答与连接测试每次都应真实调用
+    pub fn cacheable(&self) -> bool {
+        !matches!(self, LlmPurpose::Chat | LlmPurpose::ConnectionTest)
+    }
+}
+
+/// 调用失败的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmErrorClass {
+    Timeout,
+    /// 429
+    RateLimit,
+    /// 401 / 403
+    Auth,
+    /// 5xx
+    Server,
+    /// 连接失败等网络错误
+    Network,
+    /// 调用方超时或取消，请求被中途丢弃
+    Cancelled,
+    Other,
+}
+
+impl LlmErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LlmErrorClass::Timeout => "timeout",
+            LlmErrorClass::RateLimit => "rate_limit",
+            LlmErrorClass::Auth => "auth",
+            LlmErrorClass::Server => "server",
+            LlmErrorClass::Network => "network",
+            LlmErrorClass::Cancelled => "cancelled",
+            LlmErrorClass::Other => "other",
+        }
+    }
+
+    /// 按 [`HttpFailure`]、错误链中的 reqwest 错误或错误信息中的 HTTP 状态码分类
+    pub fn classify(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<HttpFailure>() {
+            Some(HttpFailure::Status { status, .. }) => return Self::from_status(status.as_u16()),
+            Some(HttpFailure::ReadTimeout { .. }) => return LlmErrorClass::Timeout,
+            Some(HttpFailure::Cancelled { .. }) => return LlmErrorClass::Cancelled,
+            None => {}
+        }
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return LlmErrorClass::Timeout;
+                }
+                if let Some(status) = e.status() {
+                    return Self::from_status(status.as_u16());
+                }
+                if e.is_connect() || e.is_request() {
+                    return LlmErrorClass::Network;
+                }
+            }
+        }
+
+        // 其他实现（如 mock）的错误信息形如 "OpenAI Chat API 返回错误: 429 Too Many Requests - ..."
+        let message = error.to_string();
+        let status_text = message.rsplit("返回错误: ").next().unwrap_or_default();
+        match status_text.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+            Some(status) => Self::from_status(status),
+            None => LlmErrorClass::Other,
+        }
+    }
+
+    fn from_status(status: u16) -> Self {
+        match status {
+            408 => LlmErrorClass::Timeout,
+            429 => LlmErrorClass::RateLimit,
+            401 | 403 => LlmErrorClass::Auth,
+            500..=599 => LlmErrorClass::Server,
+            _ => LlmErrorClass::Other,
+        }
+    }
+}
+
+/// `llm_calls` 中的一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmCallRecord {
+    pub provider: String,
+    pub model: String,
+    pub purpose: LlmPurpose,
+    pub streaming: bool,
+    pub usage: TokenUsage,
+    /// 用量是否为估算值
+    pub usage_estimated: bool,
+    pub latency_ms: i64,
+    pub cached: bool,
+    /// `None` 表示成功
+    pub error_class: Option<LlmErrorClass>,
+}
+
+pub async fn record_llm_call(pool: &SqlitePool, record: &LlmCallRecord) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO llm_calls
+            (provider, model, purpose, streaming, prompt_tokens, completion_tokens, usage_estimated, latency_ms, cached, error_class)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&record.provider)
+    .bind(&record.model)
+    .bind(record.purpose.as_str())
+    .bind(record.streaming)
+    .bind(record.usage.prompt_tokens as i64)
+    .bind(record.usage.completion_tokens as i64)
+    .bind(record.usage_estimated)
+    .bind(record.latency_ms)
+    .bind(record.cached)
+    .bind(record.error_class.map(|class| class.as_str()))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 请求内容的缓存键：提供商、模型与影响输出的全部参数的 SHA-256
+pub fn prompt_hash(provider: &str, model: &str, request: &CompletionRequest) -> String {
+    // serde_json 的对象按键排序，序列化结果稳定
+    let canonical = serde_json::json!({
+        "provider": provider,
+        "model": model,
+        "system": request.system,
+        "messages": request.messages,
+        "max_tokens": request.max_tokens,
+        "temperature": request.temperature,
+        "schema": request.response_schema.as_ref().map(|s| serde_json::json!({ "name": s.name, "schema": s.schema })),
+    });
+    Sha256::digest(canonical.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn cached_completion(pool: &SqlitePool, hash: &str) -> Result<Option<Completion>> {
+    let row: Option<(String, i64, i64)> = sqlx::query_as(
+        "SELECT response, prompt_tokens, completion_tokens FROM llm_cache WHERE prompt_hash = ? AND expires_at > ?",
+    )
+    .bind(hash)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(text, prompt_tokens, completion_tokens)| Completion {
+        text,
+        usage: Some(TokenUsage {
+            prompt_tokens: prompt_tokens as u32,
+            completion_tokens: completion_tokens as u32,
+        }),
+    }))
+}
+
+async fn store_completion(
+    pool: &SqlitePool,
+    hash: &str,
+    provider: &str,
+    model: &str,
+    completion: &Completion,
+    usage: TokenUsage,
+    ttl: Duration,
+) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    // 顺带清理过期条目
+    sqlx::query("DELETE FROM llm_cache WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "INSERT OR REPLACE INTO llm_cache
+            (prompt_hash, provider, model, response, prompt_tokens, completion_tokens, created_at, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(hash)
+    .bind(provider)
+    .bind(model)
+    .bind(&completion.text)
+    .bind(usage.prompt_tokens as i64)
+    .bind(usage.completion_tokens as i64)
+    .bind(now)
+    .bind(now + ttl.as_secs() as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 记录调用并按需使用响应缓存的 provider
+#[derive(Clone)]
+pub struct MeteredLlmProvider {
+    inner: Arc<dyn LlmProvider>,
+    pool: SqlitePool,
+    purpose: LlmPurpose,
+    cache_ttl: Option<Duration>,
+}
+
+impl MeteredLlmProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, pool: SqlitePool, purpose: LlmPurpose) -> Self {
+        Self {
+            inner,
+            pool,
+            purpose,
+            cache_ttl: None,
+        }
+    }
+
+    /// 启用响应缓存（`ttl` 为 0 时不缓存）
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl).filter(|ttl| !ttl.is_zero());
+        self
+    }
+
+    /// 同一 provider 换一个用途记录（如问答流程中的意图解析）
+    pub fn with_purpose(&self, purpose: LlmPurpose) -> Self {
+        Self {
+            purpose,
+            ..self.clone()
+        }
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl.filter(|_| self.purpose.cacheable())
+    }
+
+    fn start_call(&self, streaming: bool) -> PendingCall {
+        PendingCall {
+            pool: self.pool.clone(),
+            provider: self.inner.name().to_string(),
+            model: self.inner.model().to_string(),
+            purpose: self.purpose,
+            streaming,
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+}
+
+/// 进行中的调用；未调用 [`PendingCall::finish`] 就被丢弃时记为 [`LlmErrorClass::Cancelled`]
+struct PendingCall {
+    pool: SqlitePool,
+    provider: String,
+    model: String,
+    purpose: LlmPurpose,
+    streaming: bool,
+    started: Instant,
+    finished: bool,
+}
+
+impl PendingCall {
+    fn record(&self, usage: TokenUsage, usage_estimated: bool, cached: bool, error_class: Option<LlmErrorClass>) -> LlmCallRecord {
+        LlmCallRecord {
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            purpose: self.purpose,
+            streaming: self.streaming,
+            usage,
+            usage_estimated,
+            latency_ms: self.started.elapsed().as_millis() as i64,
+            cached,
+            error_class,
+        }
+    }
+
+    /// 按调用结果写入记录；返回实际（或估算）的用量
+    ///
+    /// 流式调用被取消时仍按已生成的部分记录用量，错误类别为 [`LlmErrorClass::Cancelled`]。
+    async fn finish(mut self, request: &CompletionRequest, result: &Result<Completion>, cancelled: bool) -> TokenUsage {
+        self.finished = true;
+        let cancelled = cancelled.then_some(LlmErrorClass::Cancelled);
+        let (usage, estimated, error_class) = match result {
+            Ok(Completion { usage: Some(usage), .. }) => (*usage, false, cancelled),
+            Ok(completion) => (estimate_usage(&self.model, request, &completion.text), true, cancelled),
+            Err(e) => (TokenUsage::default(), false, Some(LlmErrorClass::classify(e))),
+        };
+        let record = self.record(usage, estimated, false, error_class);
+        if let Err(e) = record_llm_call(&self.pool, &record).await {
+            tracing::warn!("记录 LLM 调用失败: {}", e);
+        }
+        usage
+    }
+
+    async fn finish_cached(mut self, usage: TokenUsage) {
+        self.finished = true;
+        let record = self.record(usage, false, true, None);
+        if let Err(e) = record_llm_call(&self.pool, &record).await {
+            tracing::warn!("记录 LLM 调用失败: {}", e);
+        }
+    }
+}
+
+impl Drop for PendingCall {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let record = self.record(TokenUsage::default(), false, false, Some(LlmErrorClass::Cancelled));
+        handle.spawn(async move {
+            if let Err(e) = record_llm_call(&pool, &record).await {
+                tracing::warn!("记录 LLM 调用失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 接口未返回用量时按字符数估算
+fn estimate_usage(model: &str, request: &CompletionRequest, text: &str) -> TokenUsage {
+    let estimator = TokenEstimator::for_model(model);
+    let prompt = request.system.iter().chain(request.messages.iter().map(|m| &m.content));
+    TokenUsage {
+        prompt_tokens: prompt.map(|s| estimator.estimate(s)).sum::<usize>() as u32,
+        completion_tokens: estimator.estimate(text) as u32,
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MeteredLlmProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion> {
+        let cache = self
+            .cache_ttl()
+            .map(|ttl| (ttl, prompt_hash(self.inner.name(), self.inner.model(), request)));
+
+        let call = self.start_call(false);
+        if let Some((_, hash)) = &cache {
+            match cached_completion(&self.pool, hash).await {
+                Ok(Some(completion)) => {
+                    tracing::debug!("LLM 响应缓存命中: {} ({})", hash, self.purpose.as_str());
+                    call.finish_cached(completion.usage.unwrap_or_default()).await;
+                    return Ok(completion);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("读取 LLM 响应缓存失败: {}", e),
+            }
+        }
+
+        let result = self.inner.complete(request).await;
+        let usage = call.finish(request, &result, false).await;
+        if let (Some((ttl, hash)), Ok(completion)) = (&cache, &result) {
+            let stored = store_completion(&self.pool, hash, self.name(), self.model(), completion, usage, *ttl).await;
+            if let Err(e) = stored {
+                tracing::warn!("写入 LLM 响应缓存失败: {}", e);
+            }
+        }
+        result
+    }
+
+    async fn stream(
+        &self,
+        request: &CompletionRequest,
+        on_chunk: &(dyn Fn(String) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<Completion> {
+        let call = self.start_call(true);
+        let result = self.inner.stream(request, on_chunk, cancel).await;
+        call.finish(request, &result, cancel.is_cancelled()).await;
+        result
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed(texts).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        self.inner.list_models().await
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        self.inner.supports_json_schema()
+    }
+}
+
+/// 某个提供商/模型/用途的用量
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmUsageRow {
+    pub provider: String,
+    pub model: String,
+    pub purpose: String,
+    pub calls: i64,
+    pub cache_hits: i64,
+    pub errors: i64,
+    /// 实际消耗的 token（不含缓存命中）
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// 缓存命中省下的 token
+    pub saved_tokens: i64,
+    /// 实际调用的平均耗时（没有实际调用时为 `None`）
+    pub avg_latency_ms: Option<i64>,
+}
+
+/// 一段时间内的用量汇总
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmUsageSummary {
+    pub since_ts: i64,
+    pub calls: i64,
+    pub cache_hits: i64,
+    pub errors: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub saved_tokens: i64,
+    /// 按消耗 token 从多到少排列
+    pub rows: Vec<LlmUsageRow>,
+}
+
+/// 汇总 `since_ts`（Unix 秒）之后的调用
+pub async fn llm_usage_summary(pool: &SqlitePool, since_ts: i64) -> Result<LlmUsageSummary> {
+    let rows: Vec<LlmUsageRow> = sqlx::query_as(
+        "SELECT provider, model, purpose,
+                COUNT(*) AS calls,
+                SUM(cached) AS cache_hits,
+                SUM(error_class IS NOT NULL) AS errors,
+                SUM(CASE WHEN cached = 0 THEN prompt_tokens ELSE 0 END) AS prompt_tokens,
+                SUM(CASE WHEN cached = 0 THEN completion_tokens ELSE 0 END) AS completion_tokens,
+                SUM(CASE WHEN cached = 1 THEN prompt_tokens + completion_tokens ELSE 0 END) AS saved_tokens,
+                CAST(AVG(CASE WHEN cached = 0 THEN latency_ms END) AS INTEGER) AS avg_latency_ms
+         FROM llm_calls
+         WHERE created_at >= ?
+         GROUP BY provider, model, purpose
+         ORDER BY SUM(CASE WHEN cached = 0 THEN prompt_tokens + completion_tokens ELSE 0 END) DESC, calls DESC",
+    )
+    .bind(since_ts)
+    .fetch_all(pool)
+    .await?;
+
+    let mut summary = LlmUsageSummary {
+        since_ts,
+        ..Default::default()
+    };
+    for row in &rows {
+        summary.calls += row.calls;
+        summary.cache_hits += row.cache_hits;
+        summary.errors += row.errors;
+        summary.prompt_tokens += row.prompt_tokens;
+        summary.completion_tokens += row.completion_tokens;
+        summary.saved_tokens += row.saved_tokens;
+    }
+    summary.rows = rows;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::provider::MockLlmProvider;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    /// 永远不返回的 provider，用于模拟调用方超时
+    struct HangingProvider;
+
+    #[async_trait]
+    impl LlmProvider for HangingProvider {
+        fn name(&self) -> &str {
+            "Hanging"
+        }
+
+        fn model(&self) -> &str {
+            "slow"
+        }
+
+        async fn complete(&self, _request: &CompletionRequest) -> Result<Completion> {
+            std::future::pending().await
+        }
+
+        async fn stream(
+            &self,
+            _request: &CompletionRequest,
+            _on_chunk: &(dyn Fn(String) + Send + Sync),
+            _cancel: &CancellationToken,
+        ) -> Result<Completion> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn records_calls_and_serves_repeated_requests_from_cache() {
+        let pool = setup_pool().await;
+        let mock = Arc::new(MockLlmProvider::with_responses(["第一次", "第二次"]));
+        mock.push_error("OpenAI Chat API 返回错误: 429 Too Many Requests - slow down");
+        let provider = MeteredLlmProvider::new(mock.clone(), pool.clone(), LlmPurpose::Suggestions)
+            .with_cache_ttl(Duration::from_secs(600));
+        let request = CompletionRequest::from_query("建议", "[10:00] 应用: Code", None);
+
+        // 相同请求在 TTL 内命中缓存，不再调用模型
+        assert_eq!(provider.complete(&request).await.unwrap().text, "第一次");
+        assert_eq!(provider.complete(&request).await.unwrap().text, "第一次");
+        assert_eq!(mock.requests().len(), 1);
+
+        // 过期后重新调用
+        sqlx::query("UPDATE llm_cache SET expires_at = 0").execute(&pool).await.unwrap();
+        assert_eq!(provider.complete(&request).await.unwrap().text, "第二次");
+
+        // 问答回答不走缓存；失败按状态码分类
+        let chat = provider.with_purpose(LlmPurpose::Chat);
+        assert!(chat.complete(&request).await.is_err());
+        assert_eq!(chat.complete(&request).await.unwrap().text, "mock: 建议\n\n--- 相关桌面活动记录 ---\n[10:00] 应用: Code");
+        chat.stream(&request, &|_| {}, &CancellationToken::new()).await.unwrap();
+        assert_eq!(mock.requests().len(), 5);
+
+        let classes: Vec<(String, i64, i64, Option<String>)> =
+            sqlx::query_as("SELECT purpose, cached, streaming, error_class FROM llm_calls ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let expected = [
+            ("suggestions", 0, 0, None),
+            ("suggestions", 1, 0, None),
+            ("suggestions", 0, 0, None),
+            ("chat", 0, 0, Some("rate_limit")),
+            ("chat", 0, 0, None),
+            ("chat", 0, 1, None),
+        ];
+        assert_eq!(classes.len(), expected.len());
+        for (row, (purpose, cached, streaming, error)) in classes.iter().zip(expected) {
+            assert_eq!((row.0.as_str(), row.1, row.2, row.3.as_deref()), (purpose, cached, streaming, error));
+        }
+
+        let summary = llm_usage_summary(&pool, 0).await.unwrap();
+        assert_eq!((summary.calls, summary.cache_hits, summary.errors), (6, 1, 1));
+        assert_eq!(summary.rows.len(), 2);
+        let suggestions = summary.rows.iter().find(|r| r.purpose == "suggestions").unwrap();
+        assert_eq!(suggestions.calls, 3);
+        assert!(suggestions.saved_tokens > 0);
+        assert_eq!(suggestions.prompt_tokens + suggestions.completion_tokens, 2 * suggestions.saved_tokens);
+        assert_eq!(
+            summary.prompt_tokens + summary.completion_tokens,
+            summary.rows.iter().map(|r| r.prompt_tokens + r.completion_tokens).sum::<i64>()
+        );
+        assert!(llm_usage_summary(&pool, i64::MAX).await.unwrap().rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn abandoned_calls_are_recorded_as_cancelled() {
+        let pool = setup_pool().await;
+        let provider = MeteredLlmProvider::new(Arc::new(HangingProvider), pool.clone(), LlmPurpose::Intent);
+        let request = CompletionRequest::from_query("今天", "", None);
+
+        let result = tokio::time::timeout(Duration::from_millis(20), provider.complete(&request)).await;
+        assert!(result.is_err());
+
+        let mut error_class: Option<String> = None;
+        for _ in 0..50 {
+            error_class = sqlx::query_scalar("SELECT error_class FROM llm_calls")
+                .fetch_optional(&pool)
+                .await
+                .unwrap()
+                .flatten();
+            if error_class.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(error_class.as_deref(), Some("cancelled"));
+
+        assert_eq!(
+            LlmErrorClass::classify(&anyhow::anyhow!("Anthropic Chat API 返回错误: 401 Unauthorized - bad key")),
+            LlmErrorClass::Auth
+        );
+        assert_eq!(LlmErrorClass::classify(&anyhow::anyhow!("503 Service Unavailable")), LlmErrorClass::Server);
+        assert_eq!(LlmErrorClass::classify(&anyhow::anyhow!("Ollama 返回错误: model not found")), LlmErrorClass::Other);
+    }
+}
@@ -0,0 +1,315 @@
+//! 活动文本分块的存储与检索
+//!
+//! 分块由 [`crate::ai::chunker`] 生成，每块有独立的向量（`chunk_embeddings`）和
+//! FTS 记录（`activity_chunks_fts`，由触发器维护）。
+
+use crate::ai::chunker::TextChunk;
+use crate::vector_db::{cosine_similarity, decode_vector, encode_vector, Embedding};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+/// 活动 OCR 文本中的一个块，偏移为 `ocr_text` 中的字节区间
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityChunk {
+    pub id: i64,
+    pub activity_id: i64,
+    pub chunk_index: i64,
+    pub text: String,
+    pub start_offset: i64,
+    pub end_offset: i64,
+}
+
+/// 分块检索结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkSearchResult {
+    pub chunk_id: i64,
+    pub activity_id: i64,
+    pub score: f64,
+}
+
+/// 写入活动的分块，返回与 `chunks` 顺序一致的块 ID
+///
+/// 分块与已有记录完全相同时保留原记录（以及其他模型已生成的向量），否则整体替换。
+pub async fn replace_chunks_impl(pool: &SqlitePool, activity_id: i64, chunks: &[TextChunk]) -> Result<Vec<i64>> {
+    let existing = get_activity_chunks_impl(pool, activity_id).await?;
+    let unchanged = existing.len() == chunks.len()
+        && existing.iter().zip(chunks).all(|(old, new)| {
+            old.text == new.text && old.start_offset == new.start as i64 && old.end_offset == new.end as i64
+        });
+    if unchanged {
+        return Ok(existing.into_iter().map(|c| c.id).collect());
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM activity_chunks WHERE activity_id = ?")
+        .bind(activity_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut ids = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let text_seg = crate::ai::nlp::segment_for_index(&chunk.text).unwrap_or_default();
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO activity_chunks (activity_id, chunk_index, text, text_seg, start_offset, end_offset)
+             VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(activity_id)
+        .bind(chunk.index as i64)
+        .bind(&chunk.text)
+        .bind(text_seg)
+        .bind(chunk.start as i64)
+        .bind(chunk.end as i64)
+        .fetch_one(&mut *tx)
+        .await?;
+        ids.push(id);
+    }
+    tx.commit().await?;
+    Ok(ids)
+}
+
+/// 按顺序获取活动的全部分块
+pub async fn get_activity_chunks_impl(pool: &SqlitePool, activity_id: i64) -> Result<Vec<ActivityChunk>> {
+    let chunks = sqlx::query_as::<_, ActivityChunk>(
+        "SELECT id, activity_id, chunk_index, text, start_offset, end_offset
+         FROM activity_chunks WHERE activity_id = ? ORDER BY chunk_index",
+    )
+    .bind(activity_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(chunks)
+}
+
+/// 按 ID 批量获取分块
+pub async fn get_chunks_impl(pool: &SqlitePool, ids: &[i64]) -> Result<Vec<ActivityChunk>> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut builder = sqlx::QueryBuilder::new(
+        "SELECT id, activity_id, chunk_index, text, start_offset, end_offset FROM activity_chunks WHERE id IN (",
+    );
+    let mut separated = builder.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+
+    Ok(builder.build_query_as::<ActivityChunk>().fetch_all(pool).await?)
+}
+
+/// 写入（或替换）一个块在指定模型下的向量
+pub async fn insert_chunk_embedding_impl(
+    pool: &SqlitePool,
+    chunk_id: i64,
+    activity_id: i64,
+    embedding: &Embedding,
+) -> Result<()> {
+    if embedding.vector.is_empty() {
+        return Err(anyhow::anyhow!("Empty embedding vector for chunk {}", chunk_id));
+    }
+
+    sqlx::query(
+        "INSERT INTO chunk_embeddings (chunk_id, activity_id, model_id, dim, embedding) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(chunk_id, model_id) DO UPDATE SET
+             dim = excluded.dim,
+             embedding = excluded.embedding,
+             created_at = strftime('%s', 'now')",
+    )
+    .bind(chunk_id)
+    .bind(activity_id)
+    .bind(&embedding.model_id)
+    .bind(embedding.dim() as i64)
+    .bind(encode_vector(&embedding.vector))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 分块关键词检索，按相关度降序返回命中的块
+///
+/// `fts_query` 应已经过 [`crate::ai::nlp::segment_fts_query`] 改写；
+/// `score` 为取反后的 bm25（越大越相关），只适合在同一次查询的结果之间比较。
+pub async fn search_chunks_fts_impl(pool: &SqlitePool, fts_query: &str, limit: usize) -> Result<Vec<ChunkSearchResult>> {
+    let rows = sqlx::query(
+        "SELECT c.id, c.activity_id, -bm25(activity_chunks_fts)
+         FROM activity_chunks_fts f
+         JOIN activity_chunks c ON c.id = f.rowid
+         WHERE activity_chunks_fts MATCH ?
+         ORDER BY bm25(activity_chunks_fts)
+         LIMIT ?",
+    )
+    .bind(fts_query)
+    .bind(limit as i64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ChunkSearchResult {
+            chunk_id: row.get(0),
+            activity_id: row.get(1),
+            score: row.get(2),
+        })
+        .collect())
+}
+
+/// 在指定活动的分块中做精确向量检索，按相似度降序返回
+///
+/// 只比较与 `query` 相同模型和维度的向量。
+pub async fn search_similar_chunks_impl(
+    pool: &SqlitePool,
+    query: &Embedding,
+    activity_ids: &[i64],
+) -> Result<Vec<ChunkSearchResult>> {
+    if query.vector.is_empty() {
+        return Err(anyhow::anyhow!("Query vector is empty"));
+    }
+    if activity_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut builder = sqlx::QueryBuilder::new(
+        "SELECT chunk_id, activity_id, embedding FROM chunk_embeddings WHERE model_id = ",
+    );
+    builder.push_bind(&query.model_id);
+    builder.push(" AND dim = ");
+    builder.push_bind(query.dim() as i64);
+    builder.push(" AND activity_id IN (");
+    let mut separated = builder.separated(", ");
+    for id in activity_ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+
+    let rows = builder.build().fetch_all(pool).await?;
+
+    let mut results: Vec<ChunkSearchResult> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let chunk_id: i64 = row.get(0);
+            let vector = decode_vector(&row.get::<Vec<u8>, _>(2)).or_else(|| {
+                tracing::warn!("跳过损坏的分块向量: chunk_id={}", chunk_id);
+                None
+            })?;
+            Some(ChunkSearchResult {
+                chunk_id,
+                activity_id: row.get(1),
+                score: cosine_similarity(&query.vector, &vector),
+            })
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(results)
+}
+
+/// 将各块的向量平均并归一化，作为活动整体的向量
+pub fn mean_embedding(embeddings: &[Embedding]) -> Option<Embedding> {
+    let first = embeddings.first()?;
+    let dim = first.dim();
+    let mut sum = vec![0f32; dim];
+    for embedding in embeddings.iter().filter(|e| e.dim() == dim) {
+        for (acc, v) in sum.iter_mut().zip(&embedding.vector) {
+            *acc += v;
+        }
+    }
+
+    let norm = sum.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        sum.iter_mut().for_each(|v| *v /= norm);
+    }
+    Some(Embedding::new(first.model_id.clone(), sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::chunker::{chunk_text, ChunkerConfig};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        for id in 1..=2 {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (?, 0, 'Code', 'title', '')")
+                .bind(id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    fn chunks_of(text: &str) -> Vec<TextChunk> {
+        chunk_text(text, &ChunkerConfig { max_chars: 20, overlap_chars: 0 })
+    }
+
+    #[tokio::test]
+    async fn replace_keeps_unchanged_chunks_and_cascades() {
+        let pool = setup_pool().await;
+        let chunks = chunks_of("数据库迁移失败了。重启之后恢复正常。Deploy finished at noon.");
+        let ids = replace_chunks_impl(&pool, 1, &chunks).await.unwrap();
+        assert_eq!(ids.len(), 3);
+        insert_chunk_embedding_impl(&pool, ids[0], 1, &Embedding::new("m", vec![1.0, 0.0]))
+            .await
+            .unwrap();
+
+        // 文本未变化时保留原块及其向量
+        assert_eq!(replace_chunks_impl(&pool, 1, &chunks).await.unwrap(), ids);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chunk_embeddings")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // 中文块通过预分词命中
+        let hits = search_chunks_fts_impl(&pool, "迁移", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chunk_id, ids[0]);
+        assert!(hits[0].score > 0.0);
+        let chunk = &get_chunks_impl(&pool, &[ids[0]]).await.unwrap()[0];
+        assert_eq!(chunk.text, "数据库迁移失败了。重启之后恢复正常。");
+
+        // 文本变化后整体替换，旧块的 FTS 与向量一并删除
+        let new_ids = replace_chunks_impl(&pool, 1, &chunks_of("Only one sentence.")).await.unwrap();
+        assert_eq!(new_ids.len(), 1);
+        assert!(search_chunks_fts_impl(&pool, "迁移", 10).await.unwrap().is_empty());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chunk_embeddings")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // 删除活动时级联删除分块
+        sqlx::query("DELETE FROM activity_logs WHERE id = 1").execute(&pool).await.unwrap();
+        assert!(get_activity_chunks_impl(&pool, 1).await.unwrap().is_empty());
+        assert!(search_chunks_fts_impl(&pool, "sentence", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn similar_chunks_filter_by_activity_and_model() {
+        let pool = setup_pool().await;
+        let a = replace_chunks_impl(&pool, 1, &chunks_of("First part here. Second part here.")).await.unwrap();
+        let b = replace_chunks_impl(&pool, 2, &chunks_of("Other activity.")).await.unwrap();
+        insert_chunk_embedding_impl(&pool, a[0], 1, &Embedding::new("m", vec![1.0, 0.0])).await.unwrap();
+        insert_chunk_embedding_impl(&pool, a[1], 1, &Embedding::new("m", vec![0.0, 1.0])).await.unwrap();
+        insert_chunk_embedding_impl(&pool, a[1], 1, &Embedding::new("other", vec![1.0, 0.0])).await.unwrap();
+        insert_chunk_embedding_impl(&pool, b[0], 2, &Embedding::new("m", vec![0.0, 1.0])).await.unwrap();
+
+        let query = Embedding::new("m", vec![0.1, 1.0]);
+        let results = search_similar_chunks_impl(&pool, &query, &[1]).await.unwrap();
+        assert_eq!(results.iter().map(|r| r.chunk_id).collect::<Vec<_>>(), vec![a[1], a[0]]);
+        assert!(results.iter().all(|r| r.activity_id == 1));
+
+        let mean = mean_embedding(&[Embedding::new("m", vec![1.0, 0.0]), Embedding::new("m", vec![0.0, 1.0])]).unwrap();
+        assert!((mean.vector[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!(mean_embedding(&[]).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::Datelike;
 use anyhow::Result;
+use crate::highlight::{self, HighlightOptions, MatchField, MatchSpan};
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool};
 use sqlx::{QueryBuilder, Row};
@@ -57,6 +58,7 @@ pub async fn init_db_with_path(db_path: PathBuf, screenshots_dir: PathBuf) -> Re
     // Create parent directory if needed
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent)?;
+        crate::vector_index::set_index_dir(parent.join("vector_index")).await;
     }
 
     // Create screenshots directory
@@ -194,9 +196,11 @@ pub async fn insert_activity(
 ) -> Result<i64> {
     let pool = get_pool().await?;
 
+    let window_title_seg = crate::ai::nlp::segment_for_index(window_title).unwrap_or_default();
+
     let id = sqlx::query(
-        "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, phash, app_path) 
-         VALUES (?, ?, ?, ?, ?, ?)",
+        "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, phash, app_path, window_title_seg) 
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(timestamp)
     .bind(app_name)
@@ -204,6 +208,7 @@ pub async fn insert_activity(
     .bind(image_path)
     .bind(phash)
     .bind(app_path)
+    .bind(window_title_seg)
     .execute(&pool)
     .await?
     .last_insert_rowid();
@@ -214,16 +219,80 @@ pub async fn insert_activity(
 /// 更新活动的 OCR 文本
 pub async fn update_activity_ocr(id: i64, ocr_text: &str) -> Result<()> {
     let pool = get_pool().await?;
+    update_activity_ocr_impl(&pool, id, ocr_text).await
+}
+
+/// 内部实现：同时写入分词影子列，FTS 触发器会据此重建索引
+pub async fn update_activity_ocr_impl(pool: &SqlitePool, id: i64, ocr_text: &str) -> Result<()> {
+    let ocr_text_seg = crate::ai::nlp::segment_for_index(ocr_text).unwrap_or_default();
 
-    sqlx::query("UPDATE activity_logs SET ocr_text = ? WHERE id = ?")
+    sqlx::query("UPDATE activity_logs SET ocr_text = ?, ocr_text_seg = ? WHERE id = ?")
         .bind(ocr_text)
+        .bind(ocr_text_seg)
         .bind(id)
-        .execute(&pool)
+        .execute(pool)
         .await?;
 
     Ok(())
 }
 
+/// 为尚未分词的历史记录补写分词影子列（见 migration 0012）
+///
+/// 按 id 分批处理，可重复执行；返回本次处理的记录数。
+pub async fn reindex_segmented_fts(batch_size: i64) -> Result<usize> {
+    let pool = get_pool().await?;
+    reindex_segmented_fts_impl(&pool, batch_size).await
+}
+
+pub async fn reindex_segmented_fts_impl(pool: &SqlitePool, batch_size: i64) -> Result<usize> {
+    let mut last_id = 0_i64;
+    let mut processed = 0_usize;
+
+    loop {
+        let rows = sqlx::query(
+            "SELECT id, window_title, ocr_text FROM activity_logs 
+             WHERE id > ? AND (window_title_seg IS NULL OR (ocr_text IS NOT NULL AND ocr_text_seg IS NULL)) 
+             ORDER BY id 
+             LIMIT ?",
+        )
+        .bind(last_id)
+        .bind(batch_size)
+        .fetch_all(pool)
+        .await?;
+
+        let Some(last) = rows.last() else { break };
+        last_id = last.get(0);
+
+        let mut tx = pool.begin().await?;
+        for row in &rows {
+            let id: i64 = row.get(0);
+            let window_title: Option<String> = row.get(1);
+            let ocr_text: Option<String> = row.get(2);
+
+            let title_seg = window_title
+                .as_deref()
+                .and_then(crate::ai::nlp::segment_for_index)
+                .unwrap_or_default();
+            let ocr_seg = ocr_text
+                .as_deref()
+                .map(|t| crate::ai::nlp::segment_for_index(t).unwrap_or_default());
+
+            sqlx::query("UPDATE activity_logs SET window_title_seg = ?, ocr_text_seg = ? WHERE id = ?")
+                .bind(title_seg)
+                .bind(ocr_seg)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        processed += rows.len();
+        tracing::debug!("分词索引重建进度: {} 条 (last_id={})", processed, last_id);
+    }
+
+    Ok(processed)
+}
+
 #[derive(Debug, serde::Serialize, sqlx::FromRow)]
 pub struct RecordingStat {
     pub date: String,
@@ -301,169 +370,483 @@ pub async fn find_activity_by_phash(phash: &str) -> Result<Option<i64>> {
     Ok(result.flatten())
 }
 
-pub async fn search_activities(
-    query: Option<String>,
-    app_name: Option<String>,
-    from_ts: Option<i64>,
-    to_ts: Option<i64>,
-    has_ocr: Option<bool>,
-    limit: Option<i64>,
-    offset: Option<i64>,
-    order_by: Option<String>,
-) -> Result<(Vec<ActivityLog>, i64)> {
-    let pool = get_pool().await?;
-    search_activities_impl(&pool, query, app_name, from_ts, to_ts, has_ocr, limit, offset, order_by).await
+/// `order_by = "rank"` 时使用的 bm25 表达式，列权重依次对应
+/// (window_title, app_name, ocr_text)，见 migration 0011。
+///
+/// 标题命中比 OCR 正文里的偶然出现更能代表活动主题，因此权重更高。
+pub(crate) const FTS_RANK_EXPR: &str = "bm25(activity_logs_fts, 10.0, 5.0, 1.0)";
+
+/// 活动检索条件
+///
+/// 所有过滤条件都在 `push_filters` 中统一生成 WHERE 子句，
+/// COUNT 查询与分页查询共用同一份逻辑，新增过滤条件只需改动这一处。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ActivityQuery {
+    /// FTS5 MATCH 表达式
+    pub query: Option<String>,
+    /// 排除命中该 FTS5 MATCH 表达式的记录（FTS5 不支持单独的 NOT 查询）
+    pub exclude_query: Option<String>,
+    /// 应用名（模糊匹配，忽略 `.exe` 后缀），多个之间为 OR
+    pub apps: Vec<String>,
+    /// 排除的应用名（匹配规则同 `apps`）
+    pub exclude_apps: Vec<String>,
+    /// 窗口标题子串（不区分大小写）
+    pub window_title: Option<String>,
+    /// 应用路径子串（不区分大小写）
+    pub app_path: Option<String>,
+    /// 限定活动 ID；`Some(vec![])` 表示不匹配任何记录
+    pub ids: Option<Vec<i64>>,
+    pub from_ts: Option<i64>,
+    pub to_ts: Option<i64>,
+    pub has_ocr: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// `"time"`（默认）或 `"rank"`（仅在有 query 时生效）
+    pub order_by: Option<String>,
+    /// 游标分页：只返回排在该游标之后的记录（按时间倒序）
+    pub cursor: Option<ActivityCursor>,
+    /// 为命中生成摘要与匹配位置（仅 [`search_activity_hits`] 使用）
+    pub highlight: Option<HighlightOptions>,
 }
 
-/// 内部实现，接受 pool 参数以便于单元测试
-pub async fn search_activities_impl(
-    pool: &SqlitePool,
-    query: Option<String>,
-    app_name: Option<String>,
-    from_ts: Option<i64>,
-    to_ts: Option<i64>,
-    has_ocr: Option<bool>,
-    limit: Option<i64>,
-    offset: Option<i64>,
-    order_by: Option<String>,
-) -> Result<(Vec<ActivityLog>, i64)> {
-    let has_query = query.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
+/// 基于 `(timestamp, id)` 的分页游标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityCursor {
+    pub timestamp: i64,
+    pub id: i64,
+}
+
+impl From<&ActivityLog> for ActivityCursor {
+    fn from(activity: &ActivityLog) -> Self {
+        Self {
+            timestamp: activity.timestamp,
+            id: activity.id,
+        }
+    }
+}
+
+/// 带摘要与匹配位置的检索结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityHit {
+    #[serde(flatten)]
+    pub activity: ActivityLog,
+    /// 围绕第一处命中截取的紧凑摘要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// 整段高亮后的 OCR 文本（需开启 `HighlightOptions::full_text`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<String>,
+    /// 命中在原文中的字节偏移
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<MatchSpan>,
+}
+
+impl From<ActivityLog> for ActivityHit {
+    fn from(activity: ActivityLog) -> Self {
+        Self {
+            activity,
+            snippet: None,
+            highlight: None,
+            matches: Vec::new(),
+        }
+    }
+}
+
+impl ActivityHit {
+    /// `marked` 为 FTS5 highlight() 对 (window_title, ocr_text) 的输出；没有全文查询时为 `None`
+    fn highlighted(
+        activity: ActivityLog,
+        marked: Option<(String, String)>,
+        options: &HighlightOptions,
+    ) -> Self {
+        let (title_marked, ocr_marked) = marked.unwrap_or_default();
+        let ocr_text = activity.ocr_text.as_deref().unwrap_or_default();
+
+        let title_spans = highlight::find_term_spans(
+            &activity.window_title,
+            &highlight::marked_terms(&title_marked),
+        );
+        let ocr_spans = highlight::find_term_spans(ocr_text, &highlight::marked_terms(&ocr_marked));
+
+        let snippet = if !ocr_spans.is_empty() || (title_spans.is_empty() && !ocr_text.trim().is_empty()) {
+            highlight::build_snippet(ocr_text, &ocr_spans, options)
+        } else {
+            highlight::build_snippet(&activity.window_title, &title_spans, options)
+        };
+        let full = options
+            .full_text
+            .then(|| highlight::apply_marks(ocr_text, &ocr_spans, options));
+
+        let matches = title_spans
+            .iter()
+            .map(|&(start, end)| MatchSpan { field: MatchField::WindowTitle, start, end })
+            .chain(
+                ocr_spans
+                    .iter()
+                    .map(|&(start, end)| MatchSpan { field: MatchField::OcrText, start, end }),
+            )
+            .collect();
+
+        Self {
+            activity,
+            snippet: Some(snippet),
+            highlight: full,
+            matches,
+        }
+    }
+}
+
+impl ActivityQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+
+    pub fn exclude_query(mut self, query: impl Into<String>) -> Self {
+        self.exclude_query = Some(query.into());
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.apps.push(app.into());
+        self
+    }
+
+    pub fn apps<I, S>(mut self, apps: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.apps.extend(apps.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn exclude_app(mut self, app: impl Into<String>) -> Self {
+        self.exclude_apps.push(app.into());
+        self
+    }
+
+    pub fn exclude_apps<I, S>(mut self, apps: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_apps.extend(apps.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn window_title(mut self, title: impl Into<String>) -> Self {
+        self.window_title = Some(title.into());
+        self
+    }
+
+    pub fn app_path(mut self, path: impl Into<String>) -> Self {
+        self.app_path = Some(path.into());
+        self
+    }
+
+    pub fn ids(mut self, ids: impl Into<Vec<i64>>) -> Self {
+        self.ids = Some(ids.into());
+        self
+    }
+
+    pub fn from_ts(mut self, ts: i64) -> Self {
+        self.from_ts = Some(ts);
+        self
+    }
+
+    pub fn to_ts(mut self, ts: i64) -> Self {
+        self.to_ts = Some(ts);
+        self
+    }
+
+    pub fn time_range(mut self, from_ts: Option<i64>, to_ts: Option<i64>) -> Self {
+        self.from_ts = from_ts;
+        self.to_ts = to_ts;
+        self
+    }
+
+    pub fn has_ocr(mut self, has_ocr: bool) -> Self {
+        self.has_ocr = Some(has_ocr);
+        self
+    }
 
-    // 构建 COUNT 查询以获取 total
-    let total = {
-        let mut count_builder = QueryBuilder::new("SELECT COUNT(*) FROM activity_logs a ");
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn order_by(mut self, order_by: impl Into<String>) -> Self {
+        self.order_by = Some(order_by.into());
+        self
+    }
+
+    pub fn after(mut self, cursor: ActivityCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    pub fn highlight(mut self, options: HighlightOptions) -> Self {
+        self.highlight = Some(options);
+        self
+    }
+
+    fn match_query(&self) -> Option<&str> {
+        self.query.as_deref().filter(|q| !q.is_empty())
+    }
+
+    fn order_by_rank(&self) -> bool {
+        self.match_query().is_some() && self.order_by.as_deref() == Some("rank")
+    }
+
+    /// 是否包含全文查询以外的过滤条件（应用、标题、ID、时间范围、OCR 等）
+    pub fn has_filters(&self) -> bool {
+        self.exclude_query.as_deref().is_some_and(|q| !q.is_empty())
+            || self.apps.iter().any(|a| !a.is_empty())
+            || self.exclude_apps.iter().any(|a| !a.is_empty())
+            || self.window_title.as_deref().is_some_and(|t| !t.is_empty())
+            || self.app_path.as_deref().is_some_and(|p| !p.is_empty())
+            || self.ids.is_some()
+            || self.from_ts.is_some()
+            || self.to_ts.is_some()
+            || self.has_ocr.is_some()
+    }
 
-        if has_query {
-            count_builder.push("JOIN activity_logs_fts ON a.id = activity_logs_fts.rowid ");
+    /// 追加 FROM/JOIN 及 WHERE 子句（不含游标、排序与分页）
+    ///
+    /// 活动表别名为 `a`，有全文查询时同时 JOIN `activity_logs_fts`。
+    pub(crate) fn push_filters(&self, builder: &mut QueryBuilder<'_, sqlx::Sqlite>) {
+        let match_query = self.match_query();
+
+        builder.push(" FROM activity_logs a ");
+        if match_query.is_some() {
+            builder.push("JOIN activity_logs_fts ON a.id = activity_logs_fts.rowid ");
         }
+        builder.push("WHERE 1=1 ");
 
-        count_builder.push("WHERE 1=1 ");
+        if let Some(q) = match_query {
+            builder.push("AND activity_logs_fts MATCH ");
+            builder.push_bind(crate::ai::nlp::segment_fts_query(q));
+            builder.push(" ");
+        }
 
-        if has_query {
-            count_builder.push("AND activity_logs_fts MATCH ");
-            count_builder.push_bind(query.as_ref().unwrap().clone());
-            count_builder.push(" ");
+        if let Some(q) = self.exclude_query.as_deref().filter(|q| !q.is_empty()) {
+            builder.push("AND a.id NOT IN (SELECT rowid FROM activity_logs_fts WHERE activity_logs_fts MATCH ");
+            builder.push_bind(crate::ai::nlp::segment_fts_query(q));
+            builder.push(") ");
         }
 
-        if let Some(ref app) = app_name {
-            if !app.is_empty() {
-                count_builder.push("AND (");
-                count_builder.push("LOWER(a.app_name) LIKE '%' || LOWER(");
-                count_builder.push_bind(app.clone());
-                count_builder.push(") || '%'");
-                count_builder.push(" OR LOWER(REPLACE(a.app_name, '.exe', '')) LIKE '%' || LOWER(");
-                count_builder.push_bind(app.clone());
-                count_builder.push(") || '%'");
-                count_builder.push(") ");
+        let apps: Vec<&String> = self.apps.iter().filter(|a| !a.is_empty()).collect();
+        if !apps.is_empty() {
+            builder.push("AND (");
+            for (i, app) in apps.into_iter().enumerate() {
+                if i > 0 {
+                    builder.push(" OR ");
+                }
+                push_app_match(builder, app);
             }
+            builder.push(") ");
         }
 
-        if let Some(from) = from_ts {
-            count_builder.push("AND a.timestamp >= ");
-            count_builder.push_bind(from);
+        for app in self.exclude_apps.iter().filter(|a| !a.is_empty()) {
+            builder.push("AND NOT ");
+            push_app_match(builder, app);
+            builder.push(" ");
         }
 
-        if let Some(to) = to_ts {
-            count_builder.push("AND a.timestamp <= ");
-            count_builder.push_bind(to);
+        if let Some(title) = self.window_title.as_ref().filter(|t| !t.is_empty()) {
+            builder.push("AND LOWER(a.window_title) LIKE '%' || LOWER(");
+            builder.push_bind(title.clone());
+            builder.push(") || '%' ");
         }
 
-        if let Some(ocr) = has_ocr {
-            if ocr {
-                count_builder.push("AND a.ocr_text IS NOT NULL AND a.ocr_text != '' ");
+        if let Some(path) = self.app_path.as_ref().filter(|p| !p.is_empty()) {
+            builder.push("AND LOWER(a.app_path) LIKE '%' || LOWER(");
+            builder.push_bind(path.clone());
+            builder.push(") || '%' ");
+        }
+
+        if let Some(ids) = &self.ids {
+            if ids.is_empty() {
+                builder.push("AND 0 ");
             } else {
-                count_builder.push("AND (a.ocr_text IS NULL OR a.ocr_text = '') ");
+                builder.push("AND a.id IN (");
+                let mut separated = builder.separated(", ");
+                for id in ids {
+                    separated.push_bind(*id);
+                }
+                separated.push_unseparated(") ");
             }
         }
 
-        let count_query = count_builder.build();
-        let row = count_query.fetch_one(pool).await?;
-        row.get::<i64, _>(0)
-    };
+        if let Some(from) = self.from_ts {
+            builder.push("AND a.timestamp >= ");
+            builder.push_bind(from);
+            builder.push(" ");
+        }
 
-    // 构建主查询
-    let mut builder = QueryBuilder::new(
-        "SELECT a.id, a.timestamp, a.app_name, a.window_title, a.image_path, a.ocr_text, a.phash FROM activity_logs a "
-    );
+        if let Some(to) = self.to_ts {
+            builder.push("AND a.timestamp <= ");
+            builder.push_bind(to);
+            builder.push(" ");
+        }
 
-    if has_query {
-        builder.push("JOIN activity_logs_fts ON a.id = activity_logs_fts.rowid ");
+        if let Some(ocr) = self.has_ocr {
+            if ocr {
+                builder.push("AND a.ocr_text IS NOT NULL AND a.ocr_text != '' ");
+            } else {
+                builder.push("AND (a.ocr_text IS NULL OR a.ocr_text = '') ");
+            }
+        }
     }
+}
 
-    builder.push("WHERE 1=1 ");
+/// 应用名模糊匹配（兼容带/不带 `.exe` 的写法）
+fn push_app_match(builder: &mut QueryBuilder<'_, sqlx::Sqlite>, app: &str) {
+    builder.push("(LOWER(a.app_name) LIKE '%' || LOWER(");
+    builder.push_bind(app.to_string());
+    builder.push(") || '%' OR LOWER(REPLACE(a.app_name, '.exe', '')) LIKE '%' || LOWER(");
+    builder.push_bind(app.to_string());
+    builder.push(") || '%')");
+}
 
-    if has_query {
-        builder.push("AND activity_logs_fts MATCH ");
-        builder.push_bind(query.unwrap());
-        builder.push(" ");
-    }
+pub async fn search_activities(query: &ActivityQuery) -> Result<(Vec<ActivityLog>, i64)> {
+    let pool = get_pool().await?;
+    search_activities_impl(&pool, query).await
+}
 
-    if let Some(app) = app_name {
-        if !app.is_empty() {
-            builder.push("AND (");
-            builder.push("LOWER(a.app_name) LIKE '%' || LOWER(");
-            builder.push_bind(app.clone());
-            builder.push(") || '%'");
-            builder.push(" OR LOWER(REPLACE(a.app_name, '.exe', '')) LIKE '%' || LOWER(");
-            builder.push_bind(app);
-            builder.push(") || '%'");
-            builder.push(") ");
-        }
-    }
+/// 内部实现，接受 pool 参数以便于单元测试
+///
+/// 返回匹配的记录及满足过滤条件的总数（total 不受分页与游标影响）。
+pub async fn search_activities_impl(
+    pool: &SqlitePool,
+    query: &ActivityQuery,
+) -> Result<(Vec<ActivityLog>, i64)> {
+    let total = count_activities_impl(pool, query).await?;
+    let activities = list_activities_impl(pool, query).await?;
 
-    if let Some(from) = from_ts {
-        builder.push("AND a.timestamp >= ");
-        builder.push_bind(from);
-    }
+    Ok((activities, total))
+}
 
-    if let Some(to) = to_ts {
-        builder.push("AND a.timestamp <= ");
-        builder.push_bind(to);
-    }
+/// 与 [`search_activities`] 相同，但每条结果附带摘要、高亮与匹配位置
+pub async fn search_activity_hits(query: &ActivityQuery) -> Result<(Vec<ActivityHit>, i64)> {
+    let pool = get_pool().await?;
+    search_activity_hits_impl(&pool, query).await
+}
 
-    if let Some(ocr) = has_ocr {
-        if ocr {
-            builder.push("AND a.ocr_text IS NOT NULL AND a.ocr_text != '' ");
-        } else {
-            builder.push("AND (a.ocr_text IS NULL OR a.ocr_text = '') ");
-        }
+pub async fn search_activity_hits_impl(
+    pool: &SqlitePool,
+    query: &ActivityQuery,
+) -> Result<(Vec<ActivityHit>, i64)> {
+    let total = count_activities_impl(pool, query).await?;
+    let hits = list_activity_hits_impl(pool, query).await?;
+
+    Ok((hits, total))
+}
+
+async fn count_activities_impl(pool: &SqlitePool, query: &ActivityQuery) -> Result<i64> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*)");
+    query.push_filters(&mut builder);
+    Ok(builder.build().fetch_one(pool).await?.get(0))
+}
+
+/// 按条件查询活动记录（不计算 total）
+pub async fn list_activities(query: &ActivityQuery) -> Result<Vec<ActivityLog>> {
+    let pool = get_pool().await?;
+    list_activities_impl(&pool, query).await
+}
+
+pub async fn list_activities_impl(pool: &SqlitePool, query: &ActivityQuery) -> Result<Vec<ActivityLog>> {
+    let hits = list_activity_hits_impl(pool, query).await?;
+    Ok(hits.into_iter().map(|h| h.activity).collect())
+}
+
+async fn list_activity_hits_impl(pool: &SqlitePool, query: &ActivityQuery) -> Result<Vec<ActivityHit>> {
+    // 命中词由 FTS5 highlight() 标出，再映射回原文（索引中是分词后的文本）
+    let with_marks = query.highlight.is_some() && query.match_query().is_some();
+
+    let mut builder = QueryBuilder::new(
+        "SELECT a.id, a.timestamp, a.app_name, a.window_title, a.image_path, a.ocr_text, a.phash",
+    );
+    if with_marks {
+        builder.push(
+            ", highlight(activity_logs_fts, 0, char(1), char(2)), highlight(activity_logs_fts, 2, char(1), char(2))",
+        );
     }
+    query.push_filters(&mut builder);
 
-    // Handle ordering
-    let order = order_by.unwrap_or_else(|| "time".to_string());
-    if order == "rank" && has_query {
-        builder.push("ORDER BY bm25(activity_logs_fts) ");
+    if query.order_by_rank() {
+        // 相关度排序下游标没有意义，只支持 offset 分页
+        builder.push("ORDER BY ");
+        builder.push(FTS_RANK_EXPR);
+        builder.push(", a.id DESC ");
     } else {
-        builder.push("ORDER BY a.timestamp DESC ");
+        if let Some(cursor) = query.cursor {
+            builder.push("AND (a.timestamp < ");
+            builder.push_bind(cursor.timestamp);
+            builder.push(" OR (a.timestamp = ");
+            builder.push_bind(cursor.timestamp);
+            builder.push(" AND a.id < ");
+            builder.push_bind(cursor.id);
+            builder.push(")) ");
+        }
+        builder.push("ORDER BY a.timestamp DESC, a.id DESC ");
     }
 
-    if let Some(l) = limit {
+    // SQLite 要求 OFFSET 前必须有 LIMIT
+    if query.limit.is_some() || query.offset.is_some() {
         builder.push("LIMIT ");
-        builder.push_bind(l);
+        builder.push_bind(query.limit.unwrap_or(-1));
+        builder.push(" ");
     }
 
-    if let Some(o) = offset {
+    if let Some(o) = query.offset {
         builder.push("OFFSET ");
         builder.push_bind(o);
     }
 
-    let query = builder.build();
-    let rows = query.fetch_all(pool).await?;
+    let rows = builder.build().fetch_all(pool).await?;
 
-    let activities = rows
+    let hits = rows
         .into_iter()
-        .map(|row| ActivityLog {
-            id: row.get(0),
-            timestamp: row.get(1),
-            app_name: row.get(2),
-            window_title: row.get(3),
-            image_path: row.get(4),
-            ocr_text: row.get(5),
-            phash: row.get(6),
+        .map(|row| {
+            let activity = ActivityLog {
+                id: row.get(0),
+                timestamp: row.get(1),
+                app_name: row.get(2),
+                window_title: row.get(3),
+                image_path: row.get(4),
+                ocr_text: row.get(5),
+                phash: row.get(6),
+            };
+            let marked = if with_marks {
+                Some((
+                    row.get::<Option<String>, _>(7).unwrap_or_default(),
+                    row.get::<Option<String>, _>(8).unwrap_or_default(),
+                ))
+            } else {
+                None
+            };
+            match &query.highlight {
+                Some(options) => ActivityHit::highlighted(activity, marked, options),
+                None => ActivityHit::from(activity),
+            }
         })
         .collect();
 
-    Ok((activities, total))
+    Ok(hits)
 }
 
 pub async fn get_blocklist() -> Result<Vec<String>> {
@@ -539,6 +922,11 @@ async fn try_connect_and_migrate(options: SqliteConnectOptions) -> Result<Sqlite
 
     ensure_agent_automation_schema(&pool).await?;
 
+    // 旧版 JSON 文本向量转换为二进制格式（转换失败不影响启动）
+    if let Err(e) = crate::vector_db::convert_legacy_embeddings(&pool).await {
+        tracing::warn!("旧格式向量转换失败: {}", e);
+    }
+
     // 执行完整性检查 (使用 integrity_check 以检测 FTS5 等虚拟表的损坏)
 
     tracing::info!("执行数据库完整性检查...");
@@ -1240,51 +1628,40 @@ mod stats_tests {
         }
 
         // 测试1：无过滤条件，total 应为 10
-        let (activities, total) = search_activities_impl(
-            &pool, None, None, None, None, None, Some(5), None, None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().limit(5)).await.unwrap();
         assert_eq!(total, 10, "Total should be 10 without any filters");
         assert_eq!(activities.len(), 5, "Should return 5 items with limit=5");
 
         // 测试2：按 app_name 过滤
-        let (activities, total) = search_activities_impl(
-            &pool, None, Some("Chrome".to_string()), None, None, None, Some(100), None, None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().app("Chrome").limit(100)).await.unwrap();
         assert_eq!(total, 5, "Total should be 5 for Chrome");
         assert_eq!(activities.len(), 5);
 
         // 测试3：按时间范围过滤
-        let (activities, total) = search_activities_impl(
-            &pool, None, None, Some(3000), Some(7000), None, Some(100), None, None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().from_ts(3000).to_ts(7000).limit(100)).await.unwrap();
         assert_eq!(total, 5, "Total should be 5 for timestamp 3000-7000");
         assert_eq!(activities.len(), 5);
 
         // 测试4：has_ocr = true
-        let (activities, total) = search_activities_impl(
-            &pool, None, None, None, None, Some(true), Some(100), None, None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().has_ocr(true).limit(100)).await.unwrap();
         assert_eq!(total, 5, "Total should be 5 for records with OCR text");
         assert_eq!(activities.len(), 5);
 
         // 测试5：has_ocr = false
-        let (activities, total) = search_activities_impl(
-            &pool, None, None, None, None, Some(false), Some(100), None, None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().has_ocr(false).limit(100)).await.unwrap();
         assert_eq!(total, 5, "Total should be 5 for records without OCR text");
         assert_eq!(activities.len(), 5);
 
         // 测试6：组合过滤 - Chrome + has_ocr
         let (activities, total) = search_activities_impl(
-            &pool, None, Some("Chrome".to_string()), None, None, Some(true), Some(100), None, None
+            &pool,
+            &ActivityQuery::new().app("Chrome").has_ocr(true).limit(100),
         ).await.unwrap();
         assert_eq!(total, 2, "Total should be 2 for Chrome with OCR (ids 2,4)");
         assert_eq!(activities.len(), 2);
 
         // 测试7：分页 - offset
-        let (activities, total) = search_activities_impl(
-            &pool, None, None, None, None, None, Some(3), Some(2), None
-        ).await.unwrap();
+        let (activities, total) = search_activities_impl(&pool, &ActivityQuery::new().limit(3).offset(2)).await.unwrap();
         assert_eq!(total, 10, "Total should still be 10 with pagination");
         assert_eq!(activities.len(), 3, "Should return 3 items with limit=3, offset=2");
     }
@@ -1313,7 +1690,7 @@ mod stats_tests {
         .await
         .unwrap();
 
-        sqlx::query("CREATE VIRTUAL TABLE activity_logs_fts USING fts5(ocr_text)")
+        sqlx::query("CREATE VIRTUAL TABLE activity_logs_fts USING fts5(window_title, app_name, ocr_text)")
             .execute(&pool)
             .await
             .unwrap();
@@ -1360,14 +1737,7 @@ mod stats_tests {
 
         let (activities, total) = search_activities_impl(
             &pool,
-            Some("hello".to_string()),
-            None,
-            None,
-            None,
-            None,
-            Some(10),
-            None,
-            Some("rank".to_string()),
+            &ActivityQuery::new().query("hello").limit(10).order_by("rank"),
         )
         .await
         .unwrap();
@@ -1378,20 +1748,356 @@ mod stats_tests {
 
         let (_activities, total) = search_activities_impl(
             &pool,
-            Some("world".to_string()),
-            None,
-            None,
-            None,
-            None,
-            Some(10),
-            None,
-            Some("rank".to_string()),
+            &ActivityQuery::new().query("world").limit(10).order_by("rank"),
         )
         .await
         .unwrap();
 
         assert_eq!(total, 2);
     }
+
+    #[tokio::test]
+    async fn test_multi_column_fts_migration() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let insert = |ts: i64, app: &'static str, title: &'static str, ocr: Option<&'static str>| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query(
+                    "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, ?, '', ?)",
+                )
+                .bind(ts)
+                .bind(app)
+                .bind(title)
+                .bind(ocr)
+                .execute(&pool)
+                .await
+                .unwrap()
+                .last_insert_rowid()
+            }
+        };
+
+        // 标题命中（无 OCR）、正文命中、应用名命中
+        let title_hit = insert(1000, "Code.exe", "invoice parser - memflow", None).await;
+        let body_hit = insert(2000, "chrome.exe", "Inbox", Some("please review the invoice today")).await;
+        let app_hit = insert(3000, "Invoice.exe", "Untitled", Some("")).await;
+
+        let (items, total) = search_activities_impl(
+            &pool,
+            &ActivityQuery::new().query("invoice").order_by("rank"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(items[0].id, title_hit, "Title hits should rank above body hits");
+        assert_eq!(items.last().unwrap().id, body_hit);
+        assert!(items.iter().any(|a| a.id == app_hit));
+
+        // 更新 OCR 后触发器应重建索引
+        sqlx::query("UPDATE activity_logs SET ocr_text = 'quarterly report' WHERE id = ?")
+            .bind(body_hit)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let (items, _) = search_activities_impl(&pool, &ActivityQuery::new().query("quarterly"))
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![body_hit]);
+
+        // 清空索引后重新执行迁移脚本，验证回填
+        sqlx::query("DELETE FROM activity_logs_fts").execute(&pool).await.unwrap();
+        sqlx::Executor::execute(&pool, include_str!("../migrations/0011_multi_column_fts.sql"))
+            .await
+            .unwrap();
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().query("invoice"))
+            .await
+            .unwrap();
+        assert_eq!(total, 2);
+
+        // 删除活动后 FTS 记录同步删除
+        sqlx::query("DELETE FROM activity_logs WHERE id = ?")
+            .bind(title_hit)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().query("parser"))
+            .await
+            .unwrap();
+        assert_eq!(total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cjk_segmented_search() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        // 模拟迁移前写入的历史数据：影子列为空，FTS 中是未分词的原文
+        let legacy_id = sqlx::query(
+            "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, ocr_text) VALUES (1000, 'Code.exe', '项目周报', '', '本周完成了数据库迁移失败时的错误处理改造')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().query("错误处理"))
+            .await
+            .unwrap();
+        assert_eq!(total, 0, "unicode61 alone cannot match inside a Chinese run");
+
+        let processed = reindex_segmented_fts_impl(&pool, 1).await.unwrap();
+        assert_eq!(processed, 1);
+        assert_eq!(reindex_segmented_fts_impl(&pool, 1).await.unwrap(), 0, "Reindex should be idempotent");
+
+        let (items, _) = search_activities_impl(&pool, &ActivityQuery::new().query("错误处理"))
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![legacy_id]);
+
+        // 新记录：OCR 文本经 update_activity_ocr 写入分词列
+        let new_id = sqlx::query(
+            "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, window_title_seg) VALUES (2000, 'chrome.exe', 'Rust 文档', '', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+        update_activity_ocr_impl(&pool, new_id, "我们需要改进项目里的错误处理逻辑，避免程序崩溃")
+            .await
+            .unwrap();
+
+        let (items, total) = search_activities_impl(
+            &pool,
+            &ActivityQuery::new().query("错误处理").order_by("rank"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 2);
+        assert!(items.iter().any(|a| a.id == new_id));
+
+        // 短词、短语、标题命中
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().query("崩溃"))
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().query("\"数据库迁移\""))
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+        let (items, _) = search_activities_impl(&pool, &ActivityQuery::new().query("周报"))
+            .await
+            .unwrap();
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![legacy_id]);
+    }
+
+    #[tokio::test]
+    async fn test_search_activity_hits_snippets() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let id = sqlx::query(
+            "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, window_title_seg) VALUES (1000, 'Code.exe', 'Invoice review', '', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+        let ocr = format!("{}\n本周完成了错误处理改造，Invoice 已发送。\n{}", "页眉 ".repeat(30), "页脚 ".repeat(30));
+        update_activity_ocr_impl(&pool, id, &ocr).await.unwrap();
+
+        let options = HighlightOptions {
+            open: "[".to_string(),
+            close: "]".to_string(),
+            max_chars: 30,
+            full_text: true,
+            ..Default::default()
+        };
+        let (hits, total) = search_activity_hits_impl(
+            &pool,
+            &ActivityQuery::new().query("错误处理 OR invoice").highlight(options),
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 1);
+
+        let hit = &hits[0];
+        let snippet = hit.snippet.as_deref().unwrap();
+        assert!(snippet.contains("[错误处理]"), "snippet: {}", snippet);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(hit.highlight.as_deref().unwrap().contains("[Invoice]"));
+
+        // 偏移指向原文（而不是索引中的分词文本）
+        let ocr_hits: Vec<&str> = hit
+            .matches
+            .iter()
+            .filter(|m| m.field == MatchField::OcrText)
+            .map(|m| &ocr[m.start..m.end])
+            .collect();
+        assert_eq!(ocr_hits, vec!["错误处理", "Invoice"]);
+        let title_hits: Vec<&str> = hit
+            .matches
+            .iter()
+            .filter(|m| m.field == MatchField::WindowTitle)
+            .map(|m| &hit.activity.window_title[m.start..m.end])
+            .collect();
+        assert_eq!(title_hits, vec!["Invoice"]);
+
+        // 未开启高亮时不附带摘要
+        let (hits, _) = search_activity_hits_impl(&pool, &ActivityQuery::new().query("invoice"))
+            .await
+            .unwrap();
+        assert!(hits[0].snippet.is_none() && hits[0].matches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_activities_extended_filters() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+
+        sqlx::query(
+            "CREATE TABLE activity_logs (
+                id INTEGER PRIMARY KEY,
+                timestamp INTEGER,
+                app_name TEXT,
+                window_title TEXT,
+                image_path TEXT,
+                phash TEXT,
+                app_path TEXT,
+                ocr_text TEXT
+            )",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let rows = [
+            (1000, "chrome.exe", "GitHub - Pull Requests", "C:\\Program Files\\Google\\chrome.exe"),
+            (2000, "Code.exe", "main.rs - memflow", "C:\\Users\\me\\AppData\\Code.exe"),
+            (3000, "WeChat.exe", "微信", "C:\\Program Files\\Tencent\\WeChat.exe"),
+            (4000, "chrome.exe", "Rust docs", "C:\\Program Files\\Google\\chrome.exe"),
+        ];
+        for (ts, app, title, path) in rows {
+            sqlx::query(
+                "INSERT INTO activity_logs (timestamp, app_name, window_title, app_path) VALUES (?, ?, ?, ?)",
+            )
+            .bind(ts as i64)
+            .bind(app)
+            .bind(title)
+            .bind(path)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        // 多个应用（OR）
+        let (items, total) =
+            search_activities_impl(&pool, &ActivityQuery::new().apps(["Code", "WeChat"]))
+                .await
+                .unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![3, 2]);
+
+        // 排除应用
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().exclude_app("chrome"))
+            .await
+            .unwrap();
+        assert_eq!(total, 2);
+
+        // 窗口标题（不区分大小写）
+        let (items, total) =
+            search_activities_impl(&pool, &ActivityQuery::new().window_title("rust"))
+                .await
+                .unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(items[0].id, 4);
+
+        // 应用路径
+        let (_, total) = search_activities_impl(&pool, &ActivityQuery::new().app_path("tencent"))
+            .await
+            .unwrap();
+        assert_eq!(total, 1);
+
+        // ID 列表与其它条件组合
+        let (items, total) = search_activities_impl(
+            &pool,
+            &ActivityQuery::new().ids(vec![1, 2, 4]).app("chrome"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(items.iter().map(|a| a.id).collect::<Vec<_>>(), vec![4, 1]);
+
+        // 空 ID 列表不匹配任何记录
+        let (items, total) = search_activities_impl(&pool, &ActivityQuery::new().ids(Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(total, 0);
+        assert!(items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_activities_keyset_pagination() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+
+        sqlx::query(
+            "CREATE TABLE activity_logs (
+                id INTEGER PRIMARY KEY,
+                timestamp INTEGER,
+                app_name TEXT,
+                window_title TEXT,
+                image_path TEXT,
+                phash TEXT,
+                app_path TEXT,
+                ocr_text TEXT
+            )",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // 同一秒内多条记录，验证 (timestamp, id) 游标不会丢失或重复
+        for i in 1..=7_i64 {
+            sqlx::query("INSERT INTO activity_logs (timestamp, app_name, window_title) VALUES (?, ?, ?)")
+                .bind(1000 * ((i + 1) / 2))
+                .bind("Code")
+                .bind(format!("Window {}", i))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let mut query = ActivityQuery::new().limit(3);
+        loop {
+            let (page, total) = search_activities_impl(&pool, &query).await.unwrap();
+            assert_eq!(total, 7, "Total should ignore the cursor");
+            let Some(last) = page.last() else { break };
+            query = query.after(ActivityCursor::from(last));
+            seen.extend(page.iter().map(|a| a.id));
+        }
+
+        assert_eq!(seen, vec![7, 6, 5, 4, 3, 2, 1]);
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,809 @@
+//! 日报 / 周报
+//!
+//! 按自然日或自然周（周一开始）生成 Markdown 报告：
+//! - 应用与窗口的使用时长：相邻两条记录的间隔计入前一条；间隔超过 `idle_gap` 视为离开，只计一个录制间隔
+//! - 专注度走势：`focus_metrics` 按小时（日报）或按天（周报）取平均
+//! - 任务总结：由模型根据该时段的分层摘要（[`crate::ai::summaries`]）和主要窗口写成；
+//!   没有模型或调用失败时直接列出摘要
+//! - 关键截图：主要窗口各取一张文字最多的截图，链接到本地文件
+//!
+//! 报告写入 `digests`，每个时段只生成一次；模型调用失败的报告标记为 `degraded`，
+//! 下次生成同一时段时重新调用模型并覆盖。可再导出为笔记目录下的 Markdown 文件。
+
+use crate::ai::context_builder::ContextBudget;
+use crate::ai::provider::{ChatMessage, CompletionRequest, LlmProvider};
+use crate::ai::summaries::{self, Summary, SummaryGranularity};
+use anyhow::Result;
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const TASKS_SYSTEM_PROMPT: &str = "你负责根据用户一段时间内的活动摘要和主要窗口，整理这段时间完成或推进的任务。\
+每项任务一行，写清做了什么、涉及的项目或文档，能判断时附上大致投入的时间；合并同一任务，最多 8 项。\
+只输出 Markdown 无序列表（每行以“- ”开头），不要标题和其它说明。";
+
+/// 报告时段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestPeriod {
+    Day,
+    Week,
+}
+
+impl DigestPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestPeriod::Day => "day",
+            DigestPeriod::Week => "week",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(DigestPeriod::Day),
+            "week" => Some(DigestPeriod::Week),
+            _ => None,
+        }
+    }
+
+    /// 时段边界与同粒度的活动摘要一致
+    pub fn granularity(&self) -> SummaryGranularity {
+        match self {
+            DigestPeriod::Day => SummaryGranularity::Day,
+            DigestPeriod::Week => SummaryGranularity::Week,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DigestPeriod::Day => "日报",
+            DigestPeriod::Week => "周报",
+        }
+    }
+
+    /// 包含 `ts` 的时段 `[start, end)`
+    pub fn period_containing(&self, ts: i64) -> (i64, i64) {
+        let granularity = self.granularity();
+        let start = granularity.period_start(ts);
+        (start, granularity.period_end(start))
+    }
+
+    /// `now` 之前最近一个已经结束的时段（昨天 / 上周）
+    pub fn previous(&self, now: i64) -> (i64, i64) {
+        let (current, _) = self.period_containing(now);
+        self.period_containing(current - 1)
+    }
+
+    /// 包含本地日期 `date` 的时段
+    pub fn period_of_date(&self, date: NaiveDate) -> Option<(i64, i64)> {
+        // 取正午，避开夏令时切换的时刻
+        let ts = crate::time_range::local_timestamp(date.and_hms_opt(12, 0, 0)?)?;
+        Some(self.period_containing(ts))
+    }
+}
+
+/// 一份报告
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Digest {
+    pub id: i64,
+    pub period: DigestPeriod,
+    pub start_ts: i64,
+    /// 不包含
+    pub end_ts: i64,
+    pub title: String,
+    pub markdown: String,
+    /// 导出到笔记目录后的文件路径
+    pub file_path: Option<String>,
+    /// 任务总结本应由模型写、但调用失败而改为列出摘要；再次生成时会覆盖
+    pub degraded: bool,
+    pub created_at: i64,
+}
+
+impl Digest {
+    /// 导出的文件名：`memflow-daily-2024-01-08.md`、`memflow-weekly-2024-01-08.md`（周报取周一的日期）
+    pub fn file_name(&self) -> String {
+        let kind = match self.period {
+            DigestPeriod::Day => "daily",
+            DigestPeriod::Week => "weekly",
+        };
+        let date = Local
+            .timestamp_opt(self.start_ts, 0)
+            .earliest()
+            .map(|dt| dt.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| self.start_ts.to_string());
+        format!("memflow-{}-{}.md", kind, date)
+    }
+
+    fn from_row(row: &SqliteRow) -> Result<Self> {
+        let period: String = row.get("period");
+        Ok(Self {
+            id: row.get("id"),
+            period: DigestPeriod::parse(&period).ok_or_else(|| anyhow::anyhow!("未知的报告时段: {}", period))?,
+            start_ts: row.get("start_ts"),
+            end_ts: row.get("end_ts"),
+            title: row.get("title"),
+            markdown: row.get("markdown"),
+            file_path: row.get("file_path"),
+            degraded: row.get("degraded"),
+            created_at: row.get("created_at"),
+        })
+    }
+}
+
+/// 报告生成参数
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    /// 录制间隔：离开后回来的那一条记录只计这么久
+    pub recording_interval: Duration,
+    /// 相邻两条记录的间隔超过这么久视为离开
+    pub idle_gap: Duration,
+    pub top_apps: usize,
+    pub top_windows: usize,
+    /// 关键截图数（取时长最多的几个窗口）
+    pub screenshots: usize,
+    /// 截图目录；为空时链接使用记录中的原始路径
+    pub screenshots_dir: Option<PathBuf>,
+    /// 模型写任务总结的超时
+    pub timeout: Duration,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            recording_interval: Duration::from_secs(5),
+            idle_gap: Duration::from_secs(5 * 60),
+            top_apps: 8,
+            top_windows: 10,
+            screenshots: 5,
+            screenshots_dir: None,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+struct Screenshot {
+    text_len: i64,
+    timestamp: i64,
+    image_path: String,
+}
+
+struct WindowUsage {
+    app_name: String,
+    window_title: String,
+    secs: i64,
+    screenshot: Option<Screenshot>,
+}
+
+struct Usage {
+    activity_count: usize,
+    total_secs: i64,
+    apps: Vec<(String, i64)>,
+    windows: Vec<WindowUsage>,
+}
+
+/// 每条记录计入的秒数：到下一条（最后一条到时段结束）的间隔，超过 `idle_gap` 时只计一个录制间隔
+fn time_weighted(timestamps: &[i64], end_ts: i64, config: &DigestConfig) -> Vec<i64> {
+    let idle_gap = config.idle_gap.as_secs() as i64;
+    let interval = config.recording_interval.as_secs().max(1) as i64;
+    timestamps
+        .iter()
+        .enumerate()
+        .map(|(i, ts)| {
+            let next = timestamps.get(i + 1).copied().unwrap_or(end_ts);
+            let gap = (next - ts).max(0);
+            if gap <= idle_gap && i + 1 < timestamps.len() {
+                gap
+            } else {
+                gap.min(interval)
+            }
+        })
+        .collect()
+}
+
+async fn load_usage(pool: &SqlitePool, start: i64, end: i64, config: &DigestConfig) -> Result<Usage> {
+    let rows = sqlx::query(
+        "SELECT timestamp, app_name, window_title, image_path, LENGTH(COALESCE(ocr_text, '')) AS text_len
+         FROM activity_logs WHERE timestamp >= ? AND timestamp < ? ORDER BY timestamp",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+
+    let timestamps: Vec<i64> = rows.iter().map(|row| row.get("timestamp")).collect();
+    let credits = time_weighted(&timestamps, end, config);
+
+    let mut apps: HashMap<String, i64> = HashMap::new();
+    let mut windows: HashMap<(String, String), WindowUsage> = HashMap::new();
+    for (row, secs) in rows.iter().zip(&credits) {
+        let app_name: String = row.get("app_name");
+        let window_title: String = row.get("window_title");
+        *apps.entry(app_name.clone()).or_default() += secs;
+
+        let usage = windows
+            .entry((app_name.clone(), window_title.clone()))
+            .or_insert_with(|| WindowUsage {
+                app_name,
+                window_title,
+                secs: 0,
+                screenshot: None,
+            });
+        usage.secs += secs;
+
+        let text_len: i64 = row.get("text_len");
+        if usage.screenshot.as_ref().is_none_or(|s| text_len > s.text_len) {
+            usage.screenshot = Some(Screenshot {
+                text_len,
+                timestamp: row.get("timestamp"),
+                image_path: row.get("image_path"),
+            });
+        }
+    }
+
+    let mut apps: Vec<(String, i64)> = apps.into_iter().collect();
+    apps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut windows: Vec<WindowUsage> = windows.into_values().collect();
+    windows.sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.window_title.cmp(&b.window_title)));
+
+    Ok(Usage {
+        activity_count: rows.len(),
+        total_secs: credits.iter().sum(),
+        apps,
+        windows,
+    })
+}
+
+/// 专注度走势：(区间起点, 平均分, 采样数)，日报按小时、周报按天
+async fn load_focus_trend(pool: &SqlitePool, period: DigestPeriod, start: i64, end: i64) -> Result<Vec<(i64, f64, usize)>> {
+    let rows = sqlx::query("SELECT timestamp, focus_score FROM focus_metrics WHERE timestamp >= ? AND timestamp < ?")
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    let bucket = match period {
+        DigestPeriod::Day => SummaryGranularity::Hour,
+        DigestPeriod::Week => SummaryGranularity::Day,
+    };
+    let mut buckets: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for row in rows {
+        let entry = buckets.entry(bucket.period_start(row.get("timestamp"))).or_default();
+        entry.0 += row.get::<f64, _>("focus_score");
+        entry.1 += 1;
+    }
+    Ok(buckets
+        .into_iter()
+        .map(|(start, (sum, count))| (start, sum / count as f64, count))
+        .collect())
+}
+
+async fn write_tasks(
+    provider: &dyn LlmProvider,
+    title: &str,
+    found: &[Summary],
+    usage: &Usage,
+    timeout: Duration,
+) -> Result<String> {
+    let summaries_text = if found.is_empty() {
+        "（无）".to_string()
+    } else {
+        summaries::render_summaries(found, &ContextBudget::default())
+    };
+    let windows_text = usage
+        .windows
+        .iter()
+        .take(15)
+        .map(|w| format!("- {} · {}：{}", w.app_name, w.window_title, format_duration(w.secs)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let request = CompletionRequest {
+        system: Some(TASKS_SYSTEM_PROMPT.to_string()),
+        messages: vec![ChatMessage::user(format!(
+            "{}\n\n活动摘要：\n{}\n\n主要窗口及使用时长：\n{}",
+            title, summaries_text, windows_text
+        ))],
+        ..Default::default()
+    }
+    .max_tokens(800)
+    .temperature(0.2);
+
+    let completion = tokio::time::timeout(timeout, provider.complete(&request))
+        .await
+        .map_err(|_| anyhow::anyhow!("{} 生成任务总结超时({}s)", provider.name(), timeout.as_secs()))??;
+    let tasks = completion.text.trim().to_string();
+    if tasks.is_empty() {
+        return Err(anyhow::anyhow!("{} 返回了空的任务总结", provider.name()));
+    }
+    Ok(tasks)
+}
+
+/// 任务总结：优先由模型整理，失败时直接列出该时段的摘要
+///
+/// 返回的 `bool` 表示是否降级：有模型但调用失败（没有模型时列出摘要不算降级）。
+async fn task_section(
+    pool: &SqlitePool,
+    provider: Option<&dyn LlmProvider>,
+    period: DigestPeriod,
+    title: &str,
+    (start, end): (i64, i64),
+    usage: &Usage,
+    config: &DigestConfig,
+) -> Result<(String, bool)> {
+    let found = summaries::summaries_for_range_impl(pool, start, end - 1, period.granularity()).await?;
+    if let Some(provider) = provider {
+        match write_tasks(provider, title, &found, usage, config.timeout).await {
+            Ok(tasks) => return Ok((tasks, false)),
+            Err(e) => tracing::warn!(
+                "{}: 任务总结生成失败，改为列出活动摘要，下次重新生成: {}",
+                title,
+                crate::redact::redact_secrets(&e.to_string())
+            ),
+        }
+    }
+
+    let degraded = provider.is_some();
+    if found.is_empty() {
+        return Ok(("暂无任务总结（开启活动摘要并配置模型后生成）。".to_string(), degraded));
+    }
+    let tasks = found
+        .iter()
+        .map(|s| format!("- **{}**：{}", s.label(), s.content.replace('\n', " ")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((tasks, degraded))
+}
+
+fn format_duration(secs: i64) -> String {
+    let minutes = secs / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "不到 1 分钟".to_string(),
+        (0, m) => format!("{} 分钟", m),
+        (h, 0) => format!("{} 小时", h),
+        (h, m) => format!("{} 小时 {} 分钟", h, m),
+    }
+}
+
+fn format_local(ts: i64, fmt: &str) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .earliest()
+        .map(|dt| dt.format(fmt).to_string())
+        .unwrap_or_default()
+}
+
+/// 表格单元格：去掉换行，转义竖线
+fn table_cell(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        "（无标题）".to_string()
+    } else {
+        text.replace('|', "\\|")
+    }
+}
+
+/// 本地文件的 `file://` 链接（用尖括号包起来，路径可以带空格）
+fn file_link(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("<file://{}>", path)
+    } else {
+        format!("<file:///{}>", path)
+    }
+}
+
+fn render_markdown(
+    period: DigestPeriod,
+    title: &str,
+    usage: &Usage,
+    focus: &[(i64, f64, usize)],
+    tasks: &str,
+    now: i64,
+    config: &DigestConfig,
+) -> String {
+    let mut md = format!("# {}\n\n", title);
+    md.push_str(&format!(
+        "> 记录 {} 条，活跃时长 {}，生成于 {}\n\n",
+        usage.activity_count,
+        format_duration(usage.total_secs),
+        format_local(now, "%Y-%m-%d %H:%M")
+    ));
+
+    md.push_str("## 任务总结\n\n");
+    md.push_str(tasks);
+    md.push_str("\n\n");
+
+    md.push_str("## 应用使用时长\n\n| 应用 | 时长 | 占比 |\n| --- | --- | --- |\n");
+    for (app, secs) in usage.apps.iter().take(config.top_apps) {
+        let share = *secs as f64 * 100.0 / usage.total_secs.max(1) as f64;
+        md.push_str(&format!("| {} | {} | {:.0}% |\n", table_cell(app), format_duration(*secs), share));
+    }
+    md.push('\n');
+
+    md.push_str("## 主要窗口\n\n| 窗口 | 应用 | 时长 |\n| --- | --- | --- |\n");
+    for window in usage.windows.iter().take(config.top_windows) {
+        md.push_str(&format!(
+            "| {} | {} | {} |\n",
+            table_cell(&window.window_title),
+            table_cell(&window.app_name),
+            format_duration(window.secs)
+        ));
+    }
+    md.push('\n');
+
+    md.push_str("## 专注度走势\n\n");
+    if focus.is_empty() {
+        md.push_str("没有专注度数据（未开启专注度分析）。\n\n");
+    } else {
+        let samples: usize = focus.iter().map(|(_, _, n)| n).sum();
+        let average = focus.iter().map(|(_, avg, n)| avg * *n as f64).sum::<f64>() / samples as f64;
+        md.push_str(&format!("平均专注度 {:.0}（共 {} 次采样）\n\n", average, samples));
+        md.push_str("| 时段 | 平均专注度 | 走势 |\n| --- | --- | --- |\n");
+        for (start, avg, _) in focus {
+            let bucket = match period {
+                DigestPeriod::Day => format_local(*start, "%H:00"),
+                DigestPeriod::Week => SummaryGranularity::Day.label(*start, SummaryGranularity::Day.period_end(*start)),
+            };
+            let bar = "█".repeat((avg / 10.0).round().clamp(0.0, 10.0) as usize);
+            md.push_str(&format!("| {} | {:.0} | {} |\n", bucket, avg, bar));
+        }
+        md.push('\n');
+    }
+
+    md.push_str("## 关键截图\n\n");
+    let mut shots = 0;
+    for window in &usage.windows {
+        if shots == config.screenshots {
+            break;
+        }
+        let Some(shot) = window.screenshot.as_ref().filter(|s| !s.image_path.is_empty()) else {
+            continue;
+        };
+        let path = match &config.screenshots_dir {
+            Some(dir) => dir.join(&shot.image_path),
+            None => PathBuf::from(&shot.image_path),
+        };
+        md.push_str(&format!(
+            "- {} {} · {} — [截图]({})\n",
+            format_local(shot.timestamp, "%m-%d %H:%M"),
+            table_cell(&window.app_name),
+            table_cell(&window.window_title),
+            file_link(&path)
+        ));
+        shots += 1;
+    }
+    if shots == 0 {
+        md.push_str("没有可用的截图。\n");
+    }
+    md
+}
+
+/// 生成包含 `ts` 的时段的报告（使用全局连接池）
+pub async fn generate_digest(
+    provider: Option<&dyn LlmProvider>,
+    period: DigestPeriod,
+    ts: i64,
+    config: &DigestConfig,
+) -> Result<Option<Digest>> {
+    let pool = crate::db::get_pool().await?;
+    generate_digest_impl(&pool, provider, period, ts, Local::now().timestamp(), config).await
+}
+
+/// 生成包含 `ts` 的时段的报告
+///
+/// 时段必须已经结束；已经生成过的时段直接返回已有的报告（不再调用模型），没有任何活动记录时返回 `None`。
+/// 已有的报告是降级生成的（见 [`Digest::degraded`]）时重新生成并覆盖。
+pub async fn generate_digest_impl(
+    pool: &SqlitePool,
+    provider: Option<&dyn LlmProvider>,
+    period: DigestPeriod,
+    ts: i64,
+    now: i64,
+    config: &DigestConfig,
+) -> Result<Option<Digest>> {
+    let (start, end) = period.period_containing(ts);
+    let title = format!("MemFlow {} · {}", period.label(), period.granularity().label(start, end));
+    if end > now {
+        return Err(anyhow::anyhow!("{} 的时段尚未结束", title));
+    }
+    if let Some(existing) = get_digest_impl(pool, period, start).await?.filter(|d| !d.degraded) {
+        return Ok(Some(existing));
+    }
+
+    let usage = load_usage(pool, start, end, config).await?;
+    if usage.activity_count == 0 {
+        return Ok(None);
+    }
+    let focus = load_focus_trend(pool, period, start, end).await?;
+    let (tasks, degraded) = task_section(pool, provider, period, &title, (start, end), &usage, config).await?;
+    let markdown = render_markdown(period, &title, &usage, &focus, &tasks, now, config);
+
+    // 只覆盖降级的报告；并发生成同一时段时以先写入的完整报告为准
+    sqlx::query(
+        "INSERT INTO digests (period, start_ts, end_ts, title, markdown, degraded) VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(period, start_ts) DO UPDATE SET
+             end_ts = excluded.end_ts,
+             title = excluded.title,
+             markdown = excluded.markdown,
+             degraded = excluded.degraded,
+             created_at = strftime('%s', 'now')
+         WHERE digests.degraded = 1",
+    )
+    .bind(period.as_str())
+    .bind(start)
+    .bind(end)
+    .bind(&title)
+    .bind(&markdown)
+    .bind(degraded)
+    .execute(pool)
+    .await?;
+    tracing::info!("已生成{}{}", title, if degraded { "（任务总结降级）" } else { "" });
+    get_digest_impl(pool, period, start).await
+}
+
+/// 包含 `ts` 的时段的报告（使用全局连接池）
+pub async fn get_digest(period: DigestPeriod, ts: i64) -> Result<Option<Digest>> {
+    let pool = crate::db::get_pool().await?;
+    get_digest_impl(&pool, period, ts).await
+}
+
+pub async fn get_digest_impl(pool: &SqlitePool, period: DigestPeriod, ts: i64) -> Result<Option<Digest>> {
+    let (start, _) = period.period_containing(ts);
+    let row = sqlx::query(
+        "SELECT id, period, start_ts, end_ts, title, markdown, file_path, degraded, created_at
+         FROM digests WHERE period = ? AND start_ts = ?",
+    )
+    .bind(period.as_str())
+    .bind(start)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(Digest::from_row).transpose()
+}
+
+/// 最近的报告（使用全局连接池）
+pub async fn list_digests(period: Option<DigestPeriod>, limit: i64) -> Result<Vec<Digest>> {
+    let pool = crate::db::get_pool().await?;
+    list_digests_impl(&pool, period, limit).await
+}
+
+/// 最近的报告，按时段从新到旧；`period` 为空时日报、周报都返回
+pub async fn list_digests_impl(pool: &SqlitePool, period: Option<DigestPeriod>, limit: i64) -> Result<Vec<Digest>> {
+    let rows = sqlx::query(
+        "SELECT id, period, start_ts, end_ts, title, markdown, file_path, degraded, created_at
+         FROM digests WHERE ?1 IS NULL OR period = ?1
+         ORDER BY start_ts DESC, period ASC LIMIT ?2",
+    )
+    .bind(period.map(|p| p.as_str()))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(Digest::from_row).collect()
+}
+
+/// 导出到笔记目录（使用全局连接池）
+pub async fn export_digest(digest: &Digest, dir: &Path) -> Result<PathBuf> {
+    let pool = crate::db::get_pool().await?;
+    export_digest_impl(&pool, digest, dir).await
+}
+
+/// 把报告写成 `dir` 下的 Markdown 文件（已存在时覆盖），并记录文件路径
+pub async fn export_digest_impl(pool: &SqlitePool, digest: &Digest, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(digest.file_name());
+    std::fs::write(&path, &digest.markdown)?;
+    sqlx::query("UPDATE digests SET file_path = ? WHERE id = ?")
+        .bind(path.to_string_lossy().to_string())
+        .bind(digest.id)
+        .execute(pool)
+        .await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::provider::MockLlmProvider;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().timestamp()
+    }
+
+    async fn insert_activity(pool: &SqlitePool, ts: i64, app: &str, title: &str, ocr: &str) {
+        sqlx::query(
+            "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(ts)
+        .bind(app)
+        .bind(title)
+        .bind(format!("{}.png", ts))
+        .bind(ocr)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn durations_skip_idle_gaps() {
+        let config = DigestConfig::default();
+        // 第三条之后离开了 990 秒，最后一条到时段结束也只计一个录制间隔
+        assert_eq!(time_weighted(&[0, 5, 10, 1000], 2000, &config), vec![5, 5, 5, 5]);
+        assert_eq!(time_weighted(&[0, 120, 200], 203, &config), vec![120, 80, 3]);
+
+        assert_eq!(format_duration(30), "不到 1 分钟");
+        assert_eq!(format_duration(3 * 3600 + 5 * 60), "3 小时 5 分钟");
+        assert_eq!(table_cell("a | b\nc"), "a \\| b c");
+        assert_eq!(file_link(Path::new("/shots/a b.png")), "<file:///shots/a b.png>");
+
+        let (start, end) = DigestPeriod::Week.previous(local(2024, 1, 17, 9, 0));
+        assert_eq!((start, end), (local(2024, 1, 8, 0, 0), local(2024, 1, 15, 0, 0)));
+        let date = NaiveDate::from_ymd_opt(2024, 1, 9).unwrap();
+        assert_eq!(DigestPeriod::Day.period_of_date(date), Some((local(2024, 1, 9, 0, 0), local(2024, 1, 10, 0, 0))));
+    }
+
+    #[tokio::test]
+    async fn generates_each_period_once_and_exports_markdown() {
+        let pool = setup_pool().await;
+        let config = DigestConfig {
+            screenshots_dir: Some(PathBuf::from("/shots")),
+            ..Default::default()
+        };
+
+        // 09:00-09:19 在 Code 里每分钟一条，之后离开，10:00 看了 5 分钟网页
+        for minute in 0..20 {
+            insert_activity(&pool, local(2024, 1, 8, 9, minute), "Code", "rag.rs - memflow", "fn search").await;
+        }
+        insert_activity(&pool, local(2024, 1, 8, 9, 7) + 30, "Code", "rag.rs - memflow", "fn search_with_filter() {}").await;
+        for minute in 0..5 {
+            insert_activity(&pool, local(2024, 1, 8, 10, minute), "Chrome", "SQLite FTS5 | 文档", "bm25").await;
+        }
+        insert_activity(&pool, local(2024, 1, 8, 10, 5), "Chrome", "SQLite FTS5 | 文档", "").await;
+        sqlx::query("INSERT INTO focus_metrics (timestamp, apm, window_switch_count, focus_score) VALUES (?, 80, 1, 70), (?, 40, 3, 50)")
+            .bind(local(2024, 1, 8, 9, 10))
+            .bind(local(2024, 1, 8, 9, 40))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let provider = MockLlmProvider::with_responses(["- 优化混合检索（约 20 分钟）\n- 查阅 FTS5 文档"]);
+        let now = local(2024, 1, 9, 1, 0);
+        let digest = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Day, local(2024, 1, 8, 12, 0), now, &config)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(digest.title, "MemFlow 日报 · 2024-01-08 周一");
+        assert_eq!(digest.start_ts, local(2024, 1, 8, 0, 0));
+        let md = &digest.markdown;
+        assert!(md.contains("> 记录 27 条，活跃时长 24 分钟"), "{}", md);
+        assert!(md.contains("## 任务总结\n\n- 优化混合检索（约 20 分钟）"));
+        assert!(md.contains("| Code | 19 分钟 | 79% |"), "{}", md);
+        assert!(md.contains("| SQLite FTS5 \\| 文档 | Chrome | 5 分钟 |"), "{}", md);
+        assert!(md.contains("平均专注度 60（共 2 次采样）"));
+        assert!(md.contains("| 09:00 | 60 | ██████ |"), "{}", md);
+        // 取文字最多的那一张
+        let shot = format!("[截图](<file:///shots/{}.png>)", local(2024, 1, 8, 9, 7) + 30);
+        assert!(md.contains(&shot), "{}", md);
+
+        let prompt = &provider.requests()[0].messages[0].content;
+        assert!(prompt.contains("- Code · rag.rs - memflow：19 分钟"), "{}", prompt);
+
+        // 同一时段不再重复生成
+        let again = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Day, local(2024, 1, 8, 23, 0), now, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(again, digest);
+        assert_eq!(provider.requests().len(), 1);
+
+        // 本周尚未结束；没有活动的日子不生成
+        assert!(generate_digest_impl(&pool, None, DigestPeriod::Week, now, now, &config).await.is_err());
+        let empty = generate_digest_impl(&pool, None, DigestPeriod::Day, local(2024, 1, 7, 12, 0), now, &config)
+            .await
+            .unwrap();
+        assert!(empty.is_none());
+
+        let dir = std::env::temp_dir().join(format!("memflow-digest-{}", uuid::Uuid::new_v4()));
+        let path = export_digest_impl(&pool, &digest, &dir).await.unwrap();
+        assert_eq!(path, dir.join("memflow-daily-2024-01-08.md"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), digest.markdown);
+        let listed = list_digests_impl(&pool, Some(DigestPeriod::Day), 10).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].file_path.as_deref(), Some(path.to_string_lossy().as_ref()));
+        assert!(list_digests_impl(&pool, Some(DigestPeriod::Week), 10).await.unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    async fn insert_day_summary(pool: &SqlitePool, date: (i32, u32, u32), content: &str) {
+        let (y, m, d) = date;
+        sqlx::query(
+            "INSERT INTO summaries (granularity, start_ts, end_ts, content, source_ids, provider, model)
+             VALUES ('day', ?, ?, ?, '[]', 'Mock', 'mock')",
+        )
+        .bind(local(y, m, d, 0, 0))
+        .bind(local(y, m, d + 1, 0, 0))
+        .bind(content)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stored_summaries_without_a_model() {
+        let pool = setup_pool().await;
+        insert_activity(&pool, local(2024, 1, 8, 9, 0), "Code", "rag.rs", "").await;
+        insert_day_summary(&pool, (2024, 1, 8), "修复检索排序").await;
+
+        let week = local(2024, 1, 10, 0, 0);
+        let now = local(2024, 1, 16, 0, 0);
+        let config = DigestConfig::default();
+        let digest = generate_digest_impl(&pool, None, DigestPeriod::Week, week, now, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(digest.title, "MemFlow 周报 · 2024-01-08 至 2024-01-14");
+        assert!(digest.markdown.contains("- **2024-01-08 周一**：修复检索排序"), "{}", digest.markdown);
+        assert!(digest.markdown.contains("没有专注度数据"));
+
+        // 没有配置模型时列出摘要就是最终结果，之后不再重新生成
+        assert!(!digest.degraded);
+        let provider = MockLlmProvider::default();
+        let again = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Week, week, now, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(again, digest);
+        assert!(provider.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_task_summary_is_regenerated_next_time() {
+        let pool = setup_pool().await;
+        insert_activity(&pool, local(2024, 1, 8, 9, 0), "Code", "rag.rs", "").await;
+        insert_day_summary(&pool, (2024, 1, 8), "修复检索排序").await;
+
+        let provider = MockLlmProvider::default();
+        provider.push_error("503 Service Unavailable");
+        provider.push_response("- 修复混合检索的排序问题");
+        let day = local(2024, 1, 8, 12, 0);
+        let config = DigestConfig::default();
+
+        let degraded = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Day, day, local(2024, 1, 9, 1, 0), &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(degraded.degraded);
+        assert!(degraded.markdown.contains("- **2024-01-08 周一**：修复检索排序"), "{}", degraded.markdown);
+
+        let retried = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Day, day, local(2024, 1, 9, 2, 0), &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(retried.id, degraded.id);
+        assert!(!retried.degraded);
+        assert!(retried.markdown.contains("## 任务总结\n\n- 修复混合检索的排序问题"), "{}", retried.markdown);
+
+        // 完整的报告不再被覆盖
+        let again = generate_digest_impl(&pool, Some(&provider), DigestPeriod::Day, day, local(2024, 1, 9, 3, 0), &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(again, retried);
+        assert_eq!(provider.requests().len(), 2);
+        assert_eq!(list_digests_impl(&pool, None, 10).await.unwrap().len(), 1);
+    }
+}
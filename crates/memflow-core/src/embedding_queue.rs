@@ -0,0 +1,598 @@
+//! 向量生成任务队列
+//!
+//! 结构与 OCR 队列一致：OCR/UIA 文本写入后入队，由后台 worker 批量取出生成向量。
+//! 失败的任务按指数退避重新排队，超过最大重试次数后标记为 failed。
+
+use crate::ai::chunker::{chunk_text, ChunkerConfig, TextChunk};
+use crate::ai::embedding::EmbeddingProvider;
+use crate::chunks;
+use crate::context::RuntimeContext;
+use crate::db;
+use crate::vector_db::{self, Embedding};
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+
+/// 最大重试次数，超过后标记为 failed
+pub const MAX_RETRIES: i64 = 5;
+/// 首次重试的等待时间（秒），之后每次翻倍
+const RETRY_BASE_SECS: i64 = 30;
+/// 退避等待时间上限（秒）
+const RETRY_MAX_SECS: i64 = 3600;
+/// 单个活动最多生成的块数，超出部分不参与向量检索（仍可被整体的关键词检索命中）
+pub const MAX_CHUNKS_PER_ACTIVITY: usize = 32;
+/// processing 状态超过该时间（秒）视为卡住，重新领取
+const STUCK_PROCESSING_SECS: i64 = 300;
+
+/// 回填进度事件名
+pub const BACKFILL_PROGRESS_EVENT: &str = "embedding-backfill-progress";
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct EmbeddingQueueItem {
+    pub id: i64,
+    pub activity_id: i64,
+    pub retry_count: i64,
+    pub window_title: String,
+    pub ocr_text: Option<String>,
+}
+
+impl EmbeddingQueueItem {
+    /// OCR 文本的分块（最多 [`MAX_CHUNKS_PER_ACTIVITY`] 块）
+    pub fn chunks(&self) -> Vec<TextChunk> {
+        let mut chunks = chunk_text(self.ocr_text.as_deref().unwrap_or_default(), &ChunkerConfig::default());
+        chunks.truncate(MAX_CHUNKS_PER_ACTIVITY);
+        chunks
+    }
+
+    /// 送去生成向量的文本：每块前加上窗口标题；没有 OCR 文本时只用标题
+    pub fn embedding_inputs(&self, chunks: &[TextChunk]) -> Vec<String> {
+        if chunks.is_empty() {
+            return vec![self.window_title.clone()];
+        }
+        chunks
+            .iter()
+            .map(|chunk| {
+                if self.window_title.is_empty() {
+                    chunk.text.clone()
+                } else {
+                    format!("{}\n{}", self.window_title, chunk.text)
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EmbeddingQueueStats {
+    pub pending: i64,
+    pub processing: i64,
+    pub done: i64,
+    pub failed: i64,
+}
+
+/// 一批任务的处理结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmbeddingBatchResult {
+    pub embedded: usize,
+    pub failed: usize,
+}
+
+impl EmbeddingBatchResult {
+    pub fn is_empty(&self) -> bool {
+        self.embedded == 0 && self.failed == 0
+    }
+}
+
+/// 第 `retry_count` 次失败后的等待时间（秒）
+pub fn retry_backoff_secs(retry_count: i64) -> i64 {
+    let exp = retry_count.clamp(0, 16) as u32;
+    RETRY_BASE_SECS.saturating_mul(1 << exp).min(RETRY_MAX_SECS)
+}
+
+/// 入队；已存在的任务（文本更新后）重新置为 pending
+pub async fn enqueue(activity_id: i64) -> Result<()> {
+    let pool = db::get_pool().await?;
+    enqueue_impl(&pool, activity_id).await
+}
+
+pub async fn enqueue_impl(pool: &SqlitePool, activity_id: i64) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO embedding_queue (activity_id, status) VALUES (?, 'pending')
+         ON CONFLICT(activity_id) DO UPDATE SET
+             status = 'pending',
+             retry_count = 0,
+             error_message = NULL,
+             next_attempt_at = 0,
+             updated_at = strftime('%s', 'now')",
+    )
+    .bind(activity_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 将有文本但缺少指定模型向量（整体或分块）的活动入队，返回新入队（或重新排队）的数量
+pub async fn enqueue_missing_impl(pool: &SqlitePool, model_id: &str) -> Result<u64> {
+    let result = sqlx::query(
+        "INSERT INTO embedding_queue (activity_id, status)
+         SELECT a.id, 'pending' FROM activity_logs a
+         WHERE COALESCE(a.ocr_text, '') != ''
+           AND (
+               NOT EXISTS (SELECT 1 FROM vector_embeddings v WHERE v.activity_id = a.id AND v.model_id = ?1)
+               OR NOT EXISTS (SELECT 1 FROM chunk_embeddings c WHERE c.activity_id = a.id AND c.model_id = ?1)
+           )
+         ON CONFLICT(activity_id) DO UPDATE SET
+             status = 'pending',
+             retry_count = 0,
+             error_message = NULL,
+             next_attempt_at = 0,
+             updated_at = strftime('%s', 'now')
+         WHERE embedding_queue.status IN ('done', 'failed')",
+    )
+    .bind(model_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// 领取到期的任务并标记为 processing
+///
+/// 用一条 UPDATE 完成领取：SQLite 的写操作互斥，并发的 worker（定时任务与手动补齐）不会领到同一条任务。
+pub async fn claim_pending_impl(pool: &SqlitePool, limit: i64) -> Result<Vec<EmbeddingQueueItem>> {
+    let claimed: Vec<i64> = sqlx::query_scalar(
+        r#"
+        UPDATE embedding_queue SET status = 'processing', updated_at = strftime('%s', 'now')
+        WHERE id IN (
+            SELECT q.id
+            FROM embedding_queue q
+            JOIN activity_logs a ON q.activity_id = a.id
+            WHERE (q.status = 'pending' AND q.next_attempt_at <= strftime('%s', 'now'))
+               OR (q.status = 'processing' AND q.updated_at < (strftime('%s', 'now') - ?))
+            ORDER BY q.created_at ASC, q.id ASC
+            LIMIT ?
+        )
+        RETURNING id
+        "#,
+    )
+    .bind(STUCK_PROCESSING_SECS)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    if claimed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut builder = sqlx::QueryBuilder::new(
+        "SELECT q.id, q.activity_id, q.retry_count, a.window_title, a.ocr_text
+         FROM embedding_queue q
+         JOIN activity_logs a ON q.activity_id = a.id
+         WHERE q.id IN (",
+    );
+    let mut separated = builder.separated(", ");
+    for id in &claimed {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(") ORDER BY q.created_at ASC, q.id ASC");
+    Ok(builder.build_query_as::<EmbeddingQueueItem>().fetch_all(pool).await?)
+}
+
+async fn mark_done_impl(pool: &SqlitePool, id: i64) -> Result<()> {
+    sqlx::query("UPDATE embedding_queue SET status = 'done', error_message = NULL, updated_at = strftime('%s', 'now') WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// 记录失败：未超过重试次数时按退避时间重新排队，否则标记为 failed
+async fn mark_failed_impl(pool: &SqlitePool, item: &EmbeddingQueueItem, error: &str) -> Result<()> {
+    let retry_count = item.retry_count + 1;
+    let (status, delay) = if retry_count >= MAX_RETRIES {
+        ("failed", 0)
+    } else {
+        ("pending", retry_backoff_secs(item.retry_count))
+    };
+
+    sqlx::query(
+        "UPDATE embedding_queue SET status = ?, retry_count = ?, error_message = ?,
+             next_attempt_at = strftime('%s', 'now') + ?, updated_at = strftime('%s', 'now')
+         WHERE id = ?",
+    )
+    .bind(status)
+    .bind(retry_count)
+    .bind(crate::redact::redact_secrets(error))
+    .bind(delay)
+    .bind(item.id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 领取一批任务，调用 `provider` 批量生成向量并写入
+///
+/// 每个活动的 OCR 文本先分块，所有块合并成一次请求；活动整体的向量取各块向量的平均。
+/// 整批失败时（如接口限流）每个任务各记一次失败。
+pub async fn process_batch_impl(
+    pool: &SqlitePool,
+    limit: i64,
+    provider: &dyn EmbeddingProvider,
+) -> Result<EmbeddingBatchResult> {
+    let items = claim_pending_impl(pool, limit).await?;
+    if items.is_empty() {
+        return Ok(EmbeddingBatchResult::default());
+    }
+
+    let item_chunks: Vec<Vec<TextChunk>> = items.iter().map(EmbeddingQueueItem::chunks).collect();
+    let inputs: Vec<Vec<String>> = items
+        .iter()
+        .zip(&item_chunks)
+        .map(|(item, chunks)| item.embedding_inputs(chunks))
+        .collect();
+    let texts: Vec<String> = inputs.iter().flatten().cloned().collect();
+
+    let mut embeddings = match provider.embed_documents(&texts).await {
+        Ok(embeddings) if embeddings.len() == texts.len() => embeddings.into_iter(),
+        Ok(embeddings) => {
+            let err = format!("期望 {} 个向量，实际返回 {} 个", texts.len(), embeddings.len());
+            return fail_all(pool, &items, &err).await;
+        }
+        Err(e) => return fail_all(pool, &items, &e.to_string()).await,
+    };
+
+    let mut result = EmbeddingBatchResult::default();
+    for ((item, chunks), inputs) in items.iter().zip(&item_chunks).zip(&inputs) {
+        let item_embeddings: Vec<Embedding> = embeddings.by_ref().take(inputs.len()).collect();
+        match store_embeddings_impl(pool, item.activity_id, chunks, &item_embeddings).await {
+            Ok((embedding, version)) => {
+                if let Err(e) = crate::vector_index::add(item.activity_id, &embedding, version).await {
+                    tracing::warn!("向量索引增量写入失败: {}", e);
+                }
+                mark_done_impl(pool, item.id).await?;
+                result.embedded += 1;
+            }
+            Err(e) => {
+                tracing::warn!("保存向量失败: activity_id={}, {}", item.activity_id, e);
+                mark_failed_impl(pool, item, &e.to_string()).await?;
+                result.failed += 1;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// 写入分块及其向量，再写入活动整体的向量；返回整体向量及其版本（`created_at`）
+///
+/// 没有分块时 `embeddings` 只有一个（标题的向量），直接作为整体向量。
+async fn store_embeddings_impl(
+    pool: &SqlitePool,
+    activity_id: i64,
+    item_chunks: &[TextChunk],
+    embeddings: &[Embedding],
+) -> Result<(Embedding, i64)> {
+    let chunk_ids = chunks::replace_chunks_impl(pool, activity_id, item_chunks).await?;
+    for (chunk_id, embedding) in chunk_ids.iter().zip(embeddings) {
+        chunks::insert_chunk_embedding_impl(pool, *chunk_id, activity_id, embedding).await?;
+    }
+
+    let embedding = chunks::mean_embedding(embeddings)
+        .ok_or_else(|| anyhow::anyhow!("No embedding for activity {}", activity_id))?;
+    let version = vector_db::insert_embedding_impl(pool, activity_id, &embedding).await?;
+    Ok((embedding, version))
+}
+
+async fn fail_all(pool: &SqlitePool, items: &[EmbeddingQueueItem], error: &str) -> Result<EmbeddingBatchResult> {
+    tracing::warn!("批量生成向量失败（{} 个任务）: {}", items.len(), error);
+    for item in items {
+        mark_failed_impl(pool, item, error).await?;
+    }
+    Ok(EmbeddingBatchResult {
+        embedded: 0,
+        failed: items.len(),
+    })
+}
+
+pub async fn get_embedding_queue_stats() -> Result<EmbeddingQueueStats> {
+    let pool = db::get_pool().await?;
+    get_embedding_queue_stats_impl(&pool).await
+}
+
+pub async fn get_embedding_queue_stats_impl(pool: &SqlitePool) -> Result<EmbeddingQueueStats> {
+    let stats = sqlx::query_as::<_, EmbeddingQueueStats>(
+        r#"
+        SELECT
+            COALESCE(SUM(CASE WHEN status = 'pending' THEN 1 ELSE 0 END), 0) as pending,
+            COALESCE(SUM(CASE WHEN status = 'processing' THEN 1 ELSE 0 END), 0) as processing,
+            COALESCE(SUM(CASE WHEN status = 'done' THEN 1 ELSE 0 END), 0) as done,
+            COALESCE(SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END), 0) as failed
+        FROM embedding_queue
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(stats)
+}
+
+/// 为缺少当前模型向量的历史活动补生成向量
+///
+/// 每处理完一批通过 [`BACKFILL_PROGRESS_EVENT`] 上报进度；处于退避等待中的任务留给后台 worker。
+/// 返回成功生成的向量数。
+pub async fn backfill_impl(
+    pool: &SqlitePool,
+    ctx: &dyn RuntimeContext,
+    provider: &dyn EmbeddingProvider,
+    batch_size: i64,
+) -> Result<usize> {
+    let model_id = provider.model_id();
+    let total = enqueue_missing_impl(pool, model_id).await?;
+    tracing::info!("向量回填开始: model={}, 待处理 {} 条", model_id, total);
+
+    let mut processed = EmbeddingBatchResult::default();
+    loop {
+        let batch = process_batch_impl(pool, batch_size, provider).await?;
+        if batch.is_empty() {
+            break;
+        }
+        processed.embedded += batch.embedded;
+        processed.failed += batch.failed;
+
+        let _ = ctx.emit(
+            BACKFILL_PROGRESS_EVENT,
+            serde_json::json!({
+                "modelId": model_id,
+                "total": total,
+                "embedded": processed.embedded,
+                "failed": processed.failed,
+                "done": false,
+            }),
+        );
+    }
+
+    let _ = ctx.emit(
+        BACKFILL_PROGRESS_EVENT,
+        serde_json::json!({
+            "modelId": model_id,
+            "total": total,
+            "embedded": processed.embedded,
+            "failed": processed.failed,
+            "done": true,
+        }),
+    );
+    tracing::info!(
+        "向量回填结束: 成功 {} 条，失败 {} 条",
+        processed.embedded,
+        processed.failed
+    );
+    Ok(processed.embedded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::embedding::StubEmbeddingProvider;
+    use crate::context::AiAnalysisResult;
+    use crate::vector_db::Embedding;
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::future::Future;
+    use std::path::PathBuf;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingContext {
+        events: Mutex<Vec<(String, Value)>>,
+    }
+
+    impl RuntimeContext for RecordingContext {
+        fn app_dir(&self) -> PathBuf {
+            PathBuf::from(".")
+        }
+
+        fn resource_dir(&self) -> PathBuf {
+            PathBuf::from(".")
+        }
+
+        fn emit(&self, event: &str, payload: Value) -> Result<()> {
+            self.events.lock().unwrap().push((event.to_string(), payload));
+            Ok(())
+        }
+
+        fn analyze_for_proposals(
+            &self,
+            _context_text: &str,
+        ) -> Pin<Box<dyn Future<Output = Result<AiAnalysisResult>> + Send + '_>> {
+            Box::pin(async { Ok(AiAnalysisResult { tasks: Vec::new() }) })
+        }
+    }
+
+    async fn setup_pool(count: i64) -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        for id in 1..=count {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, 'Code', ?, '', ?)")
+                .bind(id)
+                .bind(id * 1000)
+                .bind(format!("title {}", id))
+                .bind(format!("text {}", id))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    /// 总是失败的 provider，记录收到的文本
+    #[derive(Default)]
+    struct FailingProvider {
+        received: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for FailingProvider {
+        fn model_id(&self) -> &str {
+            "failing"
+        }
+
+        async fn embed_documents(&self, texts: &[String]) -> Result<Vec<Embedding>> {
+            self.received.lock().unwrap().extend_from_slice(texts);
+            Err(anyhow::anyhow!("rate limited"))
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        assert_eq!(retry_backoff_secs(0), 30);
+        assert_eq!(retry_backoff_secs(1), 60);
+        assert_eq!(retry_backoff_secs(3), 240);
+        assert_eq!(retry_backoff_secs(20), 3600);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_claims_do_not_overlap() {
+        let path = std::env::temp_dir().join(format!("memflow-queue-{}.db", uuid::Uuid::new_v4()));
+        let options = sqlx::sqlite::SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .busy_timeout(std::time::Duration::from_secs(5));
+        let pool = SqlitePoolOptions::new().max_connections(4).connect_with(options).await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        for id in 1..=40 {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path, ocr_text) VALUES (?, ?, 'Code', 't', '', 'x')")
+                .bind(id)
+                .bind(id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            enqueue_impl(&pool, id).await.unwrap();
+        }
+
+        // 定时任务与手动补齐同时领取，直到队列领空
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    let mut ids = Vec::new();
+                    loop {
+                        let items = claim_pending_impl(&pool, 3).await.unwrap();
+                        if items.is_empty() {
+                            return ids;
+                        }
+                        ids.extend(items.into_iter().map(|item| item.activity_id));
+                    }
+                })
+            })
+            .collect();
+        let mut claimed: Vec<i64> = Vec::new();
+        for worker in workers {
+            claimed.extend(worker.await.unwrap());
+        }
+        let total = claimed.len();
+        claimed.sort_unstable();
+        claimed.dedup();
+        assert_eq!(claimed.len(), total, "同一任务被领取了多次");
+        assert_eq!(total, 40);
+        assert!(claim_pending_impl(&pool, 10).await.unwrap().is_empty());
+
+        pool.close().await;
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn batches_and_retries_with_backoff() {
+        let pool = setup_pool(3).await;
+        for id in 1..=3 {
+            enqueue_impl(&pool, id).await.unwrap();
+        }
+
+        let failing = FailingProvider::default();
+        let stub = StubEmbeddingProvider::default();
+
+        let result = process_batch_impl(&pool, 2, &failing).await.unwrap();
+        assert_eq!(result, EmbeddingBatchResult { embedded: 0, failed: 2 });
+        assert_eq!(*failing.received.lock().unwrap(), vec!["title 1\ntext 1", "title 2\ntext 2"]);
+
+        // 失败的任务进入退避，只剩第 3 个可领取
+        let result = process_batch_impl(&pool, 10, &stub).await.unwrap();
+        assert_eq!(result, EmbeddingBatchResult { embedded: 1, failed: 0 });
+        assert!(process_batch_impl(&pool, 10, &stub).await.unwrap().is_empty());
+
+        let stats = get_embedding_queue_stats_impl(&pool).await.unwrap();
+        assert_eq!((stats.pending, stats.done), (2, 1));
+
+        // 退避到期后重试成功
+        sqlx::query("UPDATE embedding_queue SET next_attempt_at = 0").execute(&pool).await.unwrap();
+        let result = process_batch_impl(&pool, 10, &stub).await.unwrap();
+        assert_eq!(result.embedded, 2);
+
+        // 超过最大重试次数后标记为 failed
+        sqlx::query("UPDATE embedding_queue SET status = 'pending', retry_count = ? WHERE activity_id = 1")
+            .bind(MAX_RETRIES - 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+        process_batch_impl(&pool, 10, &failing).await.unwrap();
+        let stats = get_embedding_queue_stats_impl(&pool).await.unwrap();
+        assert_eq!((stats.failed, stats.done), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn backfill_embeds_missing_and_reports_progress() {
+        let pool = setup_pool(5).await;
+        sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (6, 1, 'Code', 'no text', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let stub = StubEmbeddingProvider::default();
+        // 活动 1 只有整体向量、缺少分块向量（分块之前的旧数据），同样需要回填
+        vector_db::insert_embedding_impl(&pool, 1, &stub.embed_text("title 1"))
+            .await
+            .unwrap();
+        // 其他模型的向量不算
+        vector_db::insert_embedding_impl(&pool, 2, &Embedding::new("other", vec![1.0, 1.0]))
+            .await
+            .unwrap();
+        // 活动 3 已完整处理过
+        let item = EmbeddingQueueItem {
+            id: 0,
+            activity_id: 3,
+            retry_count: 0,
+            window_title: "title 3".to_string(),
+            ocr_text: Some("text 3".to_string()),
+        };
+        store_embeddings_impl(&pool, 3, &item.chunks(), &[stub.embed_text("title 3\ntext 3")])
+            .await
+            .unwrap();
+
+        let ctx = RecordingContext::default();
+        let embedded = backfill_impl(&pool, &ctx, &stub, 2).await.unwrap();
+        assert_eq!(embedded, 4);
+
+        let events = ctx.events.lock().unwrap().clone();
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|(name, _)| name == BACKFILL_PROGRESS_EVENT));
+        let last = &events.last().unwrap().1;
+        assert_eq!(last["total"], 4);
+        assert_eq!(last["embedded"], 4);
+        assert_eq!(last["done"], true);
+
+        let chunk_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chunk_embeddings WHERE model_id = 'stub-64'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(chunk_count, 5);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM vector_embeddings WHERE model_id = 'stub-64'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 5);
+
+        // 已有向量时不会重复入队
+        assert_eq!(enqueue_missing_impl(&pool, stub.model_id()).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,240 @@
+//! 检索结果摘要与命中高亮
+//!
+//! FTS 索引中存放的是分词后的文本（见 migration 0012），FTS5 `snippet()` / `highlight()`
+//! 的原始输出会带有切词空格和重复的子词。这里只借助 `highlight()` 找出命中的词，
+//! 再映射回原文计算字节偏移、生成摘要。
+
+use serde::{Deserialize, Serialize};
+
+/// `highlight()` 内部使用的标记字符，不会出现在正常文本中
+pub const FTS_MARK_OPEN: char = '\u{1}';
+pub const FTS_MARK_CLOSE: char = '\u{2}';
+
+/// 命中所在的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchField {
+    WindowTitle,
+    OcrText,
+}
+
+/// 一处命中在原文中的字节区间 `[start, end)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchSpan {
+    pub field: MatchField,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 摘要与高亮选项
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HighlightOptions {
+    pub open: String,
+    pub close: String,
+    pub ellipsis: String,
+    /// 摘要最多包含的字符数（不含标记与省略号）
+    pub max_chars: usize,
+    /// 是否同时返回整段高亮后的 OCR 文本
+    pub full_text: bool,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        Self {
+            open: "<mark>".to_string(),
+            close: "</mark>".to_string(),
+            ellipsis: "…".to_string(),
+            max_chars: 80,
+            full_text: false,
+        }
+    }
+}
+
+/// 取出 `highlight()` 输出中被标记的词（去重）
+pub fn marked_terms(highlighted: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut rest = highlighted;
+    while let Some(open) = rest.find(FTS_MARK_OPEN) {
+        let after = &rest[open + FTS_MARK_OPEN.len_utf8()..];
+        let Some(close) = after.find(FTS_MARK_CLOSE) else { break };
+        // 分词文本中一个标记区间可能包含多个以空格分隔的词
+        for term in after[..close].split_whitespace() {
+            if !terms.iter().any(|t| t == term) {
+                terms.push(term.to_string());
+            }
+        }
+        rest = &after[close + FTS_MARK_CLOSE.len_utf8()..];
+    }
+    terms
+}
+
+/// 在原文中查找各个词的出现位置，返回排序并合并后的字节区间
+///
+/// ASCII 不区分大小写；以字母数字开头/结尾的词要求落在词边界上，避免 "a" 命中 "cat"。
+pub fn find_term_spans(text: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let haystack = text.to_ascii_lowercase();
+    let bytes = haystack.as_bytes();
+    let mut spans = Vec::new();
+
+    for term in terms {
+        let needle = term.to_ascii_lowercase();
+        if needle.is_empty() {
+            continue;
+        }
+        let check_start = needle.as_bytes()[0].is_ascii_alphanumeric();
+        let check_end = needle.as_bytes()[needle.len() - 1].is_ascii_alphanumeric();
+
+        for (start, matched) in haystack.match_indices(&needle) {
+            let end = start + matched.len();
+            if check_start && start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+                continue;
+            }
+            if check_end && end < bytes.len() && bytes[end].is_ascii_alphanumeric() {
+                continue;
+            }
+            spans.push((start, end));
+        }
+    }
+
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            // 相邻的中文子词（"错误" + "处理"）合并成一个区间
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// 用标记包裹原文中的命中区间
+pub fn apply_marks(text: &str, spans: &[(usize, usize)], options: &HighlightOptions) -> String {
+    let mut out = String::with_capacity(text.len() + spans.len() * 16);
+    let mut pos = 0;
+    for &(start, end) in spans {
+        out.push_str(&text[pos..start]);
+        out.push_str(&options.open);
+        out.push_str(&text[start..end]);
+        out.push_str(&options.close);
+        pos = end;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+/// 以第一处命中为中心截取一段紧凑摘要（合并空白、加省略号并高亮命中）
+///
+/// 没有命中时返回开头的一段文本。
+pub fn build_snippet(text: &str, spans: &[(usize, usize)], options: &HighlightOptions) -> String {
+    let char_starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    if char_starts.is_empty() {
+        return String::new();
+    }
+    let total = char_starts.len();
+    let max_chars = options.max_chars.max(1);
+
+    let anchor = spans
+        .first()
+        .map(|&(start, _)| char_starts.partition_point(|&b| b < start))
+        .unwrap_or(0);
+    let mut from = anchor.saturating_sub(max_chars / 4);
+    let to = (from + max_chars).min(total);
+    if to - from < max_chars {
+        from = to.saturating_sub(max_chars);
+    }
+
+    let byte_from = char_starts[from];
+    let byte_to = if to == total { text.len() } else { char_starts[to] };
+
+    let mut out = String::new();
+    if byte_from > 0 {
+        out.push_str(&options.ellipsis);
+    }
+
+    let mut pos = byte_from;
+    for &(start, end) in spans {
+        if end <= byte_from || start >= byte_to {
+            continue;
+        }
+        let (start, end) = (start.max(byte_from), end.min(byte_to));
+        push_compact(&mut out, &text[pos..start]);
+        out.push_str(&options.open);
+        push_compact(&mut out, &text[start..end]);
+        out.push_str(&options.close);
+        pos = end;
+    }
+    push_compact(&mut out, &text[pos..byte_to]);
+
+    if byte_to < text.len() {
+        let trimmed = out.trim_end().len();
+        out.truncate(trimmed);
+        out.push_str(&options.ellipsis);
+    }
+    out
+}
+
+/// 追加文本并把连续空白（含 OCR 换行）压缩为单个空格
+fn push_compact(out: &mut String, s: &str) {
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with(' ') {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn extracts_marked_terms() {
+        let hl = "本周 \u{1}错误\u{2} \u{1}处理 错误处理\u{2} 改造 \u{1}错误\u{2}";
+        assert_eq!(marked_terms(hl), terms(&["错误", "处理", "错误处理"]));
+    }
+
+    #[test]
+    fn finds_spans_in_original_text() {
+        let text = "修复 Rust 项目的错误处理；rust-analyzer 报错";
+        let spans = find_term_spans(text, &terms(&["错误", "处理", "rust"]));
+        let hits: Vec<&str> = spans.iter().map(|&(s, e)| &text[s..e]).collect();
+        assert_eq!(hits, vec!["Rust", "错误处理", "rust"]);
+
+        // 英文词要求词边界
+        assert!(find_term_spans("concatenate", &terms(&["cat"])).is_empty());
+    }
+
+    #[test]
+    fn builds_compact_snippet() {
+        let options = HighlightOptions {
+            open: "[".to_string(),
+            close: "]".to_string(),
+            ellipsis: "...".to_string(),
+            max_chars: 20,
+            full_text: false,
+        };
+        let text = format!("{}\n\nfound the   invoice here\n{}", "x".repeat(40), "y".repeat(40));
+        let spans = find_term_spans(&text, &terms(&["invoice"]));
+        let snippet = build_snippet(&text, &spans, &options);
+
+        assert!(snippet.starts_with("..."));
+        assert!(snippet.ends_with("..."));
+        assert!(snippet.contains("[invoice]"));
+        assert!(!snippet.contains('\n'));
+        assert!(!snippet.contains("  "));
+
+        // 无命中时返回开头
+        assert_eq!(build_snippet("short text", &[], &options), "short text");
+        assert_eq!(apply_marks("a b", &[(2, 3)], &options), "a [b]");
+    }
+}
@@ -5,8 +5,15 @@
 
 pub mod agent;
 pub mod ai;
+pub mod chunks;
 pub mod context;
 pub mod db;
+pub mod digest;
+pub mod embedding_queue;
 pub mod focus_analytics;
+pub mod highlight;
 pub mod redact;
+pub mod search_query;
+pub mod time_range;
 pub mod vector_db;
+pub mod vector_index;
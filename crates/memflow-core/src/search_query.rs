@@ -0,0 +1,456 @@
+//! 搜索框查询语法
+//!
+//! 把 `app:chrome title:"pull request" after:2024-05-01 before:yesterday -jenkins has:ocr`
+//! 这样的输入解析为 [`ActivityQuery`]。所有检索词都会被转义成合法的 FTS5 表达式，
+//! 用户输入不会原样进入 `MATCH`，语法问题以 [`SearchQueryError`] 返回。
+//!
+//! 支持的写法：
+//! - `word`、`"quoted phrase"`、`prefix*`：全文检索词，默认 AND，可用 `OR` 连接
+//! - `-word`、`-"phrase"`：排除命中该词的记录
+//! - `app:name` / `-app:name`：按应用名过滤 / 排除
+//! - `title:...`、`ocr:...`：只在窗口标题 / OCR 文本中检索
+//! - `after:DATE`、`before:DATE`：DATE 为 `YYYY-MM-DD`、`today`、`yesterday`、`7d`、`12h`
+//! - `has:ocr` / `-has:ocr`：是否有 OCR 文本
+
+use crate::db::ActivityQuery;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+
+/// 查询语法错误
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SearchQueryError {
+    #[error("第 {position} 个字符处的引号没有闭合")]
+    UnterminatedQuote { position: usize },
+    #[error("`{field}:` 缺少值")]
+    MissingValue { field: String },
+    #[error("未知字段 `{field}:`，支持 app、title、ocr、after、before、has")]
+    UnknownField { field: String },
+    #[error("无法识别的日期 `{value}`，支持 YYYY-MM-DD、today、yesterday、7d、12h")]
+    InvalidDate { value: String },
+    #[error("不支持 `has:{value}`，目前只支持 has:ocr")]
+    InvalidHas { value: String },
+    #[error("`OR` 两侧都需要检索词")]
+    DanglingOr,
+    #[error("`{field}:` 不能与 `-` 或 `*` 一起使用")]
+    UnsupportedModifier { field: String },
+    #[error("时间范围为空：after 不早于 before")]
+    EmptyTimeRange,
+}
+
+/// 按当前本地时间解析查询
+pub fn parse_search_query(input: &str) -> Result<ActivityQuery, SearchQueryError> {
+    parse_search_query_at(input, Local::now())
+}
+
+/// 以给定时间为“现在”解析查询（相对日期据此计算，便于测试）
+pub fn parse_search_query_at(
+    input: &str,
+    now: DateTime<Local>,
+) -> Result<ActivityQuery, SearchQueryError> {
+    let mut query = ActivityQuery::new();
+    let mut positive: Vec<String> = Vec::new();
+    let mut negative: Vec<String> = Vec::new();
+    let mut pending_or = false;
+
+    for token in tokenize(input)? {
+        if token.is_or() {
+            if positive.is_empty() || pending_or {
+                return Err(SearchQueryError::DanglingOr);
+            }
+            pending_or = true;
+            continue;
+        }
+
+        let term = match token.field.as_deref() {
+            None => Some(fts_term(&token.value, token.quoted, token.prefix, None)),
+            Some(field) => {
+                if token.value.is_empty() {
+                    return Err(SearchQueryError::MissingValue { field: field.to_string() });
+                }
+                match field {
+                    "title" => Some(fts_term(&token.value, token.quoted, token.prefix, Some("window_title"))),
+                    "ocr" => Some(fts_term(&token.value, token.quoted, token.prefix, Some("ocr_text"))),
+                    "app" | "after" | "before" | "has" if token.prefix => {
+                        return Err(SearchQueryError::UnsupportedModifier { field: field.to_string() });
+                    }
+                    "app" => {
+                        if token.negated {
+                            query.exclude_apps.push(token.value);
+                        } else {
+                            query.apps.push(token.value);
+                        }
+                        None
+                    }
+                    "after" | "before" if token.negated => {
+                        return Err(SearchQueryError::UnsupportedModifier { field: field.to_string() });
+                    }
+                    "after" => {
+                        query.from_ts = Some(parse_date(&token.value, now)?);
+                        None
+                    }
+                    "before" => {
+                        // before 不包含该时刻本身（before:2024-05-01 不含 5 月 1 日）
+                        query.to_ts = Some(parse_date(&token.value, now)? - 1);
+                        None
+                    }
+                    "has" => {
+                        if !token.value.eq_ignore_ascii_case("ocr") {
+                            return Err(SearchQueryError::InvalidHas { value: token.value });
+                        }
+                        query.has_ocr = Some(!token.negated);
+                        None
+                    }
+                    _ => return Err(SearchQueryError::UnknownField { field: field.to_string() }),
+                }
+            }
+        };
+
+        let Some(term) = term else {
+            if pending_or {
+                return Err(SearchQueryError::DanglingOr);
+            }
+            continue;
+        };
+
+        if token.negated {
+            if pending_or {
+                return Err(SearchQueryError::DanglingOr);
+            }
+            negative.push(term);
+        } else if pending_or {
+            let last = positive.last_mut().expect("checked above");
+            last.push_str(" OR ");
+            last.push_str(&term);
+            pending_or = false;
+        } else {
+            positive.push(term);
+        }
+    }
+
+    if pending_or {
+        return Err(SearchQueryError::DanglingOr);
+    }
+
+    if let (Some(from), Some(to)) = (query.from_ts, query.to_ts) {
+        if from > to {
+            return Err(SearchQueryError::EmptyTimeRange);
+        }
+    }
+
+    if !positive.is_empty() {
+        query.query = Some(join_and(positive));
+    }
+    if !negative.is_empty() {
+        query.exclude_query = Some(negative.join(" OR "));
+    }
+
+    Ok(query)
+}
+
+/// 把若干关键词组合成 `OR` 连接的安全 FTS5 表达式（用于 LLM 提取的关键词等非用户输入）
+pub fn fts_any_of<I, S>(terms: I) -> Option<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let parts: Vec<String> = terms
+        .into_iter()
+        .map(|t| t.as_ref().trim().to_string())
+        .filter(|t| !t.is_empty())
+        .map(|t| fts_term(&t, false, false, None))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" OR "))
+}
+
+#[derive(Debug)]
+struct Token {
+    field: Option<String>,
+    value: String,
+    negated: bool,
+    quoted: bool,
+    prefix: bool,
+}
+
+impl Token {
+    fn is_or(&self) -> bool {
+        self.field.is_none() && !self.negated && !self.quoted && !self.prefix && self.value == "OR"
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, SearchQueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let mut negated = false;
+        if chars[i] == '-' && chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) {
+            negated = true;
+            i += 1;
+        }
+
+        // field:value（URL 与 Windows 路径如 `https://`、`C:\` 视为普通检索词）
+        let mut field = None;
+        let name_end = chars[i..]
+            .iter()
+            .position(|c| !c.is_ascii_alphabetic())
+            .map(|p| i + p)
+            .unwrap_or(chars.len());
+        if name_end > i
+            && chars.get(name_end) == Some(&':')
+            && !matches!(chars.get(name_end + 1), Some('/') | Some('\\'))
+        {
+            field = Some(chars[i..name_end].iter().collect::<String>().to_ascii_lowercase());
+            i = name_end + 1;
+        }
+
+        let (value, quoted) = if chars.get(i) == Some(&'"') {
+            let start = i;
+            let Some(len) = chars[i + 1..].iter().position(|&c| c == '"') else {
+                return Err(SearchQueryError::UnterminatedQuote { position: start + 1 });
+            };
+            let value: String = chars[i + 1..i + 1 + len].iter().collect();
+            i += len + 2;
+            (value, true)
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() {
+                i += 1;
+            }
+            (chars[start..i].iter().collect(), false)
+        };
+
+        let mut value = value;
+        let mut prefix = false;
+        if quoted {
+            if chars.get(i) == Some(&'*') {
+                prefix = true;
+                i += 1;
+            }
+        } else if value.len() > 1 && value.ends_with('*') {
+            value.pop();
+            prefix = true;
+        }
+
+        tokens.push(Token {
+            field,
+            value,
+            negated,
+            quoted,
+            prefix,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// 把一个检索词转成安全的 FTS5 片段
+fn fts_term(value: &str, quoted: bool, prefix: bool, column: Option<&str>) -> String {
+    // 纯字母数字（含中文）的词保持裸写，以便后续按中文分词改写；其余一律加引号
+    let bare = !quoted
+        && !value.is_empty()
+        && value.chars().all(char::is_alphanumeric)
+        && !matches!(value, "AND" | "OR" | "NOT" | "NEAR");
+
+    let mut term = String::new();
+    if let Some(column) = column {
+        term.push_str(column);
+        term.push(':');
+    }
+    if bare {
+        term.push_str(value);
+    } else {
+        term.push('"');
+        term.push_str(&value.replace('"', "\"\""));
+        term.push('"');
+    }
+    if prefix {
+        term.push('*');
+    }
+    term
+}
+
+/// 多个 AND 片段中若有 OR 组合，则加括号保持语义
+fn join_and(parts: Vec<String>) -> String {
+    if parts.len() == 1 {
+        return parts.into_iter().next().unwrap_or_default();
+    }
+    parts
+        .into_iter()
+        .map(|p| if p.contains(" OR ") { format!("({})", p) } else { p })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_date(value: &str, now: DateTime<Local>) -> Result<i64, SearchQueryError> {
+    let invalid = || SearchQueryError::InvalidDate { value: value.to_string() };
+    let lower = value.to_ascii_lowercase();
+
+    let day = match lower.as_str() {
+        "today" => Some(now.date_naive()),
+        "yesterday" => Some(now.date_naive() - Duration::days(1)),
+        _ => NaiveDate::parse_from_str(&lower, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(&lower, "%Y/%m/%d"))
+            .ok(),
+    };
+    if let Some(day) = day {
+        let midnight = day.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|dt| dt.timestamp())
+            .ok_or_else(invalid);
+    }
+
+    // 相对时间：7d / 12h（用 strip_suffix 而不是按字节切分，非 ASCII 输入不会越过字符边界）
+    let parse_amount = |num: &str| num.parse::<i64>().map_err(|_| invalid());
+    let offset = if let Some(num) = lower.strip_suffix('d') {
+        Duration::try_days(parse_amount(num)?)
+    } else if let Some(num) = lower.strip_suffix('h') {
+        Duration::try_hours(parse_amount(num)?)
+    } else {
+        None
+    };
+    let offset = offset.ok_or_else(invalid)?;
+    now.checked_sub_signed(offset)
+        .map(|dt| dt.timestamp())
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 10, 15, 30, 0).unwrap()
+    }
+
+    fn midnight(y: i32, m: u32, d: u32) -> i64 {
+        Local.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap().timestamp()
+    }
+
+    #[test]
+    fn parses_full_example() {
+        let q = parse_search_query_at(
+            r#"app:chrome title:"pull request" after:2024-05-01 before:yesterday -jenkins has:ocr"#,
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(q.apps, vec!["chrome"]);
+        assert_eq!(q.query.as_deref(), Some(r#"window_title:"pull request""#));
+        assert_eq!(q.exclude_query.as_deref(), Some("jenkins"));
+        assert_eq!(q.from_ts, Some(midnight(2024, 5, 1)));
+        assert_eq!(q.to_ts, Some(midnight(2024, 5, 9) - 1));
+        assert_eq!(q.has_ocr, Some(true));
+    }
+
+    #[test]
+    fn escapes_terms_and_supports_or_prefix() {
+        let q = parse_search_query_at(r#"rust* OR golang "a""b" c++ -app:slack -has:ocr"#, now()).unwrap();
+        assert_eq!(q.query.as_deref(), Some(r#"(rust* OR golang) "a" "b" "c++""#));
+        assert_eq!(q.exclude_apps, vec!["slack"]);
+        assert_eq!(q.has_ocr, Some(false));
+
+        // 关键字与 URL 作为普通词
+        let q = parse_search_query_at("NOT https://example.com/a", now()).unwrap();
+        assert_eq!(q.query.as_deref(), Some(r#""NOT" "https://example.com/a""#));
+
+        let q = parse_search_query_at("after:7d 错误处理", now()).unwrap();
+        assert_eq!(q.from_ts, Some((now() - Duration::days(7)).timestamp()));
+        assert_eq!(q.query.as_deref(), Some("错误处理"));
+    }
+
+    #[test]
+    fn builds_any_of_expression() {
+        assert_eq!(fts_any_of(["rust", "pull request", "", "NOT"]).as_deref(), Some(r#"rust OR "pull request" OR "NOT""#));
+        assert_eq!(fts_any_of(Vec::<String>::new()), None);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let cases = [
+            (r#"title:"unterminated"#, SearchQueryError::UnterminatedQuote { position: 7 }),
+            ("app:", SearchQueryError::MissingValue { field: "app".to_string() }),
+            ("foo:bar", SearchQueryError::UnknownField { field: "foo".to_string() }),
+            ("after:someday", SearchQueryError::InvalidDate { value: "someday".to_string() }),
+            ("has:image", SearchQueryError::InvalidHas { value: "image".to_string() }),
+            ("OR rust", SearchQueryError::DanglingOr),
+            ("rust OR", SearchQueryError::DanglingOr),
+            ("rust OR -go", SearchQueryError::DanglingOr),
+            ("after:today before:yesterday", SearchQueryError::EmptyTimeRange),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_search_query_at(input, now()).unwrap_err(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_non_ascii_dates_without_panicking() {
+        for value in ["昨天", "三昨天", "7天", "3日", "🎉", "7🎉", "d", "h", "é"] {
+            let input = format!("after:{}", value);
+            assert_eq!(
+                parse_search_query_at(&input, now()).unwrap_err(),
+                SearchQueryError::InvalidDate { value: value.to_string() },
+                "input: {}",
+                input
+            );
+        }
+        assert!(parse_search_query_at("before:99999999999999d", now()).is_err());
+        assert_eq!(
+            parse_search_query_at("after:12H", now()).unwrap().from_ts,
+            Some((now() - Duration::hours(12)).timestamp())
+        );
+    }
+
+    #[tokio::test]
+    async fn parsed_queries_run_against_fts() {
+        use sqlx::sqlite::SqlitePoolOptions;
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let rows = [
+            (1000, "chrome.exe", "Pull request #42 - GitHub", "jenkins build failed"),
+            (2000, "chrome.exe", "Pull request #43 - GitHub", "review comments"),
+            (3000, "Code.exe", "main.rs", "统一错误处理逻辑"),
+        ];
+        for (ts, app, title, ocr) in rows {
+            let id = sqlx::query(
+                "INSERT INTO activity_logs (timestamp, app_name, window_title, image_path, window_title_seg) VALUES (?, ?, ?, '', '')",
+            )
+            .bind(ts as i64)
+            .bind(app)
+            .bind(title)
+            .execute(&pool)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+            crate::db::update_activity_ocr_impl(&pool, id, ocr).await.unwrap();
+        }
+
+        let search = |input: &str| {
+            let query = parse_search_query_at(input, now()).unwrap();
+            let pool = pool.clone();
+            async move {
+                let (items, _) = crate::db::search_activities_impl(&pool, &query).await.unwrap();
+                items.into_iter().map(|a| a.id).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(search(r#"app:chrome title:"pull request" -jenkins has:ocr"#).await, vec![2]);
+        assert_eq!(search("-jenkins").await, vec![3, 2]);
+        assert_eq!(search("ocr:错误处理").await, vec![3]);
+        assert_eq!(search("revi* OR 错误").await, vec![3, 2]);
+        // 特殊字符不会引发 FTS5 语法错误
+        assert_eq!(search(r#"#42 "a(b" c:\\path"#).await, Vec::<i64>::new());
+    }
+}
@@ -0,0 +1,959 @@
+//! 自然语言时间范围（中文 / 英文）
+//!
+//! 把 `last 3 days`、`上周三下午`、`between March 2 and 5`、`this morning`、`past 2 hours`
+//! 这类表达按本地时区解析为 [`TimeRange`]（Unix 秒，两端都包含）。解析是确定性的：
+//! 同一个“现在”总是得到同一个结果。对话的意图解析只负责从问题里摘出时间表达，具体时间由这里计算。
+//!
+//! 支持的写法：
+//! - 日：`today`、`yesterday`、`前天`、`3 days ago`、`3天前`
+//! - 滚动窗口（到现在为止）：`last|past N minutes|hours|days|weeks|months`、`最近/近/过去 N 分钟|小时|天|周|个月`、`N 小时内`
+//! - 日历周期：`this|last week|month|year`、`本周`、`上周`、`本月`、`上个月`、`今年`、`去年`（一周从周一开始）
+//! - 星期：`monday`、`last friday`、`周三`、`上周三`、`本周五`、`周末`
+//! - 日期：`2024-03-02`、`2024/3/2`、`3月2日`、`三月二号`、`March 2`、`2 March 2024`、`3月`、`March 2024`
+//! - 时段：`上午`、`下午`、`晚上`、`凌晨`、`中午`、`morning`、`afternoon`、`evening`、`night`，
+//!   可以跟在日期后面（`昨天下午`、`上周三晚上`、`monday morning`、`this morning`）
+//! - 区间：`between X and Y`、`from X to Y`、`X to Y`、`X 到/至 Y`、`X ~ Y`，
+//!   右侧可以省略年月（`between March 2 and 5`、`3月2日到5日`）
+//! - 兼容旧的意图取值：`today`、`yesterday`、`this_week`、`last_week`、`this_month`
+//!
+//! 没有写年份的日期如果晚于今天，按上一年计算（记录只会发生在过去）。
+
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// 解析得到的时间范围（Unix 秒，两端都包含）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeRange {
+    pub from_ts: i64,
+    pub to_ts: i64,
+}
+
+/// 从整句中查找时间表达时，候选片段的最大长度（字符）
+const MAX_EXPRESSION_CHARS: usize = 40;
+
+/// 按当前本地时间解析时间表达
+pub fn parse_time_range(expr: &str) -> Option<TimeRange> {
+    parse_time_range_at(expr, Local::now())
+}
+
+/// 以给定时间为“现在”解析时间表达（便于测试）
+pub fn parse_time_range_at(expr: &str, now: DateTime<Local>) -> Option<TimeRange> {
+    let normalized = normalize(expr);
+    if normalized.is_empty() {
+        return None;
+    }
+
+    let now = now.naive_local().with_nanosecond(0)?;
+    let span = Parser { now }.parse(&normalized)?;
+    let from_ts = local_timestamp(span.start)?;
+    let to_ts = local_timestamp(span.end)? - 1;
+    (from_ts <= to_ts).then_some(TimeRange { from_ts, to_ts })
+}
+
+/// 在一句话中找出最长的、可以解析的时间表达（如 `chrome 上 last 3 days 的记录` 中的 `last 3 days`）
+pub fn extract_time_expression(query: &str) -> Option<String> {
+    let now = Local::now();
+    let bounds: Vec<usize> = query
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(query.len()))
+        .collect();
+    // 英文单词中间不能切开
+    let is_boundary = |pos: usize| {
+        let before = query[..pos].chars().next_back();
+        let after = query[pos..].chars().next();
+        !matches!((before, after), (Some(a), Some(b)) if a.is_ascii_alphanumeric() && b.is_ascii_alphanumeric())
+    };
+
+    let mut best: Option<&str> = None;
+    for (i, &start) in bounds.iter().enumerate() {
+        if !is_boundary(start) || !query[start..].starts_with(char::is_alphanumeric) {
+            continue;
+        }
+        let last = (i + MAX_EXPRESSION_CHARS).min(bounds.len() - 1);
+        for &end in bounds[i + 1..=last].iter().rev() {
+            if best.is_some_and(|b| b.len() >= end - start) {
+                break;
+            }
+            let candidate = &query[start..end];
+            if !is_boundary(end)
+                || !candidate.ends_with(char::is_alphanumeric)
+                || candidate.ends_with('的')
+                || is_ambiguous_word(candidate)
+            {
+                continue;
+            }
+            if parse_time_range_at(candidate, now).is_some() {
+                best = Some(candidate);
+                break;
+            }
+        }
+    }
+    best.map(str::to_string)
+}
+
+/// 在普通句子里更常作为其它词出现的月份名
+fn is_ambiguous_word(candidate: &str) -> bool {
+    matches!(candidate.to_ascii_lowercase().as_str(), "may" | "march")
+}
+
+pub(crate) fn local_timestamp(dt: NaiveDateTime) -> Option<i64> {
+    // 夏令时跳过的时刻顺延一小时
+    Local
+        .from_local_datetime(&dt)
+        .earliest()
+        .or_else(|| Local.from_local_datetime(&(dt + Duration::hours(1))).earliest())
+        .map(|dt| dt.timestamp())
+}
+
+/// 统一大小写、全角数字与分隔符，合并空白，去掉末尾的标点和“的”
+fn normalize(expr: &str) -> String {
+    let mapped: String = expr
+        .chars()
+        .map(|c| match c {
+            '０'..='９' => char::from(b'0' + (c as u32 - '０' as u32) as u8),
+            '_' => ' ',
+            '，' => ',',
+            '～' => '~',
+            '－' | '—' | '–' => '-',
+            _ => c.to_ascii_lowercase(),
+        })
+        .collect();
+    let collapsed = mapped.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed
+        .trim_end_matches(['?', '？', '.', '。', '!', '！', ',', '的'])
+        .trim()
+        .to_string()
+}
+
+/// 本地时间上的半开区间 `[start, end)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+impl Span {
+    fn days(first: NaiveDate, count: i64) -> Self {
+        Self {
+            start: first.and_time(Default::default()),
+            end: (first + Duration::days(count)).and_time(Default::default()),
+        }
+    }
+
+    fn day(date: NaiveDate) -> Self {
+        Self::days(date, 1)
+    }
+
+    fn month(year: i32, month: u32) -> Option<Self> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let next = first.checked_add_months(Months::new(1))?;
+        Some(Self {
+            start: first.and_time(Default::default()),
+            end: next.and_time(Default::default()),
+        })
+    }
+
+    fn year(year: i32) -> Option<Self> {
+        Some(Self {
+            start: NaiveDate::from_ymd_opt(year, 1, 1)?.and_time(Default::default()),
+            end: NaiveDate::from_ymd_opt(year + 1, 1, 1)?.and_time(Default::default()),
+        })
+    }
+
+    /// 是否正好是某一天
+    fn single_day(&self) -> Option<NaiveDate> {
+        (self.end == (self.start.date() + Duration::days(1)).and_time(Default::default())
+            && self.start.time() == Default::default())
+        .then(|| self.start.date())
+    }
+}
+
+/// 区间右侧省略年月时沿用左侧的年月
+#[derive(Debug, Clone, Copy)]
+struct DateContext {
+    year: i32,
+    month: u32,
+}
+
+impl DateContext {
+    fn of(span: &Span) -> Self {
+        Self {
+            year: span.start.year(),
+            month: span.start.month(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DayPart {
+    EarlyMorning,
+    Morning,
+    Noon,
+    Afternoon,
+    Evening,
+}
+
+impl DayPart {
+    /// 时段对应的小时区间 `[start, end)`
+    fn hours(self) -> (u32, u32) {
+        match self {
+            DayPart::EarlyMorning => (0, 6),
+            DayPart::Morning => (6, 12),
+            DayPart::Noon => (11, 14),
+            DayPart::Afternoon => (12, 18),
+            DayPart::Evening => (18, 24),
+        }
+    }
+}
+
+/// 星期前的修饰：上周 / 本周 / 最近一个 / 英文 last（今天之前最近的一个）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WeekRef {
+    Last,
+    This,
+    Recent,
+    Previous,
+}
+
+const DAY_PARTS: &[(&str, DayPart)] = &[
+    ("凌晨", DayPart::EarlyMorning),
+    ("早上", DayPart::Morning),
+    ("早晨", DayPart::Morning),
+    ("清晨", DayPart::Morning),
+    ("上午", DayPart::Morning),
+    ("中午", DayPart::Noon),
+    ("下午", DayPart::Afternoon),
+    ("傍晚", DayPart::Evening),
+    ("晚上", DayPart::Evening),
+    ("夜里", DayPart::Evening),
+    ("early morning", DayPart::EarlyMorning),
+    ("morning", DayPart::Morning),
+    ("noon", DayPart::Noon),
+    ("afternoon", DayPart::Afternoon),
+    ("evening", DayPart::Evening),
+    ("night", DayPart::Evening),
+];
+
+/// 日期与时段连写的简称
+const DAY_PART_COMPOUNDS: &[(&str, &str, DayPart)] = &[
+    ("今早", "今天", DayPart::Morning),
+    ("今晨", "今天", DayPart::Morning),
+    ("今晚", "今天", DayPart::Evening),
+    ("昨早", "昨天", DayPart::Morning),
+    ("昨晚", "昨天", DayPart::Evening),
+    ("tonight", "today", DayPart::Evening),
+    ("last night", "yesterday", DayPart::Evening),
+];
+
+const RANGE_PREFIXES: &[&str] = &["between ", "from ", "从", "自"];
+const RANGE_SEPARATORS: &[&str] = &[" and ", " to ", " until ", " till ", " through ", "到", "至", "~", " - "];
+const POINT_PREFIXES: &[&str] = &["on ", "in ", "during ", "at ", "在"];
+
+const ROLLING_PREFIXES: &[&str] = &[
+    "in the last ",
+    "in the past ",
+    "over the last ",
+    "over the past ",
+    "within the last ",
+    "within the past ",
+    "within ",
+    "the last ",
+    "the past ",
+    "last ",
+    "past ",
+    "previous ",
+    "最近",
+    "近",
+    "过去",
+    "前",
+];
+const WITHIN_SUFFIXES: &[&str] = &["以内", "之内", "内"];
+const AGO_SUFFIXES: &[&str] = &[" ago", "以前", "之前", "前"];
+
+const UNITS: &[(&str, Unit)] = &[
+    ("minutes", Unit::Minute),
+    ("minute", Unit::Minute),
+    ("mins", Unit::Minute),
+    ("min", Unit::Minute),
+    ("hours", Unit::Hour),
+    ("hour", Unit::Hour),
+    ("hrs", Unit::Hour),
+    ("days", Unit::Day),
+    ("day", Unit::Day),
+    ("weeks", Unit::Week),
+    ("week", Unit::Week),
+    ("months", Unit::Month),
+    ("month", Unit::Month),
+    ("years", Unit::Year),
+    ("year", Unit::Year),
+    ("分钟", Unit::Minute),
+    ("个小时", Unit::Hour),
+    ("小时", Unit::Hour),
+    ("个钟头", Unit::Hour),
+    ("钟头", Unit::Hour),
+    ("天", Unit::Day),
+    ("日", Unit::Day),
+    ("个星期", Unit::Week),
+    ("星期", Unit::Week),
+    ("个礼拜", Unit::Week),
+    ("礼拜", Unit::Week),
+    ("周", Unit::Week),
+    ("个月", Unit::Month),
+    ("年", Unit::Year),
+];
+
+const MONTH_NAMES: &[(&str, u32)] = &[
+    ("january", 1),
+    ("february", 2),
+    ("march", 3),
+    ("april", 4),
+    ("may", 5),
+    ("june", 6),
+    ("july", 7),
+    ("august", 8),
+    ("september", 9),
+    ("october", 10),
+    ("november", 11),
+    ("december", 12),
+];
+
+const MONTH_ABBREVIATIONS: &[(&str, u32)] = &[
+    ("jan", 1),
+    ("feb", 2),
+    ("mar", 3),
+    ("apr", 4),
+    ("jun", 6),
+    ("jul", 7),
+    ("aug", 8),
+    ("sep", 9),
+    ("sept", 9),
+    ("oct", 10),
+    ("nov", 11),
+    ("dec", 12),
+];
+
+const WEEKDAY_NAMES: &[(&str, u32)] = &[
+    ("monday", 0),
+    ("tuesday", 1),
+    ("wednesday", 2),
+    ("thursday", 3),
+    ("friday", 4),
+    ("saturday", 5),
+    ("sunday", 6),
+];
+
+/// 周几之外的“周末”，用 7 表示（周六 + 周日）
+const WEEKEND: u32 = 7;
+
+struct Parser {
+    now: NaiveDateTime,
+}
+
+impl Parser {
+    fn today(&self) -> NaiveDate {
+        self.now.date()
+    }
+
+    fn monday(&self) -> NaiveDate {
+        let today = self.today();
+        today - Duration::days(today.weekday().num_days_from_monday() as i64)
+    }
+
+    fn parse(&self, s: &str) -> Option<Span> {
+        self.parse_between(s)
+            .or_else(|| self.parse_point(s, None).map(|(span, _)| span))
+    }
+
+    /// 区间：左右两侧分别解析，右侧可沿用左侧的年月
+    fn parse_between(&self, s: &str) -> Option<Span> {
+        let body = strip_any_prefix(s, RANGE_PREFIXES).unwrap_or(s);
+        for sep in RANGE_SEPARATORS {
+            for (idx, _) in body.match_indices(sep) {
+                let left = body[..idx].trim();
+                let right = body[idx + sep.len()..].trim();
+                if left.is_empty() || right.is_empty() {
+                    continue;
+                }
+                let Some((left, ctx)) = self.parse_point(left, None) else {
+                    continue;
+                };
+                let Some((right, _)) = self.parse_point(right, Some(ctx)) else {
+                    continue;
+                };
+                if left.start < right.end {
+                    return Some(Span {
+                        start: left.start,
+                        end: right.end,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn parse_point(&self, s: &str, ctx: Option<DateContext>) -> Option<(Span, DateContext)> {
+        let s = strip_any_prefix(s, POINT_PREFIXES).unwrap_or(s).trim();
+        if s.is_empty() {
+            return None;
+        }
+
+        if let Some((base, part)) = split_day_part(s) {
+            let day = if base.is_empty() || base == "this" {
+                self.today()
+            } else {
+                self.parse_base(base, ctx)?.0.single_day()?
+            };
+            let (from, to) = part.hours();
+            let start = day.and_hms_opt(from, 0, 0)?;
+            let span = Span {
+                start,
+                end: start + Duration::hours((to - from) as i64),
+            };
+            return Some((span, DateContext::of(&span)));
+        }
+
+        self.parse_base(s, ctx)
+    }
+
+    fn parse_base(&self, s: &str, ctx: Option<DateContext>) -> Option<(Span, DateContext)> {
+        let span = self
+            .parse_named(s)
+            .or_else(|| self.parse_rolling(s))
+            .or_else(|| self.parse_ago(s))
+            .or_else(|| self.parse_weekday(s))
+            .or_else(|| self.parse_date(s, ctx))?;
+        Some((span, DateContext::of(&span)))
+    }
+
+    fn parse_named(&self, s: &str) -> Option<Span> {
+        let today = self.today();
+        let monday = self.monday();
+        let this_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)?;
+        let last_month = this_month.checked_sub_months(Months::new(1))?;
+
+        match s {
+            "today" | "今天" | "今日" => Some(Span::day(today)),
+            "yesterday" | "昨天" | "昨日" => Some(Span::day(today - Duration::days(1))),
+            "day before yesterday" | "the day before yesterday" | "前天" => Some(Span::day(today - Duration::days(2))),
+            "this week" | "本周" | "这周" | "这一周" | "这个星期" | "这星期" | "本星期" | "这礼拜" | "这个礼拜" => {
+                Some(Span::days(monday, 7))
+            }
+            "last week" | "previous week" | "上周" | "上一周" | "上个星期" | "上星期" | "上礼拜" | "上个礼拜" => {
+                Some(Span::days(monday - Duration::days(7), 7))
+            }
+            "this month" | "本月" | "这个月" | "这月" => Span::month(this_month.year(), this_month.month()),
+            "last month" | "previous month" | "上个月" | "上月" => Span::month(last_month.year(), last_month.month()),
+            "this year" | "今年" => Span::year(today.year()),
+            "last year" | "previous year" | "去年" => Span::year(today.year() - 1),
+            _ => None,
+        }
+    }
+
+    /// 截至现在的滚动窗口：按天及以上的单位从当天零点对齐
+    fn parse_rolling(&self, s: &str) -> Option<Span> {
+        let rest = strip_any_prefix(s, ROLLING_PREFIXES)
+            .or_else(|| WITHIN_SUFFIXES.iter().find_map(|suffix| s.strip_suffix(suffix)))?;
+        let (amount, unit) = parse_amount_unit(rest.trim(), true)?;
+
+        let today = self.today();
+        let start = match unit {
+            Unit::Minute => self.now - Duration::minutes(amount as i64),
+            Unit::Hour => self.now - Duration::hours(amount as i64),
+            Unit::Day => (today - Duration::days(amount as i64 - 1)).and_time(Default::default()),
+            Unit::Week => (today - Duration::days(amount as i64 * 7 - 1)).and_time(Default::default()),
+            Unit::Month => (today.checked_sub_months(Months::new(amount))? + Duration::days(1)).and_time(Default::default()),
+            Unit::Year => {
+                (today.checked_sub_months(Months::new(amount * 12))? + Duration::days(1)).and_time(Default::default())
+            }
+        };
+        Some(Span {
+            start,
+            end: self.now + Duration::seconds(1),
+        })
+    }
+
+    /// “3 天前”“2 hours ago”：落在过去某一天 / 某一段
+    fn parse_ago(&self, s: &str) -> Option<Span> {
+        let rest = AGO_SUFFIXES.iter().find_map(|suffix| s.strip_suffix(suffix))?;
+        let (amount, unit) = parse_amount_unit(rest.trim(), false)?;
+
+        let today = self.today();
+        match unit {
+            Unit::Minute => {
+                let at = self.now - Duration::minutes(amount as i64);
+                Some(Span {
+                    start: at - Duration::minutes(5),
+                    end: at + Duration::minutes(5),
+                })
+            }
+            Unit::Hour => {
+                let at = self.now - Duration::hours(amount as i64);
+                Some(Span {
+                    start: at - Duration::minutes(30),
+                    end: at + Duration::minutes(30),
+                })
+            }
+            Unit::Day => Some(Span::day(today - Duration::days(amount as i64))),
+            Unit::Week => Some(Span::days(self.monday() - Duration::days(amount as i64 * 7), 7)),
+            Unit::Month => {
+                let month = today.checked_sub_months(Months::new(amount))?;
+                Span::month(month.year(), month.month())
+            }
+            Unit::Year => Span::year(today.year() - amount as i32),
+        }
+    }
+
+    fn parse_weekday(&self, s: &str) -> Option<Span> {
+        let (week_ref, day) = parse_weekday_expr(s)?;
+        let today = self.today();
+        let today_index = today.weekday().num_days_from_monday();
+        let monday = self.monday();
+
+        // 周末从周六算起
+        let offset = if day == WEEKEND { 5 } else { day };
+        let first = match week_ref {
+            WeekRef::Last => monday - Duration::days(7 - offset as i64),
+            WeekRef::This => monday + Duration::days(offset as i64),
+            WeekRef::Recent => today - Duration::days(((today_index + 7 - offset) % 7) as i64),
+            WeekRef::Previous if day == WEEKEND => monday - Duration::days(2),
+            WeekRef::Previous => today - Duration::days(((today_index + 6 - offset) % 7) as i64 + 1),
+        };
+        Some(Span::days(first, if day == WEEKEND { 2 } else { 1 }))
+    }
+
+    fn parse_date(&self, s: &str, ctx: Option<DateContext>) -> Option<Span> {
+        let (year, month, day) = parse_numeric_date(s)
+            .or_else(|| parse_chinese_date(s))
+            .or_else(|| parse_english_date(s))?;
+
+        let today = self.today();
+        let inferred = year.is_none() && ctx.is_none();
+        let day_only = month.is_none() && day.is_some();
+        // 单独一个数字只在区间右侧当作日
+        if day_only && ctx.is_none() && s.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let year = year.or(ctx.map(|c| c.year)).unwrap_or(today.year());
+        // 只写了日：沿用区间左侧的月份，否则按本月
+        let month = month.or_else(|| day.map(|_| ctx.map_or(today.month(), |c| c.month)));
+
+        let span = match (month, day) {
+            (Some(m), Some(d)) => Span::day(NaiveDate::from_ymd_opt(year, m, d)?),
+            (Some(m), None) => Span::month(year, m)?,
+            (None, _) => Span::year(year)?,
+        };
+        if !inferred || span.start.date() <= today {
+            return Some(span);
+        }
+
+        // 没写年份且晚于今天：指的是上一次出现（只写日时退到上个月）
+        let back = Months::new(if day_only { 1 } else { 12 });
+        let start = span.start.date().checked_sub_months(back)?;
+        match day {
+            Some(d) => Some(Span::day(NaiveDate::from_ymd_opt(start.year(), start.month(), d)?)),
+            None => Span::month(start.year(), start.month()),
+        }
+    }
+}
+
+fn strip_any_prefix<'a>(s: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| s.strip_prefix(prefix))
+}
+
+/// 拆出末尾（或单独出现）的时段：`昨天下午` → (`昨天`, 下午)，`monday morning` → (`monday`, morning)
+fn split_day_part(s: &str) -> Option<(&str, DayPart)> {
+    if let Some(&(_, base, part)) = DAY_PART_COMPOUNDS.iter().find(|(word, _, _)| *word == s) {
+        return Some((base, part));
+    }
+
+    for &(word, part) in DAY_PARTS {
+        let Some(base) = s.strip_suffix(word) else {
+            continue;
+        };
+        let base = base.trim_end_matches(['的', ' ']);
+        let ascii_word = word.is_ascii();
+        // 英文时段前必须是空格或开头（避免 `tonight` 之类的误拆）
+        if ascii_word && !(base.is_empty() || s[..s.len() - word.len()].ends_with(' ')) {
+            continue;
+        }
+        let base = base.strip_suffix(" in the").or_else(|| base.strip_suffix(" the")).unwrap_or(base).trim();
+        return Some((base, part));
+    }
+    None
+}
+
+/// 数字：阿拉伯数字、一到九十九的中文数字，或英文 a / one…twelve
+fn parse_number(s: &str) -> Option<u32> {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return s.parse().ok();
+    }
+
+    let english = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    ];
+    if let Some(i) = english.iter().position(|w| *w == s) {
+        return Some(i as u32 + 1);
+    }
+    if s == "a" || s == "an" {
+        return Some(1);
+    }
+
+    let digit = |c: char| "〇一二三四五六七八九".chars().position(|d| d == c).map(|i| i as u32);
+    let chars: Vec<char> = s.chars().collect();
+    match chars.as_slice() {
+        ['两'] => Some(2),
+        ['十'] => Some(10),
+        [c] => digit(*c),
+        ['十', b] => Some(10 + digit(*b)?),
+        [a, '十'] => Some(digit(*a)? * 10),
+        [a, '十', b] => Some(digit(*a)? * 10 + digit(*b)?),
+        _ => None,
+    }
+}
+
+/// 拆出开头的数字部分（阿拉伯数字或中文数字）
+fn split_leading_number(s: &str) -> (&str, &str) {
+    let end = s
+        .char_indices()
+        .find(|&(_, c)| !(c.is_ascii_digit() || "〇一二三四五六七八九十两".contains(c)))
+        .map_or(s.len(), |(i, _)| i);
+    s.split_at(end)
+}
+
+/// `3 days`、`两个小时`、`hour`（允许省略数量时按 1 计）
+fn parse_amount_unit(s: &str, allow_implicit_one: bool) -> Option<(u32, Unit)> {
+    let (number, unit) = match s.split_once(' ') {
+        Some((number, unit)) if parse_number(number).is_some() => (number, unit.trim()),
+        _ => {
+            let (number, unit) = split_leading_number(s);
+            (number, unit.trim())
+        }
+    };
+    let amount = if number.is_empty() {
+        if !allow_implicit_one {
+            return None;
+        }
+        1
+    } else {
+        parse_number(number)?
+    };
+    if amount == 0 || amount > 1000 {
+        return None;
+    }
+    let unit = UNITS.iter().find(|(word, _)| *word == unit).map(|&(_, unit)| unit)?;
+    Some((amount, unit))
+}
+
+/// 周几 / 周末（0 = 周一 … 6 = 周日，7 = 周末）
+fn parse_weekday_expr(s: &str) -> Option<(WeekRef, u32)> {
+    // 英文
+    let (week_ref, name) = if let Some(name) = s.strip_prefix("last ") {
+        (WeekRef::Previous, name)
+    } else if let Some(name) = s.strip_prefix("this ") {
+        (WeekRef::This, name)
+    } else {
+        (WeekRef::Recent, s)
+    };
+    if name == "weekend" {
+        return Some((week_ref, WEEKEND));
+    }
+    if let Some(&(_, day)) = WEEKDAY_NAMES.iter().find(|(word, _)| *word == name) {
+        return Some((week_ref, day));
+    }
+
+    // 中文
+    let last_prefixes = ["上个星期", "上星期", "上个礼拜", "上礼拜", "上周"];
+    let this_prefixes = ["这个星期", "这星期", "这个礼拜", "这礼拜", "本星期", "本周", "这周"];
+    let (week_ref, rest) = if let Some(rest) = strip_any_prefix(s, &last_prefixes) {
+        (WeekRef::Last, rest)
+    } else if let Some(rest) = strip_any_prefix(s, &this_prefixes) {
+        (WeekRef::This, rest)
+    } else {
+        (WeekRef::Recent, s)
+    };
+    let rest = rest.trim_start_matches('的');
+    let explicit = week_ref != WeekRef::Recent;
+    let day = match strip_any_prefix(rest, &["星期", "礼拜", "周"]) {
+        Some(day) => day,
+        None if explicit => rest,
+        None => return None,
+    };
+    let index = match day {
+        "一" | "1" => 0,
+        "二" | "2" => 1,
+        "三" | "3" => 2,
+        "四" | "4" => 3,
+        "五" | "5" => 4,
+        "六" | "6" => 5,
+        "日" | "天" | "7" => 6,
+        "末" => WEEKEND,
+        _ => return None,
+    };
+    Some((week_ref, index))
+}
+
+type DateParts = (Option<i32>, Option<u32>, Option<u32>);
+
+/// `2024-03-02`、`2024/3/2`、`2024.3.2`、`2024-03`
+fn parse_numeric_date(s: &str) -> Option<DateParts> {
+    let parts: Vec<&str> = s.split(['-', '/', '.']).collect();
+    if parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) || parts[0].len() != 4 {
+        return None;
+    }
+    let year = parts[0].parse().ok()?;
+    match parts[1..] {
+        [month] => Some((Some(year), Some(month.parse().ok()?), None)),
+        [month, day] => Some((Some(year), Some(month.parse().ok()?), Some(day.parse().ok()?))),
+        _ => None,
+    }
+}
+
+/// 只写了日：`5`、`5日`、`5号`、`5th`
+fn parse_day_only(s: &str) -> Option<u32> {
+    let s = s
+        .strip_suffix('日')
+        .or_else(|| s.strip_suffix('号'))
+        .or_else(|| ["st", "nd", "rd", "th"].iter().find_map(|suffix| s.strip_suffix(suffix)))
+        .unwrap_or(s);
+    parse_number(s).filter(|d| (1..=31).contains(d))
+}
+
+/// `2024年3月2日`、`3月2号`、`三月`、`2024年3月份`、`5号`
+fn parse_chinese_date(s: &str) -> Option<DateParts> {
+    let mut rest = s;
+    let mut year = None;
+    let mut month = None;
+    let mut day = None;
+
+    if let Some((number, tail)) = rest.split_once('年') {
+        if number.len() != 4 || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        year = Some(number.parse().ok()?);
+        rest = tail;
+    }
+    if let Some((number, tail)) = rest.split_once('月') {
+        month = Some(parse_number(number).filter(|m| (1..=12).contains(m))?);
+        rest = tail.strip_prefix('份').unwrap_or(tail);
+    }
+    if !rest.is_empty() {
+        let number = rest.strip_suffix('日').or_else(|| rest.strip_suffix('号'))?;
+        day = Some(parse_number(number).filter(|d| (1..=31).contains(d))?);
+    }
+
+    if year.is_none() && month.is_none() && day.is_none() {
+        return None;
+    }
+    // 只写年份时要带“年”，只写日时要带“日/号”
+    if month.is_none() && day.is_some() && year.is_some() {
+        return None;
+    }
+    if month.is_none() && day.is_none() && !s.ends_with('年') {
+        return None;
+    }
+    Some((year, month, day))
+}
+
+/// `march 2`、`march 2nd, 2024`、`2 march`、`march 2024`、`march`、`5th`
+fn parse_english_date(s: &str) -> Option<DateParts> {
+    let tokens: Vec<&str> = s
+        .split([' ', ','])
+        .filter(|t| !t.is_empty() && *t != "the" && *t != "of")
+        .collect();
+    let full_month = |t: &str| MONTH_NAMES.iter().find(|(name, _)| *name == t).map(|&(_, m)| m);
+    let any_month = |t: &str| {
+        full_month(t).or_else(|| {
+            MONTH_ABBREVIATIONS
+                .iter()
+                .find(|(name, _)| *name == t.trim_end_matches('.'))
+                .map(|&(_, m)| m)
+        })
+    };
+    let year = |t: &str| (t.len() == 4).then(|| t.parse::<i32>().ok()).flatten();
+
+    match tokens.as_slice() {
+        [m] if full_month(m).is_some() => Some((None, full_month(m), None)),
+        [d] => Some((None, None, Some(parse_day_only(d)?))),
+        [m, y] if full_month(m).is_some() && year(y).is_some() => Some((year(y), full_month(m), None)),
+        [m, d] if any_month(m).is_some() => Some((None, any_month(m), Some(parse_day_only(d)?))),
+        [d, m] => Some((None, Some(any_month(m)?), Some(parse_day_only(d)?))),
+        [m, d, y] if any_month(m).is_some() => Some((Some(year(y)?), any_month(m), Some(parse_day_only(d)?))),
+        [d, m, y] => Some((Some(year(y)?), Some(any_month(m)?), Some(parse_day_only(d)?))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-05-15 周三 15:30:00
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 15, 15, 30, 0).unwrap()
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().timestamp()
+    }
+
+    /// `[from, to)` 对应的 TimeRange（to 取前一秒）
+    fn range(from: i64, to_exclusive: i64) -> Option<TimeRange> {
+        Some(TimeRange {
+            from_ts: from,
+            to_ts: to_exclusive - 1,
+        })
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> Option<TimeRange> {
+        days(y, m, d, 1)
+    }
+
+    fn days(y: i32, m: u32, d: u32, count: i64) -> Option<TimeRange> {
+        let start = Local.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap();
+        let end = NaiveDate::from_ymd_opt(y, m, d).unwrap() + Duration::days(count);
+        range(start.timestamp(), at(end.year(), end.month(), end.day(), 0, 0))
+    }
+
+    /// 截至现在（含当前这一秒）
+    fn until_now(from: i64) -> Option<TimeRange> {
+        range(from, now().timestamp() + 1)
+    }
+
+    #[test]
+    fn parses_relative_and_calendar_expressions() {
+        let cases = [
+            // 日
+            ("today", day(2024, 5, 15)),
+            ("今天", day(2024, 5, 15)),
+            ("Yesterday", day(2024, 5, 14)),
+            ("前天", day(2024, 5, 13)),
+            ("3 days ago", day(2024, 5, 12)),
+            ("三天前", day(2024, 5, 12)),
+            ("十天前", day(2024, 5, 5)),
+            // 滚动窗口
+            ("last 3 days", until_now(at(2024, 5, 13, 0, 0))),
+            ("最近3天", until_now(at(2024, 5, 13, 0, 0))),
+            ("近两天", until_now(at(2024, 5, 14, 0, 0))),
+            ("past 2 hours", until_now(at(2024, 5, 15, 13, 30))),
+            ("过去两个小时", until_now(at(2024, 5, 15, 13, 30))),
+            ("3小时内", until_now(at(2024, 5, 15, 12, 30))),
+            ("past hour", until_now(at(2024, 5, 15, 14, 30))),
+            ("in the last 30 minutes", until_now(at(2024, 5, 15, 15, 0))),
+            ("past week", until_now(at(2024, 5, 9, 0, 0))),
+            ("最近一个月", until_now(at(2024, 4, 16, 0, 0))),
+            // 日历周期
+            ("this week", days(2024, 5, 13, 7)),
+            ("this_week", days(2024, 5, 13, 7)),
+            ("本周", days(2024, 5, 13, 7)),
+            ("last week", days(2024, 5, 6, 7)),
+            ("上周", days(2024, 5, 6, 7)),
+            ("this month", days(2024, 5, 1, 31)),
+            ("上个月", days(2024, 4, 1, 30)),
+            ("去年", days(2023, 1, 1, 365)),
+            // 星期
+            ("上周三", day(2024, 5, 8)),
+            ("本周五", day(2024, 5, 17)),
+            ("周一", day(2024, 5, 13)),
+            ("星期四", day(2024, 5, 9)),
+            ("monday", day(2024, 5, 13)),
+            ("last friday", day(2024, 5, 10)),
+            ("last wednesday", day(2024, 5, 8)),
+            ("上周末", days(2024, 5, 11, 2)),
+            ("周末", days(2024, 5, 11, 2)),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_time_range_at(expr, now()), expected, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn parses_dates_day_parts_and_ranges() {
+        let cases = [
+            // 日期
+            ("2024-03-02", day(2024, 3, 2)),
+            ("2024/3/2", day(2024, 3, 2)),
+            ("3月2日", day(2024, 3, 2)),
+            ("三月二号", day(2024, 3, 2)),
+            ("2023年12月25日", day(2023, 12, 25)),
+            ("March 2", day(2024, 3, 2)),
+            ("on march 2nd, 2023", day(2023, 3, 2)),
+            ("2 March", day(2024, 3, 2)),
+            ("3月", days(2024, 3, 1, 31)),
+            ("march 2024", days(2024, 3, 1, 31)),
+            // 没写年份且晚于今天：去年
+            ("12月25日", day(2023, 12, 25)),
+            ("june", days(2023, 6, 1, 30)),
+            // 时段
+            ("this morning", range(at(2024, 5, 15, 6, 0), at(2024, 5, 15, 12, 0))),
+            ("今天上午", range(at(2024, 5, 15, 6, 0), at(2024, 5, 15, 12, 0))),
+            ("下午", range(at(2024, 5, 15, 12, 0), at(2024, 5, 15, 18, 0))),
+            ("昨晚", range(at(2024, 5, 14, 18, 0), at(2024, 5, 15, 0, 0))),
+            ("yesterday afternoon", range(at(2024, 5, 14, 12, 0), at(2024, 5, 14, 18, 0))),
+            ("上周三下午", range(at(2024, 5, 8, 12, 0), at(2024, 5, 8, 18, 0))),
+            ("monday morning", range(at(2024, 5, 13, 6, 0), at(2024, 5, 13, 12, 0))),
+            ("凌晨", range(at(2024, 5, 15, 0, 0), at(2024, 5, 15, 6, 0))),
+            // 区间
+            ("between March 2 and 5", days(2024, 3, 2, 4)),
+            ("from march 28 to april 2", days(2024, 3, 28, 6)),
+            ("3月2日到5日", days(2024, 3, 2, 4)),
+            ("从3月2号至3月5号", days(2024, 3, 2, 4)),
+            ("2024-03-02 ~ 2024-03-05", days(2024, 3, 2, 4)),
+            ("周一到周三", days(2024, 5, 13, 3)),
+            ("yesterday to today", days(2024, 5, 14, 2)),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(parse_time_range_at(expr, now()), expected, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn rejects_non_time_expressions() {
+        for expr in [
+            "",
+            "rust",
+            "5",
+            "last",
+            "周",
+            "2024",
+            "13月",
+            "2月30日",
+            "between march 5 and 2",
+            "past 0 hours",
+            "chrome tabs",
+            "上周三下午三点半开会",
+        ] {
+            assert_eq!(parse_time_range_at(expr, now()), None, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn extracts_expression_from_query() {
+        let cases = [
+            ("what did I do in chrome last 3 days?", Some("last 3 days")),
+            ("上周三下午在 VS Code 里改了什么", Some("上周三下午")),
+            ("Find pdf last week rust ocr", Some("last week")),
+            ("notes between March 2 and 5 about rust", Some("between March 2 and 5")),
+            ("最近两个小时的会议记录", Some("最近两个小时")),
+            ("rust async book", None),
+            ("I may have seen it", None),
+            ("todays", None),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(extract_time_expression(query).as_deref(), expected, "{}", query);
+        }
+    }
+}
@@ -2,15 +2,66 @@
 //!
 //! This module provides Tauri-independent vector operations. The embedding
 //! generation function that requires config/API keys is moved to src-tauri.
+//!
+//! Vectors are stored as little-endian f32 BLOBs together with the model that
+//! produced them; searches only compare vectors from the same model and dimension.
 
 use crate::db;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
 use sqlx::Row;
 
-/// Vector dimension (matches embedding model output)
+/// Vector dimension of the placeholder embedding
 pub const EMBEDDING_DIM: usize = 384;
 
+/// Model id recorded for hash-based placeholder embeddings
+pub const PLACEHOLDER_MODEL_ID: &str = "placeholder";
+
+/// Below this many vectors per model an exact scan is used instead of the ANN index
+pub const EXACT_SCAN_THRESHOLD: i64 = 5_000;
+
+/// Model id of rows migrated from the old JSON format (source model unknown)
+pub const LEGACY_MODEL_ID: &str = "legacy";
+
+/// An embedding vector tagged with the model that produced it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    pub model_id: String,
+    pub vector: Vec<f32>,
+}
+
+impl Embedding {
+    pub fn new(model_id: impl Into<String>, vector: Vec<f32>) -> Self {
+        Self {
+            model_id: model_id.into(),
+            vector,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.vector.len()
+    }
+}
+
+/// Encode a vector as little-endian f32 bytes
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Decode little-endian f32 bytes; returns `None` if the length is not a multiple of 4
+pub fn decode_vector(bytes: &[u8]) -> Option<Vec<f32>> {
+    let chunks = bytes.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    Some(
+        chunks
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+    )
+}
+
 /// Calculate cosine similarity between two vectors
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     if a.len() != b.len() {
@@ -28,26 +79,45 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     (dot_product / (norm_a * norm_b)) as f64
 }
 
-/// Insert vector embedding into database
-pub async fn insert_embedding(activity_id: i64, embedding: Vec<f32>) -> Result<()> {
-    if embedding.len() != EMBEDDING_DIM {
-        return Err(anyhow::anyhow!(
-            "Vector dimension mismatch: expected {}, got {}",
-            EMBEDDING_DIM,
-            embedding.len()
-        ));
+/// Insert (or replace) the embedding of an activity for the embedding's model
+pub async fn insert_embedding(activity_id: i64, embedding: &Embedding) -> Result<()> {
+    let pool = db::get_pool().await?;
+    let version = insert_embedding_impl(&pool, activity_id, embedding).await?;
+
+    // 索引写入失败不影响数据，下次加载索引时会与数据库对账补齐
+    if let Err(e) = crate::vector_index::add(activity_id, embedding, version).await {
+        tracing::warn!("向量索引增量写入失败: {}", e);
     }
+    Ok(())
+}
 
-    let embedding_json = serde_json::to_string(&embedding)?;
-    let pool = db::get_pool().await?;
+/// 返回该行的 `created_at`，向量索引以此作为版本号与数据库对账
+pub async fn insert_embedding_impl(
+    pool: &SqlitePool,
+    activity_id: i64,
+    embedding: &Embedding,
+) -> Result<i64> {
+    if embedding.vector.is_empty() {
+        return Err(anyhow::anyhow!("Empty embedding vector for activity {}", activity_id));
+    }
 
-    sqlx::query("INSERT OR REPLACE INTO vector_embeddings (activity_id, embedding) VALUES (?, ?)")
-        .bind(activity_id)
-        .bind(embedding_json)
-        .execute(&pool)
-        .await?;
+    // 同一秒内重新生成时 created_at 也要变化，否则向量索引对账时识别不出
+    let version: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO vector_embeddings (activity_id, model_id, dim, embedding) VALUES (?, ?, ?, ?)
+         ON CONFLICT(activity_id, model_id) DO UPDATE SET
+             dim = excluded.dim,
+             embedding = excluded.embedding,
+             created_at = MAX(CAST(strftime('%s', 'now') AS INTEGER), COALESCE(vector_embeddings.created_at, 0) + 1)
+         RETURNING CAST(created_at AS INTEGER)",
+    )
+    .bind(activity_id)
+    .bind(&embedding.model_id)
+    .bind(embedding.dim() as i64)
+    .bind(encode_vector(&embedding.vector))
+    .fetch_one(pool)
+    .await?;
 
-    Ok(())
+    Ok(version.unwrap_or(0))
 }
 
 /// Search result with activity ID and similarity score
@@ -58,52 +128,124 @@ pub struct SearchResult {
 }
 
 /// Search similar vectors (full table scan version, kept for backward compatibility)
-pub async fn search_similar(query: Vec<f32>, limit: usize) -> Result<Vec<SearchResult>> {
+pub async fn search_similar(query: &Embedding, limit: usize) -> Result<Vec<SearchResult>> {
     search_similar_with_candidates(query, limit, None).await
 }
 
 /// Search similar vectors with optional candidate set filtering
+///
+/// Only vectors produced by `query.model_id` with the same dimension are compared.
+/// Without candidates, tables larger than [`EXACT_SCAN_THRESHOLD`] go through the ANN index.
 pub async fn search_similar_with_candidates(
-    query: Vec<f32>,
+    query: &Embedding,
     limit: usize,
     candidate_ids: Option<&[i64]>,
 ) -> Result<Vec<SearchResult>> {
-    if query.len() != EMBEDDING_DIM {
-        return Err(anyhow::anyhow!(
-            "Query vector dimension mismatch: expected {}, got {}",
-            EMBEDDING_DIM,
-            query.len()
-        ));
-    }
-
     let pool = db::get_pool().await?;
+    search_similar_with_candidates_impl(&pool, query, limit, candidate_ids).await
+}
 
-    let rows = match candidate_ids {
-        Some(ids) if !ids.is_empty() => {
-            let mut builder =
-                sqlx::QueryBuilder::new("SELECT activity_id, embedding FROM vector_embeddings WHERE activity_id IN (");
-            let mut separated = builder.separated(", ");
-            for id in ids {
-                separated.push_bind(*id);
-            }
-            separated.push_unseparated(")");
-            builder.build().fetch_all(&pool).await?
+pub async fn search_similar_with_candidates_impl(
+    pool: &SqlitePool,
+    query: &Embedding,
+    limit: usize,
+    candidate_ids: Option<&[i64]>,
+) -> Result<Vec<SearchResult>> {
+    let has_candidates = matches!(candidate_ids, Some(ids) if !ids.is_empty());
+    if !has_candidates
+        && count_embeddings(pool, query).await? >= EXACT_SCAN_THRESHOLD
+    {
+        match crate::vector_index::search(pool, query, limit).await {
+            Ok(results) => return Ok(results),
+            Err(e) => tracing::warn!("向量索引查询失败，回退到全表扫描: {}", e),
         }
-        _ => {
-            sqlx::query("SELECT activity_id, embedding FROM vector_embeddings")
-                .fetch_all(&pool)
-                .await?
+    }
+
+    search_similar_impl(pool, query, limit, candidate_ids).await
+}
+
+async fn count_embeddings(pool: &SqlitePool, query: &Embedding) -> Result<i64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM vector_embeddings WHERE model_id = ? AND dim = ?")
+        .bind(&query.model_id)
+        .bind(query.dim() as i64)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Exact (full scan) similarity search
+pub async fn search_similar_impl(
+    pool: &SqlitePool,
+    query: &Embedding,
+    limit: usize,
+    candidate_ids: Option<&[i64]>,
+) -> Result<Vec<SearchResult>> {
+    exact_scan(pool, query, limit, candidate_ids, None).await
+}
+
+/// Exact similarity search restricted to activities matching `filter`
+///
+/// Only the structured filters of `filter` are applied (its full-text query,
+/// ordering and paging fields are ignored), inside SQL before any vector is scored.
+pub async fn search_similar_filtered_impl(
+    pool: &SqlitePool,
+    query: &Embedding,
+    limit: usize,
+    filter: &db::ActivityQuery,
+) -> Result<Vec<SearchResult>> {
+    exact_scan(pool, query, limit, None, Some(filter)).await
+}
+
+async fn exact_scan(
+    pool: &SqlitePool,
+    query: &Embedding,
+    limit: usize,
+    candidate_ids: Option<&[i64]>,
+    filter: Option<&db::ActivityQuery>,
+) -> Result<Vec<SearchResult>> {
+    if query.vector.is_empty() {
+        return Err(anyhow::anyhow!("Query vector is empty"));
+    }
+
+    let mut builder = sqlx::QueryBuilder::new(
+        "SELECT activity_id, embedding FROM vector_embeddings WHERE model_id = ",
+    );
+    builder.push_bind(&query.model_id);
+    builder.push(" AND dim = ");
+    builder.push_bind(query.dim() as i64);
+
+    if let Some(ids) = candidate_ids.filter(|ids| !ids.is_empty()) {
+        builder.push(" AND activity_id IN (");
+        let mut separated = builder.separated(", ");
+        for id in ids {
+            separated.push_bind(*id);
         }
-    };
+        separated.push_unseparated(")");
+    }
+
+    if let Some(filter) = filter {
+        let filter = db::ActivityQuery {
+            query: None,
+            ..filter.clone()
+        };
+        builder.push(" AND activity_id IN (SELECT a.id");
+        filter.push_filters(&mut builder);
+        builder.push(")");
+    }
+
+    let rows = builder.build().fetch_all(pool).await?;
 
     let mut results = Vec::new();
 
     for row in rows {
         let activity_id: i64 = row.get(0);
-        let embedding_json: String = row.get(1);
+        let bytes: Vec<u8> = row.get(1);
 
-        let embedding: Vec<f32> = serde_json::from_str(&embedding_json)?;
-        let similarity = cosine_similarity(&query, &embedding);
+        let Some(embedding) = decode_vector(&bytes) else {
+            tracing::warn!("跳过损坏的向量数据: activity_id={}", activity_id);
+            continue;
+        };
+        let similarity = cosine_similarity(&query.vector, &embedding);
 
         results.push(SearchResult {
             id: activity_id,
@@ -122,27 +264,65 @@ pub async fn search_similar_with_candidates(
     Ok(results)
 }
 
-/// Get embedding for an activity
-pub async fn get_embedding(activity_id: i64) -> Result<Option<Vec<f32>>> {
+/// Get the embedding of an activity produced by the given model
+pub async fn get_embedding(activity_id: i64, model_id: &str) -> Result<Option<Vec<f32>>> {
     let pool = db::get_pool().await?;
 
-    let row = sqlx::query("SELECT embedding FROM vector_embeddings WHERE activity_id = ?")
+    let row = sqlx::query("SELECT embedding FROM vector_embeddings WHERE activity_id = ? AND model_id = ?")
         .bind(activity_id)
+        .bind(model_id)
         .fetch_optional(&pool)
         .await?;
 
-    if let Some(row) = row {
-        let embedding_json: String = row.get(0);
-        let embedding: Vec<f32> = serde_json::from_str(&embedding_json)?;
-        Ok(Some(embedding))
-    } else {
-        Ok(None)
+    Ok(row.and_then(|row| decode_vector(&row.get::<Vec<u8>, _>(0))))
+}
+
+/// Convert rows left in the old JSON text format by migration 0013 into binary BLOBs
+///
+/// Their source model is unknown, so they stay tagged as [`LEGACY_MODEL_ID`] and are
+/// never compared with vectors from a real model. Rows that fail to parse are removed.
+pub async fn convert_legacy_embeddings(pool: &SqlitePool) -> Result<usize> {
+    let rows = sqlx::query("SELECT id, CAST(embedding AS TEXT) FROM vector_embeddings WHERE dim = 0")
+        .fetch_all(pool)
+        .await?;
+
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut tx = pool.begin().await?;
+    let mut converted = 0;
+    for row in &rows {
+        let id: i64 = row.get(0);
+        let json: Option<String> = row.get(1);
+
+        match json.and_then(|j| serde_json::from_str::<Vec<f32>>(&j).ok()) {
+            Some(vector) if !vector.is_empty() => {
+                sqlx::query("UPDATE vector_embeddings SET dim = ?, embedding = ? WHERE id = ?")
+                    .bind(vector.len() as i64)
+                    .bind(encode_vector(&vector))
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                converted += 1;
+            }
+            _ => {
+                sqlx::query("DELETE FROM vector_embeddings WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
     }
+    tx.commit().await?;
+
+    tracing::info!("已转换 {} 条旧格式向量（共 {} 条）", converted, rows.len());
+    Ok(converted)
 }
 
 /// Generate a placeholder embedding using hash (when no API is available)
 /// Note: Real embedding generation requiring API keys is in src-tauri
-pub fn generate_placeholder_embedding(text: &str) -> Vec<f32> {
+pub fn generate_placeholder_embedding(text: &str) -> Embedding {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
 
@@ -164,5 +344,81 @@ pub fn generate_placeholder_embedding(text: &str) -> Vec<f32> {
         }
     }
 
-    embedding
+    Embedding::new(PLACEHOLDER_MODEL_ID, embedding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        for i in 1..=3 {
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (?, ?, 'Code', 'w', '')")
+                .bind(i as i64)
+                .bind(i as i64 * 1000)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    #[test]
+    fn vector_roundtrip_is_little_endian() {
+        let vector = vec![1.0f32, -0.5, 0.25];
+        let bytes = encode_vector(&vector);
+        assert_eq!(&bytes[..4], &1.0f32.to_le_bytes());
+        assert_eq!(decode_vector(&bytes), Some(vector));
+        assert_eq!(decode_vector(&[0, 1, 2]), None);
+    }
+
+    #[tokio::test]
+    async fn search_skips_other_models() {
+        let pool = setup_pool().await;
+
+        insert_embedding_impl(&pool, 1, &Embedding::new("model-a", vec![1.0, 0.0, 0.0])).await.unwrap();
+        insert_embedding_impl(&pool, 2, &Embedding::new("model-a", vec![0.0, 1.0, 0.0])).await.unwrap();
+        // 另一个模型中与查询完全相同的向量不应参与比较
+        insert_embedding_impl(&pool, 3, &Embedding::new("model-b", vec![0.0, 1.0, 0.0])).await.unwrap();
+        // 同一活动同一模型再次写入时覆盖
+        insert_embedding_impl(&pool, 1, &Embedding::new("model-a", vec![0.6, 0.8, 0.0])).await.unwrap();
+
+        let query = Embedding::new("model-a", vec![0.0, 1.0, 0.0]);
+        let results = search_similar_impl(&pool, &query, 10, None).await.unwrap();
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert!((results[1].score - 0.8).abs() < 1e-6);
+
+        let results = search_similar_impl(&pool, &query, 10, Some(&[1, 3])).await.unwrap();
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1]);
+
+        // 维度不同也视为不同的向量空间
+        let query = Embedding::new("model-a", vec![0.0, 1.0]);
+        assert!(search_similar_impl(&pool, &query, 10, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn converts_legacy_json_rows() {
+        let pool = setup_pool().await;
+
+        sqlx::query("INSERT INTO vector_embeddings (activity_id, model_id, dim, embedding) VALUES (1, 'legacy', 0, '[0.5, 0.25]'), (2, 'legacy', 0, 'not json')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(convert_legacy_embeddings(&pool).await.unwrap(), 1);
+        assert_eq!(convert_legacy_embeddings(&pool).await.unwrap(), 0);
+
+        let query = Embedding::new(LEGACY_MODEL_ID, vec![0.5, 0.25]);
+        let results = search_similar_impl(&pool, &query, 10, None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, 1);
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,975 @@
+//! 向量近似最近邻索引（HNSW）
+//!
+//! 每个 (model_id, dim) 对应一份独立的索引，持久化在数据库旁的 `vector_index/` 目录下。
+//! 索引只是 `vector_embeddings` 表的加速结构：每个节点记录写入时该行的 `created_at`（重新生成向量时必然变化），
+//! 加载时按 (activity_id, created_at) 与表对账，补齐或更新未落盘的向量、剔除已删除的记录，
+//! 因此进程异常退出也不会导致结果缺失或过期。
+
+use crate::vector_db::{decode_vector, Embedding, SearchResult};
+use anyhow::{Context, Result};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// 每层的最大邻居数（第 0 层为其两倍）
+const DEFAULT_M: usize = 16;
+/// 构建时的候选列表大小
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+/// 查询时的最小候选列表大小
+const MIN_EF_SEARCH: usize = 64;
+/// 累计多少条增量写入后落盘一次
+const FLUSH_EVERY: usize = 1000;
+/// 墓碑节点占比超过该值时从数据库重建
+const MAX_DELETED_RATIO: f64 = 0.3;
+
+const FILE_MAGIC: &[u8; 8] = b"MFHNSW02";
+
+static STORE: once_cell::sync::Lazy<tokio::sync::Mutex<VectorIndexStore>> =
+    once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(VectorIndexStore::new(None)));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    dist: f32,
+    node: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    activity_id: i64,
+    /// 对应 `vector_embeddings.created_at`，用于与数据库对账
+    version: i64,
+    /// 归一化后的向量，余弦相似度即点积
+    vector: Vec<f32>,
+    /// 每一层的邻居
+    links: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+/// 内存中的 HNSW 图
+#[derive(Debug, Clone)]
+pub struct HnswIndex {
+    model_id: String,
+    dim: usize,
+    m: usize,
+    ef_construction: usize,
+    nodes: Vec<Node>,
+    id_map: HashMap<i64, u32>,
+    entry_point: Option<u32>,
+    max_level: usize,
+    deleted: usize,
+    rng: u64,
+}
+
+impl HnswIndex {
+    pub fn new(model_id: impl Into<String>, dim: usize) -> Self {
+        Self::with_params(model_id, dim, DEFAULT_M, DEFAULT_EF_CONSTRUCTION)
+    }
+
+    pub fn with_params(model_id: impl Into<String>, dim: usize, m: usize, ef_construction: usize) -> Self {
+        Self {
+            model_id: model_id.into(),
+            dim,
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            nodes: Vec::new(),
+            id_map: HashMap::new(),
+            entry_point: None,
+            max_level: 0,
+            deleted: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// 有效（未删除）的向量数
+    pub fn len(&self) -> usize {
+        self.id_map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id_map.is_empty()
+    }
+
+    pub fn contains(&self, activity_id: i64) -> bool {
+        self.id_map.contains_key(&activity_id)
+    }
+
+    /// 该活动当前向量的版本（写入时的 `created_at`）
+    pub fn version(&self, activity_id: i64) -> Option<i64> {
+        self.id_map
+            .get(&activity_id)
+            .map(|&idx| self.nodes[idx as usize].version)
+    }
+
+    fn deleted_ratio(&self) -> f64 {
+        if self.nodes.is_empty() {
+            0.0
+        } else {
+            self.deleted as f64 / self.nodes.len() as f64
+        }
+    }
+
+    /// 插入向量；同一 activity_id 再次插入时旧节点被标记删除
+    pub fn insert(&mut self, activity_id: i64, vector: &[f32]) -> Result<()> {
+        self.insert_with_version(activity_id, vector, 0)
+    }
+
+    /// 插入向量并记录版本（`vector_embeddings.created_at`）
+    pub fn insert_with_version(&mut self, activity_id: i64, vector: &[f32], version: i64) -> Result<()> {
+        if vector.len() != self.dim {
+            return Err(anyhow::anyhow!(
+                "Vector dimension mismatch: expected {}, got {}",
+                self.dim,
+                vector.len()
+            ));
+        }
+        self.remove(activity_id);
+
+        let vector = normalize(vector);
+        let level = self.random_level();
+        let idx = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            activity_id,
+            version,
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.id_map.insert(activity_id, idx);
+
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(idx);
+            self.max_level = level;
+            return Ok(());
+        };
+
+        let query = self.nodes[idx as usize].vector.clone();
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.search_layer(&query, &[entry], 1, layer)[0].node;
+        }
+
+        let mut entries = vec![entry];
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(&query, &entries, self.ef_construction, layer);
+            let neighbors: Vec<u32> = candidates
+                .iter()
+                .take(self.max_links(layer))
+                .map(|c| c.node)
+                .collect();
+
+            for &neighbor in &neighbors {
+                self.connect(neighbor, idx, layer);
+            }
+            self.nodes[idx as usize].links[layer] = neighbors;
+            entries = candidates.iter().map(|c| c.node).collect();
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(idx);
+        }
+        Ok(())
+    }
+
+    /// 标记删除；节点仍参与图遍历，但不会出现在结果中
+    pub fn remove(&mut self, activity_id: i64) -> bool {
+        match self.id_map.remove(&activity_id) {
+            Some(idx) => {
+                self.nodes[idx as usize].deleted = true;
+                self.deleted += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 查询最相似的 `k` 个向量，返回 (activity_id, 余弦相似度)
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(i64, f64)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+        if query.len() != self.dim || k == 0 {
+            return Vec::new();
+        }
+
+        let query = normalize(query);
+        for layer in (1..=self.max_level).rev() {
+            entry = self.search_layer(&query, &[entry], 1, layer)[0].node;
+        }
+
+        // 墓碑节点会占用候选位置，按删除比例放大候选列表
+        let ef = ef.max(k) + (ef.max(k) as f64 * self.deleted_ratio()).ceil() as usize;
+        self.search_layer(&query, &[entry], ef, 0)
+            .into_iter()
+            .filter(|c| !self.nodes[c.node as usize].deleted)
+            .take(k)
+            .map(|c| (self.nodes[c.node as usize].activity_id, 1.0 - c.dist as f64))
+            .collect()
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        let vector = &self.nodes[node as usize].vector;
+        1.0 - query.iter().zip(vector).map(|(a, b)| a * b).sum::<f32>()
+    }
+
+    /// 在单层上做贪心扩展，返回按距离升序的最多 `ef` 个候选
+    fn search_layer(&self, query: &[f32], entries: &[u32], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited = vec![false; self.nodes.len()];
+        // 待扩展的候选（最小堆）与当前结果（最大堆）
+        let mut frontier: BinaryHeap<std::cmp::Reverse<Candidate>> = BinaryHeap::new();
+        let mut results: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        for &node in entries {
+            if !std::mem::replace(&mut visited[node as usize], true) {
+                let candidate = Candidate {
+                    dist: self.distance(query, node),
+                    node,
+                };
+                frontier.push(std::cmp::Reverse(candidate));
+                results.push(candidate);
+            }
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(std::cmp::Reverse(current)) = frontier.pop() {
+            if let Some(worst) = results.peek() {
+                if results.len() >= ef && current.dist > worst.dist {
+                    break;
+                }
+            }
+
+            let Some(links) = self.nodes[current.node as usize].links.get(layer) else {
+                continue;
+            };
+            for &neighbor in links {
+                if std::mem::replace(&mut visited[neighbor as usize], true) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    dist: self.distance(query, neighbor),
+                    node: neighbor,
+                };
+                let closer = results.peek().map(|w| candidate.dist < w.dist).unwrap_or(true);
+                if results.len() < ef || closer {
+                    frontier.push(std::cmp::Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// 添加一条反向边，超出上限时只保留最近的邻居
+    fn connect(&mut self, from: u32, to: u32, layer: usize) {
+        let max_links = self.max_links(layer);
+        self.nodes[from as usize].links[layer].push(to);
+        if self.nodes[from as usize].links[layer].len() <= max_links {
+            return;
+        }
+
+        let base = self.nodes[from as usize].vector.clone();
+        let mut links: Vec<Candidate> = self.nodes[from as usize].links[layer]
+            .iter()
+            .map(|&node| Candidate {
+                dist: self.distance(&base, node),
+                node,
+            })
+            .collect();
+        links.sort_unstable();
+        links.truncate(max_links);
+        self.nodes[from as usize].links[layer] = links.into_iter().map(|c| c.node).collect();
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64，保证同样的插入顺序得到同样的图，便于复现
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let level_mult = 1.0 / (self.m as f64).ln();
+        ((-uniform.ln() * level_mult) as usize).min(16)
+    }
+
+    /// 序列化为小端二进制
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.nodes.len() * (self.dim * 4 + 16 + self.m * 12));
+        out.extend_from_slice(FILE_MAGIC);
+        put_u32(&mut out, self.model_id.len() as u32);
+        out.extend_from_slice(self.model_id.as_bytes());
+        put_u32(&mut out, self.dim as u32);
+        put_u32(&mut out, self.m as u32);
+        put_u32(&mut out, self.ef_construction as u32);
+        put_u32(&mut out, self.max_level as u32);
+        out.extend_from_slice(&self.entry_point.map(i64::from).unwrap_or(-1).to_le_bytes());
+        out.extend_from_slice(&self.rng.to_le_bytes());
+        put_u32(&mut out, self.nodes.len() as u32);
+
+        for node in &self.nodes {
+            out.extend_from_slice(&node.activity_id.to_le_bytes());
+            out.extend_from_slice(&node.version.to_le_bytes());
+            out.push(node.deleted as u8);
+            out.extend_from_slice(&crate::vector_db::encode_vector(&node.vector));
+            put_u32(&mut out, node.links.len() as u32);
+            for links in &node.links {
+                put_u32(&mut out, links.len() as u32);
+                for &link in links {
+                    put_u32(&mut out, link);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(FILE_MAGIC.len())? != FILE_MAGIC {
+            return Err(anyhow::anyhow!("Not a vector index file"));
+        }
+
+        let model_len = reader.u32()? as usize;
+        let model_id = String::from_utf8(reader.take(model_len)?.to_vec())?;
+        let dim = reader.u32()? as usize;
+        let m = reader.u32()? as usize;
+        let ef_construction = reader.u32()? as usize;
+        let max_level = reader.u32()? as usize;
+        let entry = reader.i64()?;
+        let rng = reader.u64()?;
+        let count = reader.u32()? as usize;
+
+        let mut index = Self::with_params(model_id, dim, m, ef_construction);
+        index.max_level = max_level;
+        index.rng = rng;
+
+        for idx in 0..count {
+            let activity_id = reader.i64()?;
+            let version = reader.i64()?;
+            let deleted = reader.take(1)?[0] != 0;
+            let vector = decode_vector(reader.take(dim * 4)?).context("Invalid vector data")?;
+            let levels = reader.u32()? as usize;
+            let mut links = Vec::with_capacity(levels);
+            for _ in 0..levels {
+                let len = reader.u32()? as usize;
+                let mut layer = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let link = reader.u32()?;
+                    if link as usize >= count {
+                        return Err(anyhow::anyhow!("Corrupted link {} in vector index", link));
+                    }
+                    layer.push(link);
+                }
+                links.push(layer);
+            }
+
+            if deleted {
+                index.deleted += 1;
+            } else {
+                index.id_map.insert(activity_id, idx as u32);
+            }
+            index.nodes.push(Node {
+                activity_id,
+                version,
+                vector,
+                links,
+                deleted,
+            });
+        }
+
+        if entry >= 0 {
+            if entry as usize >= count {
+                return Err(anyhow::anyhow!("Corrupted entry point in vector index"));
+            }
+            index.entry_point = Some(entry as u32);
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // 先写临时文件再改名，避免写到一半时留下损坏的索引
+        let tmp = path.with_extension("hnsw.tmp");
+        std::fs::write(&tmp, self.to_bytes())?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        vector.to_vec()
+    } else {
+        vector.iter().map(|x| x / norm).collect()
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("Unexpected end of vector index file"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+struct LoadedIndex {
+    index: HnswIndex,
+    /// 上次落盘后的增量写入数
+    dirty: usize,
+}
+
+/// 按 (model_id, dim) 管理多份索引的加载、对账与落盘
+pub struct VectorIndexStore {
+    dir: Option<PathBuf>,
+    indexes: HashMap<(String, usize), LoadedIndex>,
+}
+
+impl VectorIndexStore {
+    /// `dir` 为 `None` 时索引只保存在内存中
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            indexes: HashMap::new(),
+        }
+    }
+
+    fn index_path(&self, model_id: &str, dim: usize) -> Option<PathBuf> {
+        let name: String = model_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}-{}.hnsw", name, dim)))
+    }
+
+    /// 取出已加载的索引；首次使用时从磁盘加载并与数据库对账，没有文件则从数据库构建
+    async fn get_or_load(&mut self, pool: &SqlitePool, model_id: &str, dim: usize) -> Result<&mut LoadedIndex> {
+        let key = (model_id.to_string(), dim);
+        if !self.indexes.contains_key(&key) {
+            let path = self.index_path(model_id, dim);
+            let loaded = path.as_deref().filter(|p| p.exists()).and_then(|p| {
+                match HnswIndex::load(p) {
+                    Ok(index) if index.model_id() == model_id && index.dim() == dim => Some(index),
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::warn!("向量索引文件损坏，将重新构建: {} ({})", p.display(), e);
+                        None
+                    }
+                }
+            });
+
+            let mut entry = match loaded {
+                Some(index) => LoadedIndex { index, dirty: 0 },
+                None => LoadedIndex {
+                    index: build_from_db(pool, model_id, dim).await?,
+                    dirty: 1,
+                },
+            };
+            sync_with_db(pool, &mut entry).await?;
+            if entry.index.deleted_ratio() > MAX_DELETED_RATIO {
+                entry = LoadedIndex {
+                    index: build_from_db(pool, model_id, dim).await?,
+                    dirty: 1,
+                };
+            }
+            self.indexes.insert(key.clone(), entry);
+            self.save_if_dirty(&key, 0)?;
+        }
+        Ok(self.indexes.get_mut(&key).expect("index loaded above"))
+    }
+
+    /// ANN 查询；会剔除数据库中已不存在的结果（例如被自动清理删除的活动）
+    pub async fn search(&mut self, pool: &SqlitePool, query: &Embedding, limit: usize) -> Result<Vec<SearchResult>> {
+        let entry = self.get_or_load(pool, &query.model_id, query.dim()).await?;
+        let hits = entry
+            .index
+            .search(&query.vector, limit, (limit * 4).max(MIN_EF_SEARCH));
+        if hits.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut builder = sqlx::QueryBuilder::new(
+            "SELECT activity_id FROM vector_embeddings WHERE model_id = ",
+        );
+        builder.push_bind(&query.model_id);
+        builder.push(" AND dim = ");
+        builder.push_bind(query.dim() as i64);
+        builder.push(" AND activity_id IN (");
+        let mut separated = builder.separated(", ");
+        for (id, _) in &hits {
+            separated.push_bind(*id);
+        }
+        separated.push_unseparated(")");
+        let existing: HashSet<i64> = builder
+            .build()
+            .fetch_all(pool)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let mut results = Vec::with_capacity(hits.len());
+        for (id, score) in hits {
+            if existing.contains(&id) {
+                results.push(SearchResult { id, score });
+            } else {
+                entry.index.remove(id);
+                entry.dirty += 1;
+            }
+        }
+        Ok(results)
+    }
+
+    /// 增量写入，`version` 为该行的 `created_at`；索引尚未加载时跳过（下次加载对账时会补上）
+    pub fn add(&mut self, activity_id: i64, embedding: &Embedding, version: i64) -> Result<()> {
+        let key = (embedding.model_id.clone(), embedding.dim());
+        let Some(entry) = self.indexes.get_mut(&key) else {
+            return Ok(());
+        };
+        entry.index.insert_with_version(activity_id, &embedding.vector, version)?;
+        entry.dirty += 1;
+        self.save_if_dirty(&key, FLUSH_EVERY)
+    }
+
+    /// 从数据库重建指定模型的索引，返回索引中的向量数
+    pub async fn rebuild(&mut self, pool: &SqlitePool, model_id: &str, dim: usize) -> Result<usize> {
+        let key = (model_id.to_string(), dim);
+        let index = build_from_db(pool, model_id, dim).await?;
+        let len = index.len();
+        self.indexes.insert(key.clone(), LoadedIndex { index, dirty: 1 });
+        self.save_if_dirty(&key, 0)?;
+        Ok(len)
+    }
+
+    /// 重建数据库中出现的所有 (model_id, dim) 的索引
+    pub async fn rebuild_all(&mut self, pool: &SqlitePool) -> Result<usize> {
+        let rows = sqlx::query("SELECT DISTINCT model_id, dim FROM vector_embeddings WHERE dim > 0")
+            .fetch_all(pool)
+            .await?;
+
+        let mut total = 0;
+        for row in rows {
+            let model_id: String = row.get(0);
+            let dim: i64 = row.get(1);
+            total += self.rebuild(pool, &model_id, dim as usize).await?;
+        }
+        Ok(total)
+    }
+
+    /// 将所有有未落盘修改的索引写入磁盘
+    pub fn flush(&mut self) -> Result<()> {
+        let keys: Vec<_> = self.indexes.keys().cloned().collect();
+        for key in keys {
+            self.save_if_dirty(&key, 0)?;
+        }
+        Ok(())
+    }
+
+    fn save_if_dirty(&mut self, key: &(String, usize), threshold: usize) -> Result<()> {
+        let path = self.index_path(&key.0, key.1);
+        let Some(entry) = self.indexes.get_mut(key) else {
+            return Ok(());
+        };
+        if entry.dirty == 0 || entry.dirty < threshold {
+            return Ok(());
+        }
+        if let Some(path) = path {
+            entry.index.save(&path)?;
+        }
+        entry.dirty = 0;
+        Ok(())
+    }
+}
+
+async fn build_from_db(pool: &SqlitePool, model_id: &str, dim: usize) -> Result<HnswIndex> {
+    let rows = sqlx::query(
+        "SELECT activity_id, embedding, COALESCE(created_at, 0) FROM vector_embeddings
+         WHERE model_id = ? AND dim = ? ORDER BY activity_id",
+    )
+    .bind(model_id)
+    .bind(dim as i64)
+    .fetch_all(pool)
+    .await?;
+
+    let mut index = HnswIndex::new(model_id, dim);
+    for row in rows {
+        let activity_id: i64 = row.get(0);
+        let bytes: Vec<u8> = row.get(1);
+        if let Some(vector) = decode_vector(&bytes) {
+            index.insert_with_version(activity_id, &vector, row.get(2))?;
+        }
+    }
+    tracing::info!("向量索引构建完成: model={}, dim={}, 共 {} 条", model_id, dim, index.len());
+    Ok(index)
+}
+
+/// 与数据库对账：补齐缺失或版本不一致（已重新生成）的向量，标记已删除的记录
+async fn sync_with_db(pool: &SqlitePool, entry: &mut LoadedIndex) -> Result<()> {
+    let model_id = entry.index.model_id().to_string();
+    let dim = entry.index.dim();
+
+    let db_versions: HashMap<i64, i64> = sqlx::query(
+        "SELECT activity_id, COALESCE(created_at, 0) FROM vector_embeddings WHERE model_id = ? AND dim = ?",
+    )
+    .bind(&model_id)
+    .bind(dim as i64)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| (row.get(0), row.get(1)))
+    .collect();
+
+    let stale: Vec<i64> = entry
+        .index
+        .id_map
+        .keys()
+        .filter(|id| !db_versions.contains_key(id))
+        .copied()
+        .collect();
+    for id in &stale {
+        entry.index.remove(*id);
+    }
+
+    let outdated: Vec<i64> = db_versions
+        .iter()
+        .filter(|(id, version)| entry.index.version(**id) != Some(**version))
+        .map(|(id, _)| *id)
+        .collect();
+    for chunk in outdated.chunks(500) {
+        let mut builder = sqlx::QueryBuilder::new(
+            "SELECT activity_id, embedding, COALESCE(created_at, 0) FROM vector_embeddings WHERE model_id = ",
+        );
+        builder.push_bind(&model_id);
+        builder.push(" AND dim = ");
+        builder.push_bind(dim as i64);
+        builder.push(" AND activity_id IN (");
+        let mut separated = builder.separated(", ");
+        for id in chunk {
+            separated.push_bind(*id);
+        }
+        separated.push_unseparated(")");
+
+        for row in builder.build().fetch_all(pool).await? {
+            let activity_id: i64 = row.get(0);
+            let bytes: Vec<u8> = row.get(1);
+            if let Some(vector) = decode_vector(&bytes) {
+                entry.index.insert_with_version(activity_id, &vector, row.get(2))?;
+            }
+        }
+    }
+
+    if !stale.is_empty() || !outdated.is_empty() {
+        tracing::info!(
+            "向量索引对账: model={}, 补齐/更新 {} 条, 移除 {} 条",
+            model_id,
+            outdated.len(),
+            stale.len()
+        );
+        entry.dirty += stale.len() + outdated.len();
+    }
+    Ok(())
+}
+
+/// 设置索引文件目录（通常是数据库所在目录下的 `vector_index/`），会丢弃已加载的索引
+pub async fn set_index_dir(dir: PathBuf) {
+    *STORE.lock().await = VectorIndexStore::new(Some(dir));
+}
+
+/// 使用全局索引做 ANN 查询
+pub async fn search(pool: &SqlitePool, query: &Embedding, limit: usize) -> Result<Vec<SearchResult>> {
+    STORE.lock().await.search(pool, query, limit).await
+}
+
+/// 向全局索引增量写入
+pub async fn add(activity_id: i64, embedding: &Embedding, version: i64) -> Result<()> {
+    STORE.lock().await.add(activity_id, embedding, version)
+}
+
+/// 按需重建全部索引，返回索引中的向量总数
+pub async fn rebuild_all() -> Result<usize> {
+    let pool = crate::db::get_pool().await?;
+    STORE.lock().await.rebuild_all(&pool).await
+}
+
+/// 将全局索引的未落盘修改写入磁盘
+pub async fn flush() -> Result<()> {
+    STORE.lock().await.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn random_vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state % 2000) as f32 / 1000.0 - 1.0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn exact_top_k(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<i64> {
+        let mut scored: Vec<(i64, f64)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64, crate::vector_db::cosine_similarity(query, v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn recall_matches_exact_scan() {
+        let dim = 32;
+        let vectors = random_vectors(1500, dim, 42);
+        let mut index = HnswIndex::new("test", dim);
+        for (i, v) in vectors.iter().enumerate() {
+            index.insert(i as i64, v).unwrap();
+        }
+
+        let queries = random_vectors(50, dim, 7);
+        let k = 10;
+        let mut hits = 0;
+        for query in &queries {
+            let expected: HashSet<i64> = exact_top_k(&vectors, query, k).into_iter().collect();
+            hits += index
+                .search(query, k, MIN_EF_SEARCH)
+                .iter()
+                .filter(|(id, _)| expected.contains(id))
+                .count();
+        }
+        let recall = hits as f64 / (queries.len() * k) as f64;
+        assert!(recall >= 0.95, "recall@10 too low: {}", recall);
+    }
+
+    #[test]
+    fn removed_and_replaced_vectors() {
+        let mut index = HnswIndex::new("test", 2);
+        index.insert(1, &[1.0, 0.0]).unwrap();
+        index.insert(2, &[0.0, 1.0]).unwrap();
+        index.insert(3, &[0.7, 0.7]).unwrap();
+
+        assert_eq!(index.search(&[1.0, 0.0], 1, 10)[0].0, 1);
+        index.remove(1);
+        assert_eq!(index.search(&[1.0, 0.0], 1, 10)[0].0, 3);
+
+        // 重新写入同一 activity_id 覆盖旧向量
+        index.insert(2, &[1.0, 0.0]).unwrap();
+        let top = index.search(&[1.0, 0.0], 1, 10);
+        assert_eq!(top[0].0, 2);
+        assert!((top[0].1 - 1.0).abs() < 1e-6);
+        assert_eq!(index.len(), 2);
+        assert!(index.insert(4, &[1.0]).is_err());
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let vectors = random_vectors(200, 8, 3);
+        let mut index = HnswIndex::new("BAAI/bge-small-en-v1.5", 8);
+        for (i, v) in vectors.iter().enumerate() {
+            index.insert(i as i64, v).unwrap();
+        }
+        index.remove(5);
+
+        let restored = HnswIndex::from_bytes(&index.to_bytes()).unwrap();
+        assert_eq!(restored.model_id(), "BAAI/bge-small-en-v1.5");
+        assert_eq!(restored.len(), 199);
+        assert_eq!(restored.search(&vectors[9], 5, 32), index.search(&vectors[9], 5, 32));
+
+        let bytes = index.to_bytes();
+        assert!(HnswIndex::from_bytes(&bytes[..bytes.len() - 3]).is_err());
+        assert!(HnswIndex::from_bytes(b"garbage").is_err());
+    }
+
+    #[tokio::test]
+    async fn store_persists_and_syncs_with_db() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let vectors = random_vectors(40, 4, 11);
+        for (i, v) in vectors.iter().enumerate() {
+            let id = i as i64 + 1;
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (?, ?, 'Code', 'w', '')")
+                .bind(id)
+                .bind(id * 1000)
+                .execute(&pool)
+                .await
+                .unwrap();
+            crate::vector_db::insert_embedding_impl(&pool, id, &Embedding::new("m", v.clone()))
+                .await
+                .unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("memflow-vector-index-{}", uuid::Uuid::new_v4()));
+        let query = Embedding::new("m", vectors[0].clone());
+
+        let mut store = VectorIndexStore::new(Some(dir.clone()));
+        let results = store.search(&pool, &query, 3).await.unwrap();
+        assert_eq!(results[0].id, 1);
+        let path = store.index_path("m", 4).unwrap();
+        assert!(path.exists());
+
+        // 模拟另一个进程写入新向量并删除旧活动（未更新索引文件）
+        sqlx::query("DELETE FROM activity_logs WHERE id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (100, 1, 'Code', 'w', '')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        crate::vector_db::insert_embedding_impl(&pool, 100, &query).await.unwrap();
+
+        // 已加载的索引在查询时剔除已删除的结果
+        let results = store.search(&pool, &query, 3).await.unwrap();
+        assert!(results.iter().all(|r| r.id != 1));
+
+        // 重新加载时与数据库对账
+        let mut reloaded = VectorIndexStore::new(Some(dir.clone()));
+        let results = reloaded.search(&pool, &query, 3).await.unwrap();
+        assert_eq!(results[0].id, 100);
+        assert!(results.iter().all(|r| r.id != 1));
+
+        assert_eq!(reloaded.rebuild_all(&pool).await.unwrap(), 40);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn reembedded_vectors_are_served_after_restart() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let vectors = random_vectors(20, 4, 5);
+        for (i, v) in vectors.iter().enumerate() {
+            let id = i as i64 + 1;
+            sqlx::query("INSERT INTO activity_logs (id, timestamp, app_name, window_title, image_path) VALUES (?, ?, 'Code', 'w', '')")
+                .bind(id)
+                .bind(id * 1000)
+                .execute(&pool)
+                .await
+                .unwrap();
+            crate::vector_db::insert_embedding_impl(&pool, id, &Embedding::new("m", v.clone()))
+                .await
+                .unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("memflow-vector-index-{}", uuid::Uuid::new_v4()));
+        let old_query = Embedding::new("m", vectors[1].clone());
+        let mut store = VectorIndexStore::new(Some(dir.clone()));
+        assert_eq!(store.search(&pool, &old_query, 1).await.unwrap()[0].id, 2);
+
+        // 同一秒内重新生成向量，增量写入内存索引后未落盘就退出
+        let replacement = Embedding::new("m", vec![-1.0, 0.0, 0.0, 1.0]);
+        let version = crate::vector_db::insert_embedding_impl(&pool, 2, &replacement).await.unwrap();
+        store.add(2, &replacement, version).unwrap();
+        assert_eq!(store.indexes[&("m".to_string(), 4)].dirty, 1);
+        drop(store);
+
+        let mut reloaded = VectorIndexStore::new(Some(dir.clone()));
+        let top = reloaded.search(&pool, &replacement, 1).await.unwrap();
+        assert_eq!(top[0].id, 2);
+        assert!((top[0].score - 1.0).abs() < 1e-6);
+        let old_hits = reloaded.search(&pool, &old_query, 20).await.unwrap();
+        let old_score = old_hits.iter().find(|r| r.id == 2).map(|r| r.score).unwrap_or(-1.0);
+        assert!(old_score < 0.999, "stale vector served: {}", old_score);
+        assert_eq!(reloaded.indexes[&("m".to_string(), 4)].index.version(2), Some(version));
+
+        // 显式落盘后再次加载无需对账
+        reloaded.flush().unwrap();
+        let mut entry = LoadedIndex {
+            index: HnswIndex::load(&reloaded.index_path("m", 4).unwrap()).unwrap(),
+            dirty: 0,
+        };
+        sync_with_db(&pool, &mut entry).await.unwrap();
+        assert_eq!(entry.dirty, 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,22 +1,26 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use fastembed::{InitOptions, TextEmbedding, EmbeddingModel};
-use memflow_core::ai::rag::HybridSearch;
+use memflow_core::ai::embedding::{self, EmbeddingConfig, EmbeddingProvider};
+use memflow_core::ai::rag::{self, HybridSearch, Reranker, RerankerConfig};
 use memflow_core::context::RuntimeContext;
 use memflow_core::db;
+use memflow_core::digest::{self, DigestPeriod};
+use memflow_core::highlight::{self, HighlightOptions};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::io::{self};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tracing::{error, info};
 use tracing_subscriber::{fmt, EnvFilter};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
 mod context;
 use context::McpContext;
 
-// Global model instance
-static EMBEDDING_MODEL: OnceLock<TextEmbedding> = OnceLock::new();
+// Global embedding provider (same model as the desktop app, selected from its config.json)
+static EMBEDDING_PROVIDER: OnceLock<Arc<dyn EmbeddingProvider>> = OnceLock::new();
+// Optional cross-encoder reranker (enabled by `rerankEnabled` in the same config.json)
+static RERANKER: OnceLock<Arc<dyn Reranker>> = OnceLock::new();
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -95,22 +99,28 @@ async fn main() -> Result<()> {
     info!("memflow-mcp server starting...");
     info!("Resource dir: {:?}", resource_dir);
 
-    // Initialize Embedding Model
-    info!("Initializing Embedding Model (BGESmallENV15)...");
-    let model_opts = InitOptions::new(EmbeddingModel::BGESmallENV15)
-        .with_cache_dir(resource_dir.join("models"))
-        .with_show_download_progress(false);
+    // Initialize Embedding Provider
+    let embedding_config = load_embedding_config(&app_dir, &resource_dir);
+    info!("Initializing Embedding Provider ({:?})...", embedding_config.provider);
+    match embedding::create_embedding_provider(&embedding_config).await {
+        Ok(provider) => {
+            info!("Embedding Provider initialized: {}", provider.model_id());
+            let _ = EMBEDDING_PROVIDER.set(provider);
+        }
+        Err(e) => {
+            error!("Failed to initialize Embedding Provider: {}", e);
+        }
+    }
 
-    match TextEmbedding::try_new(model_opts) {
-        Ok(model) => {
-            if EMBEDDING_MODEL.set(model).is_err() {
-                error!("Failed to set global embedding model");
-            } else {
-                info!("Embedding Model initialized successfully.");
-            }
-        },
+    let reranker_config = load_reranker_config(&app_dir, &resource_dir);
+    match rag::create_reranker(&reranker_config).await {
+        Ok(Some(reranker)) => {
+            info!("Reranker initialized: {}", reranker.model_id());
+            let _ = RERANKER.set(reranker);
+        }
+        Ok(None) => {}
         Err(e) => {
-            error!("Failed to initialize Embedding Model: {}", e);
+            error!("Failed to initialize Reranker: {}", e);
         }
     }
 
@@ -207,6 +217,24 @@ async fn process_line(line: &str) -> Result<Option<JsonRpcResponse>> {
                             },
                             "required": ["query"]
                         }
+                    },
+                    {
+                        "name": "get_digest",
+                        "description": "Get the Markdown daily or weekly activity report generated by the desktop app (apps and windows with durations, focus trend, task summary, key screenshots).",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "period": {
+                                    "type": "string",
+                                    "enum": ["day", "week"],
+                                    "description": "Report period (default day)."
+                                },
+                                "date": {
+                                    "type": "string",
+                                    "description": "Any date within the period, YYYY-MM-DD. Defaults to the most recent report."
+                                }
+                            }
+                        }
                     }
                 ]
             });
@@ -237,6 +265,21 @@ async fn process_line(line: &str) -> Result<Option<JsonRpcResponse>> {
                         Ok(Some(JsonRpcResponse::error(id, -32000, e.to_string())))
                     }
                 }
+            } else if name == "get_digest" {
+                match call_get_digest(args["period"].as_str(), args["date"].as_str()).await {
+                    Ok(result_text) => Ok(Some(JsonRpcResponse::ok(id, serde_json::json!({
+                        "content": [
+                            {
+                                "type": "text",
+                                "text": result_text
+                            }
+                        ]
+                    })))),
+                    Err(e) => {
+                        error!("Get digest failed: {}", e);
+                        Ok(Some(JsonRpcResponse::error(id, -32000, e.to_string())))
+                    }
+                }
             } else {
                  Ok(Some(JsonRpcResponse::error(id, -32601, format!("Tool not found: {}", name))))
             }
@@ -251,50 +294,127 @@ async fn process_line(line: &str) -> Result<Option<JsonRpcResponse>> {
     }
 }
 
+/// Read the embedding settings from the desktop app's config.json
+///
+/// The API key for OpenAI-compatible providers is not stored in the config file;
+/// it is taken from `MEMFLOW_EMBEDDING_API_KEY` or `OPENAI_API_KEY`.
+fn load_embedding_config(app_dir: &std::path::Path, resource_dir: &std::path::Path) -> EmbeddingConfig {
+    let value = read_app_config(app_dir);
+    let mut config = EmbeddingConfig::from_app_config(&value);
+    config.api_key = std::env::var("MEMFLOW_EMBEDDING_API_KEY")
+        .or_else(|_| std::env::var("OPENAI_API_KEY"))
+        .ok();
+    config.cache_dir = Some(resource_dir.join("models"));
+    config
+}
+
+/// Read the reranker settings from the desktop app's config.json
+fn load_reranker_config(app_dir: &std::path::Path, resource_dir: &std::path::Path) -> RerankerConfig {
+    let mut config = RerankerConfig::from_app_config(&read_app_config(app_dir));
+    config.cache_dir = Some(resource_dir.join("models"));
+    config
+}
+
+fn read_app_config(app_dir: &std::path::Path) -> Value {
+    std::fs::read_to_string(app_dir.join("config.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .unwrap_or(Value::Null)
+}
+
 async fn call_search_memory(query: &str, limit: usize) -> Result<String> {
     info!("Searching for: {} (limit: {})", query, limit);
 
-    // Check if model is available
-    let embedding = if let Some(model) = EMBEDDING_MODEL.get() {
+    // Check if provider is available
+    let embedding = if let Some(provider) = EMBEDDING_PROVIDER.get() {
         info!("Generating embedding for query...");
-        let embeddings = model.embed(vec![query], None)?;
-        // fastembed returns Vec<Vec<f32>>, we take the first one
-        if let Some(vec) = embeddings.into_iter().next() {
-            info!("Embedding generated (dim: {})", vec.len());
-            vec
-        } else {
-            return Err(anyhow::anyhow!("Failed to generate embedding: empty result"));
-        }
+        let embedding = provider.embed_query(query).await?;
+        info!("Embedding generated (dim: {})", embedding.dim());
+        embedding
     } else {
-        error!("Embedding model not initialized, falling back to placeholder.");
+        error!("Embedding provider not initialized, falling back to placeholder.");
         memflow_core::vector_db::generate_placeholder_embedding(query)
     };
 
-    let searcher = HybridSearch::new();
+    let searcher = HybridSearch::new().with_reranker(RERANKER.get().cloned());
     let results = searcher.search_with_embedding(query, embedding, limit).await?;
 
     if results.is_empty() {
         return Ok("No matching results found.".to_string());
     }
 
+    let ids: Vec<i64> = results.iter().map(|r| r.id).collect();
+    let activities: std::collections::HashMap<i64, db::ActivityLog> =
+        db::list_activities(&db::ActivityQuery::new().ids(ids))
+            .await?
+            .into_iter()
+            .map(|a| (a.id, a))
+            .collect();
+
+    // 只输出命中附近的摘要，完整内容可通过活动 ID 另行获取
+    let terms = memflow_core::ai::nlp::query_terms(query);
+    let options = HighlightOptions {
+        open: "**".to_string(),
+        close: "**".to_string(),
+        max_chars: 160,
+        ..Default::default()
+    };
+
     let mut output = String::new();
     for res in results {
-        let activity = db::get_activity_by_id(res.id).await;
-        if let Ok(act) = activity {
+        if let Some(act) = activities.get(&res.id) {
             use chrono::TimeZone;
             let dt = chrono::Local.timestamp_opt(act.timestamp, 0).unwrap();
+
+            // 优先在最相关的块内取摘要
+            let ocr_text = res
+                .chunk
+                .as_ref()
+                .map(|chunk| chunk.text.as_str())
+                .or(act.ocr_text.as_deref())
+                .unwrap_or_default();
+            let spans = highlight::find_term_spans(ocr_text, &terms);
+            let excerpt = highlight::build_snippet(ocr_text, &spans, &options);
             
             output.push_str(&format!(
-                "ID: {} | Time: {} | App: {} | Title: {}\nScore: {:.2}\nContent: {}\n---\n",
+                "ID: {} | Time: {} | App: {} | Title: {}\nScore: {:.2}\nExcerpt: {}\n---\n",
                 act.id,
                 dt.format("%Y-%m-%d %H:%M:%S"),
                 act.app_name,
                 act.window_title,
                 res.score,
-                act.ocr_text.unwrap_or_default().trim()
+                excerpt
             ));
         }
     }
 
     Ok(output)
 }
+
+/// 读取桌面端已生成的日报 / 周报（MCP 只读，不触发生成）
+async fn call_get_digest(period: Option<&str>, date: Option<&str>) -> Result<String> {
+    let period = match period {
+        Some(value) => DigestPeriod::parse(value).with_context(|| format!("Unknown period: {}", value))?,
+        None => DigestPeriod::Day,
+    };
+
+    let found = match date {
+        Some(value) => {
+            let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .with_context(|| format!("Invalid date (expected YYYY-MM-DD): {}", value))?;
+            let (start, _) = period
+                .period_of_date(date)
+                .with_context(|| format!("Invalid date: {}", value))?;
+            digest::get_digest(period, start).await?
+        }
+        None => digest::list_digests(Some(period), 1).await?.into_iter().next(),
+    };
+
+    Ok(match found {
+        Some(report) => report.markdown,
+        None => format!(
+            "No {} report found. Reports are generated by the MemFlow desktop app once enabled in Settings.",
+            period.as_str()
+        ),
+    })
+}
@@ -2,6 +2,8 @@ use crate::protocol::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, ListToolsRe
 use crate::context::McpContext;
 use memflow_core::context::RuntimeContext;
 use memflow_core::db;
+use memflow_core::highlight::HighlightOptions;
+use memflow_core::search_query::parse_search_query;
 use serde_json::{json, Value};
 use std::sync::Arc;
 use tracing::{error, info};
@@ -54,8 +56,13 @@ impl McpServer {
                 input_schema: json!({
                     "type": "object",
                     "properties": {
-                        "query": { "type": "string", "description": "Keywords to search for in OCR text or window titles" },
-                        "limit": { "type": "integer", "description": "Max number of results (default 10)" }
+                        "query": { "type": "string", "description": "Keywords to search for in OCR text or window titles. Supports app:, title:, ocr:, after:, before:, has:ocr, -exclusions, \"phrases\" and prefix*" },
+                        "limit": { "type": "integer", "description": "Max number of results (default 10)" },
+                        "apps": { "type": "array", "items": { "type": "string" }, "description": "Only include these applications" },
+                        "exclude_apps": { "type": "array", "items": { "type": "string" }, "description": "Exclude these applications" },
+                        "window_title": { "type": "string", "description": "Substring of the window title" },
+                        "from_ts": { "type": "integer", "description": "Start of time range (unix seconds)" },
+                        "to_ts": { "type": "integer", "description": "End of time range (unix seconds)" }
                     },
                     "required": ["query"]
                 }),
@@ -98,31 +105,45 @@ impl McpServer {
                 
                 info!("Executing search tool: query='{}', limit={}", query, limit);
 
-                // Initialize DB connection if needed (lazy or reusing global)
-                // In memflow-core, db init passes app_handle/path. We might need to ensure DB is initialized in main.
-                
-                // For now, let's assume global pool is set or we error out if not
-                // This is a simplified search that maps to db::search_activities
-                
-                // Note: db::search_activities signature inside memflow-core might need checking
-                // Assuming access to memflow_core::db
-                
-                let results = db::search_activities(
-                    Some(query.to_string()), // query
-                    None, // app_name
-                    None, // start_time
-                    None, // end_time
-                    None, // has_ocr
-                    Some(limit), // limit
-                    None, // offset
-                    None, // order_by
-                ).await.map_err(|e| JsonRpcError {
+                let string_list = |key: &str| -> Vec<String> {
+                    args.get(key)
+                        .and_then(|v| v.as_array())
+                        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                        .unwrap_or_default()
+                };
+
+                let mut search = parse_search_query(query)
+                    .map_err(|e| JsonRpcError {
+                        code: -32602,
+                        message: format!("Invalid query: {}", e),
+                        data: None,
+                    })?
+                    .apps(string_list("apps"))
+                    .exclude_apps(string_list("exclude_apps"))
+                    .limit(limit)
+                    .highlight(HighlightOptions::default());
+                if let Some(title) = args.get("window_title").and_then(|v| v.as_str()) {
+                    search = search.window_title(title);
+                }
+                if let Some(from) = args.get("from_ts").and_then(|v| v.as_i64()) {
+                    search = search.from_ts(from);
+                }
+                if let Some(to) = args.get("to_ts").and_then(|v| v.as_i64()) {
+                    search = search.to_ts(to);
+                }
+
+                let (mut hits, _) = db::search_activity_hits(&search).await.map_err(|e| JsonRpcError {
                     code: -32000,
                     message: format!("Search failed: {}", e),
                     data: None,
                 })?;
 
-                Ok(json!({ "activities": results.0 }))
+                // 摘要已足够定位，完整 OCR 文本通过 memflow_get_activity 获取
+                for hit in &mut hits {
+                    hit.activity.ocr_text = None;
+                }
+
+                Ok(json!({ "activities": hits }))
             }
             "memflow_get_activity" => {
                 let activity_id = args.get("activity_id").and_then(|v| v.as_i64()).ok_or_else(|| JsonRpcError {
@@ -0,0 +1,34 @@
+-- 中文分词索引：unicode61 不会切分连续中文，改为索引 jieba 预分词后的影子列
+-- *_seg 列由应用层写入（insert_activity / update_activity_ocr / reindex_segmented_fts）：
+--   NULL 表示尚未处理，'' 表示不含中文（直接索引原文），其余为空格分隔的分词结果
+
+ALTER TABLE activity_logs ADD COLUMN window_title_seg TEXT;
+ALTER TABLE activity_logs ADD COLUMN ocr_text_seg TEXT;
+
+DROP TRIGGER IF EXISTS activity_logs_fts_insert;
+DROP TRIGGER IF EXISTS activity_logs_fts_update;
+
+CREATE TRIGGER IF NOT EXISTS activity_logs_fts_insert
+AFTER INSERT ON activity_logs
+BEGIN
+    INSERT INTO activity_logs_fts(rowid, window_title, app_name, ocr_text)
+    VALUES (
+        NEW.id,
+        COALESCE(NULLIF(NEW.window_title_seg, ''), NEW.window_title, ''),
+        COALESCE(NEW.app_name, ''),
+        COALESCE(NULLIF(NEW.ocr_text_seg, ''), NEW.ocr_text, '')
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS activity_logs_fts_update
+AFTER UPDATE OF window_title, app_name, ocr_text, window_title_seg, ocr_text_seg ON activity_logs
+BEGIN
+    DELETE FROM activity_logs_fts WHERE rowid = OLD.id;
+    INSERT INTO activity_logs_fts(rowid, window_title, app_name, ocr_text)
+    VALUES (
+        NEW.id,
+        COALESCE(NULLIF(NEW.window_title_seg, ''), NEW.window_title, ''),
+        COALESCE(NEW.app_name, ''),
+        COALESCE(NULLIF(NEW.ocr_text_seg, ''), NEW.ocr_text, '')
+    );
+END;
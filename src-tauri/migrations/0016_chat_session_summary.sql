@@ -0,0 +1,8 @@
+-- 多轮对话：会话的滚动摘要
+-- 迁移文件：0016_chat_session_summary.sql
+
+-- 较早轮次由 LLM 压缩成的摘要
+ALTER TABLE chat_sessions ADD COLUMN summary TEXT;
+
+-- 已并入摘要的最后一条消息 ID（0 表示尚未压缩）
+ALTER TABLE chat_sessions ADD COLUMN summary_until_message_id INTEGER NOT NULL DEFAULT 0;
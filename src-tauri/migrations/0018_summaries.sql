@@ -0,0 +1,21 @@
+-- 分层活动摘要：小时摘要由 activity_logs 生成，逐级汇总为日摘要、周摘要
+-- 迁移文件：0018_summaries.sql
+-- 摘要由应用层（ai::summaries）写入；时间区间按本地时区对齐，start_ts 包含、end_ts 不包含
+
+CREATE TABLE IF NOT EXISTS summaries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    granularity TEXT NOT NULL, -- hour | day | week
+    start_ts INTEGER NOT NULL,
+    end_ts INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- 来源 ID（JSON 数组）：小时摘要为活动 ID，日/周摘要为下一级摘要的 ID
+    source_ids TEXT NOT NULL,
+    -- 覆盖的活动记录数
+    activity_count INTEGER NOT NULL DEFAULT 0,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (granularity, start_ts)
+);
+
+CREATE INDEX IF NOT EXISTS idx_summaries_span ON summaries(granularity, end_ts);
@@ -7,10 +7,12 @@
 pub use memflow_core::ai::{
     strip_json_code_fence,
     fallback_filter_params,
+    AiAnalysisResult,
     FilterParams,
     PromptTemplate,
     PromptsConfig,
     AgentConfig,
+    TaskContext,
 };
 
 // Keep local submodules (they wrap/extend memflow-core modules)
@@ -21,11 +23,48 @@ pub mod provider;
 pub mod rag;
 
 use crate::ai::prompts::{get_analyze_proposals_prompt, get_intent_parser_prompt};
-use crate::ai::provider::{chat_with_anthropic, chat_with_openai, ProviderConfig};
+use crate::ai::provider::{CancellationToken, CompletionRequest, LlmConfig, LlmProvider, LlmProviderKind};
 use crate::ai::rag::HybridSearch;
-use crate::vector_db;
+use memflow_core::ai::citations;
+use memflow_core::ai::context_builder::{assemble_context, ContextBudget, ContextItem};
+use memflow_core::ai::conversation::{self, Conversation, ConversationConfig};
+use memflow_core::ai::summaries::{self, SummaryGranularity};
+use memflow_core::ai::usage::{LlmPurpose, MeteredLlmProvider};
 use anyhow::Result;
-use chrono::{Duration, Local, TimeZone};
+use serde::Serialize;
+use chrono::{Local, TimeZone};
+
+/// 读取 app 配置，失败时使用默认配置
+async fn app_config_or_default() -> crate::commands::AppConfig {
+    crate::app_config::get_config().await.unwrap_or_else(|_| {
+        let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
+        cfg.ocr_enabled = true;
+        cfg
+    })
+}
+
+/// 由 app 配置与 secure storage 中的 API Key 组装 LLM 配置
+pub async fn llm_config(config: &crate::commands::AppConfig) -> Result<LlmConfig> {
+    let mut llm = LlmConfig::resolve(&config.chat_provider, &config.chat_model)?;
+    llm.base_url = match llm.provider {
+        LlmProviderKind::OpenAi => config.openai_base_url.clone(),
+        LlmProviderKind::Anthropic => config.anthropic_base_url.clone(),
+        LlmProviderKind::Ollama | LlmProviderKind::Local => config.local_llm_base_url.clone(),
+        LlmProviderKind::Mock => None,
+    };
+    if let Some(service) = llm.provider.key_service() {
+        llm.api_key = crate::secure_storage::get_api_key(service).await?;
+    }
+    Ok(llm)
+}
+
+/// 当前配置对应的 LLM provider（同一配置复用同一个实例），调用按 `purpose` 记录到 `llm_calls`
+pub async fn llm_provider(config: &crate::commands::AppConfig, purpose: LlmPurpose) -> Result<MeteredLlmProvider> {
+    let provider = memflow_core::ai::provider::shared_llm_provider(&llm_config(config).await?).await?;
+    let pool = crate::db::get_pool().await?;
+    Ok(MeteredLlmProvider::new(provider, pool, purpose)
+        .with_cache_ttl(std::time::Duration::from_secs(config.llm_cache_ttl_secs)))
+}
 
 pub async fn analyze_activity(activity_id: i64) -> Result<String> {
     // 1. 获取活动信息
@@ -37,10 +76,8 @@ pub async fn analyze_activity(activity_id: i64) -> Result<String> {
         return Ok("活动无 OCR 文本，无法分析".to_string());
     }
 
-    let embedding = vector_db::generate_embedding(&text).await?;
-
-    // 3. 保存嵌入
-    vector_db::insert_embedding(activity_id, embedding).await?;
+    // 3. 交给向量队列生成并保存嵌入（与检索使用同一个模型）
+    crate::embedding_worker::enqueue(activity_id).await;
 
     // 4. 简单的分析（实际应该调用 LLM）
     Ok(format!(
@@ -49,64 +86,22 @@ pub async fn analyze_activity(activity_id: i64) -> Result<String> {
     ))
 }
 
+/// 把意图中的时间表达解析为时间戳范围；无法解析时两端都为 None
 fn calculate_timestamps(range: &str) -> (Option<i64>, Option<i64>) {
-    let now = Local::now();
-    let today_start = now
-        .date_naive()
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_local_timezone(Local)
-        .unwrap()
-        .timestamp();
-    let today_end = now
-        .date_naive()
-        .and_hms_opt(23, 59, 59)
-        .unwrap()
-        .and_local_timezone(Local)
-        .unwrap()
-        .timestamp();
-
-    match range {
-        "today" | "今天" => (Some(today_start), Some(today_end)),
-        "yesterday" | "昨天" => {
-            let y = now - Duration::days(1);
-            let start = y
-                .date_naive()
-                .and_hms_opt(0, 0, 0)
-                .unwrap()
-                .and_local_timezone(Local)
-                .unwrap()
-                .timestamp();
-            let end = y
-                .date_naive()
-                .and_hms_opt(23, 59, 59)
-                .unwrap()
-                .and_local_timezone(Local)
-                .unwrap()
-                .timestamp();
-            (Some(start), Some(end))
-        }
-        "this_week" | "本周" => {
-            let start = (now - Duration::days(7)).timestamp();
-            (Some(start), Some(today_end))
+    match memflow_core::time_range::parse_time_range(range) {
+        Some(range) => (Some(range.from_ts), Some(range.to_ts)),
+        None => {
+            tracing::debug!("无法解析的时间表达: {}", range);
+            (None, None)
         }
-        "last_week" | "上周" => {
-            let start = (now - Duration::days(14)).timestamp();
-            let end = (now - Duration::days(7)).timestamp();
-            (Some(start), Some(end))
-        }
-        "this_month" | "本月" => {
-            let start = (now - Duration::days(30)).timestamp();
-            (Some(start), Some(today_end))
-        }
-        _ => (None, None),
     }
 }
 
 async fn build_context_from_range(
     _query: &str,
     intent: &FilterParams,
-) -> Result<(String, usize)> {
+    budget: &ContextBudget,
+) -> Result<(String, Vec<i64>)> {
     let (from_ts, to_ts) = if let Some(range) = &intent.date_range {
         calculate_timestamps(range)
     } else {
@@ -115,581 +110,386 @@ async fn build_context_from_range(
 
     // 如果指定了时间范围，但没有解析出时间戳（即未知的时间描述），则回退到 HybridSearch
     if intent.date_range.is_some() && from_ts.is_none() {
-        return Ok((String::new(), 0));
+        return Ok((String::new(), Vec::new()));
     }
 
-    let search_query = if !intent.keywords.is_empty() {
-        Some(intent.keywords.join(" OR "))
-    } else {
-        None
+    // 关键词来自 LLM，需转义后再进入 FTS MATCH
+    let search_query = memflow_core::search_query::fts_any_of(&intent.keywords);
+
+    let search = crate::db::ActivityQuery {
+        query: search_query,
+        apps: intent.app_name.clone().into_iter().collect(),
+        from_ts,
+        to_ts,
+        has_ocr: intent.has_ocr,
+        // 多取一些，由上下文预算决定最终带入哪些
+        limit: Some(200),
+        order_by: Some("time".to_string()),
+        ..Default::default()
     };
+    let activities = crate::db::list_activities(&search).await?;
+
+    let items = activities
+        .iter()
+        .map(|activity| {
+            ContextItem::new(
+                activity.id,
+                activity.timestamp,
+                &activity.app_name,
+                &activity.window_title,
+                activity.ocr_text.as_deref(),
+            )
+        })
+        .collect();
+    let context = assemble_context(items, budget);
+    tracing::info!(
+        "时间范围上下文: {} 条记录 -> {} 条，约 {}/{} tokens（合并 {}，截断 {}，丢弃 {}）",
+        activities.len(),
+        context.entries.len(),
+        context.used_tokens,
+        context.budget_tokens,
+        context.merged,
+        context.truncated,
+        context.dropped.len()
+    );
+
+    let context_text = context
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| format!("{}\n\n", citations::numbered_entry(i + 1, &context.render_entry(entry))))
+        .collect();
+
+    Ok((context_text, context.sources()))
+}
 
-    let (activities, _) = crate::db::search_activities(
-        search_query,
-        intent.app_name.clone(),
+/// 意图中的结构化条件（应用、时间范围、是否有 OCR），用作混合检索的过滤条件
+fn intent_filter(intent: &FilterParams) -> crate::db::ActivityQuery {
+    let (from_ts, to_ts) = intent
+        .date_range
+        .as_deref()
+        .map(calculate_timestamps)
+        .unwrap_or((None, None));
+
+    crate::db::ActivityQuery {
+        apps: intent.app_name.clone().into_iter().collect(),
         from_ts,
         to_ts,
-        intent.has_ocr,
-        Some(50), // 增加上下文数量以支持总结
-        None,
-        Some("time".to_string()),
-    )
-    .await?;
+        has_ocr: intent.has_ocr,
+        ..Default::default()
+    }
+}
 
-    let mut context_text = String::new();
-    let mut context_count = 0;
+/// 组装对话上下文，返回编号后的上下文文本和各编号对应的活动 ID（第 n 条为 `sources[n - 1]`）
+///
+/// 优先做带过滤条件的混合检索（语义 + 关键词，同时满足意图中的应用与时间范围）；
+/// 只有时间范围、没有检索词（如“今天做了什么”），或检索无结果时，按时间列出该范围内的活动；
+/// 时间范围超过一天时先用分层摘要（没有编号，不要求引用）。
+async fn build_chat_context(
+    query: &str,
+    intent: &FilterParams,
+    budget: &ContextBudget,
+) -> Result<(String, Vec<i64>)> {
+    let list_by_time = intent.date_range.is_some() && intent.keywords.is_empty();
+
+    if list_by_time {
+        if let Some(text) = build_context_from_summaries(intent, budget).await {
+            return Ok((text, Vec::new()));
+        }
+    } else {
+        let (text, sources) = build_context_from_search(query, intent).await?;
+        if !sources.is_empty() || intent.date_range.is_none() {
+            return Ok((text, sources));
+        }
+    }
+
+    match build_context_from_range(query, intent, budget).await {
+        Ok((text, sources)) if !sources.is_empty() => Ok((text, sources)),
+        _ => Ok((String::new(), Vec::new())),
+    }
+}
+
+/// 按时间跨度选粒度（一周以内小时、一个月以内日、更长周），取覆盖该范围的摘要；
+/// 限定了应用、跨度不超过一天或还没有摘要时返回 None
+async fn build_context_from_summaries(intent: &FilterParams, budget: &ContextBudget) -> Option<String> {
+    if intent.app_name.is_some() {
+        return None;
+    }
+    let range = memflow_core::time_range::parse_time_range(intent.date_range.as_deref()?)?;
+    let granularity = SummaryGranularity::for_span(range.from_ts, range.to_ts)?;
+    let found = match summaries::summaries_for_range(range.from_ts, range.to_ts, granularity).await {
+        Ok(found) if !found.is_empty() => found,
+        Ok(_) => return None,
+        Err(e) => {
+            tracing::warn!("读取活动摘要失败，改为列出活动记录: {}", e);
+            return None;
+        }
+    };
 
-    for activity in activities {
-        let time_str = Local.timestamp_opt(activity.timestamp, 0)
-            .unwrap()
-            .format("%H:%M:%S")
-            .to_string();
+    tracing::info!("摘要上下文: {} 条（首选粒度 {}）", found.len(), granularity.as_str());
+    Some(format!(
+        "以下是该时间范围内按时间排列的活动摘要：\n\n{}\n\n",
+        summaries::render_summaries(&found, budget)
+    ))
+}
 
-        if let Some(ref ocr_text) = activity.ocr_text {
-            if !ocr_text.trim().is_empty() {
-                context_text.push_str(&format!(
+async fn build_context_from_search(query: &str, intent: &FilterParams) -> Result<(String, Vec<i64>)> {
+    let searcher = HybridSearch::new().with_reranker(crate::vector_db::reranker().await);
+    // HybridSearch from core requires explicit embedding
+    let embedding = crate::vector_db::generate_embedding(query).await?;
+    let results = searcher
+        .search_with_filter(query, embedding, &intent_filter(intent), 5)
+        .await?;
+
+    let mut context_text = String::new();
+    let mut sources = Vec::new();
+    for result in results {
+        if let Ok(activity) = crate::db::get_activity_by_id(result.id).await {
+            let time_str = Local.timestamp_opt(activity.timestamp, 0)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string();
+
+            // 长文本只带入与问题最相关的块
+            let content = result
+                .chunk
+                .as_ref()
+                .map(|chunk| chunk.text.as_str())
+                .or(activity.ocr_text.as_deref())
+                .map(str::trim)
+                .filter(|text| !text.is_empty());
+            let entry = match content {
+                Some(text) => format!(
                     "[{}] 应用: {} | 窗口: {}\n内容: {}\n\n",
-                    time_str,
-                    activity.app_name,
-                    activity.window_title,
-                    ocr_text.trim()
-                ));
-            } else {
-                 context_text.push_str(&format!(
+                    time_str, activity.app_name, activity.window_title, text
+                ),
+                None => format!(
                     "[{}] 应用: {} | 窗口: {}\n\n",
-                    time_str,
-                    activity.app_name,
-                    activity.window_title
-                ));
-            }
-        } else {
-             context_text.push_str(&format!(
-                "[{}] 应用: {} | 窗口: {}\n\n",
-                time_str,
-                activity.app_name,
-                activity.window_title
-            ));
+                    time_str, activity.app_name, activity.window_title
+                ),
+            };
+            sources.push(activity.id);
+            context_text.push_str(&citations::numbered_entry(sources.len(), &entry));
         }
-        context_count += 1;
     }
 
-    Ok((context_text, context_count))
+    Ok((context_text, sources))
 }
 
-pub async fn chat(query: &str, _context: Vec<i64>) -> Result<String> {
-    // 1. 解析意图
-    let intent = parse_query_intent(query).await.unwrap_or_else(|_| fallback_filter_params(query));
-    
-    // 2. 获取上下文
-    let (mut context_text, mut context_count) = if intent.date_range.is_some() {
-        match build_context_from_range(query, &intent).await {
-             Ok((text, count)) if count > 0 => (text, count),
-             _ => (String::new(), 0),
-        }
-    } else {
-        (String::new(), 0)
+/// 读取会话历史：更新滚动摘要，并把追问改写成独立的检索查询（失败时按单轮提问处理）
+async fn load_conversation(provider: &dyn LlmProvider, session_id: Option<i64>, query: &str) -> Conversation {
+    let Some(session_id) = session_id else {
+        return Conversation::single(query);
     };
 
-    // 如果还没有上下文（意图解析未返回时间范围，或者时间范围搜索为空），则使用混合检索
-    if context_count == 0 {
-         let searcher = HybridSearch::new();
-         // HybridSearch from core requires explicit embedding
-         let embedding = crate::vector_db::generate_embedding(query).await?;
-         let results = searcher.search_with_embedding(query, embedding, 5).await?;
-        
-         for result in results {
-            if let Ok(activity) = crate::db::get_activity_by_id(result.id).await {
-                // 简单起见，这里复用 formatting 逻辑
-                let time_str = Local.timestamp_opt(activity.timestamp, 0)
-                    .unwrap()
-                    .format("%Y-%m-%d %H:%M:%S")
-                    .to_string();
-
-                let mut has_content = false;
-                if let Some(ref ocr_text) = activity.ocr_text {
-                    if !ocr_text.trim().is_empty() {
-                        context_text.push_str(&format!(
-                            "[{}] 应用: {} | 窗口: {}\n内容: {}\n\n",
-                            time_str,
-                            activity.app_name,
-                            activity.window_title,
-                            ocr_text.trim()
-                        ));
-                        has_content = true;
-                    }
-                }
-                if !has_content {
-                    context_text.push_str(&format!(
-                        "[{}] 应用: {} | 窗口: {}\n\n",
-                        time_str,
-                        activity.app_name, activity.window_title
-                    ));
-                }
-                context_count += 1;
+    match conversation::prepare_conversation(provider, session_id, query, &ConversationConfig::default()).await {
+        Ok(conversation) => {
+            if conversation.standalone_query != query {
+                tracing::info!("追问改写为独立查询: {}", conversation.standalone_query);
             }
+            conversation
+        }
+        Err(e) => {
+            tracing::warn!("读取会话 {} 历史失败，按单轮提问处理: {}", session_id, e);
+            Conversation::single(query)
         }
     }
-    
+}
+
+/// 带引用的回答
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatAnswer {
+    pub content: String,
+    /// 回答中引用的活动 ID，随助手消息保存到 `chat_messages.context_ids`
+    pub context_ids: Vec<i64>,
+}
+
+impl ChatAnswer {
+    /// 校验模型回答中的引用：有效编号映射为活动 ID，无效编号从正文中删除
+    fn grounded(text: &str, sources: &[i64]) -> Self {
+        Self {
+            content: citations::strip_invalid_citations(text, sources.len()),
+            context_ids: citations::resolve_citations(text, sources).activity_ids,
+        }
+    }
+}
+
+/// 编号上下文 + 引用要求的补全请求（没有上下文时不要求引用）
+fn grounded_request(conversation: &Conversation, query: &str, context_text: &str, sources: &[i64]) -> CompletionRequest {
+    let request = conversation.completion_request(query, context_text, None);
+    if sources.is_empty() {
+        request
+    } else {
+        citations::with_citation_instructions(request)
+    }
+}
+
+pub async fn chat(query: &str, session_id: Option<i64>) -> Result<ChatAnswer> {
+    let config = app_config_or_default().await;
+    let provider = match llm_provider(&config, LlmPurpose::Chat).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            return Ok(ChatAnswer {
+                content: provider_unavailable_message(&config, &e),
+                ..Default::default()
+            })
+        }
+    };
+
+    // 1. 结合会话历史得到独立的检索查询
+    let conversation = load_conversation(&provider.with_purpose(LlmPurpose::Conversation), session_id, query).await;
+    let retrieval_query = conversation.standalone_query.as_str();
+
+    // 2. 解析意图
+    let intent = parse_intent(&provider.with_purpose(LlmPurpose::Intent), &config, retrieval_query).await;
+
+    // 3. 获取上下文
+    let budget = ContextBudget::for_model(&config.chat_model);
+    let (context_text, sources) = build_chat_context(retrieval_query, &intent, &budget).await?;
+
     tracing::info!(
         "Chat Context: {} items, {} chars (Intent: DateRange={:?})",
-        context_count,
+        sources.len(),
         context_text.len(),
         intent.date_range
     );
 
-    // 3. 尝试调用 LLM API
-    let config = crate::app_config::get_config().await.unwrap_or_else(|_| {
-        let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
-        cfg.ocr_enabled = true;
-        cfg
-    });
-
-    let model_id = &config.chat_model;
-
-    // 根据模型名称自动判断提供商：如果以 "claude-" 开头则是 Anthropic，否则默认 OpenAI
-    let is_anthropic = model_id.starts_with("claude-");
-
-    if is_anthropic {
-        // 尝试使用 Anthropic API
-        match crate::secure_storage::get_api_key("anthropic").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.anthropic_base_url.clone(),
-                    "https://api.anthropic.com",
-                );
-
-                match chat_with_anthropic(query, &context_text, model_id, &provider_config, None).await {
-                    Ok(answer) => {
-                        tracing::info!("使用 Anthropic API 生成回答，模型: {}", model_id);
-                        return Ok(answer);
-                    }
-                    Err(e) => {
-                        tracing::error!(
-                            "Anthropic API 调用失败: {}",
-                            crate::redact::redact_secrets(&e.to_string())
-                        );
-                        return Ok(format!(
-                            "⚠️ Anthropic API 调用失败\n\n错误信息：{}\n\n请检查：\n1. API Key 是否有效\n2. 网络连接是否正常\n3. 模型名称是否正确（当前: {}）",
-                            crate::redact::redact_secrets(&e.to_string()),
-                            model_id
-                        ));
-                    }
-                }
-            }
-            Ok(None) => {
-                tracing::warn!("未配置 Anthropic API Key");
-                return Ok(format!(
-                    "⚠️ 未配置 Anthropic API Key\n\n当前选择的模型是 {}，需要 Anthropic API Key。\n\n请在「设置」中配置 Anthropic API Key 后再试。",
-                    model_id
-                ));
-            }
-            Err(e) => {
-                tracing::error!("获取 Anthropic API Key 失败: {}", e);
-                return Ok(format!(
-                    "⚠️ 获取 API Key 失败：{}",
-                    crate::redact::redact_secrets(&e.to_string())
-                ));
-            }
+    // 4. 调用 LLM（带上最近的对话与摘要），并校验回答中的引用
+    let request = grounded_request(&conversation, query, &context_text, &sources);
+    match provider.complete(&request).await {
+        Ok(completion) => {
+            tracing::info!("使用 {} 生成回答，模型: {}", provider.name(), provider.model());
+            Ok(ChatAnswer::grounded(&completion.text, &sources))
         }
-    } else {
-        // 默认使用 OpenAI API
-        match crate::secure_storage::get_api_key("openai").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.openai_base_url.clone(),
-                    "https://api.openai.com/v1",
-                );
-
-                match chat_with_openai(query, &context_text, model_id, &provider_config, None).await {
-                    Ok(answer) => {
-                        tracing::info!("使用 OpenAI API 生成回答，模型: {}", model_id);
-                        return Ok(answer);
-                    }
-                    Err(e) => {
-                        tracing::error!(
-                            "OpenAI API 调用失败: {}",
-                            crate::redact::redact_secrets(&e.to_string())
-                        );
-                        return Ok(format!(
-                            "⚠️ OpenAI API 调用失败\n\n错误信息：{}\n\n请检查：\n1. API Key 是否有效\n2. 网络连接是否正常\n3. 模型名称是否正确（当前: {}）\n4. 如果使用自定义 Base URL，请确认地址正确",
-                            crate::redact::redact_secrets(&e.to_string()),
-                            model_id
-                        ));
-                    }
-                }
-            }
-            Ok(None) => {
-                tracing::warn!("未配置 OpenAI API Key");
-                return Ok(format!(
-                    "⚠️ 未配置 OpenAI API Key\n\n当前选择的模型是 {}，需要 OpenAI API Key。\n\n请在「设置」中配置 OpenAI API Key 后再试。",
-                    model_id
-                ));
-            }
-            Err(e) => {
-                tracing::error!("获取 OpenAI API Key 失败: {}", e);
-                return Ok(format!(
-                    "⚠️ 获取 API Key 失败：{}",
-                    crate::redact::redact_secrets(&e.to_string())
-                ));
-            }
+        Err(e) => {
+            let message = crate::redact::redact_secrets(&e.to_string());
+            tracing::error!("{} API 调用失败: {}", provider.name(), message);
+            Ok(ChatAnswer {
+                content: format!(
+                "⚠️ {} API 调用失败\n\n错误信息：{}\n\n请检查：\n1. API Key 是否有效\n2. 网络连接是否正常\n3. 模型名称是否正确（当前: {}）\n4. 如果使用自定义 Base URL，请确认地址正确",
+                    provider.name(),
+                    message,
+                    provider.model()
+                ),
+                ..Default::default()
+            })
         }
     }
 }
 
+/// 无法创建 provider（未配置 API Key、未知的提供商等）时给用户的提示
+fn provider_unavailable_message(config: &crate::commands::AppConfig, error: &anyhow::Error) -> String {
+    let message = crate::redact::redact_secrets(&error.to_string());
+    tracing::warn!("LLM provider 不可用: {}", message);
+    format!(
+        "⚠️ {}\n\n当前选择的模型是 {}。\n\n请在「设置」中检查模型与 API Key 配置后再试。",
+        message, config.chat_model
+    )
+}
 
-pub async fn chat_stream<F>(query: &str, _context: Vec<i64>, on_chunk: F) -> Result<()>
+/// 流式回答；返回去掉无效引用后的完整回答及其引用的活动 ID
+///
+/// 已发出的片段无法修改，调用方应保存并展示返回的 `content` 而不是拼接的片段。
+/// `cancel` 被取消（界面点击停止）后立即断开模型请求，按已生成的部分返回。
+pub async fn chat_stream<F>(
+    query: &str,
+    session_id: Option<i64>,
+    on_chunk: F,
+    cancel: &CancellationToken,
+) -> Result<ChatAnswer>
 where
     F: Fn(String) + Send + Sync + 'static,
 {
-    // 1. 解析意图 (Time Awareness)
-    let intent = parse_query_intent(query).await.unwrap_or_else(|_| fallback_filter_params(query));
-    
-    // 2. 获取上下文
-    let (mut context_text, mut context_count) = if intent.date_range.is_some() {
-        match build_context_from_range(query, &intent).await {
-             Ok((text, count)) if count > 0 => (text, count),
-             _ => (String::new(), 0),
+    let config = app_config_or_default().await;
+    let provider = match llm_provider(&config, LlmPurpose::Chat).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            let message = provider_unavailable_message(&config, &e);
+            on_chunk(message.clone());
+            return Ok(ChatAnswer {
+                content: message,
+                ..Default::default()
+            });
         }
-    } else {
-        (String::new(), 0)
     };
 
-    // 如果还没有上下文，回退到 HybridSearch
-    if context_count == 0 {
-         let searcher = HybridSearch::new();
-         // HybridSearch from core requires explicit embedding
-         let embedding = crate::vector_db::generate_embedding(query).await?;
-         let results = searcher.search_with_embedding(query, embedding, 5).await?;
-        
-         for result in results {
-            if let Ok(activity) = crate::db::get_activity_by_id(result.id).await {
-                // 简单起见，这里复用 formatting 逻辑
-                let time_str = Local.timestamp_opt(activity.timestamp, 0)
-                    .unwrap()
-                    .format("%Y-%m-%d %H:%M:%S")
-                    .to_string();
-
-                let mut has_content = false;
-                if let Some(ref ocr_text) = activity.ocr_text {
-                    if !ocr_text.trim().is_empty() {
-                        context_text.push_str(&format!(
-                            "[{}] 应用: {} | 窗口: {}\n内容: {}\n\n",
-                            time_str,
-                            activity.app_name,
-                            activity.window_title,
-                            ocr_text.trim()
-                        ));
-                        has_content = true;
-                    }
-                }
-                if !has_content {
-                    context_text.push_str(&format!(
-                        "[{}] 应用: {} | 窗口: {}\n\n",
-                        time_str,
-                        activity.app_name, activity.window_title
-                    ));
-                }
-                context_count += 1;
-            }
+    // 输出第一段之前也可能被停止（或被新的提问取代），每个阶段之后检查一次，不再继续调用模型
+    let stopped = |stage: &str| {
+        let stopped = cancel.is_cancelled();
+        if stopped {
+            tracing::info!("流式回答在{}后停止", stage);
         }
+        stopped
+    };
+
+    // 1. 结合会话历史得到独立的检索查询
+    let conversation = load_conversation(&provider.with_purpose(LlmPurpose::Conversation), session_id, query).await;
+    let retrieval_query = conversation.standalone_query.as_str();
+    if stopped("改写查询") {
+        return Ok(ChatAnswer::default());
+    }
+
+    // 2. 解析意图 (Time Awareness)
+    let intent = parse_intent(&provider.with_purpose(LlmPurpose::Intent), &config, retrieval_query).await;
+    if stopped("解析意图") {
+        return Ok(ChatAnswer::default());
+    }
+
+    // 3. 获取上下文
+    let budget = ContextBudget::for_model(&config.chat_model);
+    let (context_text, sources) = build_chat_context(retrieval_query, &intent, &budget).await?;
+    if stopped("检索上下文") {
+        return Ok(ChatAnswer::default());
     }
 
     tracing::info!(
         "Chat Stream Context: {} items, {} chars (Intent: DateRange={:?})",
-        context_count,
+        sources.len(),
         context_text.len(),
         intent.date_range
     );
 
-    // 3. 尝试调用 LLM API
-    let config = crate::app_config::get_config().await.unwrap_or_else(|_| {
-        let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
-        cfg.ocr_enabled = true;
-        cfg
-    });
-
-    let model_id = &config.chat_model;
-
-    // 根据模型名称自动判断提供商：如果以 "claude-" 开头则是 Anthropic，否则默认 OpenAI
-    let is_anthropic = model_id.starts_with("claude-");
-
-    if is_anthropic {
-        // 使用支持流式的 Anthropic 调用
-        match crate::secure_storage::get_api_key("anthropic").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.anthropic_base_url.clone(),
-                    "https://api.anthropic.com",
-                );
-
-                // 调用新实现的流式函数
-                crate::ai::provider::chat_with_anthropic_stream(
-                    query, 
-                    &context_text, 
-                    model_id, 
-                    &provider_config, 
-                    None, 
-                    on_chunk
-                ).await.map(|_| ())
-            }
-            Ok(None) => {
-                on_chunk(format!("⚠️ Missing Anthropic API Key for {}", model_id));
-                Ok(())
-            }
-            Err(e) => Err(anyhow::anyhow!("Get API Key failed: {}", e)),
-        }
-    } else {
-        // 默认使用 OpenAI API
-        match crate::secure_storage::get_api_key("openai").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.openai_base_url.clone(),
-                    "https://api.openai.com/v1",
-                );
-                
-                crate::ai::provider::chat_with_openai_stream(query, &context_text, model_id, &provider_config, None, on_chunk).await.map(|_| ())
-            }
-            Ok(None) => {
-                on_chunk(format!("⚠️ Missing OpenAI API Key for {}", model_id));
-                Ok(())
-            }
-            Err(e) => Err(anyhow::anyhow!("Get API Key failed: {}", e)),
-        }
+    // 4. 流式调用 LLM（带上最近的对话与摘要）；无效引用在返回的完整回答中删除
+    let request = grounded_request(&conversation, query, &context_text, &sources);
+    let completion = provider.stream(&request, &on_chunk, cancel).await?;
+    if cancel.is_cancelled() {
+        tracing::info!("流式回答已停止，保留已生成的 {} 字符", completion.text.chars().count());
     }
-}
-
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AiAnalysisResult {
-    pub tasks: Vec<TaskContext>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TaskContext {
-    pub title: String,
-    pub summary: String,
-    pub related_urls: Vec<String>,
-    pub related_files: Vec<String>,
-    pub related_apps: Vec<String>,
+    Ok(ChatAnswer::grounded(&completion.text, &sources))
 }
 
 pub async fn analyze_for_proposals(context_text: &str) -> Result<AiAnalysisResult> {
-    let config = crate::app_config::get_config().await.unwrap_or_else(|_| {
-        let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
-        cfg.ocr_enabled = true;
-        cfg
-    });
+    let config = app_config_or_default().await;
+    let provider = llm_provider(&config, LlmPurpose::Proposals).await?;
 
-    let model_id = &config.chat_model;
-    let is_anthropic = model_id.starts_with("claude-");
-    
-    // 诊断日志：显示实际读取到的配置
+    // 诊断日志：显示实际使用的提供商
     tracing::info!(
-        "analyze_for_proposals: model={}, openai_base_url={:?}, is_anthropic={}",
-        model_id,
-        config.openai_base_url,
-        is_anthropic
+        "analyze_for_proposals: provider={}, model={}, openai_base_url={:?}",
+        provider.name(),
+        provider.model(),
+        config.openai_base_url
     );
-    
+
     // 从外部配置加载系统提示词
     let system_prompt = get_analyze_proposals_prompt().await;
-
-    let response = if is_anthropic {
-        match crate::secure_storage::get_api_key("anthropic").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.anthropic_base_url.clone(),
-                    "https://api.anthropic.com",
-                );
-
-                chat_with_anthropic(
-                    "请分析活动记录并生成建议", 
-                    context_text, 
-                    model_id, 
-                    &provider_config, 
-                    Some(&system_prompt)
-                ).await?
-            }
-            Ok(None) => return Err(anyhow::anyhow!("未配置 Anthropic API Key")),
-            Err(e) => return Err(anyhow::anyhow!("获取 API Key 失败: {}", e)),
-        }
-    } else {
-        match crate::secure_storage::get_api_key("openai").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.openai_base_url.clone(),
-                    "https://api.openai.com/v1",
-                );
-
-                chat_with_openai(
-                    "请分析活动记录并生成建议", 
-                    context_text, 
-                    model_id, 
-                    &provider_config, 
-                    Some(&system_prompt)
-                ).await?
-            }
-            Ok(None) => return Err(anyhow::anyhow!("未配置 OpenAI API Key")),
-            Err(e) => return Err(anyhow::anyhow!("获取 API Key 失败: {}", e)),
-        }
-    };
-
-    // 尝试解析 JSON（处理可能存在的 markdown 标记）
-    let json_str = response.trim();
-    let json_str = if json_str.starts_with("```json") {
-        json_str.trim_start_matches("```json").trim_end_matches("```").trim()
-    } else if json_str.starts_with("```") {
-        json_str.trim_start_matches("```").trim_end_matches("```").trim()
-    } else {
-        json_str
-    };
-
-    let result: AiAnalysisResult = serde_json::from_str(json_str)
-        .map_err(|e| anyhow::anyhow!("JSON 解析失败: {} - 原文: {}", e, json_str))?;
-    
-    Ok(result)
+    let timeout = std::time::Duration::from_secs(60);
+    Ok(memflow_core::ai::analyze_for_proposals_with(&provider, context_text, &system_prompt, timeout).await?)
 }
 
-// FilterParams and strip_json_code_fence are imported from memflow_core::ai
-
-fn parse_filter_params_from_llm_response(response: &str) -> Result<FilterParams> {
-    let json_str = strip_json_code_fence(response);
-    let params: FilterParams = serde_json::from_str(json_str)
-        .map_err(|e| anyhow::anyhow!("JSON 解析失败: {} - 原文: {}", e, json_str))?;
-    Ok(params)
-}
-
-// fallback_filter_params is imported from memflow_core::ai
+// FilterParams, strip_json_code_fence and fallback_filter_params are imported from memflow_core::ai
 
 pub async fn parse_query_intent(query: &str) -> Result<FilterParams> {
-    let config = crate::app_config::get_config().await.unwrap_or_else(|_| {
-        let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
-        cfg.ocr_enabled = true;
-        cfg
-    });
-
+    let config = app_config_or_default().await;
     if !config.ai_enabled {
         return Ok(fallback_filter_params(query));
     }
 
-    let model_id = &config.chat_model;
-    let is_anthropic = model_id.starts_with("claude-");
-    let timeout_ms = config.intent_parse_timeout_ms.unwrap_or(20_000);
-    let llm_timeout = std::time::Duration::from_millis(timeout_ms);
-
-    // 从外部配置加载系统提示词
-    let system_prompt = get_intent_parser_prompt().await;
-
-    let response = if is_anthropic {
-        match crate::secure_storage::get_api_key("anthropic").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.anthropic_base_url.clone(),
-                    "https://api.anthropic.com",
-                );
-
-                match tokio::time::timeout(
-                    llm_timeout,
-                    chat_with_anthropic(query, "", model_id, &provider_config, Some(&system_prompt)),
-                )
-                .await
-                {
-                    Ok(Ok(v)) => Some(v),
-                    Ok(Err(e)) => {
-                        tracing::warn!(
-                            "parse_query_intent: Anthropic 调用失败，使用回退解析: {}",
-                            crate::redact::redact_secrets(&e.to_string())
-                        );
-                        None
-                    }
-                    Err(_) => {
-                        tracing::warn!(
-                            "parse_query_intent: Anthropic 调用超时({}ms) model={} base_url={}，使用回退解析",
-                            timeout_ms,
-                            model_id,
-                            provider_config.base_url
-                        );
-                        None
-                    }
-                }
-            }
-            Ok(None) => {
-                tracing::debug!("parse_query_intent: 未配置 Anthropic API Key，使用回退解析");
-                None
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "parse_query_intent: 获取 API Key 失败，使用回退解析: {}",
-                    crate::redact::redact_secrets(&e.to_string())
-                );
-                None
-            }
-        }
-    } else {
-        match crate::secure_storage::get_api_key("openai").await {
-            Ok(Some(api_key)) => {
-                let provider_config = ProviderConfig::new(
-                    api_key,
-                    config.openai_base_url.clone(),
-                    "https://api.openai.com/v1",
-                );
-
-                match tokio::time::timeout(
-                    llm_timeout,
-                    chat_with_openai(query, "", model_id, &provider_config, Some(&system_prompt)),
-                )
-                .await
-                {
-                    Ok(Ok(v)) => Some(v),
-                    Ok(Err(e)) => {
-                        tracing::warn!(
-                            "parse_query_intent: OpenAI 调用失败，使用回退解析: {}",
-                            crate::redact::redact_secrets(&e.to_string())
-                        );
-                        None
-                    }
-                    Err(_) => {
-                        tracing::warn!(
-                            "parse_query_intent: OpenAI 调用超时({}ms) model={} base_url={}，使用回退解析",
-                            timeout_ms,
-                            model_id,
-                            provider_config.base_url
-                        );
-                        None
-                    }
-                }
-            }
-            Ok(None) => {
-                tracing::debug!("parse_query_intent: 未配置 OpenAI API Key，使用回退解析");
-                None
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "parse_query_intent: 获取 API Key 失败，使用回退解析: {}",
-                    crate::redact::redact_secrets(&e.to_string())
-                );
-                None
-            }
-        }
-    };
-
-    let Some(response) = response else {
-        return Ok(fallback_filter_params(query));
-    };
-
-    match parse_filter_params_from_llm_response(&response) {
-        Ok(v) => Ok(v),
+    match llm_provider(&config, LlmPurpose::Intent).await {
+        Ok(provider) => Ok(parse_intent(&provider, &config, query).await),
         Err(e) => {
-            tracing::warn!(
-                "parse_query_intent: 解析失败，使用回退解析: {}",
+            tracing::debug!(
+                "parse_query_intent: LLM provider 不可用，使用回退解析: {}",
                 crate::redact::redact_secrets(&e.to_string())
             );
             Ok(fallback_filter_params(query))
@@ -697,9 +497,32 @@ pub async fn parse_query_intent(query: &str) -> Result<FilterParams> {
     }
 }
 
+/// 用指定 provider 解析意图（未启用 AI 时只做规则解析）
+async fn parse_intent(
+    provider: &dyn LlmProvider,
+    config: &crate::commands::AppConfig,
+    query: &str,
+) -> FilterParams {
+    if !config.ai_enabled {
+        return fallback_filter_params(query);
+    }
+
+    let timeout_ms = config.intent_parse_timeout_ms.unwrap_or(20_000);
+    // 从外部配置加载系统提示词
+    let system_prompt = get_intent_parser_prompt().await;
+    memflow_core::ai::parse_query_intent_with(
+        provider,
+        query,
+        &system_prompt,
+        std::time::Duration::from_millis(timeout_ms),
+    )
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use memflow_core::ai::parse_filter_params_from_response as parse_filter_params_from_llm_response;
 
     #[test]
     fn strips_code_fences_for_filter_params() {
@@ -726,7 +549,23 @@ mod tests {
     fn fallback_never_panics_and_returns_keywords() {
         let parsed = fallback_filter_params("Find pdf last week rust ocr");
         assert!(!parsed.keywords.is_empty());
-        assert_eq!(parsed.date_range.as_deref(), Some("last_week"));
+        assert_eq!(parsed.date_range.as_deref(), Some("last week"));
         assert_eq!(parsed.has_ocr, Some(true));
     }
+
+    #[test]
+    fn grounded_answer_drops_invalid_citations() {
+        let answer = ChatAnswer::grounded("你修改了 rag.rs [1]，还看了文档 [4]。", &[11, 12]);
+        assert_eq!(answer.content, "你修改了 rag.rs [1]，还看了文档。");
+        assert_eq!(answer.context_ids, vec![11]);
+    }
+
+    #[test]
+    fn resolves_legacy_and_natural_date_ranges() {
+        for range in ["last_week", "this_month", "last 3 days", "上周三下午"] {
+            let (from_ts, to_ts) = calculate_timestamps(range);
+            assert!(from_ts.unwrap() <= to_ts.unwrap(), "{}", range);
+        }
+        assert_eq!(calculate_timestamps("someday"), (None, None));
+    }
 }
@@ -34,18 +34,29 @@ pub async fn init_config(app_handle: AppHandle) -> Result<()> {
             ai_enabled: false,
             retention_days: 30,
             chat_model: "gpt-4o-mini".to_string(),
+            chat_provider: "auto".to_string(),
             embedding_model: "text-embedding-3-small".to_string(),
             embedding_base_url: None,
             embedding_use_shared_key: true,
+            embedding_provider: "local".to_string(),
+            local_embedding_model: memflow_core::ai::embedding::DEFAULT_LOCAL_MODEL.to_string(),
+            rerank_enabled: false,
+            reranker_model: memflow_core::ai::rag::DEFAULT_RERANKER_MODEL.to_string(),
             openai_base_url: None,
             anthropic_base_url: None,
+            local_llm_base_url: None,
             blocklist_enabled: false,
             blocklist_mode: "blocklist".to_string(),
             privacy_mode_enabled: false,
             privacy_mode_until: None,
             intent_parse_timeout_ms: Some(20_000),
+            llm_cache_ttl_secs: 0,
             enable_focus_analytics: true,
             enable_proactive_assistant: false,
+            enable_activity_summaries: false,
+            summary_backfill_days: 7,
+            enable_digests: false,
+            notes_dir: None,
             ocr_redaction_enabled: true,
             ocr_redaction_level: "basic".to_string(),
             ocr_preprocess_enabled: true,
@@ -3,7 +3,8 @@ use crate::desktop_context::TauriContext;
 use std::sync::Arc;
 use crate::ai;
 use crate::ai::provider::{
-    chat_with_anthropic, chat_with_openai, embedding_with_openai, ProviderConfig,
+    create_llm_provider, embedding_with_openai, CancellationToken, CompletionRequest, LlmConfig, LlmProvider,
+    LlmProviderKind, ProviderConfig,
 };
 use crate::app_config;
 use crate::chat;
@@ -11,6 +12,7 @@ use crate::db;
 use crate::graph;
 use crate::performance;
 use crate::recorder;
+use memflow_core::ai::usage::{LlmPurpose, MeteredLlmProvider};
 use serde::{Deserialize, Serialize};
 use tauri::Manager;
 
@@ -32,6 +34,9 @@ pub struct AppConfig {
     pub retention_days: u32,
     #[serde(default = "default_chat_model", alias = "chat_model")]
     pub chat_model: String,
+    /// 对话模型的提供商：auto（按模型名推断）| openai | anthropic | ollama | local（OpenAI 兼容本地服务）| mock（仅测试）
+    #[serde(default = "default_chat_provider", alias = "chat_provider")]
+    pub chat_provider: String,
     #[serde(default = "default_embedding_model", alias = "embedding_model")]
     pub embedding_model: String,
     #[serde(default, alias = "embedding_base_url")]
@@ -41,11 +46,29 @@ pub struct AppConfig {
         alias = "embedding_use_shared_key"
     )]
     pub embedding_use_shared_key: bool,
+    /// 向量生成方式：local（进程内 fastembed 模型）| openai（OpenAI 兼容接口）| stub（仅测试）
+    #[serde(default = "default_embedding_provider", alias = "embedding_provider")]
+    pub embedding_provider: String,
+    /// embedding_provider 为 local 时使用的模型
+    #[serde(
+        default = "default_local_embedding_model",
+        alias = "local_embedding_model"
+    )]
+    pub local_embedding_model: String,
+    /// 是否用本地交叉编码器对混合检索结果重排（较慢，低配机器可关闭）
+    #[serde(default, alias = "rerank_enabled")]
+    pub rerank_enabled: bool,
+    /// 重排模型（`stub` 为仅测试用的确定性实现）
+    #[serde(default = "default_reranker_model", alias = "reranker_model")]
+    pub reranker_model: String,
     // API 配置
     #[serde(default, alias = "openai_base_url")]
     pub openai_base_url: Option<String>,
     #[serde(default, alias = "anthropic_base_url")]
     pub anthropic_base_url: Option<String>,
+    /// chat_provider 为 ollama / local 时的服务地址（为空时使用默认端口）
+    #[serde(default, alias = "local_llm_base_url")]
+    pub local_llm_base_url: Option<String>,
     #[serde(default, alias = "blocklist_enabled")]
     pub blocklist_enabled: bool,
     #[serde(default = "default_blocklist_mode", alias = "blocklist_mode")]
@@ -56,6 +79,9 @@ pub struct AppConfig {
     pub privacy_mode_until: Option<i64>,
     #[serde(default, alias = "intent_parse_timeout_ms")]
     pub intent_parse_timeout_ms: Option<u64>,
+    /// LLM 响应缓存的有效期（秒），0 表示不缓存；问答回答始终实时生成
+    #[serde(default, alias = "llm_cache_ttl_secs")]
+    pub llm_cache_ttl_secs: u64,
     #[serde(
         default = "default_enable_focus_analytics",
         alias = "enable_focus_analytics"
@@ -66,6 +92,21 @@ pub struct AppConfig {
         alias = "enable_proactive_assistant"
     )]
     pub enable_proactive_assistant: bool,
+    /// 后台生成小时/日/周活动摘要（会定期调用所选模型）
+    #[serde(default, alias = "enable_activity_summaries")]
+    pub enable_activity_summaries: bool,
+    /// 活动摘要最多回溯的天数（首次开启时不为更早的历史调用模型）
+    #[serde(
+        default = "default_summary_backfill_days",
+        alias = "summary_backfill_days"
+    )]
+    pub summary_backfill_days: u32,
+    /// 每天 / 每周自动生成日报、周报
+    #[serde(default, alias = "enable_digests")]
+    pub enable_digests: bool,
+    /// 笔记目录：日报、周报另存为其中的 Markdown 文件（为空时只保存在应用内）
+    #[serde(default, alias = "notes_dir")]
+    pub notes_dir: Option<String>,
     #[serde(
         default = "default_ocr_redaction_enabled",
         alias = "ocr_redaction_enabled"
@@ -133,6 +174,10 @@ fn default_chat_model() -> String {
     "gpt-4o-mini".to_string()
 }
 
+fn default_chat_provider() -> String {
+    "auto".to_string()
+}
+
 fn default_embedding_model() -> String {
     "text-embedding-3-small".to_string()
 }
@@ -141,6 +186,18 @@ fn default_embedding_use_shared_key() -> bool {
     true
 }
 
+fn default_embedding_provider() -> String {
+    "local".to_string()
+}
+
+fn default_local_embedding_model() -> String {
+    memflow_core::ai::embedding::DEFAULT_LOCAL_MODEL.to_string()
+}
+
+fn default_reranker_model() -> String {
+    memflow_core::ai::rag::DEFAULT_RERANKER_MODEL.to_string()
+}
+
 fn default_enable_focus_analytics() -> bool {
     true
 }
@@ -149,6 +206,10 @@ fn default_enable_proactive_assistant() -> bool {
     false
 }
 
+fn default_summary_backfill_days() -> u32 {
+    7
+}
+
 // Stats is imported from crate::db (re-exported from memflow_core)
 pub use crate::db::Stats;
 
@@ -194,26 +255,83 @@ pub async fn get_activities(limit: Option<i64>) -> Result<Vec<ActivityLog>, Stri
     }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchActivitiesParams {
+    pub query: Option<String>,
+    pub app_name: Option<String>,
+    pub apps: Option<Vec<String>>,
+    pub exclude_apps: Option<Vec<String>>,
+    pub window_title: Option<String>,
+    pub app_path: Option<String>,
+    pub ids: Option<Vec<i64>>,
+    pub from_ts: Option<i64>,
+    pub to_ts: Option<i64>,
+    pub has_ocr: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub order_by: Option<String>,
+    pub cursor: Option<db::ActivityCursor>,
+    pub highlight: Option<bool>,
+}
+
 #[tauri::command]
-pub async fn search_activities(
-    query: Option<String>,
-    app_name: Option<String>,
-    from_ts: Option<i64>,
-    to_ts: Option<i64>,
-    has_ocr: Option<bool>,
-    limit: Option<i64>,
-    offset: Option<i64>,
-    order_by: Option<String>,
-) -> Result<serde_json::Value, String> {
-    let (items, total) = db::search_activities(
-        query, app_name, from_ts, to_ts, has_ocr, limit, offset, order_by,
-    )
-    .await
-    .map_err(|e| e.to_string())?;
+pub async fn search_activities(params: SearchActivitiesParams) -> Result<serde_json::Value, String> {
+    let SearchActivitiesParams {
+        query,
+        app_name,
+        apps,
+        exclude_apps,
+        window_title,
+        app_path,
+        ids,
+        from_ts,
+        to_ts,
+        has_ocr,
+        limit,
+        offset,
+        order_by,
+        cursor,
+        highlight,
+    } = params;
+
+    // 搜索框输入按查询语法解析（app:、title:、after: 等），显式参数优先
+    let mut search = match query.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        Some(q) => memflow_core::search_query::parse_search_query(q).map_err(|e| e.to_string())?,
+        None => db::ActivityQuery::new(),
+    };
+    search.apps.extend(app_name.into_iter().chain(apps.unwrap_or_default()));
+    search.exclude_apps.extend(exclude_apps.unwrap_or_default());
+    search.window_title = window_title;
+    search.app_path = app_path;
+    search.ids = ids;
+    search.from_ts = from_ts.or(search.from_ts);
+    search.to_ts = to_ts.or(search.to_ts);
+    search.has_ocr = has_ocr.or(search.has_ocr);
+    search.limit = limit;
+    search.offset = offset;
+    search.order_by = order_by;
+    search.cursor = cursor;
+    search.highlight = highlight
+        .unwrap_or(false)
+        .then(memflow_core::highlight::HighlightOptions::default);
+
+    let (items, total) = db::search_activity_hits(&search)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // 满页时返回下一页游标，前端可据此做 keyset 分页
+    let next_cursor = match (limit, items.last()) {
+        (Some(l), Some(last)) if items.len() as i64 >= l => {
+            Some(db::ActivityCursor::from(&last.activity))
+        }
+        _ => None,
+    };
 
     Ok(serde_json::json!({
         "items": items,
-        "total": total
+        "total": total,
+        "nextCursor": next_cursor
     }))
 }
 
@@ -229,6 +347,82 @@ pub async fn get_ocr_queue_stats() -> Result<db::OcrQueueStats, String> {
     db::get_ocr_queue_stats().await.map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn get_embedding_queue_stats() -> Result<memflow_core::embedding_queue::EmbeddingQueueStats, String> {
+    memflow_core::embedding_queue::get_embedding_queue_stats()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 最近 `days` 天（默认 30 天）的 LLM 调用用量，供设置页展示
+#[tauri::command]
+pub async fn get_llm_usage(days: Option<i64>) -> Result<memflow_core::ai::LlmUsageSummary, String> {
+    let pool = db::get_pool().await.map_err(|e| e.to_string())?;
+    let since_ts = chrono::Utc::now().timestamp() - days.unwrap_or(30).max(1) * 86_400;
+    memflow_core::ai::usage::llm_usage_summary(&pool, since_ts)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn parse_digest_period(period: &str) -> Result<memflow_core::digest::DigestPeriod, String> {
+    memflow_core::digest::DigestPeriod::parse(period).ok_or_else(|| format!("未知的报告时段: {}", period))
+}
+
+/// 最近的日报 / 周报（`period` 为空时两种都返回），按时段从新到旧
+#[tauri::command]
+pub async fn list_digests(
+    period: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<memflow_core::digest::Digest>, String> {
+    let period = period.as_deref().map(parse_digest_period).transpose()?;
+    memflow_core::digest::list_digests(period, limit.unwrap_or(30))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 生成包含 `date`（YYYY-MM-DD，默认为上一个已结束的时段）的日报 / 周报；已生成过时直接返回
+///
+/// 该时段没有任何活动记录时返回 null。
+#[tauri::command]
+pub async fn generate_digest(
+    period: String,
+    date: Option<String>,
+) -> Result<Option<memflow_core::digest::Digest>, String> {
+    let period = parse_digest_period(&period)?;
+    let (start, _) = match date.as_deref() {
+        Some(date) => {
+            let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("日期格式应为 YYYY-MM-DD: {}", date))?;
+            period
+                .period_of_date(date)
+                .ok_or_else(|| format!("无效的日期: {}", date))?
+        }
+        None => period.previous(chrono::Local::now().timestamp()),
+    };
+    crate::digest::generate(period, start)
+        .await
+        .map_err(|e| crate::redact::redact_secrets(&e.to_string()))
+}
+
+/// 在后台为历史活动补生成向量，进度通过 `embedding-backfill-progress` 事件推送
+#[tauri::command]
+pub async fn backfill_embeddings(app_handle: tauri::AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = crate::embedding_worker::backfill(app_handle).await {
+            tracing::error!("向量回填失败: {}", e);
+        }
+    });
+    Ok(())
+}
+
+/// 从数据库重建向量近似检索索引，返回索引中的向量总数
+#[tauri::command]
+pub async fn rebuild_vector_index() -> Result<usize, String> {
+    memflow_core::vector_index::rebuild_all()
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_blocklist() -> Result<Vec<String>, String> {
     db::get_blocklist().await.map_err(|e| e.to_string())
@@ -380,28 +574,86 @@ pub async fn trigger_gc() -> Result<(), String> {
 }
 
 #[tauri::command]
-pub async fn ai_chat(query: String) -> Result<String, String> {
-    ai::chat(&query, vec![]).await.map_err(|e| e.to_string())
+pub async fn ai_chat(query: String, session_id: Option<i64>) -> Result<ai::ChatAnswer, String> {
+    ai::chat(&query, session_id).await.map_err(|e| e.to_string())
+}
+
+/// 正在进行的流式回答（界面同一时间只有一个），供停止按钮取消
+#[derive(Default)]
+struct ActiveChatStream {
+    next_id: u64,
+    current: Option<(u64, CancellationToken)>,
+}
+
+impl ActiveChatStream {
+    /// 登记新的回答并取消上一个，返回本次回答的编号与取消令牌
+    fn start(&mut self) -> (u64, CancellationToken) {
+        self.next_id += 1;
+        let cancel = CancellationToken::new();
+        if let Some((_, previous)) = self.current.replace((self.next_id, cancel.clone())) {
+            previous.cancel();
+        }
+        (self.next_id, cancel)
+    }
+
+    /// 回答结束时只清除自己的登记，不影响之后开始的回答
+    fn finish(&mut self, id: u64) {
+        if self.current.as_ref().is_some_and(|(current, _)| *current == id) {
+            self.current = None;
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some((_, cancel)) = self.current.take() {
+            cancel.cancel();
+        }
+    }
 }
 
+static ACTIVE_CHAT_STREAM: once_cell::sync::Lazy<std::sync::Mutex<ActiveChatStream>> =
+    once_cell::sync::Lazy::new(Default::default);
+
 #[tauri::command]
-pub async fn ai_chat_stream(query: String, app_handle: tauri::AppHandle) -> Result<(), String> {
+pub async fn ai_chat_stream(
+    query: String,
+    session_id: Option<i64>,
+    app_handle: tauri::AppHandle,
+) -> Result<ai::ChatAnswer, String> {
     use tauri::Emitter;
-    
+
+    let (stream_id, cancel) = ACTIVE_CHAT_STREAM.lock().unwrap().start();
+
     let handle = app_handle.clone();
-    let res = ai::chat_stream(&query, vec![], move |chunk| {
-        if let Err(e) = handle.emit("ai-chat-chunk", chunk) {
-            tracing::error!("Failed to emit ai-chat-chunk: {}", e);
-        }
-    })
+    let res = ai::chat_stream(
+        &query,
+        session_id,
+        move |chunk| {
+            if let Err(e) = handle.emit("ai-chat-chunk", chunk) {
+                tracing::error!("Failed to emit ai-chat-chunk: {}", e);
+            }
+        },
+        &cancel,
+    )
     .await;
+    ACTIVE_CHAT_STREAM.lock().unwrap().finish(stream_id);
 
-    if let Err(e) = res {
-        let _ = app_handle.emit("ai-chat-chunk", format!("Error: {}", e)); // Emit error as chunk or separate event? Plan says "handle error" implicitly. Sticking to simple error reporting.
-        return Err(e.to_string());
-    }
+    let answer = match res {
+        Ok(answer) => answer,
+        Err(e) => {
+            let _ = app_handle.emit("ai-chat-chunk", format!("Error: {}", e));
+            return Err(e.to_string());
+        }
+    };
 
     let _ = app_handle.emit("ai-chat-done", ());
+    // 去掉无效引用后的回答及引用的活动 ID，前端以此替换流式拼接的文本并保存
+    Ok(answer)
+}
+
+/// 停止正在进行的流式回答；已生成的部分照常返回
+#[tauri::command]
+pub async fn ai_chat_stop() -> Result<(), String> {
+    ACTIVE_CHAT_STREAM.lock().unwrap().stop();
     Ok(())
 }
 
@@ -410,6 +662,14 @@ pub async fn parse_query_intent(query: String) -> Result<ai::FilterParams, Strin
     ai::parse_query_intent(&query).await.map_err(|e| e.to_string())
 }
 
+/// 把意图中的时间表达（如 "last 3 days"、"上周三下午"）解析为本地时区的时间戳范围
+#[tauri::command]
+pub async fn resolve_time_range(
+    expression: String,
+) -> Result<Option<memflow_core::time_range::TimeRange>, String> {
+    Ok(memflow_core::time_range::parse_time_range(&expression))
+}
+
 
 #[tauri::command]
 pub async fn get_activity_heatmap_stats(year: Option<i32>) -> Result<Vec<db::HeatmapData>, String> {
@@ -471,7 +731,7 @@ pub async fn open_external_url(url: String) -> Result<(), String> {
 
 #[cfg(test)]
 mod tests {
-    use super::AppConfig;
+    use super::{ActiveChatStream, AppConfig};
 
     #[test]
     fn app_config_defaults_work() {
@@ -482,16 +742,27 @@ mod tests {
         assert_eq!(cfg.ai_enabled, false);
         assert_eq!(cfg.retention_days, 30);
         assert_eq!(cfg.chat_model, "gpt-4o-mini");
+        assert_eq!(cfg.chat_provider, "auto");
         assert_eq!(cfg.embedding_model, "text-embedding-3-small");
         assert_eq!(cfg.embedding_base_url, None);
         assert_eq!(cfg.embedding_use_shared_key, true);
+        assert_eq!(cfg.embedding_provider, "local");
+        assert_eq!(cfg.local_embedding_model, "BAAI/bge-small-en-v1.5");
+        assert_eq!(cfg.rerank_enabled, false);
+        assert_eq!(cfg.reranker_model, "BAAI/bge-reranker-base");
         assert_eq!(cfg.openai_base_url, None);
         assert_eq!(cfg.anthropic_base_url, None);
+        assert_eq!(cfg.local_llm_base_url, None);
         assert_eq!(cfg.enable_focus_analytics, true); // 修正：默认值应为 true
         assert_eq!(cfg.enable_proactive_assistant, false);
+        assert_eq!(cfg.enable_activity_summaries, false);
+        assert_eq!(cfg.summary_backfill_days, 7);
+        assert_eq!(cfg.enable_digests, false);
+        assert_eq!(cfg.notes_dir, None);
         assert_eq!(cfg.blocklist_enabled, false);
         assert_eq!(cfg.blocklist_mode, "blocklist");
         assert_eq!(cfg.privacy_mode_enabled, false);
+        assert_eq!(cfg.llm_cache_ttl_secs, 0);
         assert_eq!(cfg.ocr_redaction_enabled, true);
         assert_eq!(cfg.ocr_redaction_level, "basic");
         assert_eq!(cfg.ocr_preprocess_enabled, true);
@@ -545,6 +816,7 @@ mod tests {
         assert_eq!(cfg.ai_enabled, true);
         assert_eq!(cfg.retention_days, 7);
         assert_eq!(cfg.chat_model, "gpt-4o-mini");
+        assert_eq!(cfg.chat_provider, "auto");
         assert_eq!(cfg.embedding_model, "text-embedding-3-small");
         assert_eq!(
             cfg.embedding_base_url.as_deref(),
@@ -564,6 +836,25 @@ mod tests {
         assert_eq!(cfg.ocr_preprocess_target_width, 1280);
         assert_eq!(cfg.ocr_preprocess_max_pixels, 3_000_000);
     }
+
+    #[test]
+    fn finished_chat_stream_does_not_clear_newer_one() {
+        let mut active = ActiveChatStream::default();
+        let (first, first_cancel) = active.start();
+        let (second, second_cancel) = active.start();
+        assert!(first_cancel.is_cancelled());
+
+        // 被取代的回答结束时不能清掉新回答的登记，否则停止按钮失效
+        active.finish(first);
+        active.stop();
+        assert!(second_cancel.is_cancelled());
+
+        let (third, third_cancel) = active.start();
+        active.finish(third);
+        active.stop();
+        assert!(!third_cancel.is_cancelled());
+        assert_ne!(second, third);
+    }
 }
 
 // ============================================
@@ -573,7 +864,7 @@ mod tests {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TestChatConnectionParams {
-    pub provider: String, // openai | anthropic | custom
+    pub provider: String, // openai | anthropic | ollama | local | custom
     pub model: String,
     pub api_key: Option<String>,  // 如果前端传了，优先用；否则走安全存储
     pub base_url: Option<String>, // 可选覆盖
@@ -581,38 +872,78 @@ pub struct TestChatConnectionParams {
 
 #[tauri::command]
 pub async fn test_chat_connection(params: TestChatConnectionParams) -> Result<(), String> {
-    let provider = params.provider;
-    let model = params.model;
+    // custom 为 OpenAI 兼容接口
+    let provider = match params.provider.as_str() {
+        "anthropic" => LlmProviderKind::Anthropic,
+        "ollama" => LlmProviderKind::Ollama,
+        "local" => LlmProviderKind::Local,
+        "mock" => LlmProviderKind::Mock,
+        _ => LlmProviderKind::OpenAi,
+    };
 
-    let api_key = if let Some(k) = params.api_key.filter(|s| !s.trim().is_empty()) {
-        k
-    } else {
-        let service = if provider == "anthropic" {
-            "anthropic"
-        } else {
-            "openai"
-        };
-        crate::secure_storage::get_api_key(service)
-            .await
-            .map_err(|e| crate::redact::redact_secrets(&e.to_string()))?
-            .ok_or_else(|| format!("未配置 {} API Key", service))?
+    let api_key = match (params.api_key.filter(|s| !s.trim().is_empty()), provider.key_service()) {
+        (Some(k), _) => Some(k),
+        (None, Some(service)) => Some(
+            crate::secure_storage::get_api_key(service)
+                .await
+                .map_err(|e| crate::redact::redact_secrets(&e.to_string()))?
+                .ok_or_else(|| format!("未配置 {} API Key", service))?,
+        ),
+        (None, None) => None,
     };
 
-    if provider == "anthropic" {
-        let cfg = ProviderConfig::new(api_key, params.base_url, "https://api.anthropic.com");
-        // 真实调用一次 messages
-        chat_with_anthropic("ping", "", &model, &cfg, None)
-            .await
-            .map(|_| ())
-            .map_err(|e| crate::redact::redact_secrets(&e.to_string()))
-    } else {
-        let cfg = ProviderConfig::new(api_key, params.base_url, "https://api.openai.com/v1");
-        // 真实调用一次 chat/completions
-        chat_with_openai("ping", "", &model, &cfg, None)
-            .await
-            .map(|_| ())
-            .map_err(|e| crate::redact::redact_secrets(&e.to_string()))
-    }
+    let config = LlmConfig {
+        provider,
+        model: params.model,
+        base_url: params.base_url,
+        api_key,
+    };
+    // 真实调用一次 chat/completions（或 messages）
+    let provider = create_llm_provider(&config).map_err(|e| e.to_string())?;
+    let request = CompletionRequest::from_query("ping", "", None);
+    // 调用记录只是附带的统计，数据库不可用时照常测试连接
+    let result = match db::get_pool().await {
+        Ok(pool) => {
+            MeteredLlmProvider::new(provider, pool, LlmPurpose::ConnectionTest)
+                .complete(&request)
+                .await
+        }
+        Err(e) => {
+            tracing::debug!("数据库不可用，连接测试不记录调用: {}", e);
+            provider.complete(&request).await
+        }
+    };
+    result
+        .map(|_| ())
+        .map_err(|e| crate::redact::redact_secrets(&e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListLocalLlmModelsParams {
+    pub provider: String, // ollama | local
+    pub base_url: Option<String>,
+}
+
+/// 列出本地推理服务（Ollama / llama.cpp 等）上已有的模型，供设置页选择
+#[tauri::command]
+pub async fn list_local_llm_models(
+    params: ListLocalLlmModelsParams,
+) -> Result<Vec<memflow_core::ai::provider::ModelInfo>, String> {
+    let provider = match params.provider.as_str() {
+        "ollama" => LlmProviderKind::Ollama,
+        "local" => LlmProviderKind::Local,
+        other => return Err(format!("不支持列出模型的提供商: {}", other)),
+    };
+
+    let config = LlmConfig {
+        provider,
+        base_url: params.base_url,
+        ..Default::default()
+    };
+    memflow_core::ai::provider::list_models(&config)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -632,7 +963,26 @@ pub async fn test_embedding_connection(
     let provider = params.provider;
     let model = params.model;
 
-    // 当前实现仅支持 OpenAI 兼容 embeddings
+    // 本地模型：加载并生成一次向量（首次会下载模型文件）
+    if provider == "local" {
+        let config = memflow_core::ai::embedding::EmbeddingConfig {
+            model,
+            cache_dir: Some(crate::vector_db::local_model_cache_dir()),
+            ..Default::default()
+        };
+        let embedding = memflow_core::ai::embedding::shared_embedding_provider(&config)
+            .await
+            .map_err(|e| e.to_string())?
+            .embed_query("ping")
+            .await
+            .map_err(|e| e.to_string())?;
+        if embedding.vector.is_empty() {
+            return Err("本地向量模型返回空向量".to_string());
+        }
+        return Ok(());
+    }
+
+    // 其余走 OpenAI 兼容 embeddings
     let api_key = if let Some(k) = params.api_key.filter(|s| !s.trim().is_empty()) {
         k
     } else {
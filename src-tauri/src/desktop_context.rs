@@ -51,7 +51,7 @@ impl RuntimeContext for TauriContext {
             // Call the existing AI analysis function from src-tauri
             let result = crate::ai::analyze_for_proposals(&context_text).await?;
             
-            // Convert from src-tauri's AiAnalysisResult to memflow_core's
+            // Convert from memflow_core::ai::AiAnalysisResult to the RuntimeContext type
             Ok(AiAnalysisResult {
                 tasks: result.tasks.into_iter().map(|t| TaskContext {
                     title: t.title,
@@ -0,0 +1,55 @@
+//! 日报 / 周报的生成与导出
+//!
+//! 报告内容由 memflow_core::digest 生成；这里负责读取配置、选择模型，并导出到笔记目录。
+
+use crate::ai::provider::LlmProvider;
+use crate::{ai, app_config};
+use crate::commands::AppConfig;
+use memflow_core::ai::usage::LlmPurpose;
+use memflow_core::digest::{self, Digest, DigestConfig, DigestPeriod};
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+fn digest_config(config: &AppConfig, screenshots_dir: Option<std::path::PathBuf>) -> DigestConfig {
+    DigestConfig {
+        recording_interval: Duration::from_millis(config.recording_interval.max(1000)),
+        screenshots_dir,
+        ..Default::default()
+    }
+}
+
+/// 生成包含 `ts` 的时段的报告（已生成过时直接返回），并在配置了笔记目录时导出
+///
+/// AI 未启用、处于隐私模式或模型不可用时不写任务总结，只列出已有的活动摘要。
+pub async fn generate(period: DigestPeriod, ts: i64) -> Result<Option<Digest>> {
+    let config = app_config::get_config().await?;
+    let screenshots_dir = memflow_core::db::get_screenshots_dir().await;
+    let options = digest_config(&config, screenshots_dir);
+
+    let provider = if config.ai_enabled && !config.privacy_mode_enabled {
+        match ai::llm_provider(&config, LlmPurpose::Digest).await {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                tracing::warn!("报告: LLM provider 不可用，不生成任务总结: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let provider_ref = provider.as_ref().map(|p| p as &dyn LlmProvider);
+
+    let Some(mut digest) = digest::generate_digest(provider_ref, period, ts, &options).await? else {
+        return Ok(None);
+    };
+    if let Some(dir) = config.notes_dir.as_deref().map(str::trim).filter(|dir| !dir.is_empty()) {
+        // 只导出一次，用户之后删改笔记文件不会被覆盖；降级的报告等重新生成后再导出
+        if digest.file_path.is_none() && !digest.degraded {
+            let path = digest::export_digest(&digest, Path::new(dir)).await?;
+            tracing::info!("{} 已导出到 {}", digest.title, path.display());
+            digest.file_path = Some(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(Some(digest))
+}
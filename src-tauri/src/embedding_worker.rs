@@ -0,0 +1,98 @@
+use crate::{db, desktop_context::TauriContext, vector_db};
+use memflow_core::{embedding_queue, vector_index};
+use once_cell::sync::Lazy;
+use tauri::AppHandle;
+use tokio::sync::Notify;
+use tokio::time::{interval, Duration, Instant};
+
+static EMBEDDING_NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// 每批送去生成向量的任务数
+const BATCH_SIZE: i64 = 16;
+/// 向量索引增量写入后的落盘间隔
+const INDEX_FLUSH_INTERVAL: Duration = Duration::from_secs(300);
+
+pub fn spawn_embedding_worker() {
+    tauri::async_runtime::spawn(async move {
+        // 等待数据库初始化完成
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        run_worker().await;
+    });
+}
+
+pub fn notify_new_task() {
+    EMBEDDING_NOTIFY.notify_one();
+}
+
+/// OCR/UIA 文本写入后调用：入队并唤醒 worker
+pub async fn enqueue(activity_id: i64) {
+    match embedding_queue::enqueue(activity_id).await {
+        Ok(()) => notify_new_task(),
+        Err(e) => tracing::warn!("向量任务入队失败: {}", e),
+    }
+}
+
+async fn run_worker() {
+    let mut ticker = interval(Duration::from_secs(10));
+    let mut last_flush = Instant::now();
+    tracing::info!("Embedding Worker started");
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = EMBEDDING_NOTIFY.notified() => {}
+        }
+
+        // 索引只在累计大量写入时自动落盘，这里定期把零散的增量写入磁盘
+        if last_flush.elapsed() >= INDEX_FLUSH_INTERVAL {
+            if let Err(e) = vector_index::flush().await {
+                tracing::warn!("向量索引落盘失败: {}", e);
+            }
+            last_flush = Instant::now();
+        }
+
+        let pool = match db::get_pool().await {
+            Ok(pool) => pool,
+            Err(_) => continue,
+        };
+
+        let provider = match vector_db::embedding_provider().await {
+            Ok(provider) => provider,
+            Err(e) => {
+                tracing::warn!("Embedding Worker failed to load provider: {}", e);
+                continue;
+            }
+        };
+
+        loop {
+            let result = match embedding_queue::process_batch_impl(&pool, BATCH_SIZE, provider.as_ref()).await {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::error!("Failed to process embedding tasks: {}", e);
+                    break;
+                }
+            };
+
+            if result.is_empty() {
+                break;
+            }
+            tracing::debug!(
+                "Embedding batch done: embedded={}, failed={}",
+                result.embedded,
+                result.failed
+            );
+            // 整批失败时（如接口限流）等下一轮再试，避免空转
+            if result.embedded == 0 {
+                break;
+            }
+        }
+    }
+}
+
+/// 为缺少当前模型向量的历史活动补生成向量，进度通过 `embedding-backfill-progress` 事件上报
+pub async fn backfill(app_handle: AppHandle) -> anyhow::Result<usize> {
+    let pool = db::get_pool().await?;
+    let ctx = TauriContext::new(app_handle);
+    let provider = vector_db::embedding_provider().await?;
+    embedding_queue::backfill_impl(&pool, &ctx, provider.as_ref(), BATCH_SIZE).await
+}
@@ -4,6 +4,8 @@ pub mod app_config;
 pub mod chat;
 pub mod commands;
 pub mod db;
+pub mod digest;
+pub mod embedding_worker;
 pub mod graph;
 pub mod ocr;
 pub mod performance;
@@ -41,6 +43,8 @@ pub fn run() {
             commands::update_config,
             commands::set_privacy_mode,
             commands::search_activities,
+            commands::parse_query_intent,
+            commands::resolve_time_range,
             commands::get_blocklist,
             commands::add_blocklist_item,
             commands::remove_blocklist_item,
@@ -57,7 +61,9 @@ pub fn run() {
             commands::trigger_gc,
             commands::ai_chat,
             commands::ai_chat_stream,
+            commands::ai_chat_stop,
             commands::test_chat_connection,
+            commands::list_local_llm_models,
             commands::test_embedding_connection,
             commands::save_api_key,
             commands::get_api_key,
@@ -82,6 +88,12 @@ pub fn run() {
             commands::run_retention_cleanup,
             commands::get_recording_stats,
             commands::get_ocr_queue_stats,
+            commands::rebuild_vector_index,
+            commands::get_embedding_queue_stats,
+            commands::get_llm_usage,
+            commands::list_digests,
+            commands::generate_digest,
+            commands::backfill_embeddings,
         ])
         .setup(|app| {
             let mut filter = tracing_subscriber::EnvFilter::try_from_default_env()
@@ -132,6 +144,9 @@ pub fn run() {
             ocr_worker::spawn_ocr_worker(app_handle.clone());
             tracing::info!("ocr_worker::spawn_ocr_worker returned.");
 
+            // 初始化后台向量生成 Worker
+            embedding_worker::spawn_embedding_worker();
+
             // 初始化配置和数据库
             tauri::async_runtime::spawn(async move {
                 if let Err(e) = app_config::init_config(app_handle.clone()).await {
@@ -170,16 +185,30 @@ pub fn run() {
                     tracing::info!("Database initialization completed successfully.");
                     // 启动自动清理调度器 (等待数据库初始化完成后)
                     scheduler::spawn_retention_scheduler();
+                    // 启动活动摘要调度器（未开启时每轮直接跳过）
+                    scheduler::spawn_summary_scheduler();
+                    // 启动日报 / 周报调度器（未开启时每轮直接跳过）
+                    scheduler::spawn_digest_scheduler();
+
+                    // 为历史数据补写中文分词索引（只处理尚未分词的记录）
+                    match db::reindex_segmented_fts(500).await {
+                        Ok(0) => {}
+                        Ok(n) => tracing::info!("中文分词索引补全完成，共处理 {} 条记录", n),
+                        Err(e) => tracing::warn!("中文分词索引补全失败: {}", e),
+                    }
                 }
             });
 
             Ok(())
         })
         .on_window_event(|_window, event| {
-            // 应用退出时停止 OCR 服务
+            // 应用退出时停止 OCR 服务，并把向量索引的未落盘修改写入磁盘
             if let tauri::WindowEvent::Destroyed = event {
                 let _ = recorder::stop();
                 ocr::service::stop_service();
+                if let Err(e) = tauri::async_runtime::block_on(memflow_core::vector_index::flush()) {
+                    tracing::warn!("退出时向量索引落盘失败: {}", e);
+                }
             }
         })
         .run(tauri::generate_context!())
@@ -209,6 +209,7 @@ async fn run_worker(app_handle: AppHandle) {
                             let db_ms = t_db.elapsed().as_millis();
 
                             let _ = db::update_ocr_queue_status(task.id, "done", None).await;
+                            crate::embedding_worker::enqueue(task.activity_id).await;
                             tracing::info!("OCR task {} completed (len: {})", task.id, text.len());
                             tracing::debug!(ocr_ms = ocr_ms, db_ms = db_ms, "ocr_worker timing");
 
@@ -1,7 +1,9 @@
-use crate::ai::provider::{chat_with_anthropic, chat_with_openai, ProviderConfig};
+use crate::ai::provider::{CompletionRequest, LlmProvider, ResponseSchema};
 use crate::ai::rag::HybridSearch;
-use crate::{app_config, db, secure_storage};
+use crate::{app_config, db};
 use crate::window_info::WindowInfo;
+use memflow_core::ai::structured::{complete_structured, StructuredOutputError};
+use memflow_core::ai::usage::LlmPurpose;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
@@ -218,7 +220,7 @@ async fn load_related_memories(
     query: &str,
     app_name: &str,
 ) -> anyhow::Result<(Vec<RelatedMemory>, Vec<crate::commands::ActivityLog>)> {
-    let searcher = HybridSearch::new();
+    let searcher = HybridSearch::new().with_reranker(crate::vector_db::reranker().await);
     // HybridSearch from core requires explicit embedding
     let embedding = crate::vector_db::generate_embedding(query).await?;
     let results = searcher.search_with_embedding(query, embedding, 5).await;
@@ -241,15 +243,8 @@ async fn load_related_memories(
             Ok((related, activities))
         }
         Err(_) => {
-            let (items, _) = db::search_activities(
-                None,
-                Some(app_name.to_string()),
-                None,
-                None,
-                None,
-                Some(5),
-                Some(0),
-                Some("time".to_string()),
+            let items = db::list_activities(
+                &db::ActivityQuery::new().app(app_name).limit(5).order_by("time"),
             )
             .await?;
             let related = items
@@ -280,21 +275,64 @@ async fn build_suggested_actions(
         return Vec::new();
     }
 
-    let model_id = &config.chat_model;
-    let is_anthropic = model_id.starts_with("claude-");
+    let provider = match crate::ai::llm_provider(&config, LlmPurpose::Suggestions).await {
+        Ok(provider) => provider,
+        Err(_) => return Vec::new(),
+    };
 
-    let api_key = if is_anthropic {
-        match secure_storage::get_api_key("anthropic").await {
-            Ok(Some(v)) => v,
-            _ => return Vec::new(),
+    match suggest_actions(&provider, ctx, related).await {
+        Ok(actions) => actions,
+        Err(e) if e.is_invalid_output() => {
+            tracing::warn!("proactive context: 模型返回的建议无法解析: {}", e);
+            Vec::new()
         }
-    } else {
-        match secure_storage::get_api_key("openai").await {
-            Ok(Some(v)) => v,
-            _ => return Vec::new(),
+        Err(e) => {
+            tracing::debug!("proactive context: 生成建议失败: {}", e);
+            Vec::new()
         }
-    };
+    }
+}
+
+/// 建议操作的 JSON Schema
+fn suggested_actions_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "suggested_actions".to_string(),
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "actions": {
+                    "type": "array",
+                    "maxItems": 3,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "label": { "type": "string" },
+                            "action": { "enum": ["open_url", "search", "copy"] },
+                            "value": { "type": "string" }
+                        },
+                        "required": ["label", "action", "value"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["actions"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+struct SuggestedActions {
+    actions: Vec<SuggestedAction>,
+}
 
+/// 让 LLM 基于当前窗口与相关记忆给出建议操作；没有合适的建议时返回空列表，
+/// 调用失败或输出修正后仍无效时返回对应的错误
+async fn suggest_actions(
+    provider: &dyn LlmProvider,
+    ctx: &TriggerContext,
+    related: &[crate::commands::ActivityLog],
+) -> Result<Vec<SuggestedAction>, StructuredOutputError> {
     let mut context_text = String::new();
     for a in related.iter().take(5) {
         context_text.push_str(&format!("应用: {} | 窗口: {}\n", a.app_name, a.window_title));
@@ -309,82 +347,33 @@ async fn build_suggested_actions(
     }
 
     let system_prompt = r#"你是一个主动式个人工作助理。基于当前窗口上下文与相关记忆，给出最多 3 条“建议操作”。
-请返回 JSON 数组，每个元素包含：
+请返回 JSON 对象，`actions` 数组中的每个元素包含：
 - "label": 简短的操作描述
 - "action": 操作类型，必须是 "open_url" (打开链接), "search" (在MemFlow中搜索), "copy" (复制内容) 之一
 - "value": 对应的链接、搜索关键词或要复制的文本
 
 例如：
-[
-  { "label": "打开相关 PR", "action": "open_url", "value": "https://github.com/..." },
-  { "label": "搜索 'Rust 错误处理'", "action": "search", "value": "Rust 错误处理" }
-]
+{
+  "actions": [
+    { "label": "打开相关 PR", "action": "open_url", "value": "https://github.com/..." },
+    { "label": "搜索 'Rust 错误处理'", "action": "search", "value": "Rust 错误处理" }
+  ]
+}
+没有合适的建议时返回 { "actions": [] }。
 "#;
 
     let user_query = format!("当前窗口：{} | {}", ctx.process_name, ctx.window_title);
 
-    let provider_config = ProviderConfig::new(
-        api_key,
-        if is_anthropic {
-            config.anthropic_base_url.clone()
-        } else {
-            config.openai_base_url.clone()
-        },
-        if is_anthropic {
-            "https://api.anthropic.com"
-        } else {
-            "https://api.openai.com/v1"
-        },
-    );
-
-    let response = timeout(Duration::from_secs(8), async {
-        if is_anthropic {
-            chat_with_anthropic(
-                &user_query,
-                &context_text,
-                model_id,
-                &provider_config,
-                Some(system_prompt),
-            )
-            .await
-        } else {
-            chat_with_openai(
-                &user_query,
-                &context_text,
-                model_id,
-                &provider_config,
-                Some(system_prompt),
-            )
-            .await
-        }
-    })
-    .await;
-
-    let Ok(Ok(text)) = response else {
-        return Vec::new();
-    };
-
-    let json_str = text.trim();
-    let json_str = if json_str.starts_with("```json") {
-        json_str
-            .trim_start_matches("```json")
-            .trim_end_matches("```")
-            .trim()
-    } else if json_str.starts_with("```") {
-        json_str
-            .trim_start_matches("```")
-            .trim_end_matches("```")
-            .trim()
-    } else {
-        json_str
-    };
-
-    serde_json::from_str::<Vec<SuggestedAction>>(json_str).unwrap_or_default()
+    let request = CompletionRequest::from_query(&user_query, &context_text, Some(system_prompt));
+    let suggestions: SuggestedActions =
+        complete_structured(provider, request, &suggested_actions_schema(), Duration::from_secs(8)).await?;
+    Ok(suggestions.actions)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{levenshtein, significant_title_change, should_trigger, ContextKey};
+    use super::{levenshtein, significant_title_change, should_trigger, suggest_actions, ContextKey, TriggerContext};
+    use memflow_core::ai::MockLlmProvider;
 
     #[test]
     fn levenshtein_basic_cases() {
@@ -416,4 +405,45 @@ mod tests {
         };
         assert!(should_trigger(Some(&prev), &next));
     }
+
+    #[tokio::test]
+    async fn suggested_actions_come_from_provider() {
+        let ctx = TriggerContext {
+            triggered_at: 0,
+            process_name: "Code.exe".to_string(),
+            process_path: String::new(),
+            window_title: "rag.rs - memflow".to_string(),
+        };
+        let related = vec![crate::commands::ActivityLog {
+            id: 1,
+            timestamp: 0,
+            app_name: "chrome.exe".to_string(),
+            window_title: "PR #42".to_string(),
+            image_path: None,
+            ocr_text: Some("Fix hybrid search ranking".to_string()),
+            phash: None,
+        }];
+        let provider = MockLlmProvider::with_responses([
+            r#"```json
+{ "actions": [{ "label": "搜索 'ranking'", "action": "search", "value": "ranking" }] }
+```"#,
+            r#"{ "actions": [] }"#,
+            "抱歉，我无法给出建议",
+            r#"[{ "label": "删除文件", "action": "delete", "value": "rag.rs" }]"#,
+        ]);
+
+        let actions = suggest_actions(&provider, &ctx, &related).await.unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].action, "search");
+
+        let request = &provider.requests()[0];
+        assert!(request.messages[0].content.contains("当前窗口：Code.exe | rag.rs - memflow"));
+        assert!(request.messages[0].content.contains("Fix hybrid search ranking"));
+
+        // 没有建议与输出无效是两种结果
+        assert!(suggest_actions(&provider, &ctx, &related).await.unwrap().is_empty());
+        let error = suggest_actions(&provider, &ctx, &related).await.unwrap_err();
+        assert!(error.is_invalid_output());
+        assert_eq!(provider.requests().len(), 4);
+    }
 }
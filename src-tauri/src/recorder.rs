@@ -441,6 +441,7 @@ async fn capture_and_save() -> Result<()> {
             tracing::error!("更新 UIA 文本失败: {}", e);
         } else {
             tracing::info!("使用 UIA 文本更新数据库成功，跳过 OCR");
+            crate::embedding_worker::enqueue(activity_id).await;
             
             // 发送 OCR 更新事件到前端（实际上是 UIA 文本）
             if let Some(app_handle) = APP_HANDLE.lock().await.as_ref() {
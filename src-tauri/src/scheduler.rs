@@ -1,13 +1,28 @@
 //! 定时任务调度器
 //! 
-//! 负责在应用启动时及每日定时执行清理逻辑。
+//! 负责在应用启动时及每日定时执行清理逻辑，以及定期生成分层活动摘要和日报 / 周报。
 
 use tokio::time::{interval, Duration};
-use crate::{app_config, db};
+use crate::{ai, app_config, db};
+use memflow_core::ai::summaries::{self, SummarizerConfig};
+use memflow_core::ai::usage::LlmPurpose;
+use memflow_core::digest::DigestPeriod;
 
 /// 调度间隔（24小时）
 const CLEANUP_INTERVAL_SECS: u64 = 24 * 60 * 60;
 
+/// 活动摘要检查间隔（15 分钟）；小时摘要在整点后等待 OCR 补全再生成
+const SUMMARY_INTERVAL_SECS: u64 = 15 * 60;
+
+/// 补历史时两批摘要之间的间隔，避免短时间内大量调用模型
+const SUMMARY_BACKFILL_PAUSE_SECS: u64 = 10;
+
+/// 日报 / 周报检查间隔（30 分钟）
+const DIGEST_INTERVAL_SECS: u64 = 30 * 60;
+
+/// 时段结束后等这么久再生成报告，留出时间让活动摘要追上
+const DIGEST_DELAY_SECS: i64 = 60 * 60;
+
 /// 启动自动清理调度器
 /// 
 /// 在应用启动时立即执行一次清理，之后每 24 小时执行一次。
@@ -52,3 +67,109 @@ async fn run_cleanup() {
         }
     }
 }
+
+/// 启动活动摘要调度器
+///
+/// 每 15 分钟检查一次；未开启活动摘要、AI 未启用或处于隐私模式时跳过。
+pub fn spawn_summary_scheduler() {
+    tokio::spawn(async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        let mut ticker = interval(Duration::from_secs(SUMMARY_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            // 一次运行有调用上限，补历史时分批继续
+            while run_summaries().await {
+                tokio::time::sleep(Duration::from_secs(SUMMARY_BACKFILL_PAUSE_SECS)).await;
+            }
+        }
+    });
+}
+
+/// 生成到期的活动摘要；返回是否还有未处理完的区间
+async fn run_summaries() -> bool {
+    let config = match app_config::get_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("⚠️ 获取配置失败，跳过活动摘要: {}", e);
+            return false;
+        }
+    };
+    if !config.enable_activity_summaries || !config.ai_enabled || config.privacy_mode_enabled {
+        return false;
+    }
+
+    let provider = match ai::llm_provider(&config, LlmPurpose::Summaries).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            tracing::warn!("活动摘要: LLM provider 不可用: {}", e);
+            return false;
+        }
+    };
+
+    let summarizer = SummarizerConfig {
+        backfill_horizon: Duration::from_secs(u64::from(config.summary_backfill_days) * 24 * 60 * 60),
+        ..SummarizerConfig::default()
+    };
+    match summaries::summarize_pending(&provider, &summarizer).await {
+        Ok(stats) => {
+            if stats.written() > 0 || stats.skipped > 0 {
+                tracing::info!(
+                    "活动摘要已更新: 小时 {}，日 {}，周 {}，跳过 {}",
+                    stats.hours,
+                    stats.days,
+                    stats.weeks,
+                    stats.skipped
+                );
+            }
+            !stats.caught_up
+        }
+        Err(e) => {
+            tracing::warn!(
+                "活动摘要生成失败，下次继续: {}",
+                crate::redact::redact_secrets(&e.to_string())
+            );
+            false
+        }
+    }
+}
+
+/// 启动日报 / 周报调度器
+///
+/// 每 30 分钟检查一次，为昨天和上周生成报告；每个时段只生成一次，已生成的直接跳过。
+pub fn spawn_digest_scheduler() {
+    tokio::spawn(async {
+        tokio::time::sleep(Duration::from_secs(120)).await;
+
+        let mut ticker = interval(Duration::from_secs(DIGEST_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            run_digests().await;
+        }
+    });
+}
+
+/// 与活动摘要一样，AI 未启用或处于隐私模式时不自动生成：
+/// 否则这些时段会在没有模型的情况下定稿，之后开启 AI 也不会再有模型写的任务总结
+async fn run_digests() {
+    match app_config::get_config().await {
+        Ok(config) if config.enable_digests && config.ai_enabled && !config.privacy_mode_enabled => {}
+        Ok(_) => return,
+        Err(e) => {
+            tracing::warn!("⚠️ 获取配置失败，跳过日报 / 周报: {}", e);
+            return;
+        }
+    }
+
+    let now = chrono::Local::now().timestamp() - DIGEST_DELAY_SECS;
+    for period in [DigestPeriod::Day, DigestPeriod::Week] {
+        let (start, _) = period.previous(now);
+        if let Err(e) = crate::digest::generate(period, start).await {
+            tracing::warn!(
+                "{}生成失败，下次继续: {}",
+                period.label(),
+                crate::redact::redact_secrets(&e.to_string())
+            );
+        }
+    }
+}
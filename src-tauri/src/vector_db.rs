@@ -6,12 +6,26 @@
 // Re-export everything from memflow-core vector_db
 pub use memflow_core::vector_db::*;
 
-use crate::ai::provider::{embedding_with_openai, ProviderConfig};
 use anyhow::Result;
+use memflow_core::ai::embedding::{self, EmbeddingConfig, EmbeddingProvider, EmbeddingProviderKind};
+use memflow_core::ai::rag::{self, Reranker, RerankerConfig};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-/// Generate embedding using configured AI provider
-/// This is Tauri-specific as it uses app_config and secure_storage
-pub async fn generate_embedding(text: &str) -> Result<Vec<f32>> {
+/// Cache directory for local embedding models
+///
+/// Same location as the MCP server (`<data_dir>/com.memflow.app/memflow-resources/models`),
+/// so the model is only downloaded once.
+pub fn local_model_cache_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("com.memflow.app")
+        .join("memflow-resources")
+        .join("models")
+}
+
+/// Build the embedding provider config from app config and secure storage
+async fn embedding_config() -> EmbeddingConfig {
     // Get config
     let config = crate::app_config::get_config().await.unwrap_or_else(|_| {
         let mut cfg: crate::commands::AppConfig = serde_json::from_str("{}").unwrap();
@@ -19,59 +33,108 @@ pub async fn generate_embedding(text: &str) -> Result<Vec<f32>> {
         cfg
     });
 
-    let model_id = &config.embedding_model;
+    let provider = config
+        .embedding_provider
+        .parse::<EmbeddingProviderKind>()
+        .unwrap_or_else(|e| {
+            tracing::warn!("{}，使用本地模型", e);
+            EmbeddingProviderKind::Local
+        });
 
-    // Embedding uses OpenAI-compatible API (Anthropic doesn't support embeddings)
-    // - If embedding_use_shared_key=true: reuse openai key
-    // - Otherwise: use dedicated embedding key
-    let key_service = if config.embedding_use_shared_key {
-        "openai"
-    } else {
-        "embedding"
-    };
+    match provider {
+        EmbeddingProviderKind::Local => EmbeddingConfig {
+            provider,
+            model: config.local_embedding_model,
+            cache_dir: Some(local_model_cache_dir()),
+            ..Default::default()
+        },
+        EmbeddingProviderKind::OpenAi => {
+            // Embedding uses OpenAI-compatible API (Anthropic doesn't support embeddings)
+            // - If embedding_use_shared_key=true: reuse openai key
+            // - Otherwise: use dedicated embedding key
+            let key_service = if config.embedding_use_shared_key {
+                "openai"
+            } else {
+                "embedding"
+            };
+            let api_key = crate::secure_storage::get_api_key(key_service)
+                .await
+                .ok()
+                .flatten();
+            if api_key.is_none() {
+                tracing::debug!("未配置 Embeddings API Key(service={})", key_service);
+            }
 
-    if let Ok(Some(api_key)) = crate::secure_storage::get_api_key(key_service).await {
-        let provider_config = ProviderConfig::new(
-            api_key,
-            config
-                .embedding_base_url
-                .clone()
-                .or_else(|| config.openai_base_url.clone()),
-            "https://api.openai.com/v1",
-        );
+            EmbeddingConfig {
+                provider,
+                model: config.embedding_model,
+                base_url: config
+                    .embedding_base_url
+                    .or(config.openai_base_url),
+                api_key,
+                cache_dir: None,
+            }
+        }
+        EmbeddingProviderKind::Stub => EmbeddingConfig {
+            provider,
+            ..Default::default()
+        },
+    }
+}
 
-        // Use OpenAI Embeddings API
-        match embedding_with_openai(text, model_id, &provider_config).await {
+/// Embedding provider selected by the app config (cached until the config changes)
+pub async fn embedding_provider() -> Result<Arc<dyn EmbeddingProvider>> {
+    embedding::shared_embedding_provider(&embedding_config().await).await
+}
+
+/// Reranker selected by the app config; `None` when reranking is disabled or unavailable
+///
+/// Reranking is an optional refinement, so load failures only disable it.
+pub async fn reranker() -> Option<Arc<dyn Reranker>> {
+    let config = crate::app_config::get_config().await.ok()?;
+    let reranker_config = RerankerConfig {
+        enabled: config.rerank_enabled,
+        model: config.reranker_model,
+        cache_dir: Some(local_model_cache_dir()),
+    };
+    match rag::shared_reranker(&reranker_config).await {
+        Ok(reranker) => reranker,
+        Err(e) => {
+            tracing::warn!("重排模型不可用，跳过重排: {}", e);
+            None
+        }
+    }
+}
+
+/// Generate a query embedding using the configured provider
+/// This is Tauri-specific as it uses app_config and secure_storage
+///
+/// Falls back to the placeholder when the provider is unavailable; placeholder vectors
+/// never match documents from a real model, so hybrid search degrades to keyword search.
+pub async fn generate_embedding(text: &str) -> Result<Embedding> {
+    match embedding_provider().await {
+        Ok(provider) => match provider.embed_query(text).await {
             Ok(embedding) => {
                 tracing::debug!(
-                    "使用 OpenAI Embeddings API 生成向量，模型: {}，维度: {}",
-                    model_id,
-                    embedding.len()
+                    "生成查询向量，模型: {}，维度: {}",
+                    embedding.model_id,
+                    embedding.dim()
                 );
-                // Handle dimension adaptation
-                if embedding.len() != EMBEDDING_DIM {
-                    if embedding.len() > EMBEDDING_DIM {
-                        return Ok(embedding[..EMBEDDING_DIM].to_vec());
-                    } else {
-                        let mut result = embedding;
-                        result.resize(EMBEDDING_DIM, 0.0);
-                        return Ok(result);
-                    }
-                }
                 return Ok(embedding);
             }
             Err(e) => {
                 tracing::warn!(
-                    "OpenAI Embeddings API 调用失败，使用占位实现: {}",
+                    "Embedding 生成失败，使用占位实现: {}",
                     crate::redact::redact_secrets(&e.to_string())
                 );
             }
+        },
+        Err(e) => {
+            tracing::warn!(
+                "Embedding provider 不可用，使用占位实现: {}",
+                crate::redact::redact_secrets(&e.to_string())
+            );
         }
-    } else {
-        tracing::debug!(
-            "未配置 Embeddings API Key(service={})，使用占位实现",
-            key_service
-        );
     }
 
     // Fallback to placeholder implementation from core
@@ -5,6 +5,7 @@ import AgentProposalModal from './components/AgentProposalModal'
 import ChatHistoryModal from './components/ChatHistoryModal'
 import FeedbackModal from './components/FeedbackModal'
 import PerformanceModal from './components/PerformanceModal'
+import DigestModal from './components/DigestModal'
 import { AppProvider } from './contexts/AppContext'
 
 function App() {
@@ -13,6 +14,7 @@ function App() {
   const [chatHistoryOpen, setChatHistoryOpen] = useState(false)
   const [feedbackOpen, setFeedbackOpen] = useState(false)
   const [performanceOpen, setPerformanceOpen] = useState(false)
+  const [digestsOpen, setDigestsOpen] = useState(false)
 
   // 对话会话状态
   const [currentSessionId, setCurrentSessionId] = useState<number | null>(null)
@@ -55,6 +57,7 @@ function App() {
         onOpenChatHistory={() => setChatHistoryOpen(true)}
         onOpenFeedback={() => setFeedbackOpen(true)}
         onOpenPerformance={() => setPerformanceOpen(true)}
+        onOpenDigests={() => setDigestsOpen(true)}
         currentSessionId={currentSessionId}
         shouldSwitchToQA={shouldSwitchToQA}
         onViewSwitched={handleViewSwitched}
@@ -86,6 +89,10 @@ function App() {
         open={performanceOpen} 
         onClose={() => setPerformanceOpen(false)} 
       />
+      <DigestModal
+        open={digestsOpen}
+        onClose={() => setDigestsOpen(false)}
+      />
     </AppProvider>
   )
 }
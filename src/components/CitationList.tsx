@@ -0,0 +1,64 @@
+import { useEffect, useState } from 'react'
+import { invoke } from '@tauri-apps/api/core'
+import { Image as ImageIcon } from 'lucide-react'
+import ImagePreviewModal from './ImagePreviewModal'
+import type { ActivityLog } from '../contexts/AppContext'
+
+interface CitationListProps {
+  // 回答中引用的活动 ID（chat_messages.context_ids）
+  activityIds: number[]
+}
+
+/**
+ * 助手回答的引用来源：点击后打开对应活动的截图
+ */
+export default function CitationList({ activityIds }: CitationListProps) {
+  const [activities, setActivities] = useState<Record<number, ActivityLog>>({})
+  const [preview, setPreview] = useState<ActivityLog | null>(null)
+
+  useEffect(() => {
+    let cancelled = false
+    Promise.all(
+      activityIds.map((id) =>
+        invoke<ActivityLog>('get_activity_by_id', { id }).catch(() => null)
+      )
+    ).then((loaded) => {
+      if (cancelled) return
+      const byId: Record<number, ActivityLog> = {}
+      for (const activity of loaded) {
+        if (activity) byId[activity.id] = activity
+      }
+      setActivities(byId)
+    })
+    return () => {
+      cancelled = true
+    }
+  }, [activityIds])
+
+  if (activityIds.length === 0) return null
+
+  return (
+    <div className="mt-2 flex flex-wrap items-center gap-1.5 text-xs text-gray-400">
+      <span>引用：</span>
+      {activityIds.map((id) => {
+        const activity = activities[id]
+        const label = activity
+          ? `${activity.appName} ${new Date(activity.timestamp * 1000).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' })}`
+          : `#${id}`
+        return (
+          <button
+            key={id}
+            onClick={() => activity && setPreview(activity)}
+            disabled={!activity}
+            className="flex items-center gap-1 px-2 py-0.5 rounded bg-neon-blue/10 text-neon-blue hover:bg-neon-blue/20 transition-colors disabled:opacity-50 disabled:cursor-not-allowed"
+            title={activity ? activity.windowTitle : '活动已删除'}
+          >
+            <ImageIcon className="w-3 h-3" />
+            <span>{label}</span>
+          </button>
+        )
+      })}
+      <ImagePreviewModal open={preview !== null} activity={preview} onClose={() => setPreview(null)} />
+    </div>
+  )
+}
@@ -0,0 +1,161 @@
+import { useState, useEffect } from 'react'
+import { X, FileText, RefreshCw, Loader2 } from 'lucide-react'
+import { invoke } from '@tauri-apps/api/core'
+
+interface DigestModalProps {
+  open: boolean
+  onClose: () => void
+}
+
+type DigestPeriod = 'day' | 'week'
+
+interface Digest {
+  id: number
+  period: DigestPeriod
+  startTs: number
+  endTs: number
+  title: string
+  markdown: string
+  filePath?: string | null
+  degraded?: boolean
+  createdAt: number
+}
+
+const PERIOD_LABELS: Record<DigestPeriod, string> = {
+  day: '日报',
+  week: '周报',
+}
+
+export default function DigestModal({ open, onClose }: DigestModalProps) {
+  const [digests, setDigests] = useState<Digest[]>([])
+  const [selectedId, setSelectedId] = useState<number | null>(null)
+  const [loading, setLoading] = useState(false)
+  const [generating, setGenerating] = useState<DigestPeriod | null>(null)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    if (open) {
+      void loadDigests()
+    }
+  }, [open])
+
+  const loadDigests = async (selectId?: number) => {
+    try {
+      setLoading(true)
+      const data = await invoke<Digest[]>('list_digests', { limit: 60 })
+      setDigests(data)
+      setSelectedId((prev) => selectId ?? prev ?? data[0]?.id ?? null)
+    } catch (e) {
+      console.error('获取报告失败:', e)
+      setError(String(e))
+    } finally {
+      setLoading(false)
+    }
+  }
+
+  // 生成上一个已结束的时段（昨天 / 上周）；已生成过时直接返回已有的报告
+  const handleGenerate = async (period: DigestPeriod) => {
+    try {
+      setGenerating(period)
+      setError(null)
+      const digest = await invoke<Digest | null>('generate_digest', { period })
+      if (digest) {
+        await loadDigests(digest.id)
+      } else {
+        setError(`${period === 'day' ? '昨天' : '上周'}没有活动记录`)
+      }
+    } catch (e) {
+      console.error('生成报告失败:', e)
+      setError(String(e))
+    } finally {
+      setGenerating(null)
+    }
+  }
+
+  if (!open) return null
+
+  const selected = digests.find((d) => d.id === selectedId) ?? null
+
+  return (
+    <div className="fixed inset-0 z-50 flex items-center justify-center bg-black/50 backdrop-blur-sm">
+      <div className="glass w-full max-w-5xl rounded-lg p-6 h-[85vh] flex flex-col">
+        <div className="flex items-center justify-between mb-4">
+          <h2 className="text-2xl font-bold text-white">日报 / 周报</h2>
+          <div className="flex items-center gap-2">
+            {(['day', 'week'] as DigestPeriod[]).map((period) => (
+              <button
+                key={period}
+                onClick={() => void handleGenerate(period)}
+                disabled={generating !== null}
+                className="px-3 py-1.5 rounded-lg bg-neon-blue/20 text-neon-blue hover:bg-neon-blue/30 transition-colors flex items-center gap-2 text-sm disabled:opacity-50"
+              >
+                {generating === period ? <Loader2 className="w-4 h-4 animate-spin" /> : <RefreshCw className="w-4 h-4" />}
+                {period === 'day' ? '生成昨天日报' : '生成上周周报'}
+              </button>
+            ))}
+            <button
+              onClick={onClose}
+              className="p-2 rounded-lg hover:bg-surface transition-colors"
+            >
+              <X className="w-5 h-5" />
+            </button>
+          </div>
+        </div>
+
+        {error && <div className="mb-3 text-sm text-neon-red/90">{error}</div>}
+
+        <div className="flex-1 min-h-0 flex gap-4">
+          <div className="w-60 flex-shrink-0 overflow-y-auto space-y-1 pr-2 custom-scrollbar">
+            {loading && digests.length === 0 ? (
+              <div className="flex justify-center py-8">
+                <Loader2 className="w-5 h-5 animate-spin text-gray-500" />
+              </div>
+            ) : digests.length === 0 ? (
+              <p className="text-sm text-gray-500 text-center py-8">
+                暂无报告，可在设置中开启自动生成
+              </p>
+            ) : (
+              digests.map((digest) => (
+                <button
+                  key={digest.id}
+                  onClick={() => setSelectedId(digest.id)}
+                  className={`w-full text-left px-3 py-2 rounded-lg transition-colors flex items-start gap-2 ${
+                    digest.id === selectedId
+                      ? 'bg-neon-blue/15 text-white'
+                      : 'text-gray-400 hover:bg-surface/50 hover:text-gray-200'
+                  }`}
+                >
+                  <FileText className="w-4 h-4 mt-0.5 flex-shrink-0" />
+                  <span className="text-sm">
+                    <span className="text-xs text-gray-500 mr-1">{PERIOD_LABELS[digest.period]}</span>
+                    {digest.title.replace(/^MemFlow (日报|周报) · /, '')}
+                  </span>
+                </button>
+              ))
+            )}
+          </div>
+
+          <div className="flex-1 min-w-0 overflow-y-auto rounded-lg bg-surface/50 border border-glass-border/50 p-4 custom-scrollbar">
+            {selected ? (
+              <>
+                {selected.filePath && (
+                  <div className="mb-3 text-xs text-gray-500 break-all">已导出到 {selected.filePath}</div>
+                )}
+                {selected.degraded && (
+                  <div className="mb-3 text-xs text-amber-400">
+                    生成时模型调用失败，任务总结暂时列出的是活动摘要；再次生成时会重新调用模型
+                  </div>
+                )}
+                <pre className="whitespace-pre-wrap font-sans text-sm leading-relaxed text-gray-100">
+                  {selected.markdown}
+                </pre>
+              </>
+            ) : (
+              <p className="text-sm text-gray-500 text-center py-8">选择左侧的报告查看</p>
+            )}
+          </div>
+        </div>
+      </div>
+    </div>
+  )
+}
@@ -8,7 +8,7 @@ import ActivityHeatmap from './ActivityHeatmap'
 import ContextSidebar from './ContextSidebar'
 import ImmersiveReplay from './ImmersiveReplay'
 import { useApp } from '../contexts/AppContext'
-import { Play, Pause, Settings, Zap, History, MessageSquare, BarChart3, Calendar, X } from 'lucide-react'
+import { Play, Pause, Settings, Zap, History, MessageSquare, BarChart3, Calendar, FileText, X } from 'lucide-react'
 
 interface LayoutProps {
   onOpenSettings: () => void
@@ -16,6 +16,7 @@ interface LayoutProps {
   onOpenChatHistory: () => void
   onOpenFeedback: () => void
   onOpenPerformance: () => void
+  onOpenDigests: () => void
   // 对话会话相关
   currentSessionId?: number | null
   shouldSwitchToQA?: boolean
@@ -32,6 +33,7 @@ export default function Layout({
   onOpenChatHistory,
   onOpenFeedback,
   onOpenPerformance,
+  onOpenDigests,
   currentSessionId,
   shouldSwitchToQA,
   onViewSwitched,
@@ -178,6 +180,13 @@ export default function Layout({
           >
             <History className="w-5 h-5" />
           </button>
+          <button
+            onClick={onOpenDigests}
+            className="p-2 rounded-lg text-gray-400 hover:text-neon-purple hover:bg-neon-purple/10 transition-all"
+            title="日报 / 周报"
+          >
+            <FileText className="w-5 h-5" />
+          </button>
           <button
             onClick={onOpenPerformance}
             className="p-2 rounded-lg text-gray-400 hover:text-neon-green hover:bg-neon-green/10 transition-all"
@@ -88,7 +88,41 @@ describe('QnA', () => {
       content: '测试问题',
       contextIds: null,
     })
-    expect(mockInvoke).toHaveBeenCalledWith('ai_chat_stream', { query: '测试问题' })
+    expect(mockInvoke).toHaveBeenCalledWith('ai_chat_stream', { query: '测试问题', sessionId: 1 })
+  })
+
+  it('应该保存去掉无效引用后的回答', async () => {
+    const user = userEvent.setup()
+    mockListen.mockImplementationOnce(async (eventName: string, handler: (event: { payload: unknown }) => void) => {
+      if (eventName === 'ai-chat-chunk') {
+        handler({ payload: '你修改了 rag.rs [1]，还看了文档 [7]。' })
+      }
+      return () => {}
+    })
+    mockInvoke
+      .mockResolvedValueOnce(1) // create_chat_session
+      .mockResolvedValueOnce(2) // save_chat_message (user)
+      .mockResolvedValueOnce({ content: '你修改了 rag.rs [1]，还看了文档。', contextIds: [42] }) // ai_chat_stream
+      .mockResolvedValueOnce(3) // save_chat_message (assistant)
+
+    render(<QnA />, { wrapper: Wrapper })
+
+    await user.type(screen.getByPlaceholderText(/输入你的问题/i), '我今天做了什么')
+    await user.click(screen.getByRole('button', { name: /发送/i }))
+
+    await waitFor(() => {
+      expect(mockInvoke).toHaveBeenCalledWith('save_chat_message', {
+        sessionId: 1,
+        role: 'assistant',
+        content: '你修改了 rag.rs [1]，还看了文档。',
+        contextIds: [42],
+      })
+    })
+    expect(mockInvoke).not.toHaveBeenCalledWith(
+      'save_chat_message',
+      expect.objectContaining({ content: expect.stringContaining('[7]') })
+    )
+    expect(await screen.findByText('你修改了 rag.rs [1]，还看了文档。')).toBeInTheDocument()
   })
 
   it('应该在发送后清空输入框', async () => {
@@ -1,9 +1,10 @@
 import { useEffect, useMemo, useRef, useState, useCallback } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import { listen } from '@tauri-apps/api/event'
-import { Send, Loader2, Sparkles, RotateCcw } from 'lucide-react'
+import { Send, Loader2, Sparkles, RotateCcw, Square } from 'lucide-react'
 import MessageRating from './MessageRating'
-import type { LocalChatMessage, ChatMessage } from '../types/chat'
+import CitationList from './CitationList'
+import type { LocalChatMessage, ChatMessage, ChatAnswer } from '../types/chat'
 
 function makeLocalId() {
   return `${Date.now()}-${Math.random().toString(16).slice(2)}`
@@ -30,6 +31,8 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
   // 用于跟踪是否已初始化，避免重复加载
   const isInitializedRef = useRef(false)
   const lastLoadedSessionRef = useRef<number | null>(null)
+  // 用户是否点击了停止（停止时空回答不算错误）
+  const stoppedRef = useRef(false)
 
   // 加载历史消息
   const loadHistoryMessages = useCallback(async (sid: number) => {
@@ -43,6 +46,7 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
         ts: m.createdAt,
         dbId: m.id,
         rating: m.rating,
+        contextIds: m.contextIds,
       }))
       setMessages(localMessages)
     } catch (e) {
@@ -109,13 +113,14 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
   const saveMessage = async (
     sid: number,
     role: 'user' | 'assistant',
-    content: string
+    content: string,
+    contextIds?: number[]
   ): Promise<number> => {
     const messageId = await invoke<number>('save_chat_message', {
       sessionId: sid,
       role,
       content,
-      contextIds: null,
+      contextIds: contextIds && contextIds.length > 0 ? contextIds : null,
     })
     return messageId
   }
@@ -143,6 +148,7 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
     setError(null)
     setLoading(true)
     setInput('')
+    stoppedRef.current = false
 
     // 1. 添加用户消息到界面
     const userMsg: LocalChatMessage = {
@@ -197,28 +203,32 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
         )
       })
 
+      // 去掉无效引用后的完整回答及其引用的活动 ID
+      let answer: ChatAnswer | null = null
       try {
         // 6. 调用流式命令
         // 这个命令会等待直到流结束才返回
-        await invoke('ai_chat_stream', { query: q })
+        answer = await invoke<ChatAnswer | null>('ai_chat_stream', { query: q, sessionId: currentSessionId })
       } finally {
         // 确保取消监听
         unlisten()
       }
 
-      // 7. 流式结束，保存完整消息到数据库
-      if (accumulatedResponse.trim()) {
-        const botMsgId = await saveMessage(currentSessionId, 'assistant', accumulatedResponse)
+      // 7. 流式结束，保存完整消息到数据库（以后端校验过引用的文本为准）
+      const content = answer?.content ?? accumulatedResponse
+      const contextIds = answer?.contextIds ?? []
+      if (content.trim()) {
+        const botMsgId = await saveMessage(currentSessionId, 'assistant', content, contextIds)
 
         // 更新消息的 dbId
         setMessages((prev) =>
           prev.map((m) =>
             m.localId === botLocalId
-              ? { ...m, dbId: botMsgId } // 更新 dbId，允许显示评价按钮等
+              ? { ...m, content, dbId: botMsgId, contextIds } // 更新 dbId，允许显示评价按钮等
               : m
           )
         )
-      } else {
+      } else if (!stoppedRef.current) {
         // 极端情况：没有任何响应
         setError("AI 未返回任何内容")
       }
@@ -248,6 +258,16 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
     }
   }
 
+  // 停止生成：后端断开模型请求，已生成的部分照常保存
+  const stop = async () => {
+    stoppedRef.current = true
+    try {
+      await invoke('ai_chat_stop')
+    } catch (e) {
+      console.error('Failed to stop AI chat:', e)
+    }
+  }
+
   // 更新消息评价
   const handleRatingChange = (localId: string, rating: 1 | -1 | null) => {
     setMessages((prev) =>
@@ -302,6 +322,10 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
                 <pre className="whitespace-pre-wrap font-sans text-sm leading-relaxed">
                   {m.content || (loading && index === messages.length - 1 && m.role === 'assistant' ? '' : (m.role === 'assistant' ? '（回复内容为空）' : '（消息内容为空）'))}
                 </pre>
+                {/* 助手消息显示引用来源 */}
+                {m.role === 'assistant' && m.contextIds && m.contextIds.length > 0 && (
+                  <CitationList activityIds={m.contextIds} />
+                )}
                 {/* 助手消息显示评价按钮 */}
                 {m.role === 'assistant' && m.dbId && (
                   <MessageRating
@@ -345,6 +369,16 @@ export default function QnA({ initialSessionId, onSessionCreated, draft }: QnAPr
             placeholder="输入你的问题（Enter 发送，Shift+Enter 换行）"
             className="flex-1 min-h-[44px] max-h-40 resize-none px-4 py-2.5 bg-surface border border-glass-border rounded-lg text-white placeholder:text-gray-500 hover:border-neon-blue/30 transition-colors focus:outline-none focus:ring-2 focus:ring-neon-blue/30"
           />
+          {loading && (
+            <button
+              onClick={() => void stop()}
+              className="px-4 py-2.5 rounded-lg bg-neon-red/10 text-neon-red hover:bg-neon-red/20 transition-colors flex items-center gap-2"
+              title="停止生成"
+            >
+              <Square className="w-4 h-4" />
+              <span>停止</span>
+            </button>
+          )}
           <button
             onClick={() => void send()}
             disabled={!canSend}
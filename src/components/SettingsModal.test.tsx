@@ -0,0 +1,148 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest'
+import { render, screen, waitFor } from '@testing-library/react'
+import userEvent from '@testing-library/user-event'
+import SettingsModal from './SettingsModal'
+import { invoke } from '@tauri-apps/api/core'
+import type { AppConfig } from '../contexts/AppContext'
+
+const mockDispatch = vi.fn()
+let mockConfig: AppConfig
+
+vi.mock('../contexts/AppContext', () => ({
+  useApp: () => ({ state: { config: mockConfig }, dispatch: mockDispatch }),
+}))
+
+const mockInvoke = vi.mocked(invoke)
+
+const baseConfig: AppConfig = {
+  recordingInterval: 5000,
+  ocrEnabled: true,
+  aiEnabled: true,
+  enableFocusAnalytics: true,
+  enableProactiveAssistant: false,
+  retentionDays: 30,
+  chatModel: 'gpt-4o-mini',
+  embeddingModel: 'text-embedding-3-small',
+  privacyModeEnabled: false,
+} as AppConfig
+
+function mockBackend(models: Record<string, { id: string; sizeBytes?: number }[]>) {
+  mockInvoke.mockImplementation(async (cmd: string, args?: unknown) => {
+    switch (cmd) {
+      case 'get_api_key':
+        return null
+      case 'get_blocklist':
+        return []
+      case 'get_llm_usage':
+        return null
+      case 'list_local_llm_models': {
+        const { params } = args as { params: { provider: string } }
+        return models[params.provider] ?? []
+      }
+      default:
+        return undefined
+    }
+  })
+}
+
+function chatModelSelect(): HTMLSelectElement {
+  return screen.getByRole('option', { name: /Ollama/ }).closest('select') as HTMLSelectElement
+}
+
+function savedConfig(): AppConfig {
+  const call = mockInvoke.mock.calls.find(([cmd]) => cmd === 'update_config')
+  expect(call).toBeDefined()
+  return (call![1] as { config: AppConfig }).config
+}
+
+describe('SettingsModal 本地模型', () => {
+  beforeEach(() => {
+    vi.clearAllMocks()
+    mockConfig = { ...baseConfig }
+  })
+
+  it('应该从 Ollama 拉取模型列表并保存所选模型', async () => {
+    const user = userEvent.setup()
+    mockConfig = {
+      ...baseConfig,
+      chatProvider: 'ollama',
+      chatModel: 'qwen2.5:7b',
+      localLlmBaseUrl: 'http://192.168.1.2:11434',
+    }
+    mockBackend({
+      ollama: [
+        { id: 'qwen2.5:7b', sizeBytes: 4_700_000_000 },
+        { id: 'llama3.1:8b', sizeBytes: 4_900_000_000 },
+      ],
+    })
+
+    render(<SettingsModal open onClose={vi.fn()} />)
+
+    expect(chatModelSelect()).toHaveValue('ollama')
+    await waitFor(() => {
+      expect(mockInvoke).toHaveBeenCalledWith('list_local_llm_models', {
+        params: { provider: 'ollama', baseUrl: 'http://192.168.1.2:11434' },
+      })
+    })
+
+    expect(await screen.findByRole('option', { name: /llama3\.1:8b — 4\.9 GB/ })).toBeInTheDocument()
+    const modelSelect = screen.getByLabelText('模型')
+    expect(modelSelect.tagName).toBe('SELECT')
+    expect(modelSelect).toHaveValue('qwen2.5:7b')
+
+    await user.selectOptions(modelSelect, 'llama3.1:8b')
+    await user.click(screen.getByRole('button', { name: '保存配置' }))
+
+    const config = savedConfig()
+    expect(config.chatProvider).toBe('ollama')
+    expect(config.chatModel).toBe('llama3.1:8b')
+    expect(config.localLlmBaseUrl).toBe('http://192.168.1.2:11434')
+  })
+
+  it('应该可以切换到本地服务并填写地址和模型', async () => {
+    const user = userEvent.setup()
+    mockBackend({})
+
+    render(<SettingsModal open onClose={vi.fn()} />)
+
+    await user.selectOptions(chatModelSelect(), 'local')
+    await waitFor(() => {
+      expect(mockInvoke).toHaveBeenCalledWith('list_local_llm_models', {
+        params: { provider: 'local', baseUrl: undefined },
+      })
+    })
+    expect(await screen.findByText('服务上没有可用模型')).toBeInTheDocument()
+
+    await user.type(screen.getByPlaceholderText('http://127.0.0.1:8080/v1'), 'http://127.0.0.1:1234/v1')
+    await user.click(screen.getByTitle('刷新模型列表'))
+    await waitFor(() => {
+      expect(mockInvoke).toHaveBeenCalledWith('list_local_llm_models', {
+        params: { provider: 'local', baseUrl: 'http://127.0.0.1:1234/v1' },
+      })
+    })
+
+    await user.type(screen.getByLabelText('模型'), 'llama-3-8b-instruct')
+    await user.click(screen.getByRole('button', { name: '保存配置' }))
+
+    const config = savedConfig()
+    expect(config.chatProvider).toBe('local')
+    expect(config.chatModel).toBe('llama-3-8b-instruct')
+    expect(config.localLlmBaseUrl).toBe('http://127.0.0.1:1234/v1')
+  })
+
+  it('切回云端模型时应该恢复按模型名推断提供商', async () => {
+    const user = userEvent.setup()
+    mockConfig = { ...baseConfig, chatProvider: 'ollama', chatModel: 'qwen2.5:7b' }
+    mockBackend({ ollama: [{ id: 'qwen2.5:7b' }] })
+
+    render(<SettingsModal open onClose={vi.fn()} />)
+
+    await user.selectOptions(chatModelSelect(), 'gpt-4o')
+    expect(screen.queryByLabelText('模型')).not.toBeInTheDocument()
+    await user.click(screen.getByRole('button', { name: '保存配置' }))
+
+    const config = savedConfig()
+    expect(config.chatProvider).toBe('auto')
+    expect(config.chatModel).toBe('gpt-4o')
+  })
+})
@@ -1,5 +1,5 @@
 import { useState, useEffect, useReducer, useCallback } from 'react'
-import { X, Check, AlertCircle, Loader2, ChevronDown, Shield, Settings, Bot, Plus, Trash2, Eye, FolderOpen, Gauge, Sparkles } from 'lucide-react'
+import { X, Check, AlertCircle, Loader2, ChevronDown, Shield, Settings, Bot, Plus, Trash2, Eye, FolderOpen, Gauge, Sparkles, CalendarRange, FileText, RefreshCw } from 'lucide-react'
 import { invoke } from '@tauri-apps/api/core'
 import { open as openFileDialog } from '@tauri-apps/plugin-dialog'
 import { useApp } from '../contexts/AppContext'
@@ -19,7 +19,8 @@ const checkDialogPlugin = async () => {
 
 // ==================== Type Definitions ====================
 
-type ChatModelProvider = 'openai' | 'anthropic' | 'custom'
+type ChatModelProvider = 'openai' | 'anthropic' | 'custom' | LocalModelProvider
+type LocalModelProvider = 'ollama' | 'local'
 type EmbeddingModelProvider = 'openai' | 'custom'
 
 interface ChatModelConfig {
@@ -56,6 +57,35 @@ interface ConnectionTestState {
   embedding: { testing: boolean; result: 'idle' | 'success' | 'error'; message: string }
 }
 
+interface LocalModelInfo {
+  id: string
+  sizeBytes?: number | null
+}
+
+interface LlmUsageRow {
+  provider: string
+  model: string
+  purpose: string
+  calls: number
+  cacheHits: number
+  errors: number
+  promptTokens: number
+  completionTokens: number
+  savedTokens: number
+  avgLatencyMs?: number | null
+}
+
+interface LlmUsageSummary {
+  sinceTs: number
+  calls: number
+  cacheHits: number
+  errors: number
+  promptTokens: number
+  completionTokens: number
+  savedTokens: number
+  rows: LlmUsageRow[]
+}
+
 // ==================== Constants ====================
 
 const OPENAI_MODELS = [
@@ -70,6 +100,34 @@ const ANTHROPIC_MODELS = [
   { id: 'claude-3-sonnet-20240229', name: 'Claude 3 Sonnet', description: '平衡性能与速度' },
 ] as const
 
+const LOCAL_MODEL_PROVIDERS = [
+  { id: 'ollama', name: 'Ollama', description: '本机运行，无需 API Key' },
+  { id: 'local', name: '本地服务', description: 'llama.cpp / LM Studio 等 OpenAI 兼容服务' },
+] as const
+
+const LOCAL_DEFAULT_BASE_URLS: Record<LocalModelProvider, string> = {
+  ollama: 'http://127.0.0.1:11434',
+  local: 'http://127.0.0.1:8080/v1',
+}
+
+const LLM_PURPOSE_LABELS: Record<string, string> = {
+  chat: '问答',
+  intent: '意图解析',
+  proposals: '任务提案',
+  suggestions: '主动建议',
+  conversation: '会话摘要',
+  summaries: '活动摘要',
+  digest: '日报周报',
+  connection_test: '连接测试',
+}
+
+const LLM_CACHE_TTL_OPTIONS = [
+  { value: 0, label: '不缓存' },
+  { value: 600, label: '10 分钟' },
+  { value: 3600, label: '1 小时' },
+  { value: 86400, label: '1 天' },
+] as const
+
 const EMBEDDING_MODELS = [
   { id: 'text-embedding-3-small', name: 'Embedding 3 Small', description: '性价比高，推荐' },
   { id: 'text-embedding-3-large', name: 'Embedding 3 Large', description: '更高精度' },
@@ -97,6 +155,42 @@ function getProviderFromModelId(modelId: string): ChatModelProvider {
   return 'custom'
 }
 
+function isLocalProvider(provider: string): provider is LocalModelProvider {
+  return provider === 'ollama' || provider === 'local'
+}
+
+// 本地服务由 chatProvider 指定；其余按模型名推断（与后端 auto 一致）
+function getChatProviderFromConfig(config: { chatProvider?: string; chatModel?: string }): ChatModelProvider {
+  if (config.chatProvider && isLocalProvider(config.chatProvider)) return config.chatProvider
+  return getProviderFromModelId(config.chatModel || 'gpt-4o-mini')
+}
+
+// 自定义与本地模型的模型名保存在 modelName 中
+function usesModelName(provider: ChatModelProvider): boolean {
+  return provider === 'custom' || isLocalProvider(provider)
+}
+
+function chatFormFromConfig(config: {
+  chatProvider?: string
+  chatModel?: string
+  openaiBaseUrl?: string
+  localLlmBaseUrl?: string
+}): ChatModelConfig {
+  const provider = getChatProviderFromConfig(config)
+  return {
+    provider,
+    modelId: config.chatModel || 'gpt-4o-mini',
+    apiKey: '',
+    baseUrl: isLocalProvider(provider) ? config.localLlmBaseUrl : config.openaiBaseUrl,
+    modelName: usesModelName(provider) ? config.chatModel : undefined,
+  }
+}
+
+function formatModelSize(bytes?: number | null): string {
+  if (!bytes) return ''
+  return bytes >= 1e9 ? `${(bytes / 1e9).toFixed(1)} GB` : `${Math.round(bytes / 1e6)} MB`
+}
+
 function getEmbeddingProviderFromModelId(modelId: string): EmbeddingModelProvider {
   if (!modelId) return 'openai'
   // 当前后端仅实现 OpenAI Embeddings，因此列表外的模型名一律视为自定义（用于 UI 回填/保存）
@@ -112,14 +206,18 @@ function formReducer(state: ModelFormState, action: FormAction): ModelFormState
       let defaultModelId = ''
       if (provider === 'openai') defaultModelId = 'gpt-4o-mini'
       else if (provider === 'anthropic') defaultModelId = 'claude-3-5-sonnet-20241022'
+      // 本地服务的地址和模型名不能沿用到其他服务上
+      const keepFields =
+        usesModelName(provider) &&
+        (provider === state.chat.provider || (provider === 'custom' && !isLocalProvider(state.chat.provider)))
       return {
         ...state,
         chat: {
           ...state.chat,
           provider,
-          modelId: provider === 'custom' ? '' : defaultModelId,
-          modelName: provider === 'custom' ? state.chat.modelName : undefined,
-          baseUrl: provider === 'custom' ? state.chat.baseUrl : undefined,
+          modelId: usesModelName(provider) ? '' : defaultModelId,
+          modelName: keepFields ? state.chat.modelName : undefined,
+          baseUrl: keepFields ? state.chat.baseUrl : undefined,
         },
       }
     }
@@ -277,13 +375,7 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
 
   // Form state with reducer
   const initialFormState: ModelFormState = {
-    chat: {
-      provider: getProviderFromModelId(state.config.chatModel || 'gpt-4o-mini'),
-      modelId: state.config.chatModel || 'gpt-4o-mini',
-      apiKey: '',
-      baseUrl: state.config.openaiBaseUrl,
-      modelName: undefined,
-    },
+    chat: chatFormFromConfig(state.config),
     embedding: {
       provider: getEmbeddingProviderFromModelId(state.config.embeddingModel || ''),
       modelId: state.config.embeddingModel || 'text-embedding-3-small',
@@ -313,12 +405,21 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
     embedding: { testing: false, result: 'idle', message: '' },
   })
 
+  // LLM usage (last 30 days)
+  const [llmUsage, setLlmUsage] = useState<LlmUsageSummary | null>(null)
+
+  // 本地推理服务上已有的模型
+  const [localModels, setLocalModels] = useState<LocalModelInfo[]>([])
+  const [localModelsLoading, setLocalModelsLoading] = useState(false)
+  const [localModelsError, setLocalModelsError] = useState<string | null>(null)
+
   // Check existing API keys on open
   useEffect(() => {
     if (open) {
       checkApiKeys()
       setDraftConfig(state.config)
       loadBlocklist()
+      loadLlmUsage()
       
       // 检查 dialog 插件可用性
       checkDialogPlugin().then((available) => {
@@ -332,16 +433,7 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
       formDispatch({
         type: 'RESET_FORM',
         payload: {
-          chat: {
-            provider: getProviderFromModelId(state.config.chatModel || 'gpt-4o-mini'),
-            modelId: state.config.chatModel || 'gpt-4o-mini',
-            apiKey: '',
-            baseUrl: state.config.openaiBaseUrl,
-            modelName:
-              getProviderFromModelId(state.config.chatModel || '') === 'custom'
-                ? state.config.chatModel
-                : undefined,
-          },
+          chat: chatFormFromConfig(state.config),
           embedding: {
             provider: getEmbeddingProviderFromModelId(state.config.embeddingModel || ''),
             modelId: state.config.embeddingModel || 'text-embedding-3-small',
@@ -357,6 +449,45 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
     }
   }, [open, state.config])
 
+  const loadLocalModels = useCallback(async (provider: LocalModelProvider, baseUrl?: string) => {
+    setLocalModelsLoading(true)
+    setLocalModelsError(null)
+    try {
+      const models = await invoke<LocalModelInfo[]>('list_local_llm_models', {
+        params: { provider, baseUrl: baseUrl?.trim() || undefined },
+      })
+      setLocalModels(models)
+      if (models.length === 0) {
+        setLocalModelsError(provider === 'ollama' ? '服务上没有模型，请先用 ollama pull 下载' : '服务上没有可用模型')
+      }
+    } catch (e) {
+      setLocalModels([])
+      setLocalModelsError(`获取模型列表失败：${e}`)
+    } finally {
+      setLocalModelsLoading(false)
+    }
+  }, [])
+
+  // 切换到本地服务时自动拉取一次模型列表
+  const chatProvider = formState.chat.provider
+  useEffect(() => {
+    setLocalModels([])
+    setLocalModelsError(null)
+    if (open && isLocalProvider(chatProvider)) {
+      void loadLocalModels(chatProvider, formState.chat.baseUrl)
+    }
+    // 地址修改后由用户点击刷新，避免每次输入都请求
+  }, [open, chatProvider, loadLocalModels])
+
+  const loadLlmUsage = async () => {
+    try {
+      setLlmUsage(await invoke<LlmUsageSummary>('get_llm_usage', { days: 30 }))
+    } catch (err) {
+      console.error('Failed to load LLM usage:', err)
+      setLlmUsage(null)
+    }
+  }
+
   const loadBlocklist = async () => {
     try {
       setBlocklistLoading(true)
@@ -414,8 +545,8 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
 
   // Handle provider change from grouped select
   const handleChatModelChange = useCallback((modelId: string) => {
-    if (modelId === 'custom') {
-      formDispatch({ type: 'SET_CHAT_PROVIDER', payload: 'custom' })
+    if (modelId === 'custom' || isLocalProvider(modelId)) {
+      formDispatch({ type: 'SET_CHAT_PROVIDER', payload: modelId })
     } else {
       const provider = getProviderFromModelId(modelId)
       formDispatch({ type: 'SET_CHAT_PROVIDER', payload: provider })
@@ -509,15 +640,15 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
       if (type === 'chat') {
         const provider = formState.chat.provider
         const model =
-          provider === 'custom' && formState.chat.modelName ? formState.chat.modelName : formState.chat.modelId
+          usesModelName(provider) && formState.chat.modelName ? formState.chat.modelName : formState.chat.modelId
 
         const apiKey =
-          formState.chat.apiKey && formState.chat.apiKey !== '••••••••••••••••'
+          !isLocalProvider(provider) && formState.chat.apiKey && formState.chat.apiKey !== '••••••••••••••••'
             ? formState.chat.apiKey
             : undefined
 
         const baseUrl =
-          provider === 'custom'
+          usesModelName(provider)
             ? formState.chat.baseUrl
             : provider === 'anthropic'
             ? state.config.anthropicBaseUrl
@@ -612,6 +743,17 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
     }
   }
 
+  const handleSelectNotesDir = async () => {
+    try {
+      const selected = await openFileDialog({ directory: true, multiple: false })
+      if (selected && typeof selected === 'string') {
+        setDraftConfig((prev) => ({ ...prev, notesDir: selected }))
+      }
+    } catch (e) {
+      console.error('选择笔记目录失败:', e)
+    }
+  }
+
   const handleSelectFile = async () => {
     console.log('[黑名单] handleSelectFile 被调用')
     try {
@@ -683,14 +825,24 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
   const handleSave = async () => {
     try {
       // Determine the actual model ID to save
+      const provider = formState.chat.provider
       let chatModel = formState.chat.modelId
-      if (formState.chat.provider === 'custom' && formState.chat.modelName) {
-        chatModel = formState.chat.modelName
+      if (usesModelName(provider) && formState.chat.modelName) {
+        chatModel = formState.chat.modelName.trim()
       }
 
       const updatedConfig = {
         ...draftConfig,
         chatModel,
+        // 本地服务需要显式指定；从本地服务切回时恢复按模型名推断
+        chatProvider: isLocalProvider(provider)
+          ? provider
+          : isLocalProvider(draftConfig.chatProvider ?? '')
+          ? 'auto'
+          : draftConfig.chatProvider,
+        localLlmBaseUrl: isLocalProvider(provider)
+          ? formState.chat.baseUrl?.trim() || undefined
+          : draftConfig.localLlmBaseUrl,
         embeddingModel:
           formState.embedding.provider === 'custom'
             ? formState.embedding.modelId
@@ -717,6 +869,7 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
   const showOpenAIFields = formState.chat.provider === 'openai'
   const showAnthropicFields = formState.chat.provider === 'anthropic'
   const showCustomFields = formState.chat.provider === 'custom'
+  const localProvider = isLocalProvider(formState.chat.provider) ? formState.chat.provider : null
 
   // Can share key only if chat provider is OpenAI and embedding provider is OpenAI
   const canShareKey =
@@ -817,12 +970,13 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
                     <label className="block text-sm font-medium text-gray-300">选择模型</label>
                     <GroupedSelect
                       value={
-                        formState.chat.provider === 'custom' ? 'custom' : formState.chat.modelId
+                        usesModelName(formState.chat.provider) ? formState.chat.provider : formState.chat.modelId
                       }
                       onChange={handleChatModelChange}
                       groups={[
                         { label: 'OpenAI', options: [...OPENAI_MODELS] },
                         { label: 'Anthropic', options: [...ANTHROPIC_MODELS] },
+                        { label: '本地模型', options: [...LOCAL_MODEL_PROVIDERS] },
                       ]}
                       customOption={{ label: '自定义模型（OpenAI 兼容）', value: 'custom' }}
                     />
@@ -980,6 +1134,85 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
                       </div>
                     )}
 
+                    {/* Local Model Fields */}
+                    {localProvider && (
+                      <div className="p-4 rounded-xl bg-surface/50 border border-glass-border/30 space-y-4">
+                        <div className="flex items-center gap-2 text-sm text-sky-400">
+                          <div className="w-2 h-2 rounded-full bg-sky-400"></div>
+                          <span>{localProvider === 'ollama' ? 'Ollama' : '本地服务（OpenAI 兼容）'}</span>
+                        </div>
+                        <InputField
+                          label="服务地址"
+                          value={formState.chat.baseUrl || ''}
+                          onChange={(v) => formDispatch({ type: 'SET_CHAT_BASE_URL', payload: v })}
+                          placeholder={LOCAL_DEFAULT_BASE_URLS[localProvider]}
+                          hint="留空使用默认地址；数据不会离开本机"
+                        />
+                        <div className="space-y-1.5">
+                          <label htmlFor="local-llm-model" className="block text-sm font-medium text-gray-300">
+                            模型
+                          </label>
+                          <div className="flex gap-2">
+                            {localModels.length > 0 ? (
+                              <div className="relative flex-1">
+                                <select
+                                  id="local-llm-model"
+                                  value={formState.chat.modelName || ''}
+                                  onChange={(e) => formDispatch({ type: 'SET_CHAT_MODEL_NAME', payload: e.target.value })}
+                                  className="w-full appearance-none px-4 py-2.5 pr-10 bg-surface border border-glass-border rounded-lg text-white cursor-pointer hover:border-neon-blue/50 transition-colors focus:outline-none focus:ring-2 focus:ring-neon-blue/30"
+                                >
+                                  <option value="" disabled className="bg-surface">
+                                    选择模型
+                                  </option>
+                                  {formState.chat.modelName &&
+                                    !localModels.some((m) => m.id === formState.chat.modelName) && (
+                                      <option value={formState.chat.modelName} className="bg-surface">
+                                        {formState.chat.modelName}（服务上未找到）
+                                      </option>
+                                    )}
+                                  {localModels.map((model) => (
+                                    <option key={model.id} value={model.id} className="bg-surface">
+                                      {model.id}
+                                      {model.sizeBytes ? ` — ${formatModelSize(model.sizeBytes)}` : ''}
+                                    </option>
+                                  ))}
+                                </select>
+                                <ChevronDown className="absolute right-3 top-1/2 -translate-y-1/2 w-4 h-4 text-gray-400 pointer-events-none" />
+                              </div>
+                            ) : (
+                              <input
+                                id="local-llm-model"
+                                type="text"
+                                value={formState.chat.modelName || ''}
+                                onChange={(e) => formDispatch({ type: 'SET_CHAT_MODEL_NAME', payload: e.target.value })}
+                                placeholder={localProvider === 'ollama' ? '例如: qwen2.5:7b' : '例如: llama-3-8b-instruct'}
+                                className="flex-1 px-4 py-2.5 bg-surface border border-glass-border rounded-lg text-white placeholder:text-gray-500 hover:border-neon-blue/50 transition-colors focus:outline-none focus:ring-2 focus:ring-neon-blue/30"
+                              />
+                            )}
+                            <button
+                              onClick={() => loadLocalModels(localProvider, formState.chat.baseUrl)}
+                              disabled={localModelsLoading}
+                              className="px-4 py-2 rounded-lg bg-surface border border-glass-border hover:bg-white/10 transition-colors disabled:opacity-50"
+                              title="刷新模型列表"
+                              type="button"
+                            >
+                              {localModelsLoading ? (
+                                <Loader2 className="w-5 h-5 text-gray-300 animate-spin" />
+                              ) : (
+                                <RefreshCw className="w-5 h-5 text-gray-300" />
+                              )}
+                            </button>
+                          </div>
+                          {localModelsError && (
+                            <p className="text-xs flex items-center gap-1 text-red-400">
+                              <AlertCircle className="w-3 h-3" />
+                              {localModelsError}
+                            </p>
+                          )}
+                        </div>
+                      </div>
+                    )}
+
                     {/* Test Connection Button */}
                     <div className="flex items-center gap-4">
                       <button
@@ -1015,6 +1248,85 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
 
                 <div className="h-px bg-glass-border/50" />
 
+                {/* ==================== LLM Usage Section ==================== */}
+                <section className="space-y-4">
+                  <div className="flex items-center gap-2">
+                    <div className="w-8 h-8 rounded-lg bg-gradient-to-br from-neon-blue to-emerald-500 flex items-center justify-center">
+                      <Gauge className="w-4 h-4 text-white" />
+                    </div>
+                    <h3 className="text-lg font-semibold text-white">模型用量</h3>
+                  </div>
+
+                  {llmUsage && llmUsage.calls > 0 ? (
+                    <div className="p-4 rounded-xl bg-surface/50 border border-glass-border/30 space-y-3">
+                      <div className="text-sm text-gray-300">
+                        近 30 天共调用 {llmUsage.calls} 次，消耗{' '}
+                        {(llmUsage.promptTokens + llmUsage.completionTokens).toLocaleString()} tokens
+                        （输入 {llmUsage.promptTokens.toLocaleString()} / 输出{' '}
+                        {llmUsage.completionTokens.toLocaleString()}）
+                        {llmUsage.cacheHits > 0 &&
+                          `，缓存命中 ${llmUsage.cacheHits} 次，节省 ${llmUsage.savedTokens.toLocaleString()} tokens`}
+                        {llmUsage.errors > 0 && `，失败 ${llmUsage.errors} 次`}
+                      </div>
+                      <table className="w-full text-xs text-gray-400">
+                        <thead>
+                          <tr className="text-left text-gray-500">
+                            <th className="py-1 font-medium">模型</th>
+                            <th className="py-1 font-medium">用途</th>
+                            <th className="py-1 font-medium text-right">调用</th>
+                            <th className="py-1 font-medium text-right">Tokens</th>
+                            <th className="py-1 font-medium text-right">平均耗时</th>
+                          </tr>
+                        </thead>
+                        <tbody>
+                          {llmUsage.rows.map((row) => (
+                            <tr key={`${row.provider}-${row.model}-${row.purpose}`}>
+                              <td className="py-1 text-gray-300">
+                                {row.provider} · {row.model}
+                              </td>
+                              <td className="py-1">{LLM_PURPOSE_LABELS[row.purpose] ?? row.purpose}</td>
+                              <td className="py-1 text-right">{row.calls}</td>
+                              <td className="py-1 text-right">
+                                {(row.promptTokens + row.completionTokens).toLocaleString()}
+                              </td>
+                              <td className="py-1 text-right">
+                                {row.avgLatencyMs != null ? `${(row.avgLatencyMs / 1000).toFixed(1)}s` : '-'}
+                              </td>
+                            </tr>
+                          ))}
+                        </tbody>
+                      </table>
+                    </div>
+                  ) : (
+                    <p className="text-sm text-gray-500">近 30 天还没有模型调用记录</p>
+                  )}
+
+                  <div className="space-y-2">
+                    <label className="block text-sm font-medium text-gray-300">响应缓存</label>
+                    <select
+                      value={draftConfig.llmCacheTtlSecs ?? 0}
+                      onChange={(e) =>
+                        setDraftConfig((prev) => ({
+                          ...prev,
+                          llmCacheTtlSecs: Number(e.target.value),
+                        }))
+                      }
+                      className="w-full px-4 py-2.5 bg-surface border border-glass-border rounded-lg text-white cursor-pointer hover:border-neon-blue/50 transition-colors focus:outline-none focus:ring-2 focus:ring-neon-blue/30"
+                    >
+                      {LLM_CACHE_TTL_OPTIONS.map((option) => (
+                        <option key={option.value} value={option.value}>
+                          {option.label}
+                        </option>
+                      ))}
+                    </select>
+                    <p className="text-xs text-gray-500">
+                      有效期内完全相同的请求（意图解析、主动建议等）直接复用上次结果；问答回答始终实时生成
+                    </p>
+                  </div>
+                </section>
+
+                <div className="h-px bg-glass-border/50" />
+
                 {/* ==================== Embedding Model Section ==================== */}
                 <section className="space-y-4">
                   <div className="flex items-center gap-2">
@@ -1348,6 +1660,89 @@ export default function SettingsModal({ open, onClose }: SettingsModalProps) {
 
                 <div className="h-px bg-glass-border/50" />
 
+                <section className="space-y-4">
+                  <div className="flex items-center justify-between">
+                    <div className="flex items-center gap-2">
+                      <div className="w-8 h-8 rounded-lg bg-neon-blue/20 text-neon-blue flex items-center justify-center">
+                        <CalendarRange className="w-5 h-5" />
+                      </div>
+                      <div>
+                        <h3 className="text-lg font-semibold text-white">活动摘要</h3>
+                        <p className="text-sm text-gray-400">每小时用所选模型总结活动，并汇总为日、周摘要，用于回答较长时间范围的问题</p>
+                      </div>
+                    </div>
+                    <button
+                      onClick={() =>
+                        setDraftConfig((prev) => ({
+                          ...prev,
+                          enableActivitySummaries: !prev.enableActivitySummaries,
+                        }))
+                      }
+                      className={`w-12 h-6 rounded-full transition-colors relative ${
+                        draftConfig.enableActivitySummaries ? 'bg-neon-blue' : 'bg-gray-600'
+                      }`}
+                    >
+                      <div
+                        className={`absolute top-1 left-1 w-4 h-4 rounded-full bg-white transition-transform ${
+                          draftConfig.enableActivitySummaries ? 'translate-x-6' : 'translate-x-0'
+                        }`}
+                      />
+                    </button>
+                  </div>
+                </section>
+
+                <div className="h-px bg-glass-border/50" />
+
+                <section className="space-y-4">
+                  <div className="flex items-center justify-between">
+                    <div className="flex items-center gap-2">
+                      <div className="w-8 h-8 rounded-lg bg-neon-purple/20 text-neon-purple flex items-center justify-center">
+                        <FileText className="w-5 h-5" />
+                      </div>
+                      <div>
+                        <h3 className="text-lg font-semibold text-white">日报 / 周报</h3>
+                        <p className="text-sm text-gray-400">每天和每周结束后生成 Markdown 报告，包含应用时长、专注度走势和任务总结</p>
+                      </div>
+                    </div>
+                    <button
+                      onClick={() =>
+                        setDraftConfig((prev) => ({
+                          ...prev,
+                          enableDigests: !prev.enableDigests,
+                        }))
+                      }
+                      className={`w-12 h-6 rounded-full transition-colors relative ${
+                        draftConfig.enableDigests ? 'bg-neon-blue' : 'bg-gray-600'
+                      }`}
+                    >
+                      <div
+                        className={`absolute top-1 left-1 w-4 h-4 rounded-full bg-white transition-transform ${
+                          draftConfig.enableDigests ? 'translate-x-6' : 'translate-x-0'
+                        }`}
+                      />
+                    </button>
+                  </div>
+                  <InputField
+                    label="笔记目录"
+                    value={draftConfig.notesDir ?? ''}
+                    onChange={(v) => setDraftConfig((prev) => ({ ...prev, notesDir: v }))}
+                    placeholder="留空则只保存在应用内"
+                    hint="报告生成后会以 .md 文件写入该目录，可直接放在 Obsidian 等笔记库中"
+                    rightElement={
+                      <button
+                        onClick={handleSelectNotesDir}
+                        className="px-4 py-2 rounded-lg bg-surface border border-glass-border hover:bg-white/10 active:bg-white/20 active:scale-95 transition-all duration-100"
+                        title="选择目录"
+                        type="button"
+                      >
+                        <FolderOpen className="w-5 h-5 text-gray-300" />
+                      </button>
+                    }
+                  />
+                </section>
+
+                <div className="h-px bg-glass-border/50" />
+
                 <section className="space-y-4">
                   <div className="flex items-center justify-between">
                     <div className="flex items-center gap-2">
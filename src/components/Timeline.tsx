@@ -193,42 +193,22 @@ export default function Timeline() {
       if (intent.has_ocr !== null && intent.has_ocr !== undefined) newHasOcr = intent.has_ocr
       
       if (intent.date_range) {
-         const today = new Date()
-         const start = new Date(today)
-         const end = new Date(today)
-         
-         switch(intent.date_range) {
-             case 'today':
-                 break;
-             case 'yesterday':
-                 start.setDate(today.getDate() - 1)
-                 end.setDate(today.getDate() - 1)
-                 break;
-             case 'this_week': {
-                 const day = today.getDay() || 7; 
-                 if(day !== 1) start.setHours(-24 * (day - 1)); 
-                 break;
+         // 时间表达由后端按本地时区解析（支持 "last 3 days"、"上周三下午" 等）
+         const range = await invoke<{ fromTs: number, toTs: number } | null>('resolve_time_range', {
+             expression: intent.date_range,
+         })
+         if (range) {
+             const fmt = (ts: number) => {
+                 const d = new Date(ts * 1000)
+                 const y = d.getFullYear()
+                 const m = String(d.getMonth() + 1).padStart(2, '0')
+                 const day = String(d.getDate()).padStart(2, '0')
+                 return `${y}-${m}-${day}`
              }
-             case 'last_week': {
-                 const currentDay = today.getDay() || 7;
-                 start.setDate(today.getDate() - currentDay - 6);
-                 end.setDate(today.getDate() - currentDay);
-                 break;
-             }
-             case 'this_month':
-                 start.setDate(1)
-                 break;
-         }
-         
-         const fmt = (d: Date) => {
-             const y = d.getFullYear()
-             const m = String(d.getMonth() + 1).padStart(2, '0')
-             const day = String(d.getDate()).padStart(2, '0')
-             return `${y}-${m}-${day}`
+
+             newStartDate = fmt(range.fromTs)
+             newEndDate = fmt(range.toTs)
          }
-         
-         newStartDate = fmt(start)
-         newEndDate = fmt(end)
       }
 
       setAppName(newAppName)
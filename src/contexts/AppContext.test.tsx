@@ -570,14 +570,16 @@ describe('AppContext', () => {
       })
 
       expect(mockInvoke).toHaveBeenCalledWith('search_activities', {
-        query: 'test',
-        appName: 'Test App',
-        fromTs: undefined,
-        toTs: undefined,
-        hasOcr: undefined,
-        limit: 10,
-        offset: undefined,
-        orderBy: undefined,
+        params: {
+          query: 'test',
+          appName: 'Test App',
+          fromTs: undefined,
+          toTs: undefined,
+          hasOcr: undefined,
+          limit: 10,
+          offset: undefined,
+          orderBy: undefined,
+        },
       })
       expect(result.current.state.activities).toEqual(mockSearchResult.items)
       expect(result.current.state.lastSearchParams).toEqual(searchParams)
@@ -615,14 +617,16 @@ describe('AppContext', () => {
       })
 
       expect(mockInvoke).toHaveBeenCalledWith('search_activities', {
-        query: 'test query',
-        appName: 'Chrome',
-        fromTs: 1000000000,
-        toTs: 2000000000,
-        hasOcr: true,
-        limit: 20,
-        offset: 0,
-        orderBy: 'rank',
+        params: {
+          query: 'test query',
+          appName: 'Chrome',
+          fromTs: 1000000000,
+          toTs: 2000000000,
+          hasOcr: true,
+          limit: 20,
+          offset: 0,
+          orderBy: 'rank',
+        },
       })
     })
 
@@ -33,19 +33,30 @@ export interface AppConfig {
   aiEnabled: boolean
   enableFocusAnalytics: boolean
   enableProactiveAssistant: boolean
+  enableActivitySummaries?: boolean
+  summaryBackfillDays?: number
+  enableDigests?: boolean
+  notesDir?: string
   retentionDays: number
   apiKey?: string
   chatModel?: string
+  chatProvider?: 'auto' | 'openai' | 'anthropic' | 'ollama' | 'local' | 'mock' | string
   embeddingModel?: string
   embeddingBaseUrl?: string
   embeddingUseSharedKey?: boolean
+  embeddingProvider?: 'local' | 'openai' | 'stub' | string
+  localEmbeddingModel?: string
+  rerankEnabled?: boolean
+  rerankerModel?: string
   openaiBaseUrl?: string
   anthropicBaseUrl?: string
+  localLlmBaseUrl?: string
   blocklistEnabled: boolean
   blocklistMode: string
   privacyModeEnabled: boolean
   privacyModeUntil?: number
   intentParseTimeoutMs?: number
+  llmCacheTtlSecs?: number
 }
 
 export interface SearchParams extends Record<string, unknown> {
@@ -85,6 +96,8 @@ const initialState: AppState = {
     aiEnabled: false,
     enableFocusAnalytics: true, // 与后端默认值一致
     enableProactiveAssistant: false,
+    enableActivitySummaries: false,
+    enableDigests: false,
     retentionDays: 30,
     chatModel: 'gpt-4o-mini',
     embeddingModel: 'text-embedding-3-small',
@@ -198,14 +211,16 @@ export function AppProvider({ children }: { children: ReactNode }) {
       const result = await invoke<{ items: ActivityLog[]; total: number }>(
         'search_activities',
         {
-          query: params.query,
-          appName: params.appName,
-          fromTs: params.fromTs,
-          toTs: params.toTs,
-          hasOcr: params.hasOcr,
-          limit: params.limit,
-          offset: params.offset,
-          orderBy: params.orderBy,
+          params: {
+            query: params.query,
+            appName: params.appName,
+            fromTs: params.fromTs,
+            toTs: params.toTs,
+            hasOcr: params.hasOcr,
+            limit: params.limit,
+            offset: params.offset,
+            orderBy: params.orderBy,
+          },
         }
       )
       dispatch({
@@ -36,6 +36,13 @@ export interface LocalChatMessage {
   ts: number
   dbId?: number  // 持久化后填充
   rating?: 1 | -1 | null
+  contextIds?: number[]  // 回答引用的活动 ID
+}
+
+// ai_chat / ai_chat_stream 的返回值：无效的 [n] 引用已从正文中删除
+export interface ChatAnswer {
+  content: string
+  contextIds: number[]
 }
 
 // ============================================